@@ -0,0 +1,159 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Sends raw requests to an instance, for endpoints that don't have their own
+//! subcommand yet.
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context, Result};
+use omegaupload_common::{Url, API_ENDPOINT, OWNER_TOKEN_HEADER_NAME};
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::Method;
+use rpassword::prompt_password;
+
+use crate::failure::Failure;
+use crate::{history, log_response, Output};
+
+/// The environment variable that the admin token is read from, which is the
+/// same one the server reads it from.
+const ADMIN_TOKEN_VAR: &str = "OMEGAUPLOAD_ADMIN_TOKEN";
+
+/// How a request proves that it's allowed to do what it asks.
+#[derive(Clone, Copy, Default)]
+pub struct Auth {
+    /// Send the admin token as a bearer token.
+    pub admin: bool,
+    /// Send the owner token recorded in the history for the paste.
+    pub owner_token: bool,
+}
+
+/// Sends a request to `url` and prints the response body as it was received.
+/// Responses that aren't successful are printed too, but are then returned as
+/// errors.
+pub fn run(
+    method: &str,
+    url: &Url,
+    auth: Auth,
+    headers: &[String],
+    data: Option<&Path>,
+    output: Output,
+) -> Result<()> {
+    let method = Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+        .map_err(|_| anyhow!("Invalid method {method}"))?;
+
+    let mut req = Client::new().request(method, url.clone());
+    for header in headers {
+        let (name, value) = header
+            .split_once(':')
+            .with_context(|| format!("Invalid header {header}, expected one like `Name: value`"))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .with_context(|| format!("Invalid header name {name}"))?;
+        let value = HeaderValue::from_str(value.trim())
+            .with_context(|| format!("Invalid header value for {name}"))?;
+        req = req.header(name, value);
+    }
+
+    if auth.admin {
+        let token = match std::env::var(ADMIN_TOKEN_VAR) {
+            Ok(token) if !token.is_empty() => token,
+            _ => prompt_password("Admin token: ")?,
+        };
+        req = req.header(AUTHORIZATION, format!("Bearer {token}"));
+    }
+
+    if auth.owner_token {
+        let token = history::owner_token(&paste_url(url)?)?
+            .context("No owner token for this paste was found in the history")?;
+        req = req.header(&*OWNER_TOKEN_HEADER_NAME, token);
+    }
+
+    if let Some(data) = data {
+        let body = if data == Path::new("-") {
+            let mut body = Vec::new();
+            std::io::stdin().read_to_end(&mut body)?;
+            body
+        } else {
+            std::fs::read(data).with_context(|| format!("Failed to read {}", data.display()))?
+        };
+        req = req.body(body);
+    }
+
+    let start = Instant::now();
+    let res = req.send().context("Failed to send request")?;
+    log_response(&res, start);
+    let status = res.status();
+    if output.porcelain {
+        eprintln!("status\t{}", status.as_u16());
+    } else {
+        eprintln!("{status}");
+    }
+
+    let body = res.bytes().context("Failed to read response")?;
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&body)?;
+    // Keep the prompt on its own line after JSON, which has no trailing newline.
+    if !body.is_empty() && !body.ends_with(b"\n") && atty::is(atty::Stream::Stdout) {
+        writeln!(stdout)?;
+    }
+    stdout.flush()?;
+
+    if !status.is_success() {
+        if let Some(failure) = Failure::from_status(status) {
+            return Err(failure).context(format!("Got HTTP error {status}"));
+        }
+        bail!("Got HTTP error {status}");
+    }
+    Ok(())
+}
+
+/// The URL of the paste that an API URL like `https://example.com/api/CODE`
+/// is for, which is what the history records.
+pub fn paste_url(url: &Url) -> Result<Url> {
+    let path = url
+        .path()
+        .split_once(&format!("{API_ENDPOINT}/"))
+        .map(|(prefix, code)| format!("{prefix}/{code}"))
+        .with_context(|| format!("{url} isn't the API URL of a paste"))?;
+    let mut paste_url = url.clone();
+    paste_url.set_path(&path);
+    paste_url.set_query(None);
+    paste_url.set_fragment(None);
+    Ok(paste_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{paste_url, Url};
+
+    #[test]
+    fn finds_pastes_of_api_urls() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        assert_eq!(
+            paste_url(&url("https://example.com/api/PgRG8Hfrr9rR")).unwrap(),
+            url("https://example.com/PgRG8Hfrr9rR")
+        );
+        assert_eq!(
+            paste_url(&url("https://example.com/paste/api/PgRG8Hfrr9rR?x=1")).unwrap(),
+            url("https://example.com/paste/PgRG8Hfrr9rR")
+        );
+        assert!(paste_url(&url("https://example.com/PgRG8Hfrr9rR")).is_err());
+        assert!(paste_url(&url("https://example.com/apiary/PgRG8Hfrr9rR")).is_err());
+    }
+}
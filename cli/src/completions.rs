@@ -0,0 +1,83 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::io::Write;
+
+use anyhow::Result;
+use clap::Command;
+use clap_complete::Shell;
+
+const BIN_NAME: &str = "omegaupload";
+
+/// Completes paste URLs for `download` and instances for `upload` and `watch`
+/// from the local history. This wraps the completion function generated by
+/// clap, which handles everything else.
+const BASH_DYNAMIC: &str = r#"
+_omegaupload_dynamic() {
+    local cur
+    if declare -F _get_comp_words_by_ref > /dev/null; then
+        _get_comp_words_by_ref -n : cur
+    else
+        cur="${COMP_WORDS[COMP_CWORD]}"
+    fi
+
+    local candidates=""
+    if [[ ${COMP_CWORD} -eq 2 && "${cur}" != -* ]]; then
+        case "${COMP_WORDS[1]}" in
+            download)
+                candidates="$(omegaupload history 2> /dev/null)" ;;
+            upload|watch)
+                candidates="$(omegaupload history --instances 2> /dev/null)" ;;
+        esac
+    fi
+
+    if [[ -z "${candidates}" ]]; then
+        _omegaupload "$@"
+        return
+    fi
+
+    COMPREPLY=($(compgen -W "${candidates}" -- "${cur}"))
+    if declare -F __ltrim_colon_completions > /dev/null; then
+        __ltrim_colon_completions "${cur}"
+    fi
+}
+
+complete -F _omegaupload_dynamic -o bashdefault -o default omegaupload
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+complete -c omegaupload -n "__fish_seen_subcommand_from download" -f -a "(omegaupload history 2> /dev/null)"
+complete -c omegaupload -n "__fish_seen_subcommand_from upload watch" -a "(omegaupload history --instances 2> /dev/null)"
+"#;
+
+/// Prints a completion script for the provided shell. Bash and fish also
+/// complete URLs from the local history.
+///
+/// # Errors
+///
+/// Returns an error if the script couldn't be written to stdout.
+pub fn print(shell: Shell, command: &mut Command) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    clap_complete::generate(shell, command, BIN_NAME, &mut stdout);
+
+    match shell {
+        Shell::Bash => stdout.write_all(BASH_DYNAMIC.as_bytes())?,
+        Shell::Fish => stdout.write_all(FISH_DYNAMIC.as_bytes())?,
+        _ => (),
+    }
+
+    Ok(())
+}
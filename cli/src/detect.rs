@@ -0,0 +1,99 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Guesses hints for pastes that don't come with any, such as from stdin, so
+//! that the web frontend renders them properly. This uses the same detection
+//! as the frontend.
+
+/// Types that have an unambiguous extension, which the frontend uses to pick
+/// how to show the paste.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/svg+xml", "svg"),
+    ("image/bmp", "bmp"),
+    ("video/mp4", "mp4"),
+    ("video/webm", "webm"),
+    ("application/x-matroska", "mkv"),
+    ("audio/mpeg", "mp3"),
+    ("audio/ogg", "ogg"),
+    ("audio/flac", "flac"),
+    ("application/pdf", "pdf"),
+    ("application/zip", "zip"),
+    ("application/gzip", "gz"),
+    ("application/x-tar", "tar"),
+    ("application/x-xz", "xz"),
+    ("application/x-bzip", "bz2"),
+    ("application/x-bzip2", "bz2"),
+];
+
+/// Types of text that the frontend can't reliably tell apart from their
+/// contents alone, and the language to highlight them as.
+const LANGUAGES: &[(&str, &str)] = &[
+    // Patches from `git format-patch` start like emails.
+    ("application/mbox", "diff"),
+    ("text/x-patch", "diff"),
+    ("text/x-diff", "diff"),
+    ("application/x-shellscript", "bash"),
+    ("application/xml", "xml"),
+    ("text/xml", "xml"),
+];
+
+/// Guesses the language of a text paste.
+pub fn language(data: &[u8]) -> Option<&'static str> {
+    // Bare numbers and strings are valid JSON, but aren't worth highlighting.
+    let first = data.iter().find(|byte| !byte.is_ascii_whitespace());
+    if matches!(first, Some(b'{' | b'['))
+        && serde_json::from_slice::<serde_json::Value>(data).is_ok()
+    {
+        return Some("json");
+    }
+    let mime_type = tree_magic_mini::from_u8(data);
+    LANGUAGES
+        .iter()
+        .find(|(candidate, _)| *candidate == mime_type)
+        .map(|(_, language)| *language)
+}
+
+/// Guesses a file name for a binary paste, so that it's shown and saved as the
+/// right type.
+pub fn file_name(data: &[u8]) -> Option<String> {
+    let mime_type = tree_magic_mini::from_u8(data);
+    EXTENSIONS
+        .iter()
+        .find(|(candidate, _)| *candidate == mime_type)
+        .map(|(_, extension)| format!("paste.{extension}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{file_name, language};
+
+    #[test]
+    fn detects_hints() {
+        let test = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../test");
+        let read = |name| std::fs::read(test.join(name)).unwrap();
+        assert_eq!(language(&read("0000-test-patch.patch")), Some("diff"));
+        assert_eq!(language(&read("code.rs")), None);
+        assert_eq!(language(b" {\"a\": [1, 2]}\n"), Some("json"));
+        assert_eq!(language(b"42"), None);
+        assert_eq!(file_name(&read("image.png")).as_deref(), Some("paste.png"));
+        assert_eq!(file_name(&read("LICENSE.md")), None);
+    }
+}
@@ -0,0 +1,188 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Checks that an instance works end to end, and which optional features it
+//! supports.
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use omegaupload_common::crypto::SealOptions;
+use omegaupload_common::key_format::KeyFormat;
+use omegaupload_common::secrecy::ExposeSecret;
+use omegaupload_common::{Expiration, ParsedUrl, Url};
+use reqwest::blocking::Client;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+
+use crate::{api_url, delete_paste, fetch_paste, upload, Output, SizeLimits};
+
+const TEST_PASTE: &[u8] = b"This is a test paste uploaded by omegaupload doctor.";
+
+#[derive(Clone, Copy)]
+enum Outcome {
+    Ok,
+    Failed,
+    Unsupported,
+}
+
+struct Report {
+    output: Output,
+    failed: bool,
+}
+
+impl Report {
+    fn record(&mut self, check: &str, outcome: Outcome, elapsed: Option<Duration>) {
+        let millis = elapsed.map(|elapsed| elapsed.as_millis());
+        if matches!(outcome, Outcome::Failed) {
+            self.failed = true;
+        }
+
+        if self.output.porcelain {
+            let outcome = match outcome {
+                Outcome::Ok => "ok",
+                Outcome::Failed => "failed",
+                Outcome::Unsupported => "unsupported",
+            };
+            let millis = millis.map(|millis| millis.to_string()).unwrap_or_default();
+            println!("check\t{check}\t{outcome}\t{millis}");
+        } else {
+            let outcome = match outcome {
+                Outcome::Ok => "ok",
+                Outcome::Failed => "FAILED",
+                Outcome::Unsupported => "not supported",
+            };
+            match millis {
+                Some(millis) => println!("{check:<24} {outcome} ({millis} ms)"),
+                None => println!("{check:<24} {outcome}"),
+            }
+        }
+    }
+
+    fn timed<T>(&mut self, check: &str, f: impl FnOnce() -> Result<T>) -> Option<T> {
+        let start = Instant::now();
+        let res = f();
+        let elapsed = start.elapsed();
+        match res {
+            Ok(value) => {
+                self.record(check, Outcome::Ok, Some(elapsed));
+                Some(value)
+            }
+            Err(e) => {
+                self.record(check, Outcome::Failed, Some(elapsed));
+                eprintln!("{check}: {e:#}");
+                None
+            }
+        }
+    }
+
+    fn supported(&mut self, check: &str, supported: bool) {
+        let outcome = if supported {
+            Outcome::Ok
+        } else {
+            Outcome::Unsupported
+        };
+        self.record(check, outcome, None);
+    }
+}
+
+/// Uploads, downloads, verifies, and deletes a test paste.
+///
+/// # Errors
+///
+/// Returns an error if any required check failed.
+pub fn run(instance: Url, output: Output) -> Result<()> {
+    let mut report = Report {
+        output,
+        failed: false,
+    };
+    // Internal requests shouldn't print progress bars or touch the history.
+    let quiet = Output {
+        porcelain: true,
+        record_history: false,
+        key_format: KeyFormat::default(),
+        split_url: false,
+        url_only: false,
+    };
+
+    let expiration = Expiration::from_str("5m").ok();
+    let uploaded = match report.timed("upload", || {
+        upload(
+            instance,
+            TEST_PASTE.to_vec(),
+            None,
+            SealOptions::default(),
+            expiration,
+            None,
+            None,
+            None,
+            None,
+            None,
+            quiet,
+        )
+    }) {
+        Some(uploaded) => uploaded,
+        None => bail!("Unable to upload a test paste."),
+    };
+
+    report.supported("expiration reporting", uploaded.expiration.is_some());
+    report.supported("owner tokens", uploaded.owner_token.is_some());
+
+    let url: ParsedUrl = uploaded.url.as_str().parse()?;
+
+    if let Some((data, _)) = report.timed("download", || {
+        fetch_paste(&url, None, SizeLimits::default(), 1)
+    }) {
+        report.timed("verify", || {
+            if data.expose_secret() != TEST_PASTE {
+                bail!("Downloaded paste doesn't match what was uploaded");
+            }
+            Ok(())
+        });
+    }
+
+    let client = Client::new();
+    let head = client.head(api_url(&url)).send();
+    report.supported(
+        "HEAD requests",
+        matches!(head, Ok(ref res) if res.status() == StatusCode::OK),
+    );
+
+    let range = client.get(api_url(&url)).header(RANGE, "bytes=0-0").send();
+    report.supported(
+        "range requests",
+        matches!(range, Ok(ref res) if res.status() == StatusCode::PARTIAL_CONTENT),
+    );
+
+    report.timed("delete", || {
+        delete_paste(&url, uploaded.owner_token.as_deref())
+    });
+    report.timed("verify deletion", || {
+        let res = client.get(api_url(&url)).send()?;
+        // Newer servers remember that the paste was deleted.
+        if !matches!(res.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+            bail!("Paste still exists after deletion: {}", res.status());
+        }
+        Ok(())
+    });
+
+    if report.failed {
+        bail!("Some checks failed.");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt::Display;
+
+use omegaupload_common::crypto::Error as CryptoError;
+use reqwest::StatusCode;
+
+/// Exit code used for any failure without a more specific exit code.
+pub const GENERIC_EXIT_CODE: i32 = 1;
+
+/// Exit code used when the provided arguments could not be parsed. This is
+/// `EX_USAGE` from `sysexits.h`, as clap's default of `2` is already taken.
+pub const USAGE_EXIT_CODE: i32 = 64;
+
+/// Failures that wrappers may want to react to. Each failure has a stable exit
+/// code and name, which should be relied upon instead of the error message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// The paste does not exist on the server.
+    NotFound,
+    /// The paste existed, but has expired, been burned, or been downloaded as
+    /// many times as it may be.
+    Expired,
+    /// The provided password could not decrypt the paste.
+    BadPassword,
+    /// The paste is too large for the server to accept.
+    TooLarge,
+    /// The decryption key in the URL could not decrypt the paste.
+    BadKey,
+    /// The server refused to modify the paste, such as when an owner token is
+    /// missing or incorrect.
+    Forbidden,
+    /// The paste appears to contain credentials, and `--strict` was set.
+    Secrets,
+    /// The paste is time-locked, and can't be read yet.
+    Locked,
+}
+
+impl Failure {
+    #[must_use]
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::NotFound => 2,
+            Self::Expired => 3,
+            Self::BadPassword => 4,
+            Self::TooLarge => 5,
+            Self::BadKey => 6,
+            Self::Forbidden => 7,
+            Self::Secrets => 8,
+            Self::Locked => 9,
+        }
+    }
+
+    /// The name of the failure, as printed in porcelain mode.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::NotFound => "not-found",
+            Self::Expired => "expired",
+            Self::BadPassword => "bad-password",
+            Self::TooLarge => "too-large",
+            Self::BadKey => "bad-key",
+            Self::Forbidden => "forbidden",
+            Self::Secrets => "secrets",
+            Self::Locked => "locked",
+        }
+    }
+
+    #[must_use]
+    pub fn from_status(status: StatusCode) -> Option<Self> {
+        match status {
+            StatusCode::NOT_FOUND => Some(Self::NotFound),
+            StatusCode::GONE => Some(Self::Expired),
+            StatusCode::PAYLOAD_TOO_LARGE => Some(Self::TooLarge),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Some(Self::Forbidden),
+            StatusCode::LOCKED => Some(Self::Locked),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn from_crypto_error(error: &CryptoError) -> Option<Self> {
+        match error {
+            CryptoError::Password => Some(Self::BadPassword),
+            CryptoError::SecretKey => Some(Self::BadKey),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "The paste does not exist."),
+            Self::Expired => write!(f, "The paste has expired or has been burned."),
+            Self::BadPassword => write!(f, "The provided password was incorrect."),
+            Self::TooLarge => write!(f, "The paste is too large."),
+            Self::BadKey => write!(f, "The secret key in the URL was incorrect."),
+            Self::Forbidden => write!(f, "The server refused to modify the paste."),
+            Self::Secrets => write!(f, "The paste appears to contain credentials."),
+            Self::Locked => write!(f, "The paste can't be read yet."),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
@@ -0,0 +1,214 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A line based protocol for other programs to drive the CLI, in the style of
+//! git credential helpers.
+//!
+//! Each request is a list of `key=value` lines ending with a blank line or the
+//! end of input. Each response is in the same format, so any number of
+//! requests can be sent over one process. Unknown keys are ignored.
+//!
+//! Requests have an `action` of `create`, `fetch`, or `delete`:
+//!
+//! - `create` uploads `data`, which is standard Base64, to the instance at
+//!   `url`. `password`, `expiration`, `name`, `language`, `kdf-profile`, and
+//!   `cipher` are optional. Responds with `url`, `expires`, and, if the server
+//!   supports it, `token`.
+//! - `fetch` downloads the paste at `url`, which must include its key, with an
+//!   optional `password`. Responds with `data` in standard Base64 and
+//!   `expires`.
+//! - `delete` deletes the paste at `url`. Responds with nothing.
+//!
+//! Failed requests respond with `error`, which is the same name as in porcelain
+//! mode, and a human-readable `message`. Lines that aren't `key=value` end the
+//! helper, as the rest of the input can't be trusted.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+use ::base64::engine::general_purpose::STANDARD;
+use ::base64::Engine;
+use anyhow::{anyhow, Context, Result};
+use omegaupload_common::crypto::{Cipher, KdfProfile};
+use omegaupload_common::secrecy::{ExposeSecret, SecretString, SecretVec};
+use omegaupload_common::{Expiration, ParsedUrl, Url};
+
+use crate::failure::Failure;
+use crate::{
+    delete_paste, fetch_paste, history, porcelain_expiration, seal_options, upload, Output,
+    SizeLimits,
+};
+
+/// The attributes of a response, in the order they should be written.
+type Attributes = Vec<(String, String)>;
+
+/// Answers requests from stdin until it's closed.
+///
+/// # Errors
+///
+/// Returns an error only if stdin or stdout can't be used. Failed requests
+/// are reported in their response instead.
+pub fn run(output: Output) -> Result<()> {
+    // Progress bars would corrupt the responses.
+    let output = Output {
+        porcelain: true,
+        ..output
+    };
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    while let Some(request) = read_request(&mut stdin)? {
+        let response = handle(request, output).unwrap_or_else(|e| {
+            let failure = e.chain().find_map(|e| e.downcast_ref::<Failure>()).copied();
+            vec![
+                (
+                    "error".to_string(),
+                    failure.map_or("error", Failure::name).to_string(),
+                ),
+                // Values can't span lines.
+                ("message".to_string(), format!("{e:#}").replace('\n', " ")),
+            ]
+        });
+        write_response(&mut stdout, &response)?;
+    }
+    Ok(())
+}
+
+/// Reads the next request, or returns `None` if there are no more requests.
+/// Blank lines before a request are skipped.
+pub fn read_request(reader: &mut impl BufRead) -> Result<Option<HashMap<String, String>>> {
+    let mut request = HashMap::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            if request.is_empty() {
+                continue;
+            }
+            break;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("Expected `key=value`, got `{line}`"))?;
+        request.insert(key.to_string(), value.to_string());
+    }
+    Ok((!request.is_empty()).then_some(request))
+}
+
+fn write_response(writer: &mut impl Write, response: &Attributes) -> Result<()> {
+    for (key, value) in response {
+        writeln!(writer, "{key}={value}")?;
+    }
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn handle(mut request: HashMap<String, String>, output: Output) -> Result<Attributes> {
+    let mut take = |key: &str| request.remove(key);
+    let action = take("action").context("Missing `action`")?;
+    let url = take("url").context("Missing `url`")?;
+    match action.as_str() {
+        "create" => {
+            let url = Url::from_str(&url)?;
+            let data = STANDARD
+                .decode(take("data").context("Missing `data`")?)
+                .context("`data` isn't valid Base64")?;
+            let password = take("password").map(SecretString::new);
+            let expiration = take("expiration")
+                .map(|expiration| Expiration::from_str(&expiration))
+                .transpose()
+                .map_err(|e| anyhow!("Invalid expiration: {e}"))?;
+            let kdf_profile = take("kdf-profile")
+                .map(|profile| KdfProfile::from_str(&profile))
+                .transpose()
+                .map_err(|e| anyhow!("Invalid KDF profile: {e}"))?;
+            let cipher = take("cipher")
+                .map(|cipher| Cipher::from_str(&cipher))
+                .transpose()
+                .map_err(|e| anyhow!("Invalid cipher: {e}"))?;
+
+            let uploaded = upload(
+                url,
+                data,
+                password.as_ref(),
+                seal_options(kdf_profile, cipher),
+                expiration,
+                take("name"),
+                take("language"),
+                None,
+                None,
+                None,
+                output,
+            )?;
+            output.record(&uploaded);
+
+            let mut response = vec![
+                ("url".to_string(), uploaded.url.to_string()),
+                (
+                    "expires".to_string(),
+                    porcelain_expiration(uploaded.expiration),
+                ),
+            ];
+            if let Some(token) = uploaded.owner_token {
+                response.push(("token".to_string(), token));
+            }
+            Ok(response)
+        }
+        "fetch" => {
+            let url = ParsedUrl::from_str(&url)?;
+            let password = take("password").map(|password| SecretVec::new(password.into_bytes()));
+            let (data, expiration) = fetch_paste(&url, password, SizeLimits::default(), 1)?;
+            Ok(vec![
+                ("data".to_string(), STANDARD.encode(data.expose_secret())),
+                ("expires".to_string(), porcelain_expiration(expiration)),
+            ])
+        }
+        "delete" => {
+            let url = Url::from_str(&url)?;
+            let owner_token = history::owner_token(&url)?;
+            delete_paste(&ParsedUrl::from_str(url.as_str())?, owner_token.as_deref())?;
+            if let Err(e) = history::remove(&url) {
+                eprintln!("Failed to remove paste from history: {e:#}");
+            }
+            Ok(Vec::new())
+        }
+        action => Err(anyhow!("Unknown action `{action}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_request;
+
+    #[test]
+    fn reads_helper_requests() {
+        let mut input: &[u8] =
+            b"\naction=fetch\nurl=https://example.com/a#key\n\naction=delete\r\nurl=b=c";
+        let first = read_request(&mut input).unwrap().unwrap();
+        assert_eq!(first["action"], "fetch");
+        assert_eq!(first["url"], "https://example.com/a#key");
+        let second = read_request(&mut input).unwrap().unwrap();
+        assert_eq!(second["action"], "delete");
+        assert_eq!(second["url"], "b=c");
+        assert!(read_request(&mut input).unwrap().is_none());
+        assert!(read_request(&mut &b"not an attribute"[..]).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A local record of pastes uploaded from this machine.
+//!
+//! The history file contains full paste URLs, including their decryption keys,
+//! so it is only ever readable by the current user.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use omegaupload_common::{Expiration, Url};
+use reqwest::header::HeaderValue;
+
+/// Marker for entries whose expiration is unknown, such as those uploaded to
+/// servers that don't report it.
+const UNKNOWN_EXPIRATION: &str = "-";
+
+#[derive(Clone)]
+pub struct Entry {
+    pub url: Url,
+    pub expiration: Option<Expiration>,
+    /// Permits replacing the paste. Servers that don't support updating pastes
+    /// don't provide one.
+    pub owner_token: Option<String>,
+}
+
+impl Entry {
+    /// Whether the paste is known to no longer exist. Burn after reading pastes
+    /// are never considered expired, as we can't know if they were read.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expiration
+            .as_ref()
+            .map_or(false, Expiration::is_expired)
+    }
+
+    /// Whether this entry is for the paste at the provided URL, ignoring the
+    /// fragment.
+    fn is_for(&self, url: &Url) -> bool {
+        let mut own_url = self.url.clone();
+        own_url.set_fragment(None);
+        let mut url = url.clone();
+        url.set_fragment(None);
+        own_url == url
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let expiration = fields.next()?;
+        let expiration = if expiration == UNKNOWN_EXPIRATION {
+            None
+        } else {
+            Some(Expiration::try_from(expiration).ok()?)
+        };
+        Some(Self {
+            url: fields.next()?.parse().ok()?,
+            expiration,
+            // Entries from older versions don't have this field.
+            owner_token: fields.next().map(ToString::to_string),
+        })
+    }
+
+    fn to_line(&self) -> String {
+        let expiration = self.expiration.map_or_else(
+            || UNKNOWN_EXPIRATION.to_string(),
+            |expiration| {
+                HeaderValue::from(expiration)
+                    .to_str()
+                    .expect("expiration header values to be ascii")
+                    .to_string()
+            },
+        );
+        match self.owner_token {
+            Some(ref token) => format!("{expiration}\t{}\t{token}", self.url),
+            None => format!("{expiration}\t{}", self.url),
+        }
+    }
+}
+
+/// Returns the path to the history file, following the XDG base directory
+/// specification.
+fn path() -> Result<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .context("Unable to find a directory to store history in")?;
+    Ok(data_dir.join("omegaupload").join("history"))
+}
+
+fn open_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+}
+
+/// Loads all entries, oldest first. Lines that can't be parsed are skipped.
+///
+/// # Errors
+///
+/// Returns an error if the history file exists but couldn't be read.
+pub fn load() -> Result<Vec<Entry>> {
+    let file = match File::open(path()?) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).context("Failed to open history file"),
+    };
+
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        if let Some(entry) = Entry::parse(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Appends an entry to the history.
+///
+/// # Errors
+///
+/// Returns an error if the history file couldn't be written to.
+pub fn append(entry: &Entry) -> Result<()> {
+    let path = path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create history directory")?;
+    }
+    let mut file = open_options()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context("Failed to open history file")?;
+    writeln!(file, "{}", entry.to_line()).context("Failed to write to history file")
+}
+
+/// Replaces the history with the provided entries.
+///
+/// # Errors
+///
+/// Returns an error if the history file couldn't be written to.
+pub fn save(entries: &[Entry]) -> Result<()> {
+    let path = path()?;
+    let mut file = open_options()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&path)
+        .context("Failed to open history file")?;
+    for entry in entries {
+        writeln!(file, "{}", entry.to_line()).context("Failed to write to history file")?;
+    }
+    Ok(())
+}
+
+/// Removes all entries for the provided paste URL.
+///
+/// # Errors
+///
+/// Returns an error if the history file couldn't be read or written to.
+pub fn remove(url: &Url) -> Result<()> {
+    let mut entries = load()?;
+    let len = entries.len();
+    entries.retain(|entry| !entry.is_for(url));
+    if entries.len() != len {
+        save(&entries)?;
+    }
+    Ok(())
+}
+
+/// Replaces all entries for the provided paste URL with a new entry.
+///
+/// # Errors
+///
+/// Returns an error if the history file couldn't be read or written to.
+pub fn replace(url: &Url, entry: Entry) -> Result<()> {
+    let mut entries = load()?;
+    entries.retain(|entry| !entry.is_for(url));
+    entries.push(entry);
+    save(&entries)
+}
+
+/// Finds the most recent owner token for the provided paste URL.
+///
+/// # Errors
+///
+/// Returns an error if the history file couldn't be read.
+pub fn owner_token(url: &Url) -> Result<Option<String>> {
+    Ok(load()?
+        .into_iter()
+        .rev()
+        .filter(|entry| entry.is_for(url))
+        .find_map(|entry| entry.owner_token))
+}
@@ -18,31 +18,96 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::io::{Cursor, Read, Write};
-use std::path::PathBuf;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+use ::base64::engine::general_purpose::STANDARD;
+use ::base64::Engine;
 use anyhow::{anyhow, bail, Context, Result};
 use atty::Stream;
+use byte_unit::Byte;
 use bytes::Bytes;
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
 use indicatif::{ProgressBar, ProgressStyle};
-use omegaupload_common::crypto::{open_in_place, seal_in_place};
-use omegaupload_common::fragment::Builder;
-use omegaupload_common::secrecy::{ExposeSecret, SecretString, SecretVec};
+use omegaupload_common::crypto::{
+    hash, open_chunked_into_secret, open_into_secret, seal_chunked_in_place_with_key,
+    seal_in_place_with_key, Cipher, Envelope, KdfProfile, Key, SealOptions, HASH_SIZE,
+};
+use omegaupload_common::fragment::{self, Builder};
+use omegaupload_common::key_format::KeyFormat;
+use omegaupload_common::link::PasteLink;
+use omegaupload_common::manifest::{Manifest, Member};
+use omegaupload_common::secrecy::zeroize::Zeroizing;
+use omegaupload_common::secrecy::{ExposeSecret, Secret, SecretString, SecretVec};
 use omegaupload_common::{
-    base64, Expiration, ParsedUrl, Url, API_ENDPOINT, EXPIRATION_HEADER_NAME,
+    base64, Expiration, NotBefore, ParsedUrl, PartialParsedUrl, PasteTtl, UploadResponse, Url,
+    API_ENDPOINT, EXPIRATION_HEADER_NAME, MAX_DOWNLOADS_HEADER_NAME, NOTIFY_HEADER_NAME,
+    NOT_BEFORE_HEADER_NAME, OWNER_TOKEN_HEADER_NAME, PASTE_SIZE_LIMIT, PASTE_TTL_HEADER_NAME,
 };
-use reqwest::blocking::{Body, Client};
-use reqwest::header::EXPIRES;
+use reqwest::blocking::{Body, Client, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, ACCEPT, CONTENT_RANGE, CONTENT_TYPE, EXPIRES, RANGE};
 use reqwest::StatusCode;
 use rpassword::prompt_password;
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::failure::{Failure, GENERIC_EXIT_CODE, USAGE_EXIT_CODE};
+use crate::secrets::Scan;
 
+mod api;
+mod completions;
+mod detect;
+mod doctor;
+mod failure;
+mod helper;
+mod history;
+mod secrets;
+mod tui;
+
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser)]
 struct Opts {
     #[clap(subcommand)]
     action: Action,
+    /// Print stable, tab-separated output meant for scripts instead of
+    /// human-readable messages.
+    #[clap(long, global = true)]
+    porcelain: bool,
+    /// Don't record uploaded pastes in the local history.
+    #[clap(long, global = true)]
+    no_history: bool,
+    /// Print the decryption keys of pastes separately from their URLs, in a
+    /// format that's easier to read aloud or write down. `download --key`
+    /// reads keys in any format.
+    #[clap(long, global = true, possible_values = KeyFormat::variants(), default_value = "base64")]
+    key_format: KeyFormat,
+    /// Leave Base64 keys out of printed URLs and print them on their own line
+    /// instead, so that the two can be sent over different channels.
+    /// `download --key` puts them back together.
+    #[clap(long, global = true)]
+    split_url: bool,
+    /// Refuse to upload pastes that appear to contain credentials, such as
+    /// API keys or private keys, instead of only warning about them.
+    #[clap(long, global = true, conflicts_with = "no-secret-scan")]
+    strict: bool,
+    /// Don't check pastes for credentials before uploading them.
+    #[clap(long, global = true)]
+    no_secret_scan: bool,
+    /// Log requests, response headers, and how long they and encryption took
+    /// to stderr. Pass twice for more detail.
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
 #[derive(Parser)]
+// Only one is ever made, when parsing the arguments.
+#[allow(clippy::large_enum_variant)]
 enum Action {
     /// Upload a paste to an omegaupload server.
     Upload {
@@ -52,97 +117,1215 @@ enum Action {
         /// public access.
         #[clap(short, long)]
         password: bool,
+        /// How expensive it is to derive a key from the password. Harder
+        /// profiles take longer to open, but are harder to brute force.
+        #[clap(long, possible_values = KdfProfile::variants(), requires = "password")]
+        kdf_profile: Option<KdfProfile>,
+        /// The cipher to encrypt the paste with. `auto` picks AES-256-GCM on
+        /// CPUs with AES instructions. Older clients can only open pastes
+        /// encrypted with the default.
+        #[clap(long, possible_values = Cipher::variants())]
+        cipher: Option<Cipher>,
         /// How long for the paste to last, or until someone has read it.
         #[clap(short, long, possible_values = Expiration::variants())]
         duration: Option<Expiration>,
         /// The path to the file to upload. If none is provided, then reads
         /// stdin instead.
         path: Option<PathBuf>,
+        /// Upload the contents of this URL instead of a file. The contents are
+        /// never written to disk.
+        #[clap(long, conflicts_with = "path")]
+        from_url: Option<Url>,
         /// Hint that the uploaded file should be syntax highlighted with a
         /// specific language.
         #[clap(short, long)]
         language: Option<String>,
+        /// Hint the language from an editor's filetype, such as Vim's
+        /// `&filetype`.
+        #[clap(long, conflicts_with = "language")]
+        filetype: Option<String>,
         /// Don't provide a file name hint.
         #[clap(short = 'F', long)]
         no_file_name_hint: bool,
+        /// Read the paste from stdin and print only its URL to stdout, with
+        /// everything else on stderr. This is meant for editor commands such as
+        /// Vim's `:'<,'>w !omegaupload upload --range-stdin <url>`.
+        #[clap(long, conflicts_with_all = &["path", "from-url", "porcelain"])]
+        range_stdin: bool,
+        /// Ask the server to post to this URL, such as an ntfy topic, once the
+        /// paste is read. Only works with pastes that burn after reading, and
+        /// only if the server allows the URL's host. The server sees this URL.
+        #[clap(long)]
+        notify: Option<Url>,
+        /// Don't let the paste be read until this time, such as
+        /// `2024-05-01T09:00:00Z`, so that its link can be shared ahead of
+        /// time. The paste must not expire before then.
+        #[clap(long, value_parser = parse_not_before)]
+        not_before: Option<NotBefore>,
+        /// Stop serving the paste once it's been downloaded this many times,
+        /// while still letting it expire as usual. This can't be used with
+        /// pastes that burn after reading.
+        #[clap(long)]
+        max_downloads: Option<NonZeroU32>,
+        /// Upload each of these files as its own paste, along with a manifest
+        /// listing them, and print the manifest's URL. The web frontend shows
+        /// the manifest as a list of the files, which are each downloaded on
+        /// their own.
+        #[clap(
+            long,
+            multiple_values = true,
+            value_name = "PATHS",
+            conflicts_with_all = &["path", "from-url", "range-stdin", "notify"]
+        )]
+        bundle: Vec<PathBuf>,
     },
     /// Download a paste from an omegaupload server.
     Download {
         /// The paste to download.
-        url: ParsedUrl,
+        url: Url,
+        /// Prompt for the decryption key of the paste, for URLs that were
+        /// shared without one.
+        #[clap(short, long)]
+        key: bool,
+        /// Write binary pastes to the terminal anyways.
+        #[clap(long, conflicts_with = "base64")]
+        force_binary: bool,
+        /// Write the paste as standard Base64, for when redirecting output to
+        /// a file isn't possible.
+        #[clap(long)]
+        base64: bool,
+        /// Ask for confirmation before downloading pastes larger than this,
+        /// such as `100MiB`.
+        #[clap(long, value_parser = parse_size, default_value = "100MiB")]
+        warn_size: u64,
+        /// Refuse to download pastes larger than this, such as `1GiB`.
+        #[clap(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+        /// Don't ask for confirmation before downloading large pastes.
+        #[clap(short, long)]
+        yes: bool,
+        /// Download the paste over this many connections at once, if the
+        /// server supports it.
+        #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=64))]
+        parallel: u64,
+    },
+    /// Watch a file, updating the paste every time it changes. Servers without
+    /// owner tokens get a new paste each time instead.
+    Watch {
+        /// The OmegaUpload instance to upload data to.
+        url: Url,
+        /// The path to the file to watch.
+        path: PathBuf,
+        /// Encrypt the uploaded pastes with the provided password. The password
+        /// is only prompted for once.
+        #[clap(short, long)]
+        password: bool,
+        /// How expensive it is to derive a key from the password.
+        #[clap(long, possible_values = KdfProfile::variants(), requires = "password")]
+        kdf_profile: Option<KdfProfile>,
+        /// The cipher to encrypt the pastes with.
+        #[clap(long, possible_values = Cipher::variants())]
+        cipher: Option<Cipher>,
+        /// How long for each paste to last, or until someone has read it.
+        #[clap(short, long, possible_values = Expiration::variants())]
+        duration: Option<String>,
+        /// Hint that the uploaded file should be syntax highlighted with a
+        /// specific language.
+        #[clap(short, long)]
+        language: Option<String>,
+        /// Don't provide a file name hint.
+        #[clap(short = 'F', long)]
+        no_file_name_hint: bool,
+        /// How long the file must remain unchanged, in milliseconds, before
+        /// it is uploaded again.
+        #[clap(long, default_value_t = 500)]
+        debounce: u64,
+    },
+    /// Re-encrypt a paste with a new key, deleting the old paste. Use this if
+    /// the URL to a paste was leaked. Pastes whose owner token isn't in the
+    /// history can only be deleted by servers that don't hand out tokens.
+    Rekey {
+        /// The paste to re-encrypt.
+        url: Url,
+        /// How expensive it is to derive a key from the password of the new
+        /// paste. This must be provided if the old paste used a profile other
+        /// than the default.
+        #[clap(long, possible_values = KdfProfile::variants())]
+        kdf_profile: Option<KdfProfile>,
+        /// The cipher to encrypt the new paste with.
+        #[clap(long, possible_values = Cipher::variants())]
+        cipher: Option<Cipher>,
+    },
+    /// Replace the contents of a paste, keeping its URL. This requires the
+    /// owner token of the paste, which is recorded in the history on upload.
+    Update {
+        /// The paste to replace.
+        url: Url,
+        /// The path to the file with the new contents.
+        path: PathBuf,
+        /// The owner token of the paste. If none is provided, then the token
+        /// from the history is used.
+        #[clap(short, long)]
+        token: Option<String>,
+        /// Change how long the paste lasts from now, or make it burn after it
+        /// has been read.
+        #[clap(short, long, possible_values = Expiration::variants())]
+        duration: Option<Expiration>,
+        /// How expensive it is to derive a key from the password. This must be
+        /// provided if the paste used a profile other than the default, and
+        /// can't otherwise be changed without changing the URL.
+        #[clap(long, possible_values = KdfProfile::variants())]
+        kdf_profile: Option<KdfProfile>,
+        /// The cipher to encrypt the new contents with. This can't be changed
+        /// from the default without changing the URL, unless the paste already
+        /// used a different cipher.
+        #[clap(long, possible_values = Cipher::variants())]
+        cipher: Option<Cipher>,
+    },
+    /// Copy a paste to another instance, such as a mirror, without decrypting
+    /// it. The new link keeps the key and hints of the old one. Copying a burn
+    /// after reading paste burns it.
+    Copy {
+        /// The paste to copy. Its key may be left out.
+        url: Url,
+        /// The OmegaUpload instance to copy the paste to.
+        instance: Url,
+        /// How long for the copy to last. Defaults to how long the paste has
+        /// left.
+        #[clap(short, long, possible_values = Expiration::variants())]
+        duration: Option<Expiration>,
+    },
+    /// Check that an instance works, and which optional features it supports.
+    /// This uploads and then deletes a small test paste.
+    Doctor {
+        /// The OmegaUpload instance to check.
+        url: Url,
+    },
+    /// Browse the history of pastes uploaded from this machine.
+    Tui,
+    /// List the unexpired pastes uploaded from this machine, newest first.
+    History {
+        /// List the instances pastes were uploaded to instead.
+        #[clap(long)]
+        instances: bool,
+    },
+    /// Send a request to any endpoint of an instance and print the response as
+    /// it was received, for endpoints that don't have their own subcommand.
+    Api {
+        /// The HTTP method, such as `GET` or `DELETE`.
+        method: String,
+        /// The endpoint, such as `https://paste.example.com/api/info`, or an
+        /// admin endpoint such as `http://localhost:8081/metrics`.
+        url: Url,
+        /// Send the admin token, which is read from `OMEGAUPLOAD_ADMIN_TOKEN`
+        /// or prompted for.
+        #[clap(long)]
+        admin: bool,
+        /// Send the owner token recorded in the history for the paste, for
+        /// endpoints like `https://paste.example.com/api/<code>`.
+        #[clap(long)]
+        owner_token: bool,
+        /// A header to send, such as `Burn-After: 1h`. This may be repeated.
+        #[clap(short = 'H', long = "header")]
+        headers: Vec<String>,
+        /// Send the contents of this file as the body, or stdin if it's `-`.
+        #[clap(short, long)]
+        data: Option<PathBuf>,
+    },
+    /// Answer requests to create, fetch, and delete pastes over stdin and
+    /// stdout, for editors and other tools. See the README for the protocol.
+    Helper,
+    /// Print a completion script for the provided shell.
+    Completions {
+        #[clap(value_parser)]
+        shell: Shell,
     },
 }
 
-fn main() -> Result<()> {
-    let opts = Opts::parse();
+fn main() {
+    let opts = match Opts::try_parse() {
+        Ok(opts) => opts,
+        // Help and version output aren't errors.
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            std::process::exit(USAGE_EXIT_CODE);
+        }
+    };
+
+    init_logging(opts.verbose);
+
+    let output = Output {
+        porcelain: opts.porcelain,
+        record_history: !opts.no_history,
+        key_format: opts.key_format,
+        split_url: opts.split_url,
+        url_only: false,
+    };
+
+    let scan = if opts.no_secret_scan {
+        Scan::Off
+    } else if opts.strict {
+        Scan::Strict
+    } else {
+        Scan::Warn
+    };
 
-    match opts.action {
+    if let Err(e) = run(opts.action, output, scan) {
+        let failure = e.chain().find_map(|e| e.downcast_ref::<Failure>()).copied();
+        if output.porcelain {
+            eprintln!("error\t{}\t{e}", failure.map_or("error", Failure::name));
+        } else {
+            eprintln!("Error: {e:?}");
+        }
+        std::process::exit(failure.map_or(GENERIC_EXIT_CODE, Failure::exit_code));
+    }
+}
+
+/// Logs warnings to stderr, and more with each `--verbose`. Libraries such as
+/// the HTTP client only log in detail at the highest verbosity, as they're
+/// noisy.
+fn init_logging(verbose: u8) {
+    let (ours, libraries) = match verbose {
+        0 => (LevelFilter::WARN, LevelFilter::WARN),
+        1 => (LevelFilter::INFO, LevelFilter::WARN),
+        2 => (LevelFilter::DEBUG, LevelFilter::INFO),
+        _ => (LevelFilter::TRACE, LevelFilter::TRACE),
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_target(false)
+                .without_time(),
+        )
+        .with(
+            Targets::new()
+                .with_target(env!("CARGO_CRATE_NAME"), ours)
+                .with_default(libraries),
+        )
+        .init();
+}
+
+#[allow(clippy::too_many_lines)]
+fn run(action: Action, output: Output, scan: Scan) -> Result<()> {
+    match action {
         Action::Upload {
             url,
             password,
+            kdf_profile,
+            cipher,
+            duration,
+            no_file_name_hint,
+            not_before,
+            max_downloads,
+            bundle,
+            ..
+        } if !bundle.is_empty() => handle_bundle(
+            url,
+            password,
+            seal_options(kdf_profile, cipher),
+            duration,
+            &bundle,
+            no_file_name_hint,
+            not_before,
+            max_downloads,
+            output,
+            scan,
+        ),
+        Action::Upload {
+            url,
+            password,
+            kdf_profile,
+            cipher,
             duration,
             path,
+            from_url,
             language,
+            filetype,
+            no_file_name_hint,
+            range_stdin,
+            notify,
+            not_before,
+            max_downloads,
+            ..
+        } => handle_upload(
+            url,
+            password,
+            seal_options(kdf_profile, cipher),
+            duration,
+            match (path, from_url) {
+                (Some(path), _) => Source::Path(path),
+                (None, Some(url)) => Source::Url(url),
+                (None, None) => Source::Stdin,
+            },
+            language.or_else(|| filetype.as_deref().and_then(language_for_filetype)),
             no_file_name_hint,
-        } => handle_upload(url, password, duration, path, language, no_file_name_hint),
-        Action::Download { url } => handle_download(url),
-    }?;
+            notify.as_ref(),
+            not_before,
+            max_downloads,
+            Output {
+                url_only: range_stdin,
+                ..output
+            },
+            scan,
+        ),
+        Action::Download {
+            url,
+            key,
+            force_binary,
+            base64,
+            warn_size,
+            max_size,
+            yes,
+            parallel,
+        } => handle_download(
+            parse_paste_url(url, key)?,
+            force_binary,
+            base64,
+            SizeLimits {
+                warn: (!yes).then_some(warn_size),
+                max: max_size,
+            },
+            parallel,
+            output,
+        ),
+        Action::Watch {
+            url,
+            path,
+            password,
+            kdf_profile,
+            cipher,
+            duration,
+            language,
+            no_file_name_hint,
+            debounce,
+        } => handle_watch(
+            url,
+            &path,
+            password,
+            seal_options(kdf_profile, cipher),
+            duration.as_deref(),
+            language,
+            no_file_name_hint,
+            Duration::from_millis(debounce),
+            output,
+            scan,
+        ),
+        Action::Rekey {
+            url,
+            kdf_profile,
+            cipher,
+        } => handle_rekey(&url, kdf_profile, cipher, output),
+        Action::Update {
+            url,
+            path,
+            token,
+            duration,
+            kdf_profile,
+            cipher,
+        } => handle_update(
+            &url,
+            &path,
+            token,
+            duration,
+            kdf_profile,
+            cipher,
+            output,
+            scan,
+        ),
+        Action::Copy {
+            url,
+            instance,
+            duration,
+        } => handle_copy(&url, &instance, duration, output),
+        Action::Doctor { url } => doctor::run(url, output),
+        Action::Tui => tui::run(),
+        Action::History { instances } => handle_history(instances),
+        Action::Api {
+            method,
+            url,
+            admin,
+            owner_token,
+            headers,
+            data,
+        } => api::run(
+            &method,
+            &url,
+            api::Auth { admin, owner_token },
+            &headers,
+            data.as_deref(),
+            output,
+        ),
+        Action::Helper => helper::run(output),
+        Action::Completions { shell } => completions::print(shell, &mut Opts::command()),
+    }
+}
 
-    Ok(())
+/// The options to seal new pastes with. Only non-default choices are recorded,
+/// so that older clients can still open pastes that use the defaults.
+fn seal_options(kdf_profile: Option<KdfProfile>, cipher: Option<Cipher>) -> SealOptions {
+    SealOptions {
+        kdf: kdf_profile.unwrap_or_default().recorded_params(),
+        cipher: cipher.filter(|cipher| *cipher != Cipher::default()),
+    }
+}
+
+/// How results are reported to the user.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy)]
+struct Output {
+    porcelain: bool,
+    record_history: bool,
+    /// How to print decryption keys. Keys in Base64 are left in the URL unless
+    /// `split_url` is set.
+    key_format: KeyFormat,
+    split_url: bool,
+    /// Whether only paste URLs go to stdout, so that editors can insert them.
+    url_only: bool,
 }
 
+impl Output {
+    /// Reports a newly uploaded paste, recording it in the history.
+    fn uploaded(self, uploaded: &Uploaded) {
+        self.paste_url(&uploaded.url);
+        self.uploaded_expiration(uploaded.expiration);
+
+        if let Some(ref token) = uploaded.owner_token {
+            if self.porcelain {
+                println!("token\t{token}");
+            } else if !self.record_history {
+                // Otherwise, the only copy of the token is in the history.
+                eprintln!("Owner token: {token}");
+            }
+        }
+
+        self.record(uploaded);
+    }
+
+    /// Reports a paste that was updated in place, replacing its old URL in the
+    /// history.
+    fn updated(self, old_url: &Url, updated: &Uploaded) {
+        self.paste_url(&updated.url);
+        self.uploaded_expiration(updated.expiration);
+
+        if self.record_history {
+            let entry = history::Entry {
+                url: updated.url.clone(),
+                expiration: updated.expiration,
+                owner_token: updated.owner_token.clone(),
+            };
+            // Failing to record history shouldn't fail the update.
+            if let Err(e) = history::replace(old_url, entry) {
+                warn!("Failed to record paste in history: {e:#}");
+            }
+        }
+    }
+
+    /// Records a newly uploaded paste in the history, if enabled.
+    fn record(self, uploaded: &Uploaded) {
+        if self.record_history {
+            let entry = history::Entry {
+                url: uploaded.url.clone(),
+                expiration: uploaded.expiration,
+                owner_token: uploaded.owner_token.clone(),
+            };
+            // Failing to record history shouldn't fail the upload.
+            if let Err(e) = history::append(&entry) {
+                warn!("Failed to record paste in history: {e:#}");
+            }
+        }
+    }
+
+    fn paste_url(self, url: &Url) {
+        let key = match self.key_format {
+            KeyFormat::Base64 if !self.split_url => None,
+            // Keys in Base64 carry the hash of the paste with them. Other
+            // formats only encode the key, so the hash is left out entirely.
+            KeyFormat::Base64 => fragment::split_key(url.fragment().unwrap_or_default()).0,
+            format => url
+                .fragment()
+                .and_then(|fragment| PartialParsedUrl::try_from(fragment).ok())
+                .and_then(|parsed| parsed.decryption_key)
+                .map(|key| format.encode(&key)),
+        };
+        let url = if key.is_some() {
+            without_key(url)
+        } else {
+            url.clone()
+        };
+
+        if self.porcelain {
+            println!("url\t{url}");
+        } else {
+            println!("{url}");
+        }
+
+        if let Some(key) = key {
+            if self.porcelain {
+                println!("key\t{}", key.expose_secret());
+            } else if self.url_only {
+                eprintln!("Key: {}", key.expose_secret());
+            } else {
+                println!("Key: {}", key.expose_secret());
+            }
+        }
+    }
+
+    /// Reports when an uploaded paste expires.
+    fn uploaded_expiration(self, expiration: Option<Expiration>) {
+        if self.porcelain {
+            println!("expires\t{}", porcelain_expiration(expiration));
+        } else if let Some(expiration) = expiration {
+            eprintln!("{}", upload_expiration_text(expiration));
+        }
+    }
+
+    /// Reports when a downloaded paste expires. This always goes to stderr, as
+    /// stdout contains the paste.
+    fn downloaded_expiration(self, expiration: Option<Expiration>) {
+        if self.porcelain {
+            eprintln!("expires\t{}", porcelain_expiration(expiration));
+        } else {
+            eprintln!(
+                "{}",
+                expiration.as_ref().map_or_else(
+                    || "This paste will not expire.".to_string(),
+                    ToString::to_string,
+                )
+            );
+        }
+    }
+
+    fn progress_bar(self, len: u64) -> ProgressBar {
+        if self.porcelain {
+            return ProgressBar::hidden();
+        }
+
+        let progress_style = ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40} {bytes}/{total_bytes} {eta_precise}",
+        )
+        .unwrap();
+        ProgressBar::new(len).with_style(progress_style)
+    }
+}
+
+/// Formats an expiration as either `read`, `read+` and the seconds it lasts
+/// once it's read, `never`, or an RFC 3339 timestamp.
+fn porcelain_expiration(expiration: Option<Expiration>) -> String {
+    match expiration {
+        Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_)) => {
+            "read".to_string()
+        }
+        Some(Expiration::ReadWindow(window) | Expiration::ReadWindowWithDeadline(window, _)) => {
+            format!("read+{window}")
+        }
+        Some(Expiration::UnixTime(time)) => time.to_rfc3339(),
+        None => "never".to_string(),
+    }
+}
+
+/// Where the data to upload comes from.
+enum Source {
+    Path(PathBuf),
+    Url(Url),
+    Stdin,
+}
+
+impl Source {
+    fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Path(path) => Ok(std::fs::read(path)?),
+            Self::Url(url) => {
+                info!("Fetching {url}");
+                let start = Instant::now();
+                let res = reqwest::blocking::ClientBuilder::new()
+                    .timeout(None)
+                    .build()?
+                    .get(url.as_ref())
+                    .send()
+                    .with_context(|| format!("Failed to fetch {url}"))?;
+                log_response(&res, start);
+                if !res.status().is_success() {
+                    bail!("Failed to fetch {url}. Got HTTP error {}", res.status());
+                }
+                Ok(res.bytes()?.to_vec())
+            }
+            Self::Stdin => {
+                let mut container = vec![];
+                std::io::stdin().lock().read_to_end(&mut container)?;
+                Ok(container)
+            }
+        }
+    }
+
+    fn file_name_hint(&self) -> Option<String> {
+        match self {
+            Self::Path(path) => file_name_hint(path),
+            Self::Url(url) => url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .filter(|name| !name.is_empty())
+                .map(ToString::to_string),
+            Self::Stdin => None,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn handle_upload(
-    mut url: Url,
+    url: Url,
     password: bool,
+    options: SealOptions,
     duration: Option<Expiration>,
-    path: Option<PathBuf>,
+    source: Source,
     language: Option<String>,
     no_file_name_hint: bool,
+    notify: Option<&Url>,
+    not_before: Option<NotBefore>,
+    max_downloads: Option<NonZeroU32>,
+    output: Output,
+    scan: Scan,
 ) -> Result<()> {
-    url.set_fragment(None);
-
-    if password && path.is_none() {
+    if password && matches!(source, Source::Stdin) {
         bail!("Reading data from stdin is incompatible with a password. Provide a path to a file to upload.");
     }
 
-    let (data, key) = {
-        let mut container = if let Some(ref path) = path {
-            std::fs::read(path)?
-        } else {
-            let mut container = vec![];
-            std::io::stdin().lock().read_to_end(&mut container)?;
-            container
-        };
+    let burns = matches!(
+        duration,
+        Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_))
+    );
+    if notify.is_some() && !burns {
+        bail!("Only pastes that burn after reading can notify when they're read.");
+    }
+    if max_downloads.is_some() && burns {
+        bail!("Pastes that burn after reading can only be downloaded once.");
+    }
+
+    let data = source.read()?;
+    scan.check(&data, output)?;
+
+    let password = if password {
+        Some(SecretString::new(prompt_password(
+            "Please set the password for this paste: ",
+        )?))
+    } else {
+        None
+    };
+
+    let file_name = if no_file_name_hint {
+        None
+    } else {
+        source.file_name_hint().or_else(|| detect::file_name(&data))
+    };
+    let language = language.or_else(|| detect::language(&data).map(ToString::to_string));
+
+    let uploaded = upload(
+        url,
+        data,
+        password.as_ref(),
+        options,
+        duration,
+        file_name,
+        language,
+        notify,
+        not_before,
+        max_downloads,
+        output,
+    )?;
+
+    output.uploaded(&uploaded);
+
+    Ok(())
+}
+
+/// Uploads each file as its own paste, sealed with a key derived from the key
+/// of a [manifest](omegaupload_common::manifest), then uploads the manifest.
+/// Only the manifest is reported, but every paste is recorded in the history.
+#[allow(clippy::too_many_arguments)]
+fn handle_bundle(
+    url: Url,
+    password: bool,
+    options: SealOptions,
+    duration: Option<Expiration>,
+    paths: &[PathBuf],
+    no_file_name_hint: bool,
+    not_before: Option<NotBefore>,
+    max_downloads: Option<NonZeroU32>,
+    output: Output,
+    scan: Scan,
+) -> Result<()> {
+    let burns = matches!(
+        duration,
+        Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_))
+    );
+    if max_downloads.is_some() && burns {
+        bail!("Pastes that burn after reading can only be downloaded once.");
+    }
+
+    let files = paths
+        .iter()
+        .map(|path| {
+            let data = std::fs::read(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            scan.check(&data, output)?;
+            Ok((path, data))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let password = if password {
+        Some(SecretString::new(prompt_password(
+            "Please set the password for this bundle: ",
+        )?))
+    } else {
+        None
+    };
+
+    let key = Key::random_secret();
+    let mut members = Vec::with_capacity(files.len());
+    for (index, (path, data)) in (0..).zip(files) {
+        let name = file_name_hint(path).unwrap_or_else(|| format!("file-{index}"));
+        let size = data.len() as u64;
+        let language = detect::language(&data).map(ToString::to_string);
+        info!("Uploading {name}");
+        let uploaded = upload_with_key(
+            url.clone(),
+            data,
+            &key.expose_secret().derive_member(index),
+            password.as_ref(),
+            options,
+            duration,
+            (!no_file_name_hint).then(|| name.clone()),
+            language,
+            None,
+            not_before,
+            max_downloads,
+            output,
+        )?;
+        output.record(&uploaded);
+
+        let (_, hints) = fragment::strip_key(uploaded.url.fragment().unwrap_or_default());
+        let mut url = uploaded.url;
+        url.set_fragment(None);
+        members.push(Member {
+            name,
+            size,
+            url,
+            hints,
+        });
+    }
+
+    let manifest = serde_json::to_vec(&Manifest::new(members))?;
+    let uploaded = upload_with_key(
+        url,
+        manifest,
+        &key,
+        password.as_ref(),
+        options,
+        duration,
+        None,
+        None,
+        None,
+        not_before,
+        max_downloads,
+        output,
+    )?;
+    output.uploaded(&uploaded);
 
-        if container.is_empty() {
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_watch(
+    url: Url,
+    path: &Path,
+    password: bool,
+    options: SealOptions,
+    duration: Option<&str>,
+    language: Option<String>,
+    no_file_name_hint: bool,
+    debounce: Duration,
+    output: Output,
+    scan: Scan,
+) -> Result<()> {
+    // How often we check the file for changes. This is intentionally shorter
+    // than any reasonable debounce period.
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    let password = if password {
+        Some(SecretString::new(prompt_password(
+            "Please set the password for these pastes: ",
+        )?))
+    } else {
+        None
+    };
+
+    let file_name = if no_file_name_hint {
+        None
+    } else {
+        file_name_hint(path)
+    };
+
+    // Parse the expiration for every upload, as relative durations are relative
+    // to when the paste is uploaded.
+    let read_file = || -> Result<(Vec<u8>, Option<Expiration>)> {
+        let duration = duration
+            .map(Expiration::from_str)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid duration: {e}"))?;
+        let data =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if data.is_empty() {
             bail!("Nothing to upload.");
         }
+        scan.check(&data, output)?;
+        Ok((data, duration))
+    };
 
-        let password = if password {
-            let maybe_password = prompt_password("Please set the password for this paste: ")?;
-            Some(SecretVec::new(maybe_password.into_bytes()))
-        } else {
-            None
-        };
+    let upload_file = || -> Result<Uploaded> {
+        let (data, duration) = read_file()?;
+        upload(
+            url.clone(),
+            data,
+            password.as_ref(),
+            options,
+            duration,
+            file_name.clone(),
+            language.clone(),
+            None,
+            None,
+            None,
+            output,
+        )
+    };
+
+    // Sealed with the same key and options, so that the URL stays the same
+    // other than its content hash.
+    let update_file = |uploaded: &Uploaded, owner_token: &str| -> Result<Uploaded> {
+        let (mut data, duration) = read_file()?;
+        let parsed_url: ParsedUrl = uploaded.url.as_str().parse()?;
+        let url = with_hash(&uploaded.url, hash(&data));
+        timed("Encrypted paste", || {
+            seal_in_place_with_key(
+                &mut data,
+                &parsed_url.decryption_key,
+                password.as_ref().map(expose_password),
+                options,
+            )
+        })?;
+        let expiration = replace(&parsed_url, data, duration, owner_token, output)?;
+        Ok(Uploaded {
+            url,
+            expiration,
+            owner_token: Some(owner_token.to_owned()),
+        })
+    };
+
+    let last_modified = || {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+
+    // Upload once immediately, so there's always a URL to share.
+    let mut current = upload_file()?;
+    output.uploaded(&current);
+    eprintln!(
+        "Watching {} for changes. Press Ctrl-C to stop.",
+        path.display()
+    );
+
+    let mut seen_modified = last_modified();
+    let mut changed_at: Option<Instant> = None;
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+
+        let modified = last_modified();
+        if modified != seen_modified {
+            seen_modified = modified;
+            changed_at = Some(Instant::now());
+            continue;
+        }
+
+        if changed_at.map_or(false, |instant| instant.elapsed() >= debounce) {
+            changed_at = None;
+            let result = match current.owner_token.clone() {
+                Some(owner_token) => match update_file(&current, &owner_token) {
+                    // Burned and expired pastes can't be updated, so they're
+                    // uploaded again.
+                    Err(e) if is_gone(&e) => upload_file().map(|uploaded| (uploaded, false)),
+                    res => res.map(|updated| (updated, true)),
+                },
+                None => upload_file().map(|uploaded| (uploaded, false)),
+            };
+            // A failed upload shouldn't stop us from watching the file.
+            match result {
+                Ok((updated, true)) => {
+                    output.updated(&current.url, &updated);
+                    current = updated;
+                }
+                Ok((uploaded, false)) => {
+                    output.uploaded(&uploaded);
+                    current = uploaded;
+                }
+                Err(e) => error!("Upload failed: {e:#}"),
+            }
+        }
+    }
+}
+
+/// Whether an error is because the paste no longer exists.
+fn is_gone(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| {
+        matches!(
+            e.downcast_ref::<Failure>(),
+            Some(Failure::NotFound | Failure::Expired)
+        )
+    })
+}
+
+fn file_name_hint(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|str| str.to_string_lossy().to_string())
+}
+
+/// Editor filetypes that are named differently from the language the web
+/// frontend highlights them as.
+const FILETYPES: &[(&str, &str)] = &[
+    ("sh", "bash"),
+    ("zsh", "bash"),
+    ("javascriptreact", "javascript"),
+    ("typescriptreact", "typescript"),
+    ("cs", "csharp"),
+    ("make", "makefile"),
+    ("dosini", "ini"),
+    ("tex", "latex"),
+    ("objc", "objectivec"),
+    ("ps1", "powershell"),
+];
+
+/// The language hint for an editor filetype. Most filetypes are already the
+/// name of their language, and plain text has no hint.
+fn language_for_filetype(filetype: &str) -> Option<String> {
+    // Vim joins compound filetypes with dots, such as `markdown.pandoc`.
+    let filetype = filetype.split('.').next().unwrap_or_default();
+    if matches!(filetype, "" | "text" | "plaintext") {
+        return None;
+    }
+    let language = FILETYPES
+        .iter()
+        .find(|(name, _)| *name == filetype)
+        .map_or(filetype, |(_, language)| language);
+    Some(language.to_string())
+}
+
+/// A paste that was just uploaded.
+struct Uploaded {
+    url: Url,
+    /// When the server says the paste expires.
+    expiration: Option<Expiration>,
+    owner_token: Option<String>,
+}
+
+/// Encrypts and uploads the provided data. The KDF parameters in `options` are
+/// only used if there's a password. If `notify` is provided, the server posts
+/// to it once the paste is read, and if `not_before` is provided, the paste
+/// can't be read until then. If `max_downloads` is provided, the server stops
+/// serving the paste after that many downloads.
+#[allow(clippy::too_many_arguments)]
+fn upload(
+    url: Url,
+    data: Vec<u8>,
+    password: Option<&SecretString>,
+    options: SealOptions,
+    duration: Option<Expiration>,
+    file_name: Option<String>,
+    language: Option<String>,
+    notify: Option<&Url>,
+    not_before: Option<NotBefore>,
+    max_downloads: Option<NonZeroU32>,
+    output: Output,
+) -> Result<Uploaded> {
+    upload_with_key(
+        url,
+        data,
+        &Key::random_secret(),
+        password,
+        options,
+        duration,
+        file_name,
+        language,
+        notify,
+        not_before,
+        max_downloads,
+        output,
+    )
+}
+
+/// Like [`upload`], but encrypts the data with `key` instead of a new one.
+#[allow(clippy::too_many_arguments)]
+fn upload_with_key(
+    mut url: Url,
+    mut data: Vec<u8>,
+    key: &Secret<Key>,
+    password: Option<&SecretString>,
+    options: SealOptions,
+    duration: Option<Expiration>,
+    file_name: Option<String>,
+    language: Option<String>,
+    notify: Option<&Url>,
+    not_before: Option<NotBefore>,
+    max_downloads: Option<NonZeroU32>,
+    output: Output,
+) -> Result<Uploaded> {
+    url.set_fragment(None);
+
+    if data.is_empty() {
+        bail!("Nothing to upload.");
+    }
+    if data.len() as u64 >= PASTE_SIZE_LIMIT {
+        return Err(Failure::TooLarge).context(format!(
+            "The paste is {}, but pastes must be smaller than {}",
+            format_size(data.len() as u64),
+            format_size(PASTE_SIZE_LIMIT)
+        ));
+    }
+
+    let content_hash = hash(&data);
+    timed("Encrypted paste", || {
+        seal_in_place_with_key(&mut data, key, password.map(expose_password), options)
+    })?;
+
+    let mut req = upload_request(&url, duration);
+
+    if let Some(notify) = notify {
+        req = req.header(&*NOTIFY_HEADER_NAME, notify.as_str());
+    }
+
+    if let Some(not_before) = not_before {
+        req = req.header(&*NOT_BEFORE_HEADER_NAME, &not_before);
+    }
+
+    if let Some(max_downloads) = max_downloads {
+        req = req.header(&*MAX_DOWNLOADS_HEADER_NAME, max_downloads.to_string());
+    }
+
+    let uploaded = post_sealed(url, req, data, output)?;
+
+    Ok(Uploaded {
+        url: with_fragment(
+            uploaded.url,
+            key,
+            password.is_some(),
+            options.envelope(password.is_some()),
+            file_name,
+            language,
+            content_hash,
+        ),
+        ..uploaded
+    })
+}
+
+/// The request to upload a paste to an instance, with its expiration. The
+/// sealed paste is sent with [`post_sealed`].
+fn upload_request(url: &Url, duration: Option<Expiration>) -> RequestBuilder {
+    let req = Client::new()
+        .post(url.as_ref())
+        .header(ACCEPT, "application/json");
+
+    if let Some(duration) = duration {
+        req.header(&*EXPIRATION_HEADER_NAME, duration)
+    } else {
+        req
+    }
+}
 
-        let enc_key = seal_in_place(&mut container, password)?;
-        let key = SecretString::new(base64::encode(&enc_key.expose_secret().as_ref()));
-        (container, key)
+/// Sends an already sealed paste with `req`, returning the URL of the new paste
+/// on the instance at `url`, without a fragment.
+fn post_sealed(
+    mut url: Url,
+    req: RequestBuilder,
+    data: Vec<u8>,
+    output: Output,
+) -> Result<Uploaded> {
+    let res = send_with_progress(req, data, output)?;
+
+    if res.status() != StatusCode::OK {
+        if let Some(failure) = Failure::from_status(res.status()) {
+            return Err(failure).context("Upload failed");
+        }
+        bail!("Upload failed. Got HTTP error {}", res.status());
+    }
+
+    let expiration = response_expiration(res.headers());
+    let owner_token = res
+        .headers()
+        .get(&*OWNER_TOKEN_HEADER_NAME)
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.starts_with("application/json"));
+    // Older servers only return the short code.
+    let (code, canonical_url) = if is_json {
+        let response: UploadResponse = serde_json::from_str(&res.text()?)
+            .context("The server returned an invalid upload response")?;
+        (response.code, response.url)
+    } else {
+        (res.text()?, None)
     };
 
-    let mut req = Client::new().post(url.as_ref());
+    if let Some(canonical_url) = canonical_url {
+        url = canonical_url;
+    } else {
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Failed to get base URL"))?
+            .extend(std::iter::once(code));
+    }
+
+    Ok(Uploaded {
+        url,
+        expiration,
+        owner_token,
+    })
+}
+
+/// Replaces the contents of a paste with already sealed data, returning when
+/// the server says the paste expires.
+fn replace(
+    url: &ParsedUrl,
+    data: Vec<u8>,
+    duration: Option<Expiration>,
+    owner_token: &str,
+    output: Output,
+) -> Result<Option<Expiration>> {
+    let mut req = Client::new()
+        .put(api_url(url))
+        .header(&*OWNER_TOKEN_HEADER_NAME, owner_token);
 
     if let Some(duration) = duration {
         req = req.header(&*EXPIRATION_HEADER_NAME, duration);
     }
 
+    let res = send_with_progress(req, data, output)?;
+
+    if res.status() != StatusCode::OK {
+        if let Some(failure) = Failure::from_status(res.status()) {
+            return Err(failure).context("Update failed");
+        }
+        bail!("Update failed. Got HTTP error {}", res.status());
+    }
+
+    Ok(response_expiration(res.headers()))
+}
+
+fn send_with_progress(req: RequestBuilder, data: Vec<u8>, output: Output) -> Result<Response> {
     let data_size = data.len() as u64;
-    let progress_style = ProgressStyle::with_template(
-        "[{elapsed_precise}] {bar:40} {bytes}/{total_bytes} {eta_precise}",
-    )
-    .unwrap();
-    let progress_bar = ProgressBar::new(data_size).with_style(progress_style);
-    let res = req
+    let progress_bar = output.progress_bar(data_size);
+    let req = req
         .body(Body::sized(
             WrappedBody::new(
                 move |amt| {
@@ -154,33 +1337,70 @@ fn handle_upload(
         ))
         .build()
         .expect("Failed to build body");
+    info!(
+        "Sending {data_size} bytes with {} {}",
+        req.method(),
+        req.url()
+    );
+    let start = Instant::now();
     let res = reqwest::blocking::ClientBuilder::new()
         .timeout(None)
         .build()?
-        .execute(res)
+        .execute(req)
         .context("Request to server failed")?;
+    log_response(&res, start);
+    Ok(res)
+}
 
-    if res.status() != StatusCode::OK {
-        bail!("Upload failed. Got HTTP error {}", res.status());
+/// Logs how long a request took and what the server responded with. Owner
+/// tokens are left out, as logs end up in bug reports.
+fn log_response(res: &Response, start: Instant) {
+    info!(
+        "Got {} from {} after {} ms",
+        res.status(),
+        res.url(),
+        start.elapsed().as_millis()
+    );
+    for (name, value) in res.headers() {
+        if name == *OWNER_TOKEN_HEADER_NAME {
+            debug!("{name}: <redacted>");
+        } else {
+            debug!("{name}: {value:?}");
+        }
     }
+}
 
-    url.path_segments_mut()
-        .map_err(|_| anyhow!("Failed to get base URL"))?
-        .extend(std::iter::once(res.text()?));
+/// Runs `f`, logging how long it took, such as for encrypting a large paste.
+fn timed<T>(what: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let res = f();
+    debug!("{what} in {} ms", start.elapsed().as_millis());
+    res
+}
 
-    let mut fragment = Builder::new(key);
-    if password {
+fn expose_password(password: &SecretString) -> SecretVec<u8> {
+    SecretVec::new(password.expose_secret().as_bytes().to_vec())
+}
+
+/// Sets the fragment of a paste URL, which contains the decryption key and
+/// hints for the reader.
+fn with_fragment(
+    mut url: Url,
+    key: &Secret<Key>,
+    needs_password: bool,
+    envelope: Envelope,
+    file_name: Option<String>,
+    language: Option<String>,
+    content_hash: [u8; HASH_SIZE],
+) -> Url {
+    let key = SecretString::new(base64::encode(key.expose_secret().as_ref()));
+    let mut fragment = Builder::new(key).hash(content_hash).envelope(envelope);
+    if needs_password {
         fragment = fragment.needs_password();
     }
 
-    if !no_file_name_hint {
-        let file_name = path.and_then(|path| {
-            path.file_name()
-                .map(|str| str.to_string_lossy().to_string())
-        });
-        if let Some(file_name) = file_name {
-            fragment = fragment.file_name(file_name);
-        }
+    if let Some(file_name) = file_name {
+        fragment = fragment.file_name(file_name);
     }
 
     if let Some(language) = language {
@@ -188,10 +1408,88 @@ fn handle_upload(
     }
 
     url.set_fragment(Some(fragment.build().expose_secret()));
+    url
+}
+
+/// Removes the decryption key from a paste URL, keeping the rest of the
+/// fragment as is.
+fn without_key(url: &Url) -> Url {
+    let mut url = url.clone();
+    let (_, rest) = fragment::split_key(url.fragment().unwrap_or_default());
+    url.set_fragment((!rest.is_empty()).then_some(rest.as_str()));
+    url
+}
 
-    println!("{url}");
+/// Adds a decryption key to a paste URL that was shared without one. The key
+/// is in Base64, and may carry a hash as from [`fragment::split_key`].
+fn with_key(url: &Url, key: &SecretString) -> Url {
+    let mut url = url.clone();
+    let fragment = fragment::join_key(key, url.fragment().unwrap_or_default());
+    url.set_fragment(Some(fragment.expose_secret()));
+    url
+}
 
-    Ok(())
+/// Parses the URL of a paste to download, prompting for its decryption key if
+/// it was shared separately.
+fn parse_paste_url(url: Url, prompt_key: bool) -> Result<ParsedUrl> {
+    let url = if prompt_key {
+        eprint!("Please enter the decryption key for this paste: ");
+        let mut key = Zeroizing::new(String::new());
+        std::io::stdin().read_line(&mut key)?;
+        let key = key.trim();
+        let key = if key.starts_with("key:") {
+            SecretString::new(key.to_owned())
+        } else {
+            KeyFormat::Base64.encode(&KeyFormat::detect(key).decode(key)?)
+        };
+        with_key(&without_key(&url), &key)
+    } else {
+        url
+    };
+    Ok(url.as_str().parse()?)
+}
+
+/// Replaces the content hash in a paste URL, if it has one, keeping the rest of
+/// the fragment as is.
+fn with_hash(url: &Url, content_hash: [u8; HASH_SIZE]) -> Url {
+    let mut url = url.clone();
+    let fragment = url.fragment().unwrap_or_default();
+    if !fragment.split('!').any(|arg| arg.starts_with("h:")) {
+        return url;
+    }
+
+    let fragment = fragment
+        .split('!')
+        .map(|arg| {
+            if arg.starts_with("h:") {
+                format!("h:{}", base64::encode(content_hash))
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("!");
+    url.set_fragment(Some(&fragment));
+    url
+}
+
+/// The `Display` impl of `Expiration` is written for the reader of a paste, so
+/// we need a different message for the uploader.
+fn upload_expiration_text(expiration: Expiration) -> String {
+    match expiration {
+        Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_) => {
+            "This paste will be deleted once it has been read.".to_string()
+        }
+        Expiration::UnixTime(time) => time
+            .format("This paste will expire on %A, %B %-d, %Y at %T %Z.")
+            .to_string(),
+        Expiration::ReadWindow(window) | Expiration::ReadWindowWithDeadline(window, _) => {
+            format!(
+                "This paste will be deleted {} minutes after it's first read.",
+                window / 60
+            )
+        }
+    }
 }
 
 struct WrappedBody<Callback> {
@@ -218,51 +1516,622 @@ impl<Callback: FnMut(usize)> Read for WrappedBody<Callback> {
     }
 }
 
-fn handle_download(mut url: ParsedUrl) -> Result<()> {
-    url.sanitized_url
-        .set_path(&format!("{API_ENDPOINT}{}", url.sanitized_url.path()));
-    let res = Client::new()
-        .get(url.sanitized_url)
-        .send()
-        .context("Failed to get data")?;
+fn handle_download(
+    url: ParsedUrl,
+    force_binary: bool,
+    base64: bool,
+    size_limits: SizeLimits,
+    connections: u64,
+    output: Output,
+) -> Result<()> {
+    let password = if url.needs_password {
+        // Only print prompt on interactive, else it messes with output
+        let maybe_password = prompt_password("Please enter the password to access this paste: ")?;
+        Some(SecretVec::new(maybe_password.into_bytes()))
+    } else {
+        None
+    };
 
-    if res.status() != StatusCode::OK {
-        bail!("Got bad response from server: {}", res.status());
+    let (data, expiration) = fetch_paste(&url, password, size_limits, connections)?;
+    let data = data.expose_secret();
+
+    // Manifests are only useful to people with the links to their members,
+    // while scripts may want to read them as they are.
+    let manifest = atty::is(Stream::Stdout)
+        .then(|| serde_json::from_slice::<Manifest>(data).ok())
+        .flatten()
+        .filter(Manifest::is_supported);
+
+    if let Some(manifest) = manifest {
+        for (index, member) in manifest.members.iter().enumerate() {
+            if let Some(link) = manifest.member_link(&url.decryption_key, index) {
+                println!("{} ({}): {link}", member.name, format_size(member.size));
+            }
+        }
+    } else if base64 {
+        println!("{}", *Zeroizing::new(STANDARD.encode(data)));
+    } else if force_binary || !atty::is(Stream::Stdout) || std::str::from_utf8(data).is_ok() {
+        std::io::stdout().write_all(data)?;
+    } else {
+        bail!("Binary output detected. Please pipe to a file, or use --base64 or --force-binary.");
     }
 
-    let expiration_text = res
-        .headers()
-        .get(EXPIRES)
-        .and_then(|v| Expiration::try_from(v).ok())
-        .as_ref()
-        .map_or_else(
-            || "This paste will not expire.".to_string(),
-            ToString::to_string,
+    output.downloaded_expiration(expiration);
+
+    Ok(())
+}
+
+fn handle_rekey(
+    url: &Url,
+    kdf_profile: Option<KdfProfile>,
+    cipher: Option<Cipher>,
+    output: Output,
+) -> Result<()> {
+    let parsed_url: ParsedUrl = url.as_str().parse()?;
+    // We want to preserve the file name and language hints.
+    let PartialParsedUrl { name, language, .. } =
+        PartialParsedUrl::try_from(url.fragment().unwrap_or_default())?;
+
+    // The parameters of the old paste aren't known until it's opened, so we
+    // don't silently fall back to a weaker profile.
+    let kdf = match (kdf_profile, parsed_url.recorded_kdf) {
+        (Some(profile), _) => profile.recorded_params(),
+        (None, false) => None,
+        (None, true) => bail!(
+            "This paste uses a KDF profile other than the default. Provide one with --kdf-profile."
+        ),
+    };
+    let options = SealOptions {
+        kdf,
+        ..seal_options(None, cipher)
+    };
+
+    // The password is only used to authenticate the reader, so we reuse it.
+    let password = if parsed_url.needs_password {
+        Some(SecretString::new(prompt_password(
+            "Please enter the password to access this paste: ",
+        )?))
+    } else {
+        None
+    };
+
+    let (data, expiration) = fetch_paste(
+        &parsed_url,
+        password.as_ref().map(expose_password),
+        SizeLimits::default(),
+        1,
+    )?;
+    // This copy is sealed in place below, which overwrites the plaintext.
+    let mut data = data.expose_secret().clone();
+
+    let is_burned = matches!(
+        expiration,
+        Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_))
+    );
+
+    // If we own the paste, we can replace it in place. Burned pastes are gone,
+    // so they always need to be uploaded again.
+    let owner_token = history::owner_token(url)?.filter(|_| !is_burned);
+    if let Some(owner_token) = owner_token {
+        let content_hash = hash(&data);
+        let key = Key::random_secret();
+        timed("Encrypted paste", || {
+            seal_in_place_with_key(
+                &mut data,
+                &key,
+                password.as_ref().map(expose_password),
+                options,
+            )
+        })?;
+        let expiration = replace(&parsed_url, data, None, &owner_token, output)?;
+        let new_url = with_fragment(
+            parsed_url.sanitized_url,
+            &key,
+            password.is_some(),
+            options.envelope(password.is_some()),
+            name,
+            language,
+            content_hash,
         );
+        let updated = Uploaded {
+            url: new_url,
+            expiration,
+            owner_token: Some(owner_token),
+        };
+        output.updated(url, &updated);
+        return Ok(());
+    }
 
-    let mut data = res.bytes()?.as_ref().to_vec();
+    let mut instance = parsed_url.sanitized_url.clone();
+    instance
+        .path_segments_mut()
+        .map_err(|_| anyhow!("Failed to get base URL"))?
+        .pop();
 
-    let password = if url.needs_password {
-        // Only print prompt on interactive, else it messes with output
-        let maybe_password = prompt_password("Please enter the password to access this paste: ")?;
-        Some(SecretVec::new(maybe_password.into_bytes()))
+    let uploaded = upload(
+        instance,
+        data,
+        password.as_ref(),
+        options,
+        expiration,
+        name,
+        language,
+        None,
+        None,
+        None,
+        output,
+    )?;
+
+    // The new paste is reported first, so that it isn't lost if the old one
+    // can't be deleted.
+    output.uploaded(&uploaded);
+
+    if !is_burned {
+        // The owner token of the old paste isn't known, so servers that gave
+        // it one refuse to delete it.
+        delete_paste(&parsed_url, None).context(
+            "Uploaded the new paste, but failed to delete the old one. \
+             Servers require the owner token to delete pastes that have one.",
+        )?;
+    }
+    if let Err(e) = history::remove(url) {
+        warn!("Failed to remove old paste from history: {e:#}");
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_update(
+    url: &Url,
+    path: &Path,
+    owner_token: Option<String>,
+    duration: Option<Expiration>,
+    kdf_profile: Option<KdfProfile>,
+    cipher: Option<Cipher>,
+    output: Output,
+    scan: Scan,
+) -> Result<()> {
+    let parsed_url: ParsedUrl = url.as_str().parse()?;
+    // Whether the parameters and cipher are recorded is part of the URL, so it
+    // must stay the same.
+    let kdf = match (kdf_profile, parsed_url.recorded_kdf) {
+        (Some(profile), true) => Some(profile.params()),
+        (Some(KdfProfile::Interactive) | None, false) => None,
+        (Some(_), false) => {
+            bail!("The KDF profile of this paste can't be changed without changing its URL.")
+        }
+        (None, true) => bail!(
+            "This paste uses a KDF profile other than the default. Provide one with --kdf-profile."
+        ),
+    };
+    let cipher = match (cipher, parsed_url.algorithm_id) {
+        (cipher, true) => Some(cipher.unwrap_or_default()),
+        (None, false) => None,
+        (Some(cipher), false) if cipher == Cipher::default() => None,
+        (Some(_), false) => {
+            bail!("The cipher of this paste can't be changed without changing its URL.")
+        }
+    };
+    let options = SealOptions { kdf, cipher };
+    let owner_token = match owner_token {
+        Some(token) => token,
+        None => history::owner_token(url)?
+            .context("No owner token was provided, and none was found in the history")?,
+    };
+
+    let mut data = std::fs::read(path)?;
+    if data.is_empty() {
+        bail!("Nothing to upload.");
+    }
+    scan.check(&data, output)?;
+
+    let password = if parsed_url.needs_password {
+        let password = prompt_password("Please enter the password for this paste: ")?;
+        Some(SecretVec::new(password.into_bytes()))
     } else {
         None
     };
 
-    open_in_place(&mut data, &url.decryption_key, password)?;
+    // Reuse the existing key and format, so the URL to the paste doesn't change,
+    // other than its content hash.
+    let new_url = with_hash(url, hash(&data));
+    timed("Encrypted paste", || {
+        if parsed_url.chunked {
+            seal_chunked_in_place_with_key(&mut data, &parsed_url.decryption_key, password, options)
+        } else {
+            seal_in_place_with_key(&mut data, &parsed_url.decryption_key, password, options)
+        }
+    })?;
+    let expiration = replace(&parsed_url, data, duration, &owner_token, output)?;
+
+    output.paste_url(&new_url);
+    output.uploaded_expiration(expiration);
+
+    if &new_url != url && history::owner_token(url)?.is_some() {
+        let entry = history::Entry {
+            url: new_url,
+            expiration,
+            owner_token: Some(owner_token),
+        };
+        history::replace(url, entry)?;
+    }
+
+    Ok(())
+}
+
+/// Copies a paste to another instance as it was sealed, so that only its link
+/// changes. The key is never needed, as the paste is never opened.
+fn handle_copy(
+    url: &Url,
+    instance: &Url,
+    duration: Option<Expiration>,
+    output: Output,
+) -> Result<()> {
+    let source = PasteLink::new(url)?;
+    // The instance is checked first, as fetching burns burn after reading
+    // pastes.
+    let target = source.clone().with_instance(instance)?;
+
+    let mut source_url = source.to_url();
+    source_url.set_fragment(None);
+    let (data, expiration) = fetch_sealed(&paste_api_url(&source_url), SizeLimits::default(), 1)?;
+
+    let req = upload_request(target.instance(), duration.or(expiration));
+    let uploaded = post_sealed(target.instance().clone(), req, data, output)?;
+    let url = target.with_paste(&uploaded.url)?.to_url();
+
+    output.uploaded(&Uploaded { url, ..uploaded });
+
+    Ok(())
+}
 
-    if atty::is(Stream::Stdout) {
-        if let Ok(data) = String::from_utf8(data) {
-            std::io::stdout().write_all(data.as_bytes())?;
+fn handle_history(instances: bool) -> Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for entry in history::load()?.into_iter().rev() {
+        if entry.is_expired() {
+            continue;
+        }
+
+        let url = if instances {
+            let mut instance = entry.url;
+            instance.set_fragment(None);
+            if let Ok(mut segments) = instance.path_segments_mut() {
+                segments.pop();
+            }
+            instance
         } else {
-            bail!("Binary output detected. Please pipe to a file.");
+            entry.url
+        };
+
+        if seen.insert(url.to_string()) {
+            println!("{url}");
+        }
+    }
+    Ok(())
+}
+
+/// Returns the API URL for a paste.
+fn api_url(url: &ParsedUrl) -> Url {
+    paste_api_url(&url.sanitized_url)
+}
+
+/// Returns the API URL for a paste URL without a fragment.
+fn paste_api_url(url: &Url) -> Url {
+    let mut api_url = url.clone();
+    api_url.set_path(&format!("{API_ENDPOINT}{}", url.path()));
+    api_url
+}
+
+fn parse_size(size: &str) -> Result<u64, String> {
+    Byte::from_str(size)
+        .map_err(|e| e.to_string())
+        .and_then(|size| u64::try_from(size.get_bytes()).map_err(|e| e.to_string()))
+}
+
+fn parse_not_before(time: &str) -> Result<NotBefore, String> {
+    NotBefore::try_from(time)
+        .map_err(|_| format!("`{time}` isn't a time like `2024-05-01T09:00:00Z`"))
+}
+
+fn format_size(size: u64) -> String {
+    Byte::from_bytes(u128::from(size))
+        .get_appropriate_unit(true)
+        .to_string()
+}
+
+/// Limits on how large of a paste we're willing to download.
+#[derive(Clone, Copy, Default)]
+struct SizeLimits {
+    /// Pastes larger than this need to be confirmed by the user.
+    warn: Option<u64>,
+    /// Pastes larger than this are never downloaded.
+    max: Option<u64>,
+}
+
+impl SizeLimits {
+    /// Checks the size advertised by the server. Servers that don't advertise a
+    /// size are always allowed.
+    fn check(self, size: Option<u64>) -> Result<()> {
+        let size = match size {
+            Some(size) => size,
+            None => return Ok(()),
+        };
+
+        if matches!(self.max, Some(max) if size > max) {
+            return Err(Failure::TooLarge).context(format!(
+                "The paste is {}, which exceeds the maximum size",
+                format_size(size)
+            ));
+        }
+
+        if matches!(self.warn, Some(warn) if size > warn) {
+            if !atty::is(Stream::Stdin) {
+                bail!(
+                    "The paste is {}. Pass --yes to download it anyways.",
+                    format_size(size)
+                );
+            }
+
+            eprint!(
+                "This paste is {}. Download it anyways? [y/N] ",
+                format_size(size)
+            );
+            std::io::stderr().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                bail!("Download cancelled.");
+            }
         }
+
+        Ok(())
+    }
+}
+
+/// Reads when a paste expires from a response. Deadlines are moved onto our
+/// clock using how long the server says is left, as our clock may not agree
+/// with the server's.
+fn response_expiration(headers: &HeaderMap) -> Option<Expiration> {
+    let expiration = headers
+        .get(EXPIRES)
+        .and_then(|v| Expiration::try_from(v).ok())?;
+    let ttl = headers
+        .get(&*PASTE_TTL_HEADER_NAME)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| PasteTtl::try_from(v).ok());
+    Some(ttl.map_or(expiration, |ttl| ttl.rebase(expiration)))
+}
+
+/// Downloads and decrypts a paste, returning its contents and when it expires.
+/// The contents are zeroed once they're dropped.
+/// If more than one connection is requested and the server supports ranges,
+/// then the paste is downloaded in that many chunks at once.
+fn fetch_paste(
+    url: &ParsedUrl,
+    password: Option<SecretVec<u8>>,
+    size_limits: SizeLimits,
+    connections: u64,
+) -> Result<(SecretVec<u8>, Option<Expiration>)> {
+    let (data, expiration) = fetch_sealed(&api_url(url), size_limits, connections)?;
+    Ok((open_paste(data, url, password)?, expiration))
+}
+
+/// Downloads a paste from its API URL without opening it, returning it along
+/// with when the server says it expires.
+fn fetch_sealed(
+    api_url: &Url,
+    size_limits: SizeLimits,
+    connections: u64,
+) -> Result<(Vec<u8>, Option<Expiration>)> {
+    let client = Client::new();
+
+    let mut req = client.get(api_url.clone());
+    if connections > 1 {
+        // Only ask for the first byte, so we learn how large the paste is.
+        // Servers that don't support ranges will send the whole paste instead.
+        req = req.header(RANGE, "bytes=0-0");
+    }
+    info!("Fetching {api_url}");
+    let start = Instant::now();
+    let res = req.send().context("Failed to get data")?;
+    log_response(&res, start);
+
+    if !matches!(res.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT) {
+        let not_before = res
+            .headers()
+            .get(&*NOT_BEFORE_HEADER_NAME)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| NotBefore::try_from(v).ok());
+        if let (StatusCode::LOCKED, Some(not_before)) = (res.status(), not_before) {
+            return Err(Failure::Locked).context(not_before.to_string());
+        }
+        if let Some(failure) = Failure::from_status(res.status()) {
+            return Err(failure.into());
+        }
+        bail!("Got bad response from server: {}", res.status());
+    }
+
+    let expiration = response_expiration(res.headers());
+
+    // We haven't read the body yet, so this is our chance to back out. Note
+    // that burn after reading pastes have already been burned at this point.
+    let data = if res.status() == StatusCode::PARTIAL_CONTENT {
+        let len = res
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit_once('/'))
+            .and_then(|(_, len)| len.parse().ok())
+            .context("Server sent an invalid Content-Range header")?;
+        size_limits.check(Some(len))?;
+        fetch_ranges(&client, api_url, len, connections)?
+    } else {
+        size_limits.check(res.content_length())?;
+        let data = res.bytes()?.as_ref().to_vec();
+        debug!(
+            "Downloaded {} bytes in {} ms",
+            data.len(),
+            start.elapsed().as_millis()
+        );
+        data
+    };
+
+    Ok((data, expiration))
+}
+
+/// Opens a downloaded paste, in whichever format its URL says it has.
+fn open_paste(
+    data: Vec<u8>,
+    url: &ParsedUrl,
+    password: Option<SecretVec<u8>>,
+) -> Result<SecretVec<u8>> {
+    let open = if url.chunked {
+        open_chunked_into_secret
     } else {
-        std::io::stdout().write_all(&data)?;
+        open_into_secret
+    };
+    timed("Decrypted paste", || {
+        open(data, &url.decryption_key, password, url.envelope())
+    })
+    .map_err(|e| Failure::from_crypto_error(&e).map_or_else(|| anyhow!(e), anyhow::Error::new))
+}
+
+/// Downloads `len` bytes in `connections` ranges at once, returning the
+/// reassembled data.
+fn fetch_ranges(client: &Client, url: &Url, len: u64, connections: u64) -> Result<Vec<u8>> {
+    let chunk_size = ((len + connections - 1) / connections).max(1);
+    let ranges: Vec<_> = (0..len)
+        .step_by(usize::try_from(chunk_size)?)
+        .map(|start| (start, (start + chunk_size).min(len) - 1))
+        .collect();
+
+    let chunks = std::thread::scope(|scope| {
+        let handles: Vec<_> = ranges
+            .into_iter()
+            .map(|(start, end)| {
+                scope.spawn(move || -> Result<Bytes> {
+                    let started = Instant::now();
+                    let res = client
+                        .get(url.clone())
+                        .header(RANGE, format!("bytes={start}-{end}"))
+                        .send()
+                        .context("Failed to get data")?;
+                    if res.status() != StatusCode::PARTIAL_CONTENT {
+                        bail!("Got bad response from server: {}", res.status());
+                    }
+                    let chunk = res.bytes()?;
+                    debug!(
+                        "Downloaded bytes {start}-{end} in {} ms",
+                        started.elapsed().as_millis()
+                    );
+                    if chunk.len() as u64 != end - start + 1 {
+                        bail!("Server sent a range of the wrong size");
+                    }
+                    Ok(chunk)
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .map_err(|_| anyhow!("Download thread panicked"))?
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut data = Vec::with_capacity(usize::try_from(len)?);
+    for chunk in chunks {
+        data.extend_from_slice(&chunk);
     }
+    Ok(data)
+}
 
-    eprintln!("{expiration_text}");
+/// Deletes a paste from the server, which requires `owner_token` if the paste
+/// was uploaded with one.
+fn delete_paste(url: &ParsedUrl, owner_token: Option<&str>) -> Result<()> {
+    info!("Deleting {}", api_url(url));
+    let start = Instant::now();
+    let mut req = Client::new().delete(api_url(url));
+    if let Some(owner_token) = owner_token {
+        req = req.header(&*OWNER_TOKEN_HEADER_NAME, owner_token);
+    }
+    let res = req.send().context("Failed to delete paste")?;
+    log_response(&res, start);
+
+    if res.status() != StatusCode::OK {
+        if let Some(failure) = Failure::from_status(res.status()) {
+            return Err(failure.into());
+        }
+        bail!("Got bad response from server: {}", res.status());
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn testdata() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../testdata")
+    }
+
+    /// The CLI must open every paste in `testdata`, just like the web frontend.
+    #[test]
+    fn opens_all_vectors() {
+        let manifest = std::fs::read_to_string(testdata().join("vectors.txt")).unwrap();
+        for vector in manifest.lines().filter(|line| !line.starts_with('#')) {
+            let [name, password, fragment]: [&str; 3] =
+                vector.split('\t').collect::<Vec<_>>().try_into().unwrap();
+            let url: ParsedUrl = format!("https://example.com/{name}#{fragment}")
+                .parse()
+                .unwrap();
+            let password = (password != "-").then(|| SecretVec::new(password.as_bytes().to_vec()));
+            let expected = PartialParsedUrl::try_from(fragment).unwrap().hash;
+
+            let data = std::fs::read(testdata().join(name)).unwrap();
+            match open_paste(data, &url, password) {
+                Ok(data) => assert_eq!(Some(hash(data.expose_secret())), expected, "{name}"),
+                Err(e) => panic!("failed to open {name}: {e:#}"),
+            }
+        }
+    }
+
+    #[test]
+    fn maps_filetypes_to_languages() {
+        assert_eq!(language_for_filetype("rust").as_deref(), Some("rust"));
+        assert_eq!(language_for_filetype("sh").as_deref(), Some("bash"));
+        assert_eq!(
+            language_for_filetype("typescriptreact.jest").as_deref(),
+            Some("typescript")
+        );
+        assert_eq!(language_for_filetype("text"), None);
+        assert_eq!(language_for_filetype(""), None);
+    }
+
+    #[test]
+    fn splits_keys_from_urls() {
+        let key = Key::random_secret();
+        let encoded = KeyFormat::Base64.encode(&key);
+        for (fragment, rest) in [
+            (
+                format!("key:{}!pw!chunked", encoded.expose_secret()),
+                Some("!pw!chunked"),
+            ),
+            (encoded.expose_secret().clone(), None),
+        ] {
+            let url: Url = format!("https://example.com/code#{fragment}")
+                .parse()
+                .unwrap();
+            let stripped = without_key(&url);
+            assert_eq!(stripped.fragment(), rest);
+            assert_eq!(with_key(&stripped, &encoded), url);
+        }
+    }
+}
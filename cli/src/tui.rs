@@ -0,0 +1,290 @@
+// OmegaUpload CLI Client
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! An interactive browser for the paste history.
+
+use std::io::Stdout;
+
+use anyhow::{bail, Context, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use omegaupload_common::secrecy::{ExposeSecret, SecretVec};
+use omegaupload_common::{Expiration, ParsedUrl, PartialParsedUrl};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Terminal;
+
+use crate::history::{self, Entry};
+use crate::{delete_paste, fetch_paste, SizeLimits};
+
+const HELP: &str =
+    "j/k: move  p: preview  s: save  c: copy URL  d: delete  x: forget expired  q: quit";
+
+struct App {
+    entries: Vec<Entry>,
+    state: ListState,
+    preview: String,
+    status: String,
+}
+
+impl App {
+    fn new(mut entries: Vec<Entry>) -> Self {
+        // Most recent pastes first.
+        entries.reverse();
+        let mut state = ListState::default();
+        if !entries.is_empty() {
+            state.select(Some(0));
+        }
+        Self {
+            entries,
+            state,
+            preview: String::new(),
+            status: HELP.to_string(),
+        }
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.state.selected().and_then(|i| self.entries.get(i))
+    }
+
+    fn move_selection(&mut self, forward: bool) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let last = self.entries.len() - 1;
+        let selected = match (self.state.selected(), forward) {
+            (Some(i), true) => (i + 1).min(last),
+            (Some(i), false) => i.saturating_sub(1),
+            (None, _) => 0,
+        };
+        self.state.select(Some(selected));
+        self.preview.clear();
+    }
+
+    fn run_action(&mut self, action: impl FnOnce(&mut Self) -> Result<String>) {
+        self.status = match action(self) {
+            Ok(status) => status,
+            Err(e) => format!("Error: {e:#}"),
+        };
+    }
+
+    fn preview(&mut self) -> Result<String> {
+        let data = self.fetch_selected()?;
+        match std::str::from_utf8(data.expose_secret()) {
+            Ok(text) => {
+                text.clone_into(&mut self.preview);
+                Ok("Loaded preview.".to_string())
+            }
+            Err(_) => bail!("This paste is binary and can't be previewed. Save it instead."),
+        }
+    }
+
+    fn save(&mut self) -> Result<String> {
+        let entry = self.selected().context("No paste selected")?;
+        let file_name = entry
+            .url
+            .fragment()
+            .and_then(|fragment| PartialParsedUrl::try_from(fragment).ok())
+            .and_then(|partial| partial.name)
+            .or_else(|| short_code(entry).map(ToString::to_string))
+            .context("Unable to determine a file name")?;
+        if std::path::Path::new(&file_name).exists() {
+            bail!("{file_name} already exists.");
+        }
+        let data = self.fetch_selected()?;
+        std::fs::write(&file_name, data.expose_secret())?;
+        Ok(format!("Saved to {file_name}."))
+    }
+
+    fn copy_url(&self) -> Result<String> {
+        let entry = self.selected().context("No paste selected")?;
+        arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.set_text(entry.url.to_string()))
+            .context("Failed to access the clipboard")?;
+        Ok("Copied URL to the clipboard.".to_string())
+    }
+
+    fn delete(&mut self) -> Result<String> {
+        let index = self.state.selected().context("No paste selected")?;
+        let entry = &self.entries[index];
+        let url: ParsedUrl = entry.url.as_str().parse()?;
+        delete_paste(&url, entry.owner_token.as_deref())?;
+        self.entries.remove(index);
+        self.save_history()?;
+        self.fix_selection();
+        self.preview.clear();
+        Ok("Deleted paste.".to_string())
+    }
+
+    fn forget_expired(&mut self) -> Result<String> {
+        let before = self.entries.len();
+        self.entries.retain(|entry| !entry.is_expired());
+        self.save_history()?;
+        self.fix_selection();
+        Ok(format!(
+            "Forgot {} expired pastes.",
+            before - self.entries.len()
+        ))
+    }
+
+    fn fetch_selected(&self) -> Result<SecretVec<u8>> {
+        let entry = self.selected().context("No paste selected")?;
+        if matches!(
+            entry.expiration,
+            Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_))
+        ) {
+            bail!("Reading this paste would burn it. Use the download command instead.");
+        }
+        if matches!(
+            entry.expiration,
+            Some(Expiration::ReadWindow(_) | Expiration::ReadWindowWithDeadline(..))
+        ) {
+            bail!("Reading this paste would start its deletion. Use the download command instead.");
+        }
+        let url: ParsedUrl = entry.url.as_str().parse()?;
+        if url.needs_password {
+            bail!("This paste needs a password. Use the download command instead.");
+        }
+        // The user explicitly asked for this paste, so don't prompt.
+        Ok(fetch_paste(&url, None, SizeLimits::default(), 1)?.0)
+    }
+
+    fn save_history(&self) -> Result<()> {
+        // The history file is stored oldest first.
+        let entries: Vec<_> = self.entries.iter().rev().cloned().collect();
+        history::save(&entries)
+    }
+
+    fn fix_selection(&mut self) {
+        let selected = match self.state.selected() {
+            _ if self.entries.is_empty() => None,
+            Some(i) => Some(i.min(self.entries.len() - 1)),
+            None => Some(0),
+        };
+        self.state.select(selected);
+    }
+}
+
+fn short_code(entry: &Entry) -> Option<&str> {
+    entry
+        .url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+}
+
+fn entry_label(entry: &Entry) -> String {
+    let status = match entry.expiration {
+        _ if entry.is_expired() => "expired".to_string(),
+        Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_)) => {
+            "burn after reading".to_string()
+        }
+        Some(Expiration::UnixTime(time)) => time.format("expires %F %R").to_string(),
+        Some(Expiration::ReadWindow(window) | Expiration::ReadWindowWithDeadline(window, _)) => {
+            format!("expires {}m after reading", window / 60)
+        }
+        None => "unknown expiration".to_string(),
+    };
+    let name = entry
+        .url
+        .fragment()
+        .and_then(|fragment| PartialParsedUrl::try_from(fragment).ok())
+        .and_then(|partial| partial.name);
+    format!(
+        "{} {} ({status})",
+        entry.url.host_str().unwrap_or_default(),
+        name.as_deref()
+            .or_else(|| short_code(entry))
+            .unwrap_or_default(),
+    )
+}
+
+/// Runs the TUI until the user quits.
+///
+/// # Errors
+///
+/// Returns an error if the history couldn't be loaded or if the terminal
+/// couldn't be used.
+pub fn run() -> Result<()> {
+    let mut app = App::new(history::load()?);
+
+    enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let res = event_loop(&mut terminal, &mut app);
+
+    // Always try to restore the terminal, even if the event loop failed.
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    res
+}
+
+fn event_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, app: &mut App) -> Result<()> {
+    loop {
+        terminal.draw(|f| {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+                .split(f.size());
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+                .split(rows[0]);
+
+            let items: Vec<_> = app
+                .entries
+                .iter()
+                .map(|entry| ListItem::new(entry_label(entry)))
+                .collect();
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title("History"))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_stateful_widget(list, columns[0], &mut app.state);
+
+            let preview = Paragraph::new(app.preview.as_str())
+                .block(Block::default().borders(Borders::ALL).title("Preview"))
+                .wrap(Wrap { trim: false });
+            f.render_widget(preview, columns[1]);
+
+            f.render_widget(Paragraph::new(app.status.as_str()), rows[1]);
+        })?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('j') | KeyCode::Down => app.move_selection(true),
+                KeyCode::Char('k') | KeyCode::Up => app.move_selection(false),
+                KeyCode::Char('p') | KeyCode::Enter => app.run_action(App::preview),
+                KeyCode::Char('s') => app.run_action(App::save),
+                KeyCode::Char('c') => app.run_action(|app| app.copy_url()),
+                KeyCode::Char('d') => app.run_action(App::delete),
+                KeyCode::Char('x') => app.run_action(App::forget_expired),
+                _ => app.status = HELP.to_string(),
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright (c) 2021 Edward Shen
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Throughput of sealing and opening pastes with each cipher, and what deriving
+//! a key from a password costs at different Argon2 settings.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use omegaupload_common::crypto::{
+    open_chunked_in_place, open_in_place, seal_chunked_in_place, seal_chunked_in_place_with_key,
+    seal_in_place, seal_in_place_with_key, ChunkedOpener, Cipher, Envelope, Key, SealOptions,
+    CHUNK_SIZE,
+};
+use omegaupload_common::secrecy::SecretVec;
+
+const SIZES: &[usize] = &[1024, 64 * 1024, 1024 * 1024, 16 * 1024 * 1024];
+
+fn plaintext(size: usize) -> Vec<u8> {
+    (0..size).map(|i| i as u8).collect()
+}
+
+fn seal(c: &mut Criterion) {
+    let mut group = c.benchmark_group("seal");
+    for &size in SIZES {
+        let data = plaintext(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("whole", size), &data, |b, data| {
+            b.iter_batched(
+                || data.clone(),
+                |mut data| seal_in_place(&mut data, None).unwrap(),
+                BatchSize::LargeInput,
+            );
+        });
+        group.bench_with_input(BenchmarkId::new("chunked", size), &data, |b, data| {
+            b.iter_batched(
+                || data.clone(),
+                |mut data| seal_chunked_in_place(&mut data, None).unwrap(),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn open(c: &mut Criterion) {
+    let mut group = c.benchmark_group("open");
+    for &size in SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+
+        let mut sealed = plaintext(size);
+        let key = seal_in_place(&mut sealed, None).unwrap();
+        group.bench_with_input(BenchmarkId::new("whole", size), &sealed, |b, sealed| {
+            b.iter_batched(
+                || sealed.clone(),
+                |mut data| open_in_place(&mut data, &key, None, Envelope::default()).unwrap(),
+                BatchSize::LargeInput,
+            );
+        });
+
+        let mut sealed = plaintext(size);
+        let key = seal_chunked_in_place(&mut sealed, None).unwrap();
+        group.bench_with_input(BenchmarkId::new("chunked", size), &sealed, |b, sealed| {
+            b.iter_batched(
+                || sealed.clone(),
+                |mut data| {
+                    open_chunked_in_place(&mut data, &key, None, Envelope::default()).unwrap()
+                },
+                BatchSize::LargeInput,
+            );
+        });
+        // As the web frontend does while downloading.
+        group.bench_with_input(
+            BenchmarkId::new("chunked-incremental", size),
+            &sealed,
+            |b, sealed| {
+                b.iter(|| {
+                    let mut opener = ChunkedOpener::new(&key, None, Envelope::default());
+                    let mut len = 0;
+                    for piece in sealed.chunks(CHUNK_SIZE) {
+                        len += opener.update(piece).unwrap().len();
+                    }
+                    len + opener.finish().unwrap().len()
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+// Compares the ciphers, to pick `Cipher::preferred` on each target.
+fn cipher(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cipher");
+    let key = Key::random_secret();
+    for cipher in [Cipher::XChaCha20Poly1305, Cipher::Aes256Gcm] {
+        let options = SealOptions {
+            cipher: Some(cipher),
+            ..SealOptions::default()
+        };
+        let envelope = options.envelope(false);
+        for &size in SIZES {
+            let data = plaintext(size);
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{cipher}/seal-whole"), size),
+                &data,
+                |b, data| {
+                    b.iter_batched(
+                        || data.clone(),
+                        |mut data| seal_in_place_with_key(&mut data, &key, None, options).unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("{cipher}/seal-chunked"), size),
+                &data,
+                |b, data| {
+                    b.iter_batched(
+                        || data.clone(),
+                        |mut data| {
+                            seal_chunked_in_place_with_key(&mut data, &key, None, options).unwrap();
+                        },
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+
+            let mut sealed = data.clone();
+            seal_in_place_with_key(&mut sealed, &key, None, options).unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format!("{cipher}/open-whole"), size),
+                &sealed,
+                |b, sealed| {
+                    b.iter_batched(
+                        || sealed.clone(),
+                        |mut data| open_in_place(&mut data, &key, None, envelope).unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+
+            let mut sealed = data;
+            seal_chunked_in_place_with_key(&mut sealed, &key, None, options).unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format!("{cipher}/open-chunked"), size),
+                &sealed,
+                |b, sealed| {
+                    b.iter_batched(
+                        || sealed.clone(),
+                        |mut data| open_chunked_in_place(&mut data, &key, None, envelope).unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn argon2(c: &mut Criterion) {
+    let mut group = c.benchmark_group("argon2");
+    group.sample_size(10);
+    // Memory in KiB, iterations, and lanes. The first is the interactive
+    // profile, and the last two are the moderate and paranoid profiles.
+    for (m_cost, t_cost, p_cost) in [
+        (15 * 1024, 2, 2),
+        (15 * 1024, 1, 2),
+        (15 * 1024, 2, 1),
+        (19 * 1024, 2, 1),
+        (64 * 1024, 3, 4),
+        (256 * 1024, 4, 4),
+    ] {
+        let params = Params::new(m_cost, t_cost, p_cost, None).unwrap();
+        let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+        let id = format!("m={m_cost},t={t_cost},p={p_cost}");
+        group.bench_function(id, |b| {
+            b.iter(|| {
+                let mut key = [0; 32];
+                argon
+                    .hash_password_into(b"password", &[0; 16], &mut key)
+                    .unwrap();
+                key
+            });
+        });
+    }
+
+    let password = || Some(SecretVec::new(b"password".to_vec()));
+    group.bench_function("seal-with-password", |b| {
+        b.iter_batched(
+            || plaintext(1024),
+            |mut data| seal_in_place(&mut data, password()).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
+    let mut sealed = plaintext(1024);
+    let key = seal_in_place(&mut sealed, password()).unwrap();
+    group.bench_function("open-with-password", |b| {
+        b.iter_batched(
+            || sealed.clone(),
+            |mut data| open_in_place(&mut data, &key, password(), Envelope::default()).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(benches, seal, open, cipher, argon2);
+criterion_main!(benches);
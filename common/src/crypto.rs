@@ -18,18 +18,24 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use std::fmt::Display;
 use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
 
+use aes_gcm::Aes256Gcm;
 use argon2::{Argon2, ParamsBuilder};
+use blake2::{Blake2s256, Digest};
 use chacha20poly1305::aead::generic_array::sequence::GenericSequence;
 use chacha20poly1305::aead::generic_array::GenericArray;
-use chacha20poly1305::aead::{AeadInPlace};
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{AeadCore, AeadInPlace};
+use chacha20poly1305::KeyInit;
 use chacha20poly1305::XChaCha20Poly1305;
 use chacha20poly1305::XNonce;
 use rand::{CryptoRng, Rng};
+use secrecy::zeroize::Zeroizing;
 use secrecy::{DebugSecret, ExposeSecret, Secret, SecretVec, Zeroize};
 use typenum::Unsigned;
-use chacha20poly1305::KeyInit;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -41,6 +47,8 @@ pub enum Error {
     Encryption,
     #[error("An error occurred while trying to derive a secret key.")]
     Kdf,
+    #[error("The blob was sealed with an unknown cipher.")]
+    Cipher,
 }
 
 // This struct intentionally prevents implement Clone or Copy
@@ -50,10 +58,29 @@ pub struct Key(chacha20poly1305::Key);
 impl Key {
     /// Encloses a secret key in a secret `Key` struct.
     pub fn new_secret(vec: Vec<u8>) -> Option<Secret<Self>> {
-        chacha20poly1305::Key::from_exact_iter(vec.into_iter())
+        let vec = Zeroizing::new(vec);
+        chacha20poly1305::Key::from_exact_iter(vec.iter().copied())
             .map(Self)
             .map(Secret::new)
     }
+
+    /// Generates a new random secret key, such as for use with a
+    /// `ChunkedSealer`.
+    #[must_use]
+    pub fn random_secret() -> Secret<Self> {
+        gen_key()
+    }
+
+    /// Derives the key of the member at `index` of a
+    /// [manifest](crate::manifest) from the manifest's own key, so that only
+    /// the manifest's key needs to be shared.
+    #[must_use]
+    pub fn derive_member(&self, index: u32) -> Secret<Self> {
+        let mut hasher = Blake2s256::new_with_prefix(b"omegaupload manifest member");
+        hasher.update(self.0.as_slice());
+        hasher.update(index.to_le_bytes());
+        Secret::new(Self(hasher.finalize()))
+    }
 }
 
 impl DebugSecret for Key {}
@@ -83,6 +110,39 @@ impl Zeroize for Key {
     }
 }
 
+/// Options for sealing a message. The defaults seal messages that older clients
+/// can also open.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SealOptions {
+    /// Argon2 parameters to derive the password with and record, instead of the
+    /// default ones. This is ignored if there's no password.
+    pub kdf: Option<KdfParams>,
+    /// The cipher to seal with and record, instead of the default one.
+    pub cipher: Option<Cipher>,
+}
+
+impl SealOptions {
+    /// The envelope that messages sealed with these options must be opened
+    /// with.
+    #[must_use]
+    pub const fn envelope(self, has_password: bool) -> Envelope {
+        Envelope {
+            recorded_kdf: has_password && self.kdf.is_some(),
+            algorithm_id: self.cipher.is_some(),
+        }
+    }
+}
+
+/// What a sealed message records beyond the default format, which determines
+/// how it's laid out. This is carried in the URL of a paste.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Envelope {
+    /// Whether the Argon2 parameters are recorded after the salt.
+    pub recorded_kdf: bool,
+    /// Whether the id of the cipher is recorded.
+    pub algorithm_id: bool,
+}
+
 /// Seals the provided message with an optional password, returning the secret
 /// key used to encrypt the message and mutating the buffer to contain necessary
 /// metadata.
@@ -107,6 +167,10 @@ impl Zeroize for Key {
 ///  - `kdf(pw, salt)` represents a key derived from Argon2.
 ///  - `nonce` represents a randomly generated nonce.
 ///
+/// Messages sealed with `seal_in_place_with_key` and non-default `SealOptions`
+/// additionally have the Argon2 parameters appended after the salt, and the id
+/// of the cipher appended after that. AES-256-GCM uses a 12 byte nonce.
+///
 /// Note that the lengths for the nonce, key, and salt follow recommended
 /// values. As of writing this doc (2021-10-31), the nonce size is 24 bytes, the
 /// salt size is 16 bytes, and the key size is 32 bytes.
@@ -121,29 +185,86 @@ pub fn seal_in_place(
     pw: Option<SecretVec<u8>>,
 ) -> Result<Secret<Key>, Error> {
     let (key, nonce) = gen_key_nonce();
-    let cipher = XChaCha20Poly1305::new(key.expose_secret());
+    seal_in_place_with_nonce(message, &key, nonce, pw, SealOptions::default())?;
+    Ok(key)
+}
+
+/// Seals the provided message with an existing key, such as when replacing the
+/// contents of a paste without changing its URL. The sealed message is in the
+/// same format as `seal_in_place`, and a fresh nonce is always used.
+///
+/// The sealed message must be opened with the `Envelope` returned by
+/// `SealOptions::envelope`.
+///
+/// # Errors
+///
+/// This message will return an error if and only if there was a problem
+/// encrypting the message or deriving a secret key from the password, if one
+/// was provided.
+pub fn seal_in_place_with_key(
+    message: &mut Vec<u8>,
+    key: &Secret<Key>,
+    pw: Option<SecretVec<u8>>,
+    options: SealOptions,
+) -> Result<(), Error> {
+    seal_in_place_with_nonce(message, key, gen_nonce(), pw, options)
+}
+
+fn seal_in_place_with_nonce(
+    message: &mut Vec<u8>,
+    key: &Secret<Key>,
+    nonce: Nonce,
+    pw: Option<SecretVec<u8>>,
+    options: SealOptions,
+) -> Result<(), Error> {
+    reserve_zeroizing(
+        message,
+        TAG_SIZE * 2 + Nonce::SIZE + Salt::SIZE + KdfParams::SIZE + 1,
+    );
+    let cipher = options.cipher.unwrap_or_default();
+    let nonce_size = cipher.nonce_size();
     cipher
-        .encrypt_in_place(&nonce, &[], message)
+        .encrypt_in_place(key.expose_secret(), &nonce[..nonce_size], message)
         .map_err(|_| Error::Encryption)?;
 
     let mut maybe_salt_string = None;
     if let Some(password) = pw {
-        let (key, salt_string) = kdf(&password).map_err(|_| Error::Kdf)?;
+        let params = options.kdf.unwrap_or_default();
+        let (key, salt_string) = kdf(&password, params)?;
         maybe_salt_string = Some(salt_string);
-        let cipher = XChaCha20Poly1305::new(key.expose_secret());
         cipher
-            .encrypt_in_place(&nonce.increment(), &[], message)
+            .encrypt_in_place(
+                key.expose_secret(),
+                &nonce.increment()[..nonce_size],
+                message,
+            )
             .map_err(|_| Error::Encryption)?;
     }
 
-    message.extend_from_slice(nonce.as_slice());
+    message.extend_from_slice(&nonce[..nonce_size]);
     if let Some(maybe_salted_string) = maybe_salt_string {
         message.extend_from_slice(maybe_salted_string.as_ref());
+        if let Some(params) = options.kdf {
+            message.extend_from_slice(&params.to_bytes());
+        }
     }
-    Ok(key)
+    if options.cipher.is_some() {
+        message.push(cipher.id());
+    }
+    Ok(())
+}
+
+/// Stages of opening a sealed message, in the order they occur.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenStage {
+    /// A key is being derived from the password. This is skipped if no password
+    /// was provided.
+    DerivingKey,
+    Decrypting,
 }
 
-/// Opens a message that has been sealed with `seal_in_place`.
+/// Opens a message that has been sealed with `seal_in_place`. `envelope` must
+/// match the options the message was sealed with.
 ///
 /// # Errors
 ///
@@ -153,46 +274,583 @@ pub fn open_in_place(
     data: &mut Vec<u8>,
     key: &Secret<Key>,
     password: Option<SecretVec<u8>>,
+    envelope: Envelope,
 ) -> Result<(), Error> {
+    open_in_place_with_progress(data, key, password, envelope, |_| ())
+}
+
+/// Opens a message that has been sealed with `seal_in_place`, calling
+/// `on_stage` as each stage begins. Both stages can take a while for large
+/// messages or slow devices, so this is useful for reporting progress.
+///
+/// # Errors
+///
+/// Returns an error if there was a decryption failure or if there was a problem
+/// deriving a secret key from the password.
+pub fn open_in_place_with_progress(
+    data: &mut Vec<u8>,
+    key: &Secret<Key>,
+    password: Option<SecretVec<u8>>,
+    envelope: Envelope,
+    mut on_stage: impl FnMut(OpenStage),
+) -> Result<(), Error> {
+    let cipher = if envelope.algorithm_id {
+        Cipher::from_id(data.pop().ok_or(Error::Encryption)?)?
+    } else {
+        Cipher::default()
+    };
+    let nonce_size = cipher.nonce_size();
+    let header_size = match (&password, envelope.recorded_kdf) {
+        (Some(_), true) => nonce_size + Salt::SIZE + KdfParams::SIZE,
+        (Some(_), false) => nonce_size + Salt::SIZE,
+        (None, _) => nonce_size,
+    };
+    if data.len() < header_size {
+        return Err(Error::Encryption);
+    }
+
     let pw_key = if let Some(password) = password {
+        on_stage(OpenStage::DerivingKey);
+        let params = if envelope.recorded_kdf {
+            KdfParams::from_bytes(&data.split_off(data.len() - KdfParams::SIZE))?
+        } else {
+            KdfParams::default()
+        };
         let salt_buf = data.split_off(data.len() - Salt::SIZE);
-        let argon = get_argon2();
-        let mut pw_key = Key::default();
+        let argon = params.argon2()?;
+        let mut pw_key = Zeroizing::new(Key::default());
         argon
             .hash_password_into(password.expose_secret(), &salt_buf, &mut pw_key)
             .map_err(|_| Error::Kdf)?;
-        Some(Secret::new(pw_key))
+        Some(pw_key)
     } else {
         None
     };
 
-    let nonce = Nonce::from_slice(&data.split_off(data.len() - Nonce::SIZE));
+    // Shorter nonces are padded, so that they can be incremented the same way.
+    let mut nonce = Nonce::default();
+    nonce[..nonce_size].copy_from_slice(&data.split_off(data.len() - nonce_size));
 
     // At this point we should have a buffer that's only the ciphertext.
 
+    on_stage(OpenStage::Decrypting);
+
     if let Some(key) = pw_key {
-        let cipher = XChaCha20Poly1305::new(key.expose_secret());
         cipher
-            .decrypt_in_place(&nonce.increment(), &[], data)
+            .decrypt_in_place(&key, &nonce.increment()[..nonce_size], data)
             .map_err(|_| Error::Password)?;
     }
 
-    let cipher = XChaCha20Poly1305::new(key.expose_secret());
     cipher
-        .decrypt_in_place(&nonce, &[], data)
+        .decrypt_in_place(key.expose_secret(), &nonce[..nonce_size], data)
         .map_err(|_| Error::SecretKey)?;
 
     Ok(())
 }
 
+/// Opens a message that has been sealed with `seal_in_place`, returning the
+/// plaintext as a secret so that it's zeroed once it's dropped.
+///
+/// # Errors
+///
+/// Returns an error if there was a decryption failure or if there was a problem
+/// deriving a secret key from the password.
+pub fn open_into_secret(
+    mut data: Vec<u8>,
+    key: &Secret<Key>,
+    password: Option<SecretVec<u8>>,
+    envelope: Envelope,
+) -> Result<SecretVec<u8>, Error> {
+    open_in_place(&mut data, key, password, envelope)?;
+    Ok(SecretVec::new(data))
+}
+
+/// The maximum amount of plaintext in each chunk of a message sealed with
+/// `seal_chunked_in_place`.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+// Both ciphers use the same tag size.
+const TAG_SIZE: usize = <<XChaCha20Poly1305 as AeadCore>::TagSize as Unsigned>::USIZE;
+
+/// Seals the provided message in fixed size chunks, so that it can be opened
+/// incrementally with a `ChunkedOpener` as it is received. Like
+/// `seal_in_place`, this returns the secret key used to encrypt the message.
+///
+/// The resulting sealed message is one of the following, depending if there
+/// was a password provided:
+///
+/// ```text
+/// modified = nonce || S(message, rng_key, nonce)
+/// ```
+/// or
+/// ```text
+/// modified = nonce || salt || S(S(message, rng_key, nonce), kdf(pw, salt), nonce)
+/// ```
+///
+/// As with `seal_in_place`, recorded Argon2 parameters follow the salt, but the
+/// id of the cipher comes before the nonce, so that it's known before the rest
+/// of the header is read. The nonce is 7 bytes long with AES-256-GCM.
+///
+/// Where `S(message, key, nonce)` represents encrypting each `CHUNK_SIZE` chunk
+/// of a message with the STREAM construction over `XChaCha20Poly1305`. The
+/// password layer is applied to each chunk individually, so every chunk grows
+/// by one tag per layer.
+///
+/// # Errors
+///
+/// This message will return an error if and only if there was a problem
+/// encrypting the message or deriving a secret key from the password, if one
+/// was provided.
+pub fn seal_chunked_in_place(
+    message: &mut Vec<u8>,
+    pw: Option<SecretVec<u8>>,
+) -> Result<Secret<Key>, Error> {
+    let key = gen_key();
+    seal_chunked_in_place_with_key(message, &key, pw, SealOptions::default())?;
+    Ok(key)
+}
+
+/// Seals the provided message in chunks with an existing key. See
+/// `seal_chunked_in_place` and `seal_in_place_with_key`.
+///
+/// # Errors
+///
+/// This message will return an error if and only if there was a problem
+/// encrypting the message or deriving a secret key from the password, if one
+/// was provided.
+pub fn seal_chunked_in_place_with_key(
+    message: &mut Vec<u8>,
+    key: &Secret<Key>,
+    pw: Option<SecretVec<u8>>,
+    options: SealOptions,
+) -> Result<(), Error> {
+    let (mut sealer, header) = ChunkedSealer::new(key, pw, options)?;
+
+    let chunks = message.len() / CHUNK_SIZE + 1;
+    let mut ciphertext = Vec::with_capacity(header.len() + message.len() + chunks * TAG_SIZE * 2);
+    ciphertext.extend_from_slice(&header);
+
+    // The last chunk is always present, even if it's empty, as it marks the end
+    // of the stream.
+    let last_start = message.len().saturating_sub(1) / CHUNK_SIZE * CHUNK_SIZE;
+    let (body, last) = message.split_at(last_start);
+
+    for chunk in body.chunks(CHUNK_SIZE) {
+        ciphertext.extend_from_slice(&sealer.seal_next(chunk)?);
+    }
+    ciphertext.extend_from_slice(&sealer.seal_last(last)?);
+
+    message.zeroize();
+    *message = ciphertext;
+    Ok(())
+}
+
+/// Incrementally seals a message in the same format as `seal_chunked_in_place`,
+/// such as when the message is too large to be in memory all at once.
+pub struct ChunkedSealer {
+    inner: StreamEncryptor,
+    outer: Option<StreamEncryptor>,
+}
+
+impl ChunkedSealer {
+    /// Creates a sealer, returning it alongside the header that must precede
+    /// the sealed chunks. `options` are handled as in `seal_in_place_with_key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a problem deriving a secret key from the
+    /// password, if one was provided.
+    pub fn new(
+        key: &Secret<Key>,
+        pw: Option<SecretVec<u8>>,
+        options: SealOptions,
+    ) -> Result<(Self, Vec<u8>), Error> {
+        let cipher = options.cipher.unwrap_or_default();
+        let mut nonce = vec![0; cipher.stream_nonce_size()];
+        get_csrng().fill(nonce.as_mut_slice());
+
+        let mut header = vec![];
+        if options.cipher.is_some() {
+            header.push(cipher.id());
+        }
+        header.extend_from_slice(&nonce);
+        let outer = if let Some(password) = pw {
+            let (pw_key, salt) = kdf(&password, options.kdf.unwrap_or_default())?;
+            header.extend_from_slice(salt.as_ref());
+            if let Some(params) = options.kdf {
+                header.extend_from_slice(&params.to_bytes());
+            }
+            Some(StreamEncryptor::new(cipher, pw_key.expose_secret(), &nonce))
+        } else {
+            None
+        };
+        let inner = StreamEncryptor::new(cipher, key.expose_secret(), &nonce);
+
+        Ok((Self { inner, outer }, header))
+    }
+
+    /// Seals a chunk that isn't the last one, which must be exactly
+    /// `CHUNK_SIZE` bytes long.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk is the wrong size, or if there was a
+    /// problem encrypting it.
+    pub fn seal_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        if chunk.len() != CHUNK_SIZE {
+            return Err(Error::Encryption);
+        }
+
+        let mut chunk = self.inner.encrypt_next(chunk)?;
+        if let Some(outer) = self.outer.as_mut() {
+            chunk = outer.encrypt_next(chunk.as_slice())?;
+        }
+        Ok(chunk)
+    }
+
+    /// Seals the last chunk, which may be empty but can't be longer than
+    /// `CHUNK_SIZE` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk is too large, or if there was a problem
+    /// encrypting it.
+    pub fn seal_last(self, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        if chunk.len() > CHUNK_SIZE {
+            return Err(Error::Encryption);
+        }
+
+        let mut chunk = self.inner.encrypt_last(chunk)?;
+        if let Some(outer) = self.outer {
+            chunk = outer.encrypt_last(chunk.as_slice())?;
+        }
+        Ok(chunk)
+    }
+}
+
+/// Opens a message that has been sealed with `seal_chunked_in_place` all at
+/// once.
+///
+/// # Errors
+///
+/// Returns an error if there was a decryption failure or if there was a problem
+/// deriving a secret key from the password.
+pub fn open_chunked_in_place(
+    data: &mut Vec<u8>,
+    key: &Secret<Key>,
+    password: Option<SecretVec<u8>>,
+    envelope: Envelope,
+) -> Result<(), Error> {
+    let mut opener = ChunkedOpener::new(key, password, envelope);
+    // Opened messages are always smaller, so this is never reallocated.
+    let mut plaintext = Vec::with_capacity(data.len());
+    plaintext.extend_from_slice(&Zeroizing::new(opener.update(data)?));
+    plaintext.extend_from_slice(&Zeroizing::new(opener.finish()?));
+    *data = plaintext;
+    Ok(())
+}
+
+/// Opens a message that has been sealed with `seal_chunked_in_place`, returning
+/// the plaintext as a secret so that it's zeroed once it's dropped.
+///
+/// # Errors
+///
+/// Returns an error if there was a decryption failure or if there was a problem
+/// deriving a secret key from the password.
+pub fn open_chunked_into_secret(
+    mut data: Vec<u8>,
+    key: &Secret<Key>,
+    password: Option<SecretVec<u8>>,
+    envelope: Envelope,
+) -> Result<SecretVec<u8>, Error> {
+    open_chunked_in_place(&mut data, key, password, envelope)?;
+    Ok(SecretVec::new(data))
+}
+
+/// Incrementally opens a message that has been sealed with
+/// `seal_chunked_in_place`, such as while it is being downloaded.
+pub struct ChunkedOpener {
+    /// The key and password, until the header has been received. The cipher
+    /// may not be known until then.
+    pending: Option<(Zeroizing<Key>, Option<SecretVec<u8>>)>,
+    decryptors: Option<ChunkDecryptors>,
+    has_password: bool,
+    envelope: Envelope,
+    buffer: Vec<u8>,
+}
+
+struct ChunkDecryptors {
+    inner: StreamDecryptor,
+    outer: Option<StreamDecryptor>,
+}
+
+impl ChunkDecryptors {
+    fn open_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        let chunk = match self.outer.as_mut() {
+            Some(outer) => outer.decrypt_next(chunk).map_err(|_| Error::Password)?,
+            None => chunk.to_vec(),
+        };
+        self.inner
+            .decrypt_next(chunk.as_slice())
+            .map_err(|_| Error::SecretKey)
+    }
+
+    fn open_last(self, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        let chunk = match self.outer {
+            Some(outer) => outer.decrypt_last(chunk).map_err(|_| Error::Password)?,
+            None => chunk.to_vec(),
+        };
+        self.inner
+            .decrypt_last(chunk.as_slice())
+            .map_err(|_| Error::SecretKey)
+    }
+}
+
+impl ChunkedOpener {
+    /// Creates an opener. `envelope` must match the options the message was
+    /// sealed with.
+    #[must_use]
+    pub fn new(key: &Secret<Key>, password: Option<SecretVec<u8>>, envelope: Envelope) -> Self {
+        Self {
+            has_password: password.is_some(),
+            envelope,
+            pending: Some((Zeroizing::new(Key(key.expose_secret().0)), password)),
+            decryptors: None,
+            buffer: vec![],
+        }
+    }
+
+    /// Whether the next call to `update` may derive a key from the password,
+    /// which can take a while.
+    #[must_use]
+    pub const fn will_derive_key(&self) -> bool {
+        self.has_password && self.decryptors.is_none()
+    }
+
+    const fn header_size(&self, cipher: Cipher) -> usize {
+        let id_size = if self.envelope.algorithm_id { 1 } else { 0 };
+        let nonce_size = id_size + cipher.stream_nonce_size();
+        match (self.has_password, self.envelope.recorded_kdf) {
+            (true, true) => nonce_size + Salt::SIZE + KdfParams::SIZE,
+            (true, false) => nonce_size + Salt::SIZE,
+            (false, _) => nonce_size,
+        }
+    }
+
+    const fn sealed_chunk_size(&self) -> usize {
+        if self.has_password {
+            CHUNK_SIZE + TAG_SIZE * 2
+        } else {
+            CHUNK_SIZE + TAG_SIZE
+        }
+    }
+
+    /// Provides more of the sealed message, returning any newly opened data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a decryption failure or if there was a
+    /// problem deriving a secret key from the password.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the decryptors weren't set up after reading the
+    /// header, which `read_header` always does.
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.buffer.extend_from_slice(data);
+        if !self.read_header()? {
+            return Ok(vec![]);
+        }
+
+        let sealed_chunk_size = self.sealed_chunk_size();
+        let decryptors = self.decryptors.as_mut().expect("header to have been read");
+        let mut opened = Vec::with_capacity(self.buffer.len());
+        let mut consumed = 0;
+        // The last chunk must be opened differently, so a full chunk is only
+        // opened once we know more data follows it.
+        while self.buffer.len() - consumed > sealed_chunk_size {
+            let chunk = &self.buffer[consumed..consumed + sealed_chunk_size];
+            opened.extend_from_slice(&Zeroizing::new(decryptors.open_next(chunk)?));
+            consumed += sealed_chunk_size;
+        }
+        self.buffer.drain(..consumed);
+        Ok(opened)
+    }
+
+    /// Opens the rest of the sealed message. This must be called once all of
+    /// the sealed message has been provided, as it verifies that the message
+    /// wasn't truncated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there was a decryption failure, if there was a
+    /// problem deriving a secret key from the password, or if the sealed
+    /// message was truncated.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the decryptors weren't set up after reading the
+    /// header, which `read_header` always does.
+    pub fn finish(mut self) -> Result<Vec<u8>, Error> {
+        if !self.read_header()? {
+            return Err(Error::Encryption);
+        }
+
+        let decryptors = self.decryptors.take().expect("header to have been read");
+        decryptors.open_last(&self.buffer)
+    }
+
+    /// Sets up the decryptors once the header has been received, returning
+    /// whether they're ready.
+    fn read_header(&mut self) -> Result<bool, Error> {
+        if self.decryptors.is_some() {
+            return Ok(true);
+        }
+
+        let cipher = if self.envelope.algorithm_id {
+            match self.buffer.first() {
+                Some(&id) => Cipher::from_id(id)?,
+                None => return Ok(false),
+            }
+        } else {
+            Cipher::default()
+        };
+        let header_size = self.header_size(cipher);
+        if self.buffer.len() < header_size {
+            return Ok(false);
+        }
+
+        let (key, password) = self.pending.take().expect("header to not have been read");
+        let header: Vec<u8> = self.buffer.drain(..header_size).collect();
+        let header = &header[usize::from(self.envelope.algorithm_id)..];
+        let (nonce, salt) = header.split_at(cipher.stream_nonce_size());
+
+        let outer = if let Some(password) = password {
+            let (salt, params) = salt.split_at(Salt::SIZE);
+            let params = if self.envelope.recorded_kdf {
+                KdfParams::from_bytes(params)?
+            } else {
+                KdfParams::default()
+            };
+            let mut pw_key = Zeroizing::new(Key::default());
+            params
+                .argon2()?
+                .hash_password_into(password.expose_secret(), salt, &mut pw_key)
+                .map_err(|_| Error::Kdf)?;
+            Some(StreamDecryptor::new(cipher, &pw_key, nonce))
+        } else {
+            None
+        };
+
+        self.decryptors = Some(ChunkDecryptors {
+            inner: StreamDecryptor::new(cipher, &key, nonce),
+            outer,
+        });
+        Ok(true)
+    }
+}
+
+/// A STREAM encryptor for either cipher.
+enum StreamEncryptor {
+    XChaCha20Poly1305(EncryptorBE32<XChaCha20Poly1305>),
+    Aes256Gcm(Box<EncryptorBE32<Aes256Gcm>>),
+}
+
+impl StreamEncryptor {
+    fn new(cipher: Cipher, key: &chacha20poly1305::Key, nonce: &[u8]) -> Self {
+        match cipher {
+            Cipher::XChaCha20Poly1305 => Self::XChaCha20Poly1305(EncryptorBE32::from_aead(
+                XChaCha20Poly1305::new(key),
+                GenericArray::from_slice(nonce),
+            )),
+            Cipher::Aes256Gcm => Self::Aes256Gcm(Box::new(EncryptorBE32::from_aead(
+                Aes256Gcm::new(key),
+                GenericArray::from_slice(nonce),
+            ))),
+        }
+    }
+
+    fn encrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::XChaCha20Poly1305(encryptor) => encryptor.encrypt_next(chunk),
+            Self::Aes256Gcm(encryptor) => encryptor.encrypt_next(chunk),
+        }
+        .map_err(|_| Error::Encryption)
+    }
+
+    fn encrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::XChaCha20Poly1305(encryptor) => encryptor.encrypt_last(chunk),
+            Self::Aes256Gcm(encryptor) => encryptor.encrypt_last(chunk),
+        }
+        .map_err(|_| Error::Encryption)
+    }
+}
+
+/// A STREAM decryptor for either cipher.
+enum StreamDecryptor {
+    XChaCha20Poly1305(DecryptorBE32<XChaCha20Poly1305>),
+    Aes256Gcm(Box<DecryptorBE32<Aes256Gcm>>),
+}
+
+impl StreamDecryptor {
+    fn new(cipher: Cipher, key: &chacha20poly1305::Key, nonce: &[u8]) -> Self {
+        match cipher {
+            Cipher::XChaCha20Poly1305 => Self::XChaCha20Poly1305(DecryptorBE32::from_aead(
+                XChaCha20Poly1305::new(key),
+                GenericArray::from_slice(nonce),
+            )),
+            Cipher::Aes256Gcm => Self::Aes256Gcm(Box::new(DecryptorBE32::from_aead(
+                Aes256Gcm::new(key),
+                GenericArray::from_slice(nonce),
+            ))),
+        }
+    }
+
+    fn decrypt_next(&mut self, chunk: &[u8]) -> Result<Vec<u8>, chacha20poly1305::Error> {
+        match self {
+            Self::XChaCha20Poly1305(decryptor) => decryptor.decrypt_next(chunk),
+            Self::Aes256Gcm(decryptor) => decryptor.decrypt_next(chunk),
+        }
+    }
+
+    fn decrypt_last(self, chunk: &[u8]) -> Result<Vec<u8>, chacha20poly1305::Error> {
+        match self {
+            Self::XChaCha20Poly1305(decryptor) => decryptor.decrypt_last(chunk),
+            Self::Aes256Gcm(decryptor) => decryptor.decrypt_last(chunk),
+        }
+    }
+}
+
+/// Makes room for `additional` more bytes. Messages are sealed in place, so if
+/// the buffer has to move, what it held is zeroed rather than left behind.
+fn reserve_zeroizing(buffer: &mut Vec<u8>, additional: usize) {
+    if buffer.capacity() - buffer.len() >= additional {
+        return;
+    }
+    let mut grown = Vec::with_capacity(buffer.len() + additional);
+    grown.extend_from_slice(buffer);
+    buffer.zeroize();
+    *buffer = grown;
+}
+
 #[must_use]
 fn gen_key_nonce() -> (Secret<Key>, Nonce) {
-    let mut rng = get_csrng();
+    (gen_key(), gen_nonce())
+}
+
+#[must_use]
+fn gen_key() -> Secret<Key> {
     let mut key = GenericArray::default();
-    rng.fill(key.as_mut_slice());
+    get_csrng().fill(key.as_mut_slice());
+    Secret::new(Key(key))
+}
+
+#[must_use]
+fn gen_nonce() -> Nonce {
     let mut nonce = Nonce::default();
-    rng.fill(nonce.as_mut_slice());
-    (Secret::new(Key(key)), nonce)
+    get_csrng().fill(nonce.as_mut_slice());
+    nonce
 }
 
 // Type alias; to ensure that we're consistent on what the inner impl is.
@@ -227,14 +885,10 @@ impl Nonce {
     #[must_use]
     pub fn increment(&self) -> Self {
         let mut inner = self.0;
-        inner.as_mut_slice()[0] += 1;
+        // Release builds always wrapped, so sealed messages depend on this.
+        inner.as_mut_slice()[0] = inner[0].wrapping_add(1);
         Self(inner)
     }
-
-    #[must_use]
-    pub fn from_slice(slice: &[u8]) -> Self {
-        Self(*NonceImpl::from_slice(slice))
-    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -257,31 +911,312 @@ impl AsRef<[u8]> for Salt {
 }
 
 /// Hashes an input to output a usable key.
-fn kdf(password: &SecretVec<u8>) -> Result<(Secret<Key>, Salt), argon2::Error> {
+fn kdf(password: &SecretVec<u8>, params: KdfParams) -> Result<(Secret<Key>, Salt), Error> {
     let salt = Salt::random();
-    let hasher = get_argon2();
-    let mut key = Key::default();
-    hasher.hash_password_into(password.expose_secret().as_ref(), salt.as_ref(), &mut key)?;
+    let hasher = params.argon2()?;
+    let mut key = Zeroizing::new(Key::default());
+    hasher
+        .hash_password_into(password.expose_secret().as_ref(), salt.as_ref(), &mut key)
+        .map_err(|_| Error::Kdf)?;
 
-    Ok((Secret::new(key), salt))
+    Ok((Secret::new(Key(**key)), salt))
 }
 
-/// Returns Argon2id configured as follows:
-///  - 15MiB of memory (`m`),
-///  - an iteration count of 2 (`t`),
-///  - and 2 degrees of parallelism (`p`).
-///
-/// This follows the [minimum recommended parameters suggested by OWASP][rec].
+/// The length of a content hash, in bytes.
+pub const HASH_SIZE: usize = 32;
+
+/// Hashes the plaintext of a paste, so that the paste can be verified once
+/// it's decrypted. This uses BLAKE2s, which Argon2 already depends on.
+#[derive(Default)]
+pub struct ContentHasher(Blake2s256);
+
+impl ContentHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    #[must_use]
+    pub fn finish(self) -> [u8; HASH_SIZE] {
+        self.0.finalize().into()
+    }
+}
+
+/// Hashes the entire plaintext of a paste at once.
+#[must_use]
+pub fn hash(data: &[u8]) -> [u8; HASH_SIZE] {
+    let mut hasher = ContentHasher::default();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// How expensive it is to derive a key from a password. Harder profiles are
+/// slower to open for everyone, including anyone guessing the password.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum KdfProfile {
+    /// The parameters pastes have always been sealed with. These aren't
+    /// recorded in the sealed message, so older clients can still open it.
+    #[default]
+    Interactive,
+    Moderate,
+    Paranoid,
+}
+
+impl KdfProfile {
+    #[must_use]
+    pub const fn variants() -> &'static [&'static str] {
+        &["interactive", "moderate", "paranoid"]
+    }
+
+    #[must_use]
+    pub const fn params(self) -> KdfParams {
+        match self {
+            Self::Interactive => KdfParams::INTERACTIVE,
+            Self::Moderate => KdfParams {
+                m_cost: 64 * 1024, // 64 MiB
+                t_cost: 3,
+                p_cost: 4,
+            },
+            Self::Paranoid => KdfParams {
+                m_cost: 256 * 1024, // 256 MiB
+                t_cost: 4,
+                p_cost: 4,
+            },
+        }
+    }
+
+    /// The parameters to record in the sealed message, if any.
+    #[must_use]
+    pub const fn recorded_params(self) -> Option<KdfParams> {
+        match self {
+            Self::Interactive => None,
+            _ => Some(self.params()),
+        }
+    }
+}
+
+impl FromStr for KdfProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interactive" => Ok(Self::Interactive),
+            "moderate" => Ok(Self::Moderate),
+            "paranoid" => Ok(Self::Paranoid),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+impl Display for KdfProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Interactive => "interactive",
+            Self::Moderate => "moderate",
+            Self::Paranoid => "paranoid",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Parameters for Argon2id, which is used to derive a key from a password.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KdfParams {
+    /// Memory cost, in KiB (`m`).
+    pub m_cost: u32,
+    /// Iteration count (`t`).
+    pub t_cost: u32,
+    /// Degrees of parallelism (`p`).
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    /// 15MiB of memory, an iteration count of 2, and 2 degrees of parallelism.
+    ///
+    /// This follows the [minimum recommended parameters suggested by OWASP][rec].
+    ///
+    /// [rec]: https://link.eddie.sh/vaQ6a.
+    pub const INTERACTIVE: Self = Self {
+        m_cost: 15 * 1024, // 15 MiB
+        t_cost: 2,
+        p_cost: 2,
+    };
+
+    const SIZE: usize = 12;
+
+    // Recorded parameters come from whoever sealed the message, so they're
+    // bounded to keep a malicious paste from exhausting the reader's memory.
+    const MAX_M_COST: u32 = 1024 * 1024; // 1 GiB
+    const MAX_T_COST: u32 = 16;
+    const MAX_P_COST: u32 = 16;
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..4].copy_from_slice(&self.m_cost.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.t_cost.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.p_cost.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let word = |i: usize| {
+            bytes
+                .get(i * 4..i * 4 + 4)
+                .and_then(|word| word.try_into().ok())
+                .map(u32::from_le_bytes)
+                .ok_or(Error::Kdf)
+        };
+        let params = Self {
+            m_cost: word(0)?,
+            t_cost: word(1)?,
+            p_cost: word(2)?,
+        };
+        if params.m_cost > Self::MAX_M_COST
+            || params.t_cost > Self::MAX_T_COST
+            || params.p_cost > Self::MAX_P_COST
+        {
+            return Err(Error::Kdf);
+        }
+        Ok(params)
+    }
+
+    /// Returns Argon2id configured with these parameters.
+    fn argon2(self) -> Result<Argon2<'static>, Error> {
+        let mut params = ParamsBuilder::new();
+        params
+            .m_cost(self.m_cost)
+            .t_cost(self.t_cost)
+            .p_cost(self.p_cost);
+        let params = params.build().map_err(|_| Error::Kdf)?;
+        Ok(Argon2::new(
+            argon2::Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            params,
+        ))
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::INTERACTIVE
+    }
+}
+
+/// The AEAD that a message is sealed with.
 ///
-/// [rec]: https://link.eddie.sh/vaQ6a.
-fn get_argon2() -> Argon2<'static> {
-    let mut params = ParamsBuilder::new();
-    params
-        .m_cost(15 * 1024) // 15 MiB
-        .t_cost(2)
-        .p_cost(2);
-    let params = params.build().expect("Hard coded params to work");
-    Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+/// `XChaCha20Poly1305` is the default, and the only cipher older clients can
+/// open. AES-256-GCM is faster on CPUs with AES instructions. Its 12 byte nonce
+/// is too short to pick randomly for many messages, but every paste has its own
+/// key, which is only reused when the paste is updated.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Cipher {
+    #[default]
+    XChaCha20Poly1305,
+    Aes256Gcm,
+}
+
+impl Cipher {
+    #[must_use]
+    pub const fn variants() -> &'static [&'static str] {
+        &["xchacha20poly1305", "aes256gcm", "auto"]
+    }
+
+    /// The faster cipher on this machine, going by the `cipher` benchmarks.
+    /// AES-256-GCM is only faster with AES-NI, which wasm can't use.
+    #[must_use]
+    pub fn preferred() -> Self {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if std::is_x86_feature_detected!("aes") && std::is_x86_feature_detected!("pclmulqdq") {
+            return Self::Aes256Gcm;
+        }
+        Self::XChaCha20Poly1305
+    }
+
+    const fn id(self) -> u8 {
+        match self {
+            Self::XChaCha20Poly1305 => 0,
+            Self::Aes256Gcm => 1,
+        }
+    }
+
+    const fn from_id(id: u8) -> Result<Self, Error> {
+        match id {
+            0 => Ok(Self::XChaCha20Poly1305),
+            1 => Ok(Self::Aes256Gcm),
+            _ => Err(Error::Cipher),
+        }
+    }
+
+    const fn nonce_size(self) -> usize {
+        match self {
+            Self::XChaCha20Poly1305 => Nonce::SIZE,
+            Self::Aes256Gcm => <Aes256Gcm as AeadCore>::NonceSize::USIZE,
+        }
+    }
+
+    /// The STREAM construction reserves 5 bytes of the nonce for the chunk
+    /// counter and last chunk flag.
+    const fn stream_nonce_size(self) -> usize {
+        self.nonce_size() - 5
+    }
+
+    fn encrypt_in_place(
+        self,
+        key: &chacha20poly1305::Key,
+        nonce: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), chacha20poly1305::Error> {
+        match self {
+            Self::XChaCha20Poly1305 => XChaCha20Poly1305::new(key).encrypt_in_place(
+                GenericArray::from_slice(nonce),
+                &[],
+                buffer,
+            ),
+            Self::Aes256Gcm => {
+                Aes256Gcm::new(key).encrypt_in_place(GenericArray::from_slice(nonce), &[], buffer)
+            }
+        }
+    }
+
+    fn decrypt_in_place(
+        self,
+        key: &chacha20poly1305::Key,
+        nonce: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), chacha20poly1305::Error> {
+        match self {
+            Self::XChaCha20Poly1305 => XChaCha20Poly1305::new(key).decrypt_in_place(
+                GenericArray::from_slice(nonce),
+                &[],
+                buffer,
+            ),
+            Self::Aes256Gcm => {
+                Aes256Gcm::new(key).decrypt_in_place(GenericArray::from_slice(nonce), &[], buffer)
+            }
+        }
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xchacha20poly1305" => Ok(Self::XChaCha20Poly1305),
+            "aes256gcm" => Ok(Self::Aes256Gcm),
+            "auto" => Ok(Self::preferred()),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+impl Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::XChaCha20Poly1305 => "xchacha20poly1305",
+            Self::Aes256Gcm => "aes256gcm",
+        };
+        f.write_str(name)
+    }
 }
 
 /// Fetches a cryptographically secure random number generator. This indirection
@@ -295,9 +1230,30 @@ pub fn get_csrng() -> impl CryptoRng + Rng {
 
 #[cfg(test)]
 mod test {
+    use super::open_chunked_in_place;
+    use super::open_chunked_into_secret;
     use super::open_in_place;
+    use super::open_in_place_with_progress;
+    use super::open_into_secret;
+    use super::seal_chunked_in_place;
+    use super::seal_chunked_in_place_with_key;
     use super::seal_in_place;
+    use super::seal_in_place_with_key;
+    use super::ChunkedOpener;
+    use super::ChunkedSealer;
+    use super::Cipher;
+    use super::Envelope;
+    use super::Error;
+    use super::KdfParams;
+    use super::KdfProfile;
+    use super::Key;
+    use super::Nonce;
+    use super::OpenStage;
+    use super::SealOptions;
+    use super::CHUNK_SIZE;
+    use super::{hash, ContentHasher};
     use crate::crypto::SecretVec;
+    use secrecy::ExposeSecret;
 
     macro_rules! test_encryption {
         ($($name:ident, $content:expr, $password:expr),*) => {
@@ -308,7 +1264,7 @@ mod test {
                     let n: Vec<u8> = $content;
                     let key = seal_in_place(&mut m, $password).unwrap();
                     assert_ne!(m, n);
-                    assert!(open_in_place(&mut m, &key, $password).is_ok());
+                    assert!(open_in_place(&mut m, &key, $password, Envelope::default()).is_ok());
                     assert_eq!(m, n);
                 }
             )*
@@ -331,4 +1287,294 @@ mod test {
         vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
         Some(SecretVec::from(b"password".to_vec()))
     );
+
+    #[test]
+    fn reseal_with_same_key() {
+        let n = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        let mut m = n.clone();
+        let key = seal_in_place(&mut m, None).unwrap();
+        let mut resealed = n.clone();
+        seal_in_place_with_key(&mut resealed, &key, None, SealOptions::default()).unwrap();
+        assert_ne!(m, resealed);
+        assert!(open_in_place(&mut resealed, &key, None, Envelope::default()).is_ok());
+        assert_eq!(resealed, n);
+    }
+
+    #[test]
+    fn open_reports_stages() {
+        let mut m = vec![0, 1, 2, 3];
+        let key = seal_in_place(&mut m, None).unwrap();
+        let mut stages = vec![];
+        open_in_place_with_progress(&mut m, &key, None, Envelope::default(), |stage| {
+            stages.push(stage);
+        })
+        .unwrap();
+        assert_eq!(stages, [OpenStage::Decrypting]);
+
+        let password = || Some(SecretVec::from(b"password".to_vec()));
+        let key = seal_in_place(&mut m, password()).unwrap();
+        let mut stages = vec![];
+        open_in_place_with_progress(&mut m, &key, password(), Envelope::default(), |stage| {
+            stages.push(stage);
+        })
+        .unwrap();
+        assert_eq!(stages, [OpenStage::DerivingKey, OpenStage::Decrypting]);
+    }
+
+    #[test]
+    fn truncated() {
+        let key = Key::random_secret();
+        for len in [0, 1, 23] {
+            assert!(matches!(
+                open_in_place(&mut vec![0; len], &key, None, Envelope::default()),
+                Err(Error::Encryption)
+            ));
+        }
+        let password = Some(SecretVec::from(b"password".to_vec()));
+        assert!(matches!(
+            open_in_place(&mut vec![0; 39], &key, password, Envelope::default()),
+            Err(Error::Encryption)
+        ));
+    }
+
+    #[test]
+    fn open_into_secret_round_trip() {
+        let n: Vec<u8> = (0..CHUNK_SIZE + 7).map(|i| i as u8).collect();
+        let password = || Some(SecretVec::from(b"password".to_vec()));
+
+        let mut m = n.clone();
+        let key = seal_in_place(&mut m, password()).unwrap();
+        let opened = open_into_secret(m, &key, password(), Envelope::default()).unwrap();
+        assert_eq!(*opened.expose_secret(), n);
+
+        let mut m = n.clone();
+        let key = seal_chunked_in_place(&mut m, password()).unwrap();
+        let opened = open_chunked_into_secret(m, &key, password(), Envelope::default()).unwrap();
+        assert_eq!(*opened.expose_secret(), n);
+    }
+
+    #[test]
+    fn seals_without_spare_capacity() {
+        let n = vec![7; 100];
+        let mut m = n.clone();
+        m.shrink_to_fit();
+        let key = seal_in_place(&mut m, None).unwrap();
+        assert!(open_in_place(&mut m, &key, None, Envelope::default()).is_ok());
+        assert_eq!(m, n);
+    }
+
+    // Cheaper than any profile, to keep the tests quick.
+    const TEST_KDF: KdfParams = KdfParams {
+        m_cost: 8 * 1024,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn recorded_kdf_round_trip() {
+        let n: Vec<u8> = (0..CHUNK_SIZE + 7).map(|i| i as u8).collect();
+        let password = || Some(SecretVec::from(b"password".to_vec()));
+        let key = Key::random_secret();
+        let options = SealOptions {
+            kdf: Some(TEST_KDF),
+            ..SealOptions::default()
+        };
+        let envelope = options.envelope(true);
+        assert!(envelope.recorded_kdf);
+
+        let mut m = n.clone();
+        seal_in_place_with_key(&mut m, &key, password(), options).unwrap();
+        assert!(open_in_place(&mut m.clone(), &key, password(), Envelope::default()).is_err());
+        assert!(open_in_place(&mut m, &key, password(), envelope).is_ok());
+        assert_eq!(m, n);
+
+        let mut m = n.clone();
+        seal_chunked_in_place_with_key(&mut m, &key, password(), options).unwrap();
+        assert!(
+            open_chunked_in_place(&mut m.clone(), &key, password(), Envelope::default()).is_err()
+        );
+        assert!(open_chunked_in_place(&mut m, &key, password(), envelope).is_ok());
+        assert_eq!(m, n);
+    }
+
+    #[test]
+    fn aes_round_trip() {
+        let n: Vec<u8> = (0..CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+        let key = Key::random_secret();
+        let options = SealOptions {
+            kdf: Some(TEST_KDF),
+            cipher: Some(Cipher::Aes256Gcm),
+        };
+
+        for has_password in [false, true] {
+            let pw = || has_password.then(|| SecretVec::from(b"password".to_vec()));
+            let envelope = options.envelope(has_password);
+            assert!(envelope.algorithm_id);
+
+            let mut m = n.clone();
+            seal_in_place_with_key(&mut m, &key, pw(), options).unwrap();
+            assert!(open_in_place(&mut m.clone(), &key, pw(), Envelope::default()).is_err());
+            assert!(open_in_place(&mut m, &key, pw(), envelope).is_ok());
+            assert_eq!(m, n);
+
+            let mut m = n.clone();
+            seal_chunked_in_place_with_key(&mut m, &key, pw(), options).unwrap();
+            let mut opener = ChunkedOpener::new(&key, pw(), envelope);
+            let mut opened = vec![];
+            for chunk in m.chunks(1000) {
+                opened.extend(opener.update(chunk).unwrap());
+            }
+            opened.extend(opener.finish().unwrap());
+            assert_eq!(opened, n);
+        }
+    }
+
+    #[test]
+    fn unknown_cipher() {
+        let key = Key::random_secret();
+        let envelope = Envelope {
+            algorithm_id: true,
+            ..Envelope::default()
+        };
+        let mut m = vec![0, 1, 2, 3];
+        seal_in_place_with_key(&mut m, &key, None, SealOptions::default()).unwrap();
+        m.push(0xFF);
+        assert!(matches!(
+            open_in_place(&mut m, &key, None, envelope),
+            Err(Error::Cipher)
+        ));
+
+        let mut m = vec![0, 1, 2, 3];
+        seal_chunked_in_place_with_key(&mut m, &key, None, SealOptions::default()).unwrap();
+        m.insert(0, 0xFF);
+        assert!(matches!(
+            open_chunked_in_place(&mut m, &key, None, envelope),
+            Err(Error::Cipher)
+        ));
+    }
+
+    #[test]
+    fn recorded_kdf_is_bounded() {
+        let huge = KdfParams {
+            m_cost: u32::MAX,
+            ..TEST_KDF
+        };
+        assert!(matches!(
+            KdfParams::from_bytes(&huge.to_bytes()),
+            Err(Error::Kdf)
+        ));
+        assert_eq!(
+            KdfParams::from_bytes(&TEST_KDF.to_bytes()).unwrap(),
+            TEST_KDF
+        );
+        for profile in KdfProfile::variants() {
+            let params = profile.parse::<KdfProfile>().unwrap().params();
+            assert_eq!(KdfParams::from_bytes(&params.to_bytes()).unwrap(), params);
+        }
+    }
+
+    #[test]
+    fn nonce_increment_wraps() {
+        let mut nonce = Nonce::default();
+        nonce.fill(0xFF);
+        assert_eq!(nonce.increment()[0], 0);
+    }
+
+    macro_rules! test_chunked_encryption {
+        ($($name:ident, $len:expr, $password:expr),*) => {
+            $(
+                #[test]
+                fn $name() {
+                    let n: Vec<u8> = (0..$len).map(|i| i as u8).collect();
+                    let mut m = n.clone();
+                    let key = seal_chunked_in_place(&mut m, $password).unwrap();
+                    assert_ne!(m, n);
+                    assert!(open_chunked_in_place(&mut m, &key, $password, Envelope::default()).is_ok());
+                    assert_eq!(m, n);
+                }
+            )*
+        };
+    }
+
+    test_chunked_encryption!(chunked_empty, 0, None);
+    test_chunked_encryption!(chunked_normal, 16, None);
+    test_chunked_encryption!(chunked_exact_chunk, CHUNK_SIZE, None);
+    test_chunked_encryption!(chunked_many_chunks, CHUNK_SIZE * 3 + 7, None);
+    test_chunked_encryption!(
+        chunked_empty_password,
+        0,
+        Some(SecretVec::from(b"password".to_vec()))
+    );
+    test_chunked_encryption!(
+        chunked_many_chunks_password,
+        CHUNK_SIZE * 2 + 7,
+        Some(SecretVec::from(b"password".to_vec()))
+    );
+
+    #[test]
+    fn chunked_incremental() {
+        let n: Vec<u8> = (0..CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+        let mut m = n.clone();
+        let key = seal_chunked_in_place(&mut m, None).unwrap();
+
+        let mut opener = ChunkedOpener::new(&key, None, Envelope::default());
+        let mut opened = vec![];
+        for chunk in m.chunks(1000) {
+            opened.extend(opener.update(chunk).unwrap());
+        }
+        opened.extend(opener.finish().unwrap());
+        assert_eq!(opened, n);
+    }
+
+    #[test]
+    fn chunked_incremental_seal() {
+        let n: Vec<u8> = (0..CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+        let key = Key::random_secret();
+
+        let (mut sealer, mut m) = ChunkedSealer::new(&key, None, SealOptions::default()).unwrap();
+        m.extend(sealer.seal_next(&n[..CHUNK_SIZE]).unwrap());
+        m.extend(sealer.seal_next(&n[CHUNK_SIZE..CHUNK_SIZE * 2]).unwrap());
+        m.extend(sealer.seal_last(&n[CHUNK_SIZE * 2..]).unwrap());
+
+        assert!(open_chunked_in_place(&mut m, &key, None, Envelope::default()).is_ok());
+        assert_eq!(m, n);
+    }
+
+    #[test]
+    fn chunked_truncated() {
+        let mut m: Vec<u8> = (0..CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+        let key = seal_chunked_in_place(&mut m, None).unwrap();
+        m.truncate(m.len() - 7 - 16);
+        assert!(matches!(
+            open_chunked_in_place(&mut m, &key, None, Envelope::default()),
+            Err(Error::SecretKey)
+        ));
+    }
+
+    #[test]
+    fn chunked_wrong_password() {
+        let mut m = vec![0, 1, 2, 3];
+        let key =
+            seal_chunked_in_place(&mut m, Some(SecretVec::from(b"password".to_vec()))).unwrap();
+        assert!(matches!(
+            open_chunked_in_place(
+                &mut m,
+                &key,
+                Some(SecretVec::from(b"hunter2".to_vec())),
+                Envelope::default()
+            ),
+            Err(Error::Password)
+        ));
+    }
+
+    #[test]
+    fn incremental_hash_matches() {
+        let n: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        let mut hasher = ContentHasher::default();
+        for chunk in n.chunks(999) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish(), hash(&n));
+        assert_ne!(hash(&n), hash(&n[1..]));
+    }
 }
@@ -1,10 +1,18 @@
+use crate::base64;
+use crate::crypto::{Envelope, HASH_SIZE};
 use crate::secrecy::{ExposeSecret, SecretString};
 
+#[allow(clippy::struct_excessive_bools)]
 pub struct Builder {
     decryption_key: SecretString,
     needs_password: bool,
     file_name: Option<String>,
     language: Option<String>,
+    chunked: bool,
+    recorded_kdf: bool,
+    algorithm_id: bool,
+    private: bool,
+    hash: Option<[u8; HASH_SIZE]>,
 }
 
 impl Builder {
@@ -15,6 +23,11 @@ impl Builder {
             needs_password: false,
             file_name: None,
             language: None,
+            chunked: false,
+            recorded_kdf: false,
+            algorithm_id: false,
+            private: false,
+            hash: None,
         }
     }
 
@@ -40,9 +53,62 @@ impl Builder {
         self
     }
 
+    /// Marks the paste as sealed with `seal_chunked_in_place`.
+    #[must_use]
+    pub const fn chunked(mut self) -> Self {
+        self.chunked = true;
+        self
+    }
+
+    /// Marks the paste as sealed with recorded Argon2 parameters.
+    #[must_use]
+    pub const fn recorded_kdf(mut self) -> Self {
+        self.recorded_kdf = true;
+        self
+    }
+
+    /// Marks the paste as sealed with the id of its cipher.
+    #[must_use]
+    pub const fn algorithm_id(mut self) -> Self {
+        self.algorithm_id = true;
+        self
+    }
+
+    /// Marks the paste as sealed in the provided envelope.
+    #[must_use]
+    pub const fn envelope(mut self, envelope: Envelope) -> Self {
+        self.recorded_kdf |= envelope.recorded_kdf;
+        self.algorithm_id |= envelope.algorithm_id;
+        self
+    }
+
+    /// Asks the web frontend not to cache the decrypted paste in browser
+    /// storage.
+    #[must_use]
+    pub const fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    /// Adds the hash of the plaintext, so that the paste can be verified once
+    /// it's decrypted.
+    #[must_use]
+    pub const fn hash(mut self, hash: [u8; HASH_SIZE]) -> Self {
+        self.hash = Some(hash);
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> SecretString {
-        if !self.needs_password && self.file_name.is_none() && self.language.is_none() {
+        if !self.needs_password
+            && self.file_name.is_none()
+            && self.language.is_none()
+            && !self.chunked
+            && !self.recorded_kdf
+            && !self.algorithm_id
+            && !self.private
+            && self.hash.is_none()
+        {
             return self.decryption_key;
         }
         let mut args = String::new();
@@ -57,6 +123,22 @@ impl Builder {
             args.push_str("!lang:");
             args.push_str(&language);
         }
+        if self.chunked {
+            args.push_str("!chunked");
+        }
+        if self.recorded_kdf {
+            args.push_str("!kdf");
+        }
+        if self.algorithm_id {
+            args.push_str("!alg");
+        }
+        if self.private {
+            args.push_str("!private");
+        }
+        if let Some(hash) = self.hash {
+            args.push_str("!h:");
+            args.push_str(&base64::encode(hash));
+        }
         SecretString::new(format!(
             "key:{}{}",
             self.decryption_key.expose_secret(),
@@ -64,3 +146,141 @@ impl Builder {
         ))
     }
 }
+
+/// Splits the decryption key out of a fragment.
+///
+/// This lets a link to a paste and its key be shared separately. The rest of
+/// the fragment starts with `!` so that it isn't mistaken for a bare key, and
+/// is empty if the fragment was only the key. The hash of the plaintext goes
+/// with the key, as anyone with the hash could check a guess at what the paste
+/// says. The key is then `key:<key>!h:<hash>` instead of a bare key.
+#[must_use]
+pub fn split_key(fragment: &str) -> (Option<SecretString>, String) {
+    let (key, hints) = strip_key(fragment);
+    let Some(key) = key else {
+        return (None, hints);
+    };
+
+    let mut hash = None;
+    let mut rest = String::new();
+    for arg in hints.split('!').filter(|arg| !arg.is_empty()) {
+        if arg.starts_with("h:") {
+            hash = Some(arg);
+        } else {
+            rest.push('!');
+            rest.push_str(arg);
+        }
+    }
+    let key = match hash {
+        Some(hash) => SecretString::new(format!("key:{}!{hash}", key.expose_secret())),
+        None => key,
+    };
+    (Some(key), rest)
+}
+
+/// Removes the decryption key from a fragment, keeping every hint.
+///
+/// Unlike [`split_key`], the hash of the plaintext stays in the hints, so this
+/// is only for fragments that are kept secret anyways, such as those in a
+/// manifest.
+#[must_use]
+pub fn strip_key(fragment: &str) -> (Option<SecretString>, String) {
+    // Fragments without any hints are only the key.
+    if !fragment.contains("key:") {
+        return if fragment.is_empty() || fragment.starts_with('!') {
+            (None, fragment.to_owned())
+        } else {
+            (Some(SecretString::new(fragment.to_owned())), String::new())
+        };
+    }
+
+    let mut key = None;
+    let mut rest = String::new();
+    for arg in fragment.split('!').filter(|arg| !arg.is_empty()) {
+        if let Some(value) = arg.strip_prefix("key:") {
+            key = Some(SecretString::new(value.to_owned()));
+        } else {
+            rest.push('!');
+            rest.push_str(arg);
+        }
+    }
+    (key, rest)
+}
+
+/// Adds a decryption key back to the rest of a fragment from [`split_key`] or
+/// [`strip_key`]. The key may carry the hash of the plaintext, as it does when
+/// it's from [`split_key`].
+#[must_use]
+pub fn join_key(key: &SecretString, rest: &str) -> SecretString {
+    let key = key.expose_secret();
+    if key.starts_with("key:") {
+        SecretString::new(format!("{key}{rest}"))
+    } else if rest.is_empty() {
+        SecretString::new(key.clone())
+    } else {
+        SecretString::new(format!("key:{key}{rest}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::secrecy::ExposeSecret;
+
+    use super::{join_key, split_key, strip_key};
+    use crate::PartialParsedUrl;
+
+    const KEY: &str = "ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=";
+
+    #[test]
+    fn splits_keys() {
+        for (fragment, rest) in [
+            (format!("key:{KEY}!pw!chunked"), "!pw!chunked"),
+            (format!("pw!key:{KEY}"), "!pw"),
+            (format!("key:{KEY}"), ""),
+            (KEY.to_owned(), ""),
+        ] {
+            let (key, split) = split_key(&fragment);
+            let key = key.unwrap();
+            assert_eq!(key.expose_secret(), KEY);
+            assert_eq!(split, rest);
+            assert!(split_key(&split).0.is_none());
+            assert_eq!(
+                join_key(&key, &split)
+                    .expose_secret()
+                    .parse::<PartialParsedUrl>(),
+                fragment.parse()
+            );
+        }
+    }
+
+    #[test]
+    fn splits_hashes_off_with_the_key() {
+        const HASH: &str = "pS24x2gvA0FeWia0-S-e4dFCmUm2uoI_at5F8jqREv8=";
+        for (fragment, rest) in [
+            (
+                format!("key:{KEY}!pw!h:{HASH}!name:a.txt"),
+                "!pw!name:a.txt",
+            ),
+            (format!("h:{HASH}!key:{KEY}"), ""),
+        ] {
+            let (key, split) = split_key(&fragment);
+            let key = key.unwrap();
+            assert_eq!(split, rest);
+            assert!(!split.contains("h:"));
+            assert_eq!(key.expose_secret(), &format!("key:{KEY}!h:{HASH}"));
+            assert_eq!(
+                join_key(&key, &split)
+                    .expose_secret()
+                    .parse::<PartialParsedUrl>(),
+                fragment.parse()
+            );
+        }
+
+        // Manifests keep the hashes of their members with the rest of their
+        // hints.
+        let fragment = format!("key:{KEY}!h:{HASH}");
+        let (key, hints) = strip_key(&fragment);
+        assert_eq!(key.unwrap().expose_secret(), KEY);
+        assert_eq!(hints, format!("!h:{HASH}"));
+    }
+}
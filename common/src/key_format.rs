@@ -0,0 +1,224 @@
+// Copyright (c) 2021 Edward Shen
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Encodings for decryption keys, other than the Base64 used in URLs. These are
+//! easier to read over the phone or write on paper, and both catch typos with a
+//! checksum.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use bech32::{FromBase32, ToBase32, Variant};
+use bip39::Mnemonic;
+use secrecy::zeroize::Zeroizing;
+use secrecy::{ExposeSecret, Secret, SecretString};
+
+use crate::base64;
+use crate::crypto::Key;
+
+/// The human-readable part of Bech32 keys.
+pub const BECH32_HRP: &str = "ou";
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error("The key isn't valid Base64.")]
+    Base64,
+    #[error("The key isn't a valid list of words. Check for typos or missing words.")]
+    Words,
+    #[error("The key isn't valid Bech32. Check for typos.")]
+    Bech32,
+    #[error("The key is the wrong length.")]
+    Length,
+}
+
+/// How a decryption key is written out.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum KeyFormat {
+    /// URL-safe Base64, as used in the fragment of paste URLs.
+    #[default]
+    Base64,
+    /// 24 words from the BIP39 English word list, the last of which is partly
+    /// a checksum.
+    Words,
+    /// Bech32m, with `ou` as the human-readable part.
+    Bech32,
+}
+
+impl KeyFormat {
+    #[must_use]
+    pub const fn variants() -> &'static [&'static str] {
+        &["base64", "words", "bech32"]
+    }
+
+    /// Writes out a key in this format.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn encode(self, key: &Secret<Key>) -> SecretString {
+        let key = key.expose_secret().as_ref().as_slice();
+        SecretString::new(match self {
+            Self::Base64 => base64::encode(key),
+            Self::Words => Mnemonic::from_entropy(key)
+                .expect("keys to be a valid entropy length")
+                .to_string(),
+            Self::Bech32 => bech32::encode(BECH32_HRP, key.to_base32(), Variant::Bech32m)
+                .expect("the human-readable part to be valid"),
+        })
+    }
+
+    /// Reads a key written out in this format. Words are case insensitive and
+    /// may be separated by any whitespace.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key isn't valid in this format, such as if its
+    /// checksum doesn't match.
+    pub fn decode(self, encoded: &str) -> Result<Secret<Key>, Error> {
+        let encoded = encoded.trim();
+        // `new_secret` zeroes the decoded key once it's been copied.
+        let key = match self {
+            Self::Base64 => base64::decode(encoded).map_err(|_| Error::Base64)?,
+            Self::Words => {
+                let words = Zeroizing::new(encoded.to_lowercase());
+                Mnemonic::parse_normalized(&words)
+                    .map_err(|_| Error::Words)?
+                    .to_entropy()
+            }
+            Self::Bech32 => match bech32::decode(encoded) {
+                Ok((hrp, data, Variant::Bech32m)) if hrp == BECH32_HRP => {
+                    Vec::<u8>::from_base32(&data).map_err(|_| Error::Bech32)?
+                }
+                _ => return Err(Error::Bech32),
+            },
+        };
+        Key::new_secret(key).ok_or(Error::Length)
+    }
+
+    /// Guesses the format of a key. Only word lists have spaces, and Bech32
+    /// keys always start with `ou1`, which Base64 keys are too short to be
+    /// mistaken for.
+    #[must_use]
+    pub fn detect(encoded: &str) -> Self {
+        let encoded = encoded.trim();
+        if encoded.contains(char::is_whitespace) {
+            Self::Words
+        } else if encoded.len() > 44
+            && encoded
+                .to_ascii_lowercase()
+                .starts_with(&format!("{BECH32_HRP}1"))
+        {
+            Self::Bech32
+        } else {
+            Self::Base64
+        }
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base64" => Ok(Self::Base64),
+            "words" => Ok(Self::Words),
+            "bech32" => Ok(Self::Bech32),
+            _ => Err(format!("Unknown key format: {s}")),
+        }
+    }
+}
+
+impl Display for KeyFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Base64 => "base64",
+            Self::Words => "words",
+            Self::Bech32 => "bech32",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{Error, KeyFormat};
+    use crate::crypto::Key;
+
+    fn zero_key() -> Secret<Key> {
+        Key::new_secret(vec![0; 32]).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let key = Key::random_secret();
+        for format in [KeyFormat::Base64, KeyFormat::Words, KeyFormat::Bech32] {
+            let encoded = format.encode(&key);
+            assert_eq!(KeyFormat::detect(encoded.expose_secret()), format);
+            let decoded = format.decode(encoded.expose_secret()).unwrap();
+            assert!(decoded.expose_secret() == key.expose_secret());
+        }
+    }
+
+    #[test]
+    fn known_encodings() {
+        let words = KeyFormat::Words.encode(&zero_key());
+        assert_eq!(
+            words.expose_secret(),
+            &format!("{}art", "abandon ".repeat(23))
+        );
+
+        let bech32 = KeyFormat::Bech32.encode(&zero_key());
+        assert_eq!(
+            bech32.expose_secret(),
+            "ou1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqfg5mf0"
+        );
+    }
+
+    #[test]
+    fn lenient_input() {
+        let words = KeyFormat::Words.encode(&zero_key());
+        let shouted = format!(
+            "  {}\n",
+            words.expose_secret().to_uppercase().replace(' ', "\n")
+        );
+        let decoded = KeyFormat::Words.decode(&shouted).unwrap();
+        assert!(decoded.expose_secret() == zero_key().expose_secret());
+
+        let bech32 = KeyFormat::Bech32.encode(&zero_key());
+        let decoded = KeyFormat::Bech32
+            .decode(&bech32.expose_secret().to_uppercase())
+            .unwrap();
+        assert!(decoded.expose_secret() == zero_key().expose_secret());
+    }
+
+    #[test]
+    fn rejects_typos() {
+        let words = KeyFormat::Words.encode(&zero_key());
+        let swapped = words.expose_secret().replacen("abandon", "ability", 1);
+        assert_eq!(KeyFormat::Words.decode(&swapped).err(), Some(Error::Words));
+        let missing = words.expose_secret().replacen("abandon ", "", 1);
+        assert_eq!(KeyFormat::Words.decode(&missing).err(), Some(Error::Words));
+
+        let bech32 = KeyFormat::Bech32.encode(&zero_key());
+        let typo = bech32.expose_secret().replacen('q', "p", 1);
+        assert_eq!(KeyFormat::Bech32.decode(&typo).err(), Some(Error::Bech32));
+
+        assert_eq!(KeyFormat::Base64.decode("AAAA").err(), Some(Error::Length));
+    }
+}
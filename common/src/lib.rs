@@ -37,26 +37,83 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 pub use url::Url;
 
-use crate::crypto::Key;
+use crate::crypto::{Envelope, Key, HASH_SIZE};
 
 pub mod base64;
 pub mod crypto;
 pub mod fragment;
+pub mod key_format;
+pub mod link;
+pub mod manifest;
 
 pub const API_ENDPOINT: &str = "/api";
 
+/// Pastes must be smaller than this many bytes on any server; this is a
+/// soft-limit of `RocksDB`. Servers may accept less, which they advertise in
+/// [`ServerInfo::paste_size_limit`].
+pub const PASTE_SIZE_LIMIT: u64 = 3 * 1024 * 1024 * 1024;
+
+/// The limit on the size of pastes for a server whose database can't store
+/// values larger than `max_value_size` bytes.
+///
+/// Pastes are stored with their length in front of them, so they must be a
+/// little smaller than that.
+#[must_use]
+pub fn paste_size_limit(max_value_size: Option<u64>) -> u64 {
+    max_value_size.map_or(PASTE_SIZE_LIMIT, |max| {
+        PASTE_SIZE_LIMIT.min(max.saturating_sub(std::mem::size_of::<u64>() as u64))
+    })
+}
+
+#[allow(clippy::struct_excessive_bools)]
 pub struct ParsedUrl {
     pub sanitized_url: Url,
     pub decryption_key: Secret<Key>,
     pub needs_password: bool,
+    /// Whether the paste was sealed with `seal_chunked_in_place`.
+    pub chunked: bool,
+    /// Whether the Argon2 parameters are recorded in the sealed paste.
+    pub recorded_kdf: bool,
+    /// Whether the id of the cipher is recorded in the sealed paste.
+    pub algorithm_id: bool,
 }
 
+impl ParsedUrl {
+    #[must_use]
+    pub const fn envelope(&self) -> Envelope {
+        Envelope {
+            recorded_kdf: self.recorded_kdf,
+            algorithm_id: self.algorithm_id,
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Default, Debug)]
 pub struct PartialParsedUrl {
     pub decryption_key: Option<Secret<Key>>,
     pub needs_password: bool,
     pub name: Option<String>,
     pub language: Option<String>,
+    pub chunked: bool,
+    pub recorded_kdf: bool,
+    pub algorithm_id: bool,
+    /// Whether the web frontend should avoid caching the decrypted paste in
+    /// browser storage.
+    pub private: bool,
+    /// The hash of the plaintext, to verify the paste against once it's
+    /// decrypted.
+    pub hash: Option<[u8; HASH_SIZE]>,
+}
+
+impl PartialParsedUrl {
+    #[must_use]
+    pub const fn envelope(&self) -> Envelope {
+        Envelope {
+            recorded_kdf: self.recorded_kdf,
+            algorithm_id: self.algorithm_id,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -71,7 +128,13 @@ impl PartialEq for PartialParsedUrl {
             }
         };
 
-        decryption_key_matches && self.needs_password == other.needs_password
+        decryption_key_matches
+            && self.needs_password == other.needs_password
+            && self.chunked == other.chunked
+            && self.recorded_kdf == other.recorded_kdf
+            && self.algorithm_id == other.algorithm_id
+            && self.private == other.private
+            && self.hash == other.hash
     }
 }
 
@@ -79,6 +142,8 @@ impl PartialEq for PartialParsedUrl {
 pub enum PartialParsedUrlParseError {
     #[error("A decryption key that was not valid web base64 was provided.")]
     InvalidDecryptionKey,
+    #[error("A content hash that was not valid web base64 was provided.")]
+    InvalidHash,
 }
 
 impl TryFrom<&str> for PartialParsedUrl {
@@ -88,8 +153,9 @@ impl TryFrom<&str> for PartialParsedUrl {
         // Short circuit if the fragment only contains the key.
 
         // Base64 has an interesting property that the length of an encoded text
-        // is always 4/3rds larger than the original data.
-        if !fragment.contains("key:") {
+        // is always 4/3rds larger than the original data. Fragments that start
+        // with `!` have had their key split out, and are parsed as hints.
+        if !fragment.contains("key:") && !fragment.starts_with('!') {
             let decryption_key = base64::decode(fragment)
                 .map_err(|_| PartialParsedUrlParseError::InvalidDecryptionKey)?;
             let decryption_key = Key::new_secret(decryption_key);
@@ -113,6 +179,12 @@ impl TryFrom<&str> for PartialParsedUrl {
         let mut needs_password = false;
         let mut name = None;
         let mut language = None;
+        let mut chunked = false;
+        let mut recorded_kdf = false;
+        let mut algorithm_id = false;
+        let mut private = false;
+        let mut hash = None;
+        let mut has_hints = false;
 
         for (key, value) in args {
             match (key, value) {
@@ -126,8 +198,26 @@ impl TryFrom<&str> for PartialParsedUrl {
                 }
                 ("name", Some(provided_name)) => name = Some(provided_name.to_owned()),
                 ("lang", Some(provided_lang)) => language = Some(provided_lang.to_owned()),
-                _ => (),
+                ("chunked", _) => chunked = true,
+                ("kdf", _) => recorded_kdf = true,
+                ("alg", _) => algorithm_id = true,
+                ("private", _) => private = true,
+                ("h", Some(value)) => {
+                    let value = base64::decode(value)
+                        .ok()
+                        .and_then(|value| value.try_into().ok())
+                        .ok_or(PartialParsedUrlParseError::InvalidHash)?;
+                    hash = Some(value);
+                }
+                _ => continue,
             }
+            has_hints = true;
+        }
+
+        // Fragments with their key split out must still have a hint, so that
+        // garbage isn't mistaken for one.
+        if !fragment.contains("key:") && !has_hints {
+            return Err(PartialParsedUrlParseError::InvalidDecryptionKey);
         }
 
         Ok(Self {
@@ -135,6 +225,11 @@ impl TryFrom<&str> for PartialParsedUrl {
             needs_password,
             name,
             language,
+            chunked,
+            recorded_kdf,
+            algorithm_id,
+            private,
+            hash,
         })
     }
 }
@@ -170,6 +265,9 @@ impl FromStr for ParsedUrl {
         let PartialParsedUrl {
             mut decryption_key,
             needs_password,
+            chunked,
+            recorded_kdf,
+            algorithm_id,
             ..
         } = PartialParsedUrl::try_from(fragment)?;
 
@@ -181,15 +279,63 @@ impl FromStr for ParsedUrl {
             sanitized_url: url,
             decryption_key,
             needs_password,
+            chunked,
+            recorded_kdf,
+            algorithm_id,
         })
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Expiration {
     BurnAfterReading,
     BurnAfterReadingWithDeadline(DateTime<Utc>),
     UnixTime(DateTime<Utc>),
+    /// Expires this many seconds after the paste is first read, so that a
+    /// failed download can be retried. Servers replace this with a
+    /// [`Self::UnixTime`] once the paste is read.
+    ReadWindow(u32),
+    /// A [`Self::ReadWindow`] that's deleted at the deadline if it's never
+    /// read.
+    ReadWindowWithDeadline(u32, DateTime<Utc>),
+}
+
+impl Expiration {
+    /// When the paste is deleted even if it's never read, if ever.
+    #[must_use]
+    pub const fn deadline(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::BurnAfterReading | Self::ReadWindow(_) => None,
+            Self::BurnAfterReadingWithDeadline(time)
+            | Self::UnixTime(time)
+            | Self::ReadWindowWithDeadline(_, time) => Some(*time),
+        }
+    }
+
+    /// How long the paste lasts once it's first read, if it lasts any time at
+    /// all.
+    #[must_use]
+    pub fn read_window(&self) -> Option<Duration> {
+        match self {
+            Self::ReadWindow(window) | Self::ReadWindowWithDeadline(window, _) => {
+                Some(Duration::seconds(i64::from(*window)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the paste's deadline had passed at `now`.
+    #[must_use]
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.deadline().is_some_and(|deadline| deadline < now)
+    }
+
+    /// Whether the paste's deadline has passed, in which case it must no
+    /// longer be served.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
 }
 
 // This impl is used for the CLI. We use a macro here to ensure that possible
@@ -222,6 +368,8 @@ macro_rules! expiration_from_str {
 
 expiration_from_str! {
     "read" => Self::BurnAfterReading,
+    "read+10m" => Self::ReadWindow(10 * 60),
+    "read+1h" => Self::ReadWindow(60 * 60),
     "5m" => Self::UnixTime(Utc::now() + Duration::minutes(5)),
     "10m" => Self::UnixTime(Utc::now() + Duration::minutes(10)),
     "1h" => Self::UnixTime(Utc::now() + Duration::hours(1)),
@@ -241,12 +389,35 @@ impl Display for Expiration {
                 "{}",
                 time.format("This item will expire on %A, %B %-d, %Y at %T %Z.")
             ),
+            Self::ReadWindow(window) | Self::ReadWindowWithDeadline(window, _) => write!(
+                f,
+                "This item will expire {} minutes after it's first read.",
+                window / 60
+            ),
         }
     }
 }
 
 lazy_static! {
     pub static ref EXPIRATION_HEADER_NAME: HeaderName = HeaderName::from_static("burn-after");
+    /// Header containing the secret that permits replacing a paste. This is
+    /// returned by the server on upload, and must be provided on update.
+    pub static ref OWNER_TOKEN_HEADER_NAME: HeaderName = HeaderName::from_static("owner-token");
+    /// Header describing what happened to a paste that no longer exists. This
+    /// is returned by the server alongside a `410 Gone`.
+    pub static ref TOMBSTONE_HEADER_NAME: HeaderName = HeaderName::from_static("tombstone");
+    /// Header with a URL that the server posts to once a burn after reading
+    /// paste is read, if the server allows the URL's host.
+    pub static ref NOTIFY_HEADER_NAME: HeaderName = HeaderName::from_static("burn-notify");
+    /// Header with the time a paste may first be read. This is sent on upload,
+    /// and returned by the server alongside a paste's expiration.
+    pub static ref NOT_BEFORE_HEADER_NAME: HeaderName = HeaderName::from_static("not-before");
+    /// Header with how many times a paste may be downloaded before the server
+    /// stops serving it. This is sent on upload.
+    pub static ref MAX_DOWNLOADS_HEADER_NAME: HeaderName = HeaderName::from_static("max-downloads");
+    /// Header with how many seconds a paste has left, returned by the server
+    /// alongside its expiration.
+    pub static ref PASTE_TTL_HEADER_NAME: HeaderName = HeaderName::from_static("paste-ttl");
 }
 
 impl Header for Expiration {
@@ -279,6 +450,9 @@ impl From<&Expiration> for HeaderValue {
                     Bytes::from_static(b"0")
                 }
                 Expiration::UnixTime(duration) => Bytes::from(duration.to_rfc3339()),
+                Expiration::ReadWindow(window) | Expiration::ReadWindowWithDeadline(window, _) => {
+                    Bytes::from(format!("read+{window}"))
+                }
             })
         }
     }
@@ -333,6 +507,13 @@ impl TryFrom<&str> for Expiration {
             return Ok(Self::BurnAfterReading);
         }
 
+        if let Some(window) = value.strip_prefix("read+") {
+            return window
+                .parse()
+                .map(Self::ReadWindow)
+                .map_err(|_| ParseHeaderValueError);
+        }
+
         value
             .parse::<DateTime<Utc>>()
             .map_err(|_| ParseHeaderValueError)
@@ -346,13 +527,466 @@ impl Default for Expiration {
     }
 }
 
+/// Why a paste no longer exists.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TombstoneReason {
+    /// The paste was burned after it was read.
+    Burned,
+    /// The paste was deleted by its owner.
+    Deleted,
+    /// The paste was downloaded as many times as its uploader allowed.
+    Exhausted,
+}
+
+/// What happened to a paste that no longer exists. Servers remember this for a
+/// while after a paste is burned or deleted, so that anyone else with the link
+/// can be told what happened to it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tombstone {
+    pub reason: TombstoneReason,
+    pub time: DateTime<Utc>,
+}
+
+impl Tombstone {
+    #[must_use]
+    pub fn now(reason: TombstoneReason) -> Self {
+        Self {
+            reason,
+            time: Utc::now(),
+        }
+    }
+}
+
+impl Display for Tombstone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = match self.reason {
+            TombstoneReason::Burned => "This paste was burned on %A, %B %-d, %Y at %T %Z.",
+            TombstoneReason::Deleted => {
+                "This paste was deleted by its owner on %A, %B %-d, %Y at %T %Z."
+            }
+            TombstoneReason::Exhausted => {
+                "This paste reached its download limit on %A, %B %-d, %Y at %T %Z."
+            }
+        };
+        write!(f, "{}", self.time.format(format))
+    }
+}
+
+impl From<&Tombstone> for HeaderValue {
+    fn from(tombstone: &Tombstone) -> Self {
+        let reason = match tombstone.reason {
+            TombstoneReason::Burned => "burned",
+            TombstoneReason::Deleted => "deleted",
+            TombstoneReason::Exhausted => "exhausted",
+        };
+        Self::from_str(&format!("{reason} {}", tombstone.time.to_rfc3339()))
+            .expect("tombstone to be a valid header value")
+    }
+}
+
+impl TryFrom<&str> for Tombstone {
+    type Error = ParseHeaderValueError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (reason, time) = value.split_once(' ').ok_or(ParseHeaderValueError)?;
+        let reason = match reason {
+            "burned" => TombstoneReason::Burned,
+            "deleted" => TombstoneReason::Deleted,
+            "exhausted" => TombstoneReason::Exhausted,
+            _ => return Err(ParseHeaderValueError),
+        };
+        let time = time
+            .parse::<DateTime<Utc>>()
+            .map_err(|_| ParseHeaderValueError)?;
+        Ok(Self { reason, time })
+    }
+}
+
+/// When a time-locked paste may first be read, so that its link can be shared
+/// ahead of time. Servers refuse to serve the paste before then with a
+/// `423 Locked`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotBefore(pub DateTime<Utc>);
+
+impl NotBefore {
+    /// Whether the paste could be read at `now`.
+    #[must_use]
+    pub fn has_passed_at(&self, now: DateTime<Utc>) -> bool {
+        self.0 <= now
+    }
+
+    /// Whether the paste can be read now.
+    #[must_use]
+    pub fn has_passed(&self) -> bool {
+        self.has_passed_at(Utc::now())
+    }
+}
+
+impl Display for NotBefore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.0
+                .format("This paste can't be read until %A, %B %-d, %Y at %T %Z.")
+        )
+    }
+}
+
+impl Header for NotBefore {
+    fn name() -> &'static HeaderName {
+        &NOT_BEFORE_HEADER_NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        Self: Sized,
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| Self::try_from(value).ok())
+            .ok_or_else(headers::Error::invalid)
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, container: &mut E) {
+        container.extend(std::iter::once(self.into()));
+    }
+}
+
+impl From<&NotBefore> for HeaderValue {
+    fn from(not_before: &NotBefore) -> Self {
+        Self::from_str(&not_before.0.to_rfc3339()).expect("time to be a valid header value")
+    }
+}
+
+impl TryFrom<&str> for NotBefore {
+    type Error = ParseHeaderValueError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value
+            .parse::<DateTime<Utc>>()
+            .map(Self)
+            .map_err(|_| ParseHeaderValueError)
+    }
+}
+
+/// How many seconds a paste had left when the server responded. Unlike the
+/// absolute `Expires` header, this doesn't rely on the client's clock agreeing
+/// with the server's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PasteTtl(pub u64);
+
+impl PasteTtl {
+    /// How long `expiration` has left at `now`, if it has a deadline.
+    #[must_use]
+    pub fn remaining_at(expiration: Expiration, now: DateTime<Utc>) -> Option<Self> {
+        let remaining = (expiration.deadline()? - now).num_seconds();
+        Some(Self(remaining.try_into().unwrap_or_default()))
+    }
+
+    /// How long `expiration` has left, if it has a deadline.
+    #[must_use]
+    pub fn remaining(expiration: Expiration) -> Option<Self> {
+        Self::remaining_at(expiration, Utc::now())
+    }
+
+    /// Moves the deadline of `expiration` to this long after `now`, so that it's
+    /// on the client's clock instead of the server's.
+    #[must_use]
+    pub fn rebase_at(self, expiration: Expiration, now: DateTime<Utc>) -> Expiration {
+        let deadline = now + Duration::seconds(self.0.try_into().unwrap_or(i64::MAX));
+        match expiration {
+            Expiration::UnixTime(_) => Expiration::UnixTime(deadline),
+            Expiration::BurnAfterReadingWithDeadline(_) => {
+                Expiration::BurnAfterReadingWithDeadline(deadline)
+            }
+            Expiration::ReadWindowWithDeadline(window, _) => {
+                Expiration::ReadWindowWithDeadline(window, deadline)
+            }
+            expiration => expiration,
+        }
+    }
+
+    /// Moves the deadline of `expiration` to this long from now.
+    #[must_use]
+    pub fn rebase(self, expiration: Expiration) -> Expiration {
+        self.rebase_at(expiration, Utc::now())
+    }
+}
+
+impl Header for PasteTtl {
+    fn name() -> &'static HeaderName {
+        &PASTE_TTL_HEADER_NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        Self: Sized,
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| Self::try_from(value).ok())
+            .ok_or_else(headers::Error::invalid)
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, container: &mut E) {
+        container.extend(std::iter::once(self.into()));
+    }
+}
+
+impl From<&PasteTtl> for HeaderValue {
+    fn from(ttl: &PasteTtl) -> Self {
+        Self::from(ttl.0)
+    }
+}
+
+impl TryFrom<&str> for PasteTtl {
+    type Error = ParseHeaderValueError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse().map(Self).map_err(|_| ParseHeaderValueError)
+    }
+}
+
+/// What a server accepts, as returned by `GET /api/info`. Clients can check
+/// uploads against this before spending time encrypting them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// Pastes must be smaller than this many bytes.
+    pub paste_size_limit: u64,
+    /// The values of [`Expiration::variants`] that the server accepts.
+    pub expirations: Vec<String>,
+    /// Whether uploads must say when they expire. Older servers never require
+    /// it.
+    #[serde(default)]
+    pub expiration_required: bool,
+}
+
+impl ServerInfo {
+    /// Whether the server accepts pastes of `size` bytes.
+    #[must_use]
+    pub const fn accepts_size(&self, size: u64) -> bool {
+        size < self.paste_size_limit
+    }
+}
+
+/// What a server returns for an upload that accepts `application/json`. Older
+/// servers, and uploads that don't accept JSON, get only the short code as
+/// text.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UploadResponse {
+    /// The paste's short code.
+    pub code: String,
+    /// The path of the paste, such as `/CODE`, including the path the server is
+    /// hosted under if it knows it.
+    pub path: String,
+    /// The absolute URL of the paste, if the server knows its public URL.
+    /// Clients should prefer this to building the URL themselves, which may
+    /// be wrong behind a reverse proxy.
+    pub url: Option<Url>,
+}
+
+#[cfg(test)]
+mod expiration {
+    use std::str::FromStr;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use headers::HeaderValue;
+
+    use crate::Expiration;
+
+    #[test]
+    fn deadlines_expire() {
+        let now = Utc.with_ymd_and_hms(2022, 1, 2, 3, 4, 5).unwrap();
+        let past = now - Duration::seconds(1);
+        let future = now + Duration::seconds(1);
+        assert!(Expiration::UnixTime(past).is_expired_at(now));
+        assert!(!Expiration::UnixTime(future).is_expired_at(now));
+        assert!(Expiration::BurnAfterReadingWithDeadline(past).is_expired_at(now));
+        assert!(!Expiration::BurnAfterReadingWithDeadline(future).is_expired_at(now));
+        assert!(!Expiration::BurnAfterReading.is_expired_at(now));
+        assert!(!Expiration::ReadWindow(60).is_expired_at(now));
+        assert!(Expiration::ReadWindowWithDeadline(60, past).is_expired_at(now));
+    }
+
+    #[test]
+    fn read_windows_round_trip() {
+        let header = HeaderValue::from(Expiration::ReadWindow(600));
+        assert_eq!(header, "read+600");
+        assert_eq!(
+            Expiration::try_from(&header).ok(),
+            Some(Expiration::ReadWindow(600))
+        );
+        assert!(Expiration::try_from("read+soon").is_err());
+        assert_eq!(
+            Expiration::from_str("read+10m"),
+            Ok(Expiration::ReadWindow(600))
+        );
+    }
+}
+
+#[cfg(test)]
+mod tombstone {
+    use chrono::{TimeZone, Utc};
+    use headers::HeaderValue;
+
+    use crate::{Tombstone, TombstoneReason};
+
+    #[test]
+    fn header_round_trip() {
+        for reason in [
+            TombstoneReason::Burned,
+            TombstoneReason::Deleted,
+            TombstoneReason::Exhausted,
+        ] {
+            let tombstone = Tombstone {
+                reason,
+                time: Utc.with_ymd_and_hms(2022, 1, 2, 3, 4, 5).unwrap(),
+            };
+            let header = HeaderValue::from(&tombstone);
+            assert_eq!(
+                Tombstone::try_from(header.to_str().unwrap()).ok(),
+                Some(tombstone)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert!(Tombstone::try_from("burned").is_err());
+        assert!(Tombstone::try_from("eaten 2022-01-02T03:04:05+00:00").is_err());
+        assert!(Tombstone::try_from("burned yesterday").is_err());
+    }
+}
+
+#[cfg(test)]
+mod not_before {
+    use chrono::{Duration, TimeZone, Utc};
+    use headers::HeaderValue;
+
+    use crate::NotBefore;
+
+    #[test]
+    fn header_round_trip() {
+        let not_before = NotBefore(Utc.with_ymd_and_hms(2022, 1, 2, 3, 4, 5).unwrap());
+        let header = HeaderValue::from(&not_before);
+        assert_eq!(
+            NotBefore::try_from(header.to_str().unwrap()).ok(),
+            Some(not_before)
+        );
+        assert!(NotBefore::try_from("tomorrow").is_err());
+    }
+
+    #[test]
+    fn passes() {
+        let now = Utc.with_ymd_and_hms(2022, 1, 2, 3, 4, 5).unwrap();
+        assert!(NotBefore(now).has_passed_at(now));
+        assert!(NotBefore(now - Duration::seconds(1)).has_passed_at(now));
+        assert!(!NotBefore(now + Duration::seconds(1)).has_passed_at(now));
+    }
+}
+
+#[cfg(test)]
+mod paste_ttl {
+    use chrono::{Duration, TimeZone, Utc};
+    use headers::HeaderValue;
+
+    use crate::{Expiration, PasteTtl};
+
+    #[test]
+    fn header_round_trip() {
+        let header = HeaderValue::from(&PasteTtl(90));
+        assert_eq!(
+            PasteTtl::try_from(header.to_str().unwrap()).ok(),
+            Some(PasteTtl(90))
+        );
+        assert!(PasteTtl::try_from("-1").is_err());
+        assert!(PasteTtl::try_from("soon").is_err());
+    }
+
+    #[test]
+    fn remaining() {
+        let now = Utc.with_ymd_and_hms(2022, 1, 2, 3, 4, 5).unwrap();
+        let in_a_minute = now + Duration::minutes(1);
+        assert_eq!(
+            PasteTtl::remaining_at(Expiration::UnixTime(in_a_minute), now),
+            Some(PasteTtl(60))
+        );
+        assert_eq!(
+            PasteTtl::remaining_at(Expiration::UnixTime(now - Duration::minutes(1)), now),
+            Some(PasteTtl(0))
+        );
+        assert_eq!(
+            PasteTtl::remaining_at(Expiration::BurnAfterReading, now),
+            None
+        );
+    }
+
+    #[test]
+    fn rebases_deadlines() {
+        let server_time = Utc.with_ymd_and_hms(2022, 1, 2, 3, 4, 5).unwrap();
+        // The client's clock is an hour behind the server's.
+        let client_time = server_time - Duration::hours(1);
+        let ttl = PasteTtl(60);
+        assert_eq!(
+            ttl.rebase_at(Expiration::UnixTime(server_time), client_time),
+            Expiration::UnixTime(client_time + Duration::minutes(1))
+        );
+        assert_eq!(
+            ttl.rebase_at(
+                Expiration::ReadWindowWithDeadline(600, server_time),
+                client_time
+            ),
+            Expiration::ReadWindowWithDeadline(600, client_time + Duration::minutes(1))
+        );
+        assert_eq!(
+            ttl.rebase_at(Expiration::BurnAfterReading, client_time),
+            Expiration::BurnAfterReading
+        );
+    }
+}
+
+#[cfg(test)]
+mod size_limits {
+    use super::*;
+
+    #[test]
+    fn defaults_to_the_paste_size_limit() {
+        assert_eq!(paste_size_limit(None), PASTE_SIZE_LIMIT);
+        assert_eq!(paste_size_limit(Some(u64::MAX)), PASTE_SIZE_LIMIT);
+    }
+
+    #[test]
+    fn leaves_room_for_the_length() {
+        assert_eq!(paste_size_limit(Some(1024)), 1016);
+        assert_eq!(paste_size_limit(Some(4)), 0);
+    }
+
+    #[test]
+    fn accepts_sizes_below_the_limit() {
+        let info = ServerInfo {
+            paste_size_limit: 1024,
+            expirations: Vec::new(),
+            expiration_required: false,
+        };
+        assert!(info.accepts_size(1023));
+        assert!(!info.accepts_size(1024));
+    }
+}
+
 #[cfg(test)]
 mod partial_parsed_url_parsing {
     use secrecy::Secret;
 
     use crate::base64;
     use crate::crypto::Key;
-    use crate::PartialParsedUrl;
+    use crate::{PartialParsedUrl, PartialParsedUrlParseError};
 
     #[test]
     fn empty() {
@@ -427,6 +1061,98 @@ mod partial_parsed_url_parsing {
         );
     }
 
+    #[test]
+    fn chunked() {
+        let input = "key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=!chunked";
+        assert_eq!(
+            input.parse(),
+            Ok(PartialParsedUrl {
+                decryption_key: decryption_key(),
+                chunked: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn recorded_kdf() {
+        let input = "key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=!pw!kdf";
+        assert_eq!(
+            input.parse(),
+            Ok(PartialParsedUrl {
+                decryption_key: decryption_key(),
+                needs_password: true,
+                recorded_kdf: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn algorithm_id() {
+        let input = "key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=!alg";
+        assert_eq!(
+            input.parse(),
+            Ok(PartialParsedUrl {
+                decryption_key: decryption_key(),
+                algorithm_id: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn private() {
+        let input = "key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=!private";
+        assert_eq!(
+            input.parse(),
+            Ok(PartialParsedUrl {
+                decryption_key: decryption_key(),
+                private: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn with_hash() {
+        let hash = crate::crypto::hash(b"hello");
+        let input = format!(
+            "key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=!h:{}",
+            base64::encode(hash)
+        );
+        assert_eq!(
+            input.parse(),
+            Ok(PartialParsedUrl {
+                decryption_key: decryption_key(),
+                hash: Some(hash),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn split_key() {
+        let input = "!pw!chunked";
+        assert_eq!(
+            input.parse(),
+            Ok(PartialParsedUrl {
+                needs_password: true,
+                chunked: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_hash_fails() {
+        let input = "key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=!h:aGVsbG8=";
+        assert_eq!(
+            input.parse::<PartialParsedUrl>(),
+            Err(PartialParsedUrlParseError::InvalidHash)
+        );
+    }
+
     #[test]
     fn order_does_not_matter() {
         let input = "pw!key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=";
@@ -0,0 +1,261 @@
+//! Rewrites links to pastes so that they point at another instance.
+//!
+//! Links are split into the instance they're on, their short code, and their
+//! fragment, so that they can be pointed at a mirror, or at a copy of the paste
+//! uploaded elsewhere. The fragment holds the key and hints of the paste, so
+//! it's carried over exactly as it was written.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use thiserror::Error;
+use url::Url;
+
+use crate::{PartialParsedUrl, PartialParsedUrlParseError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PasteLink {
+    /// The instance the paste is on, whose path always ends with a slash.
+    instance: Url,
+    code: String,
+    fragment: Option<String>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("The provided url was bad")]
+    BadUrl,
+    #[error("Only http and https instances are supported")]
+    UnsupportedScheme,
+    #[error("The url doesn't have a short code")]
+    MissingCode,
+    #[error(transparent)]
+    InvalidFragment(#[from] PartialParsedUrlParseError),
+}
+
+impl PasteLink {
+    /// Splits up a link to a paste. Links may leave out the fragment, such as
+    /// when the key is shared separately, but a fragment that's there must be
+    /// valid.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the link isn't to a paste on an http or https instance, or its
+    /// fragment isn't valid.
+    pub fn new(url: &Url) -> Result<Self, Error> {
+        let (instance, code) = split_location(url)?;
+        let fragment = url.fragment().filter(|fragment| !fragment.is_empty());
+        if let Some(fragment) = fragment {
+            PartialParsedUrl::try_from(fragment)?;
+        }
+
+        Ok(Self {
+            instance,
+            code,
+            fragment: fragment.map(ToOwned::to_owned),
+        })
+    }
+
+    /// The instance the paste is on, ending with a slash.
+    #[must_use]
+    pub const fn instance(&self) -> &Url {
+        &self.instance
+    }
+
+    #[must_use]
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    #[must_use]
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// Points the link at the same short code on another instance, such as a
+    /// mirror. Instances may be hosted under a path.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the instance isn't an http or https URL.
+    pub fn with_instance(self, instance: &Url) -> Result<Self, Error> {
+        let mut instance = base_url(instance)?;
+        // The instance is always a base, so this can't fail.
+        if let Ok(mut segments) = instance.path_segments_mut() {
+            segments.pop_if_empty().push("");
+        }
+        Ok(Self { instance, ..self })
+    }
+
+    /// Points the link at another paste, such as a copy of this one that was
+    /// uploaded to another instance. The fragment of `paste`, if any, is
+    /// ignored.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `paste` isn't a link to a paste on an http or https instance.
+    pub fn with_paste(self, paste: &Url) -> Result<Self, Error> {
+        let (instance, code) = split_location(paste)?;
+        Ok(Self {
+            instance,
+            code,
+            ..self
+        })
+    }
+
+    #[must_use]
+    pub fn to_url(&self) -> Url {
+        let mut url = self.instance.clone();
+        // The instance is always a base, so this can't fail.
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(&self.code);
+        }
+        url.set_fragment(self.fragment.as_deref());
+        url
+    }
+}
+
+impl FromStr for PasteLink {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(&Url::parse(s).map_err(|_| Error::BadUrl)?)
+    }
+}
+
+impl Display for PasteLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_url().fmt(f)
+    }
+}
+
+/// Rewrites a link to a paste so that it points at the same paste on another
+/// instance, keeping its fragment.
+///
+/// # Errors
+///
+/// Fails if either URL is invalid, as described in [`PasteLink::new`] and
+/// [`PasteLink::with_instance`].
+pub fn rewrite_instance(paste: &str, instance: &str) -> Result<Url, Error> {
+    let instance = Url::parse(instance).map_err(|_| Error::BadUrl)?;
+    Ok(paste
+        .parse::<PasteLink>()?
+        .with_instance(&instance)?
+        .to_url())
+}
+
+/// Splits the location of a paste into its instance and short code.
+fn split_location(url: &Url) -> Result<(Url, String), Error> {
+    let mut instance = base_url(url)?;
+    let code = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|code| !code.is_empty())
+        .ok_or(Error::MissingCode)?
+        .to_owned();
+    // The instance is always a base, so this can't fail.
+    if let Ok(mut segments) = instance.path_segments_mut() {
+        segments.pop().push("");
+    }
+    Ok((instance, code))
+}
+
+/// Strips an instance down to its scheme, host, and path.
+fn base_url(url: &Url) -> Result<Url, Error> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::UnsupportedScheme);
+    }
+    if url.cannot_be_a_base() || url.host().is_none() {
+        return Err(Error::BadUrl);
+    }
+
+    let mut url = url.clone();
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rewrite_instance, Error, PasteLink};
+    use crate::{PartialParsedUrlParseError, Url};
+
+    const FRAGMENT: &str = "key:ddLod7sGy_EjFDjWqZoH4i5n_XU8bIpEuEo3-pjfAIE=!pw!name:a%20b.txt";
+
+    #[test]
+    fn splits_links() {
+        let link: PasteLink = format!("https://example.com/paste/abcd#{FRAGMENT}")
+            .parse()
+            .unwrap();
+        assert_eq!(link.instance().as_str(), "https://example.com/paste/");
+        assert_eq!(link.code(), "abcd");
+        assert_eq!(link.fragment(), Some(FRAGMENT));
+
+        let link: PasteLink = "https://example.com/abcd".parse().unwrap();
+        assert_eq!(link.instance().as_str(), "https://example.com/");
+        assert_eq!(link.fragment(), None);
+
+        let mirror = Url::parse("https://example.org/paste").unwrap();
+        let link = link.with_instance(&mirror).unwrap();
+        assert_eq!(link.instance().as_str(), "https://example.org/paste/");
+    }
+
+    #[test]
+    fn rewrites_instances() {
+        let paste = format!("https://example.com/abcd?x=y#{FRAGMENT}");
+        for (instance, expected) in [
+            ("https://mirror.example", "https://mirror.example/abcd"),
+            ("http://localhost:8080/", "http://localhost:8080/abcd"),
+            (
+                "https://example.org/paste",
+                "https://example.org/paste/abcd",
+            ),
+            (
+                "https://example.org/paste/",
+                "https://example.org/paste/abcd",
+            ),
+            ("https://example.org/?x=y#z", "https://example.org/abcd"),
+        ] {
+            assert_eq!(
+                rewrite_instance(&paste, instance).unwrap().as_str(),
+                format!("{expected}#{FRAGMENT}")
+            );
+        }
+    }
+
+    #[test]
+    fn moves_to_other_pastes() {
+        let link: PasteLink = format!("https://example.com/abcd#{FRAGMENT}")
+            .parse()
+            .unwrap();
+        let copy = Url::parse("https://example.org/paste/efgh#ignored").unwrap();
+        assert_eq!(
+            link.with_paste(&copy).unwrap().to_string(),
+            format!("https://example.org/paste/efgh#{FRAGMENT}")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_parts() {
+        let paste = format!("https://example.com/abcd#{FRAGMENT}");
+        assert_eq!(
+            rewrite_instance(&paste, "ftp://example.org"),
+            Err(Error::UnsupportedScheme)
+        );
+        assert_eq!(
+            rewrite_instance(&paste, "mailto:a@example.org"),
+            Err(Error::UnsupportedScheme)
+        );
+        assert_eq!(rewrite_instance(&paste, "example.org"), Err(Error::BadUrl));
+        assert_eq!(
+            "https://example.com/#key".parse::<PasteLink>(),
+            Err(Error::MissingCode)
+        );
+        assert_eq!(
+            "https://example.com/abcd#not base64".parse::<PasteLink>(),
+            Err(Error::InvalidFragment(
+                PartialParsedUrlParseError::InvalidDecryptionKey
+            ))
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Manifests list pastes that were uploaded together as a bundle, so that they
+//! can be shared with one link while each is still downloaded on its own.
+//!
+//! A manifest is itself a paste, whose plaintext is a [`Manifest`] as JSON.
+//! Each member is sealed with a key derived from the manifest's key with
+//! [`Key::derive_member`], so their keys aren't stored anywhere, and anyone with
+//! the manifest's link can open every member.
+
+use secrecy::{ExposeSecret, Secret, SecretString};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::crypto::Key;
+use crate::{base64, fragment};
+
+/// The version of the manifest format that this client writes and reads.
+pub const VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    /// The version of the manifest format, which also tells manifests apart
+    /// from other JSON pastes.
+    #[serde(rename = "omegaupload_manifest")]
+    pub version: u8,
+    pub members: Vec<Member>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    /// The file name of the member.
+    pub name: String,
+    /// The size of the member before it was encrypted, in bytes.
+    pub size: u64,
+    /// The URL of the member, without a fragment.
+    pub url: Url,
+    /// The fragment of the member's URL without its key, as returned by
+    /// [`fragment::strip_key`].
+    pub hints: String,
+}
+
+impl Manifest {
+    #[must_use]
+    pub const fn new(members: Vec<Member>) -> Self {
+        Self {
+            version: VERSION,
+            members,
+        }
+    }
+
+    /// Whether this client understands the manifest. Newer versions may
+    /// derive the keys of their members differently.
+    #[must_use]
+    pub const fn is_supported(&self) -> bool {
+        self.version == VERSION
+    }
+
+    /// The full URL of the member at `index`, with its key, given the key of
+    /// the manifest. Returns `None` if there's no such member.
+    #[must_use]
+    pub fn member_link(&self, key: &Secret<Key>, index: usize) -> Option<Url> {
+        let member = self.members.get(index)?;
+        let member_key = key
+            .expose_secret()
+            .derive_member(u32::try_from(index).ok()?);
+        let member_key = SecretString::new(base64::encode(member_key.expose_secret().as_ref()));
+        let mut url = member.url.clone();
+        url.set_fragment(Some(
+            fragment::join_key(&member_key, &member.hints).expose_secret(),
+        ));
+        Some(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::{Manifest, Member};
+    use crate::crypto::Key;
+    use crate::{ParsedUrl, Url};
+
+    fn manifest() -> Manifest {
+        let member = |code: &str, hints: &str| Member {
+            name: format!("{code}.txt"),
+            size: 5,
+            url: Url::parse(&format!("https://example.com/{code}")).unwrap(),
+            hints: hints.to_owned(),
+        };
+        Manifest::new(vec![
+            member("first", ""),
+            member("second", "!name:second.txt"),
+        ])
+    }
+
+    #[test]
+    fn derives_a_key_per_member() {
+        let key = Key::random_secret();
+        let first = key.expose_secret().derive_member(0);
+        let second = key.expose_secret().derive_member(1);
+        assert!(first.expose_secret() != second.expose_secret());
+        assert!(first.expose_secret() != key.expose_secret());
+        assert!(first.expose_secret() == key.expose_secret().derive_member(0).expose_secret());
+    }
+
+    #[test]
+    fn links_to_members_with_their_keys() {
+        let key = Key::random_secret();
+        let manifest = manifest();
+        for (index, member) in (0..).zip(&manifest.members) {
+            let link = manifest.member_link(&key, index as usize).unwrap();
+            let parsed: ParsedUrl = link.as_str().parse().unwrap();
+            assert_eq!(parsed.sanitized_url, member.url);
+            let expected = key.expose_secret().derive_member(index);
+            assert!(parsed.decryption_key.expose_secret() == expected.expose_secret());
+        }
+        let link = manifest.member_link(&key, 1).unwrap();
+        assert!(link.fragment().unwrap().ends_with("!name:second.txt"));
+        assert!(manifest.member_link(&key, 2).is_none());
+    }
+}
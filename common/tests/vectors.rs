@@ -0,0 +1,59 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Opens the sealed pastes in `testdata`, which the CLI and web frontend also
+//! test against, so that changes to the crypto module can't silently break
+//! pastes that were already shared.
+
+use std::path::PathBuf;
+
+use omegaupload_common::crypto::{hash, open_chunked_in_place, open_in_place};
+use omegaupload_common::secrecy::SecretVec;
+use omegaupload_common::PartialParsedUrl;
+
+fn testdata() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../testdata")
+}
+
+#[test]
+fn opens_all_vectors() {
+    let manifest = std::fs::read_to_string(testdata().join("vectors.txt")).unwrap();
+    let vectors: Vec<_> = manifest
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    assert!(!vectors.is_empty());
+
+    for vector in vectors {
+        let [name, password, fragment]: [&str; 3] =
+            vector.split('\t').collect::<Vec<_>>().try_into().unwrap();
+        let url = PartialParsedUrl::try_from(fragment).unwrap();
+        let key = url.decryption_key.as_ref().unwrap();
+        let password = (password != "-").then(|| SecretVec::new(password.as_bytes().to_vec()));
+        assert_eq!(url.needs_password, password.is_some(), "{name}");
+
+        let open = if url.chunked {
+            open_chunked_in_place
+        } else {
+            open_in_place
+        };
+        let mut data = std::fs::read(testdata().join(name)).unwrap();
+        if let Err(e) = open(&mut data, key, password, url.envelope()) {
+            panic!("failed to open {name}: {e}");
+        }
+        assert_eq!(Some(hash(&data)), url.hash, "{name}");
+    }
+}
@@ -0,0 +1,37 @@
+#![no_main]
+
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+//! Expirations come from the `Burn-After` header of uploads.
+
+use headers::HeaderValue;
+use libfuzzer_sys::fuzz_target;
+use omegaupload_common::Expiration;
+
+fuzz_target!(|value: &str| {
+    if let Ok(expiration) = Expiration::try_from(value) {
+        // Whatever is accepted is echoed back to the uploader, who must be able
+        // to read it again.
+        let header = HeaderValue::from(expiration);
+        assert_eq!(
+            Expiration::try_from(&header).ok(),
+            Some(expiration),
+            "{value:?} was sent back as {header:?}"
+        );
+    }
+});
@@ -0,0 +1,36 @@
+#![no_main]
+
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+//! Sealed pastes are downloaded from a server, which may not be trustworthy.
+//! Runs with a password are much slower, as they derive a key first, and
+//! slower still if they read recorded Argon2 parameters.
+
+use libfuzzer_sys::fuzz_target;
+use omegaupload_common::crypto::{open_in_place, Envelope, Key};
+use omegaupload_common::secrecy::SecretVec;
+
+fuzz_target!(|input: ([u8; 32], Option<Vec<u8>>, bool, bool, Vec<u8>)| {
+    let (key, password, recorded_kdf, algorithm_id, mut sealed) = input;
+    let key = Key::new_secret(key.to_vec()).expect("a key to be 32 bytes");
+    let envelope = Envelope {
+        recorded_kdf,
+        algorithm_id,
+    };
+    let _ = open_in_place(&mut sealed, &key, password.map(SecretVec::new), envelope);
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+//! URL fragments come from whoever shared the link.
+
+use libfuzzer_sys::fuzz_target;
+use omegaupload_common::PartialParsedUrl;
+
+fuzz_target!(|fragment: &str| {
+    let _ = PartialParsedUrl::try_from(fragment);
+});
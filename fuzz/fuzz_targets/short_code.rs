@@ -0,0 +1,34 @@
+#![no_main]
+
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+//! Short codes come from the path of every request for a paste.
+
+use libfuzzer_sys::fuzz_target;
+use omegaupload_server::short_code::ShortCode;
+use omegaupload_server::SHORT_CODE_SIZE;
+use serde::de::value::{BorrowedStrDeserializer, Error};
+use serde::Deserialize;
+
+fuzz_target!(|code: &str| {
+    let deserializer = BorrowedStrDeserializer::<Error>::new(code);
+    if let Ok(short_code) = ShortCode::<SHORT_CODE_SIZE>::deserialize(deserializer) {
+        assert_eq!(short_code.as_bytes(), code.as_bytes());
+        short_code.is_reserved(&[]);
+    }
+});
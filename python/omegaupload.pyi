@@ -0,0 +1,27 @@
+from typing import Optional, Tuple
+
+class Error(Exception):
+    """Raised when a paste can't be uploaded, downloaded, sealed, or opened."""
+
+def upload(
+    url: str,
+    data: bytes,
+    *,
+    password: Optional[str] = None,
+    expiration: Optional[str] = None,
+    name: Optional[str] = None,
+    language: Optional[str] = None,
+) -> str:
+    """Encrypts `data` and uploads it to the instance at `url`, returning the
+    URL of the paste with its decryption key."""
+
+def download(url: str, *, password: Optional[str] = None) -> bytes:
+    """Downloads and decrypts the paste at `url`, which must include its
+    decryption key. Burn after reading pastes are burned."""
+
+def seal(data: bytes, *, password: Optional[str] = None) -> Tuple[bytes, str]:
+    """Encrypts `data` without uploading it, returning the sealed data and its
+    decryption key in base64."""
+
+def open(data: bytes, key: str, *, password: Optional[str] = None) -> bytes:
+    """Decrypts data sealed with `seal`, given its decryption key in base64."""
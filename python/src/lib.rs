@@ -0,0 +1,252 @@
+// OmegaUpload Python Bindings
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Python bindings for uploading and downloading pastes, and for sealing and
+//! opening data without a server. Pastes are uploaded in the same format as
+//! the CLI uploads them, so either can read what the other wrote.
+
+#![warn(clippy::nursery, clippy::pedantic)]
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use omegaupload_common::crypto::{
+    hash, open_chunked_into_secret, open_into_secret, seal_in_place, seal_in_place_with_key,
+    Envelope, Key, SealOptions,
+};
+use omegaupload_common::fragment::Builder;
+use omegaupload_common::secrecy::{ExposeSecret, SecretString, SecretVec};
+use omegaupload_common::{
+    base64, Expiration, ParsedUrl, PartialParsedUrl, UploadResponse, Url, API_ENDPOINT,
+    EXPIRATION_HEADER_NAME,
+};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use reqwest::blocking::Client;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::StatusCode;
+
+create_exception!(
+    omegaupload,
+    Error,
+    PyException,
+    "Raised when a paste can't be uploaded, downloaded, sealed, or opened."
+);
+
+fn to_py_err(e: &anyhow::Error) -> PyErr {
+    // The alternate format includes the context, like the CLI prints.
+    Error::new_err(format!("{e:#}"))
+}
+
+fn password_bytes(password: Option<String>) -> Option<SecretVec<u8>> {
+    password.map(|password| SecretVec::new(password.into_bytes()))
+}
+
+/// Encrypts `data` and uploads it to the instance at `url`, returning the URL
+/// of the paste with its decryption key.
+#[pyfunction]
+#[pyo3(signature = (url, data, *, password = None, expiration = None, name = None, language = None))]
+#[allow(clippy::needless_pass_by_value)]
+fn upload(
+    py: Python<'_>,
+    url: &str,
+    data: Vec<u8>,
+    password: Option<String>,
+    expiration: Option<&str>,
+    name: Option<String>,
+    language: Option<String>,
+) -> PyResult<String> {
+    let expiration = expiration
+        .map(Expiration::from_str)
+        .transpose()
+        .map_err(|e| Error::new_err(format!("Invalid expiration: {e}")))?;
+    py.allow_threads(|| do_upload(url, data, password, expiration, name, language))
+        .map_err(|e| to_py_err(&e))
+}
+
+fn do_upload(
+    url: &str,
+    mut data: Vec<u8>,
+    password: Option<String>,
+    expiration: Option<Expiration>,
+    name: Option<String>,
+    language: Option<String>,
+) -> Result<String> {
+    let mut url = Url::from_str(url).context("Invalid URL")?;
+    url.set_fragment(None);
+
+    if data.is_empty() {
+        bail!("Nothing to upload.");
+    }
+
+    let needs_password = password.is_some();
+    let options = SealOptions::default();
+    let content_hash = hash(&data);
+    let key = Key::random_secret();
+    seal_in_place_with_key(&mut data, &key, password_bytes(password), options)?;
+
+    let mut req = Client::new()
+        .post(url.as_ref())
+        .header(ACCEPT, "application/json")
+        .body(data);
+    if let Some(expiration) = expiration {
+        req = req.header(&*EXPIRATION_HEADER_NAME, expiration);
+    }
+    let res = req.send().context("Request to server failed")?;
+
+    match res.status() {
+        StatusCode::OK => (),
+        StatusCode::PAYLOAD_TOO_LARGE => bail!("The paste is too large for this server."),
+        status => bail!("Upload failed. Got HTTP error {status}"),
+    }
+
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.starts_with("application/json"));
+    // Older servers only return the short code.
+    let (code, canonical_url) = if is_json {
+        let response: UploadResponse = serde_json::from_str(&res.text()?)
+            .context("The server returned an invalid upload response")?;
+        (response.code, response.url)
+    } else {
+        (res.text()?, None)
+    };
+
+    if let Some(canonical_url) = canonical_url {
+        url = canonical_url;
+    } else {
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Failed to get base URL"))?
+            .extend(std::iter::once(code));
+    }
+
+    let key = SecretString::new(base64::encode(key.expose_secret().as_ref()));
+    let mut fragment = Builder::new(key)
+        .hash(content_hash)
+        .envelope(options.envelope(needs_password));
+    if needs_password {
+        fragment = fragment.needs_password();
+    }
+    if let Some(name) = name {
+        fragment = fragment.file_name(name);
+    }
+    if let Some(language) = language {
+        fragment = fragment.language(language);
+    }
+    url.set_fragment(Some(fragment.build().expose_secret()));
+    Ok(url.to_string())
+}
+
+/// Downloads and decrypts the paste at `url`, which must include its
+/// decryption key. Burn after reading pastes are burned.
+#[pyfunction]
+#[pyo3(signature = (url, *, password = None))]
+fn download(py: Python<'_>, url: &str, password: Option<String>) -> PyResult<PyObject> {
+    let data = py
+        .allow_threads(|| do_download(url, password))
+        .map_err(|e| to_py_err(&e))?;
+    Ok(PyBytes::new(py, data.expose_secret()).into())
+}
+
+fn do_download(url: &str, password: Option<String>) -> Result<SecretVec<u8>> {
+    let parsed = ParsedUrl::from_str(url)?;
+    let content_hash = Url::from_str(url)?
+        .fragment()
+        .map(PartialParsedUrl::try_from)
+        .transpose()?
+        .and_then(|partial| partial.hash);
+    if parsed.needs_password && password.is_none() {
+        bail!("This paste requires a password.");
+    }
+
+    let mut api_url = parsed.sanitized_url.clone();
+    api_url.set_path(&format!("{API_ENDPOINT}{}", parsed.sanitized_url.path()));
+    let res = Client::new()
+        .get(api_url)
+        .send()
+        .context("Failed to get data")?;
+
+    match res.status() {
+        StatusCode::OK => (),
+        StatusCode::NOT_FOUND => bail!("The paste doesn't exist, or has expired."),
+        StatusCode::GONE => bail!("The paste was burned or deleted."),
+        status => bail!("Got bad response from server: {status}"),
+    }
+
+    let open = if parsed.chunked {
+        open_chunked_into_secret
+    } else {
+        open_into_secret
+    };
+    let data = open(
+        res.bytes()?.to_vec(),
+        &parsed.decryption_key,
+        password_bytes(password),
+        parsed.envelope(),
+    )?;
+    if let Some(content_hash) = content_hash {
+        if hash(data.expose_secret()) != content_hash {
+            bail!("The paste doesn't match the hash in its URL.");
+        }
+    }
+    Ok(data)
+}
+
+/// Encrypts `data` without uploading it, returning the sealed data and its
+/// decryption key in base64.
+#[pyfunction]
+#[pyo3(signature = (data, *, password = None))]
+fn seal(
+    py: Python<'_>,
+    mut data: Vec<u8>,
+    password: Option<String>,
+) -> PyResult<(PyObject, String)> {
+    let key = py
+        .allow_threads(|| seal_in_place(&mut data, password_bytes(password)))
+        .map_err(|e| to_py_err(&e.into()))?;
+    let key = base64::encode(key.expose_secret().as_ref());
+    Ok((PyBytes::new(py, &data).into(), key))
+}
+
+/// Decrypts data sealed with `seal`, given its decryption key in base64.
+#[pyfunction]
+#[pyo3(signature = (data, key, *, password = None))]
+fn open(py: Python<'_>, data: Vec<u8>, key: &str, password: Option<String>) -> PyResult<PyObject> {
+    let key = base64::decode(key)
+        .ok()
+        .and_then(Key::new_secret)
+        .ok_or_else(|| Error::new_err("Invalid decryption key"))?;
+    let data = py
+        .allow_threads(|| {
+            open_into_secret(data, &key, password_bytes(password), Envelope::default())
+        })
+        .map_err(|e| to_py_err(&e.into()))?;
+    Ok(PyBytes::new(py, data.expose_secret()).into())
+}
+
+#[pymodule]
+fn omegaupload(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("Error", py.get_type::<Error>())?;
+    m.add_function(wrap_pyfunction!(upload, m)?)?;
+    m.add_function(wrap_pyfunction!(download, m)?)?;
+    m.add_function(wrap_pyfunction!(seal, m)?)?;
+    m.add_function(wrap_pyfunction!(open, m)?)?;
+    Ok(())
+}
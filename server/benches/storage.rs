@@ -0,0 +1,78 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Round trips of paste contents through each store, and through the database
+//! with each backend.
+
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use omegaupload_server::config::{Durability, StorageClasses};
+use omegaupload_server::db::Database;
+use omegaupload_server::storage::{self, Store};
+
+const SIZES: &[usize] = &[1024, 64 * 1024, 1024 * 1024, 16 * 1024 * 1024];
+
+/// What a short code looks like as a key.
+const KEY: &[u8] = b"xWqjrGvCcF2m";
+
+fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("omegaupload-bench-{}", std::process::id()))
+}
+
+fn round_trip(c: &mut Criterion) {
+    let scratch = scratch_dir();
+    storage::init(StorageClasses {
+        directory: Some(scratch.join("pastes")),
+        ..StorageClasses::default()
+    })
+    .unwrap();
+    let blobs = storage::blobs();
+
+    let sqlite = Database::open_sqlite(":memory:", Durability::default()).unwrap();
+    let rocksdb_path = scratch.join("rocksdb");
+    let rocksdb =
+        Database::open_rocksdb(rocksdb_path.to_str().unwrap(), Durability::default()).unwrap();
+
+    let mut group = c.benchmark_group("round-trip");
+    for &size in SIZES {
+        let data = Bytes::from(vec![0xA5; size]);
+        group.throughput(Throughput::Bytes(size as u64));
+        for (name, db, store) in [
+            ("rocksdb", &rocksdb, Store::Database),
+            ("sqlite", &sqlite, Store::Database),
+            ("memory", &sqlite, Store::Memory),
+            ("directory", &sqlite, Store::Directory),
+        ] {
+            group.bench_with_input(BenchmarkId::new(name, size), &data, |b, data| {
+                b.iter(|| {
+                    blobs.put(db, store, KEY, data.clone()).unwrap();
+                    let read = blobs.get(db, store, KEY).unwrap().unwrap();
+                    blobs.delete(db, store, KEY).unwrap();
+                    read.len()
+                });
+            });
+        }
+    }
+    group.finish();
+
+    drop(rocksdb);
+    let _ = std::fs::remove_dir_all(scratch);
+}
+
+criterion_group!(benches, round_trip);
+criterion_main!(benches);
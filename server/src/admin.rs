@@ -0,0 +1,205 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Operational endpoints, served on their own listener so that they're never
+//! exposed on the public interface by accident.
+//!
+//! - `GET /healthz` returns `200 OK` while the database can be read.
+//! - `GET /metrics` returns paste counts, database sizes, and paste bytes held
+//!   in memory in the Prometheus text format.
+//! - `DELETE /pastes/:code` deletes a paste without its owner token, such as to
+//!   take down abuse.
+//!
+//! Everything but `/healthz` requires the admin token, if one is configured, as
+//! `Authorization: Bearer <token>`.
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::{delete, get};
+use axum::{Extension, Router};
+use futures::stream;
+use omegaupload_common::TombstoneReason;
+use tokio::net::UnixListener;
+use tracing::{error, info, instrument};
+
+use crate::config::{AdminBind, AdminConfig};
+use crate::db::Database;
+use crate::load::Load;
+use crate::short_code::ShortCode;
+use crate::storage::blobs;
+use crate::{add_tombstone, delete_entry, META_CF_NAME, TOMBSTONE_CF_NAME};
+
+/// Serves the admin endpoints until the server shuts down.
+pub async fn serve<const N: usize>(
+    config: AdminConfig,
+    db: Arc<Database>,
+    load: Arc<Load>,
+) -> Result<()> {
+    let mut router = Router::new()
+        .route("/metrics", get(metrics))
+        .route("/pastes/:code", delete(delete_paste::<N>));
+    if let Some(token) = config.token {
+        router = router.route_layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            require_token,
+        ));
+    }
+    // Added after the token is required, so health checks don't need it.
+    let router = router
+        .route("/healthz", get(health))
+        .layer(Extension(db))
+        .layer(Extension(load))
+        .into_make_service();
+
+    match config.bind {
+        AdminBind::Tcp(addr) => {
+            info!("Serving admin endpoints on {addr}");
+            axum::Server::bind(&addr).serve(router).await?;
+        }
+        AdminBind::Unix(path) => {
+            // A socket left over from a previous run can't be bound to again.
+            let _ = std::fs::remove_file(&path);
+            let listener = UnixListener::bind(&path)?;
+            info!("Serving admin endpoints on {}", path.display());
+            let connections = stream::poll_fn(|cx| {
+                listener
+                    .poll_accept(cx)
+                    .map(|conn| Some(conn.map(|(stream, _)| stream)))
+            });
+            axum::Server::builder(hyper::server::accept::from_stream(connections))
+                .serve(router)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Rejects requests without the admin token, comparing it in constant time.
+async fn require_token<B>(
+    State(token): State<Arc<str>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let provided = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    let matches = provided.len() == token.len()
+        && provided
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+    if matches {
+        Ok(next.run(request).await)
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+async fn health(Extension(db): Extension<Arc<Database>>) -> StatusCode {
+    match db.estimate_num_keys(META_CF_NAME) {
+        Ok(_) => StatusCode::OK,
+        Err(e) => {
+            error!("Health check failed: {e}");
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+/// Reports estimates from the database, which are cheap to compute but may be
+/// off after many deletions.
+async fn metrics(
+    Extension(db): Extension<Arc<Database>>,
+    Extension(load): Extension<Arc<Load>>,
+) -> Result<String, StatusCode> {
+    let internal_error = |e| {
+        error!("Failed to read database statistics: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut metrics = String::new();
+    let mut gauge = |name: &str, help: &str, value: u64| {
+        let _ = writeln!(metrics, "# HELP {name} {help}");
+        let _ = writeln!(metrics, "# TYPE {name} gauge");
+        let _ = writeln!(metrics, "{name} {value}");
+    };
+    gauge(
+        "omegaupload_pastes",
+        "Estimated number of stored pastes.",
+        db.estimate_num_keys(META_CF_NAME).map_err(internal_error)?,
+    );
+    gauge(
+        "omegaupload_tombstones",
+        "Estimated number of remembered burned or deleted pastes.",
+        db.estimate_num_keys(TOMBSTONE_CF_NAME)
+            .map_err(internal_error)?,
+    );
+    gauge(
+        "omegaupload_database_size_bytes",
+        "Estimated size of the database on disk and in memory.",
+        db.estimate_size().map_err(internal_error)?,
+    );
+    gauge(
+        "omegaupload_memory_store_bytes",
+        "Bytes of pastes kept in memory by their storage class.",
+        blobs().memory_usage() as u64,
+    );
+    gauge(
+        "omegaupload_upload_bytes_in_flight",
+        "Bytes held in memory by uploads in flight.",
+        load.uploads.in_use() as u64,
+    );
+    gauge(
+        "omegaupload_download_bytes_in_flight",
+        "Bytes held in memory by downloads in flight.",
+        load.downloads.in_use() as u64,
+    );
+    Ok(metrics)
+}
+
+#[instrument(skip(db))]
+async fn delete_paste<const N: usize>(
+    Extension(db): Extension<Arc<Database>>,
+    Path(code): Path<ShortCode<N>>,
+) -> StatusCode {
+    let key = code.as_bytes();
+    match db.get(META_CF_NAME, &key) {
+        Ok(Some(_)) => (),
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to look up paste: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    match delete_entry(Arc::clone(&db), key).await {
+        Ok(Ok(())) => {
+            add_tombstone(&db, key, TombstoneReason::Deleted);
+            info!("Deleted paste from the admin endpoint");
+            StatusCode::OK
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
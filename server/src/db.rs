@@ -0,0 +1,468 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The database that pastes are kept in. `RocksDB` suits busy instances, while
+//! a single `SQLite` file is simpler to run, back up, and move for small ones.
+//!
+//! Postgres and S3 let several servers share the same pastes.
+//!
+//! All keep the same column families of keys and values. In `SQLite` and
+//! Postgres, each column family is a table, except that Postgres leaves paste
+//! contents to S3.
+
+use std::future::Future;
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use deadpool_postgres::{Pool, Runtime};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use rocksdb::{ColumnFamilyDescriptor, IteratorMode, Options, WriteOptions, DB};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::runtime::Handle;
+use tokio_postgres::NoTls;
+use tracing::warn;
+
+use crate::config::{Durability, PostgresConfig};
+use crate::{
+    BLOB_CF_NAME, DOWNLOADS_CF_NAME, META_CF_NAME, NOTIFY_CF_NAME, OWNER_CF_NAME,
+    TOMBSTONE_CF_NAME, TRANSFER_CF_NAME,
+};
+
+const COLUMN_FAMILIES: [&str; 7] = [
+    BLOB_CF_NAME,
+    DOWNLOADS_CF_NAME,
+    META_CF_NAME,
+    NOTIFY_CF_NAME,
+    OWNER_CF_NAME,
+    TOMBSTONE_CF_NAME,
+    TRANSFER_CF_NAME,
+];
+
+/// Identifies the advisory lock that the sweeper holds in Postgres.
+const SWEEPER_LOCK_ID: i64 = 0x6f6d_6567_6175_706c;
+
+/// The largest value `SQLite` stores by default.
+const SQLITE_MAX_LENGTH: usize = 1_000_000_000;
+
+pub enum Database {
+    RocksDb {
+        db: DB,
+        /// The options every write is made with.
+        write_options: WriteOptions,
+    },
+    /// `SQLite` connections can't be shared between threads, so only one
+    /// request uses the database at a time.
+    Sqlite(Mutex<Connection>),
+    /// Paste contents are objects in S3 named after their short code, and
+    /// can't be listed.
+    Postgres {
+        pool: Pool,
+        blobs: AmazonS3,
+        /// For connections that mustn't go back to the pool.
+        url: String,
+    },
+}
+
+/// Held by the one server that sweeps expired pastes out of a shared database.
+pub struct SweeperLock(tokio_postgres::Client);
+
+impl SweeperLock {
+    /// Whether this server still has the lock. It's lost if the connection
+    /// that took it closes.
+    pub fn is_held(&self) -> bool {
+        !self.0.is_closed()
+    }
+}
+
+type Entries<'a> = Box<dyn Iterator<Item = Result<(Box<[u8]>, Box<[u8]>)>> + 'a>;
+
+impl Database {
+    pub fn open_rocksdb(path: &str, durability: Durability) -> Result<Self> {
+        let mut db_options = Options::default();
+        db_options.create_if_missing(true);
+        db_options.create_missing_column_families(true);
+        db_options.set_compression_type(rocksdb::DBCompressionType::Zstd);
+        db_options.set_manual_wal_flush(durability.wal_flush_interval.is_some());
+        let mut write_options = WriteOptions::default();
+        write_options.set_sync(durability.sync_writes);
+        write_options.disable_wal(durability.disable_wal);
+        let db = DB::open_cf_descriptors(
+            &db_options,
+            path,
+            COLUMN_FAMILIES.map(|name| ColumnFamilyDescriptor::new(name, Options::default())),
+        )?;
+        Ok(Self::RocksDb { db, write_options })
+    }
+
+    /// Opens a `SQLite` database in WAL mode. Syncing every write makes it
+    /// survive the machine crashing, and without the write-ahead log, writes
+    /// aren't synced at all.
+    pub fn open_sqlite(path: &str, durability: Durability) -> Result<Self> {
+        if durability.wal_flush_interval.is_some() {
+            warn!("OMEGAUPLOAD_WAL_FLUSH_SECS only applies to RocksDB; ignoring it");
+        }
+        let conn = Connection::open(path)?;
+        // Changing the journal mode returns the new mode.
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        let synchronous = if durability.disable_wal {
+            "OFF"
+        } else if durability.sync_writes {
+            "FULL"
+        } else {
+            "NORMAL"
+        };
+        conn.pragma_update(None, "synchronous", synchronous)?;
+        for name in COLUMN_FAMILIES {
+            conn.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {name} (key BLOB PRIMARY KEY, value BLOB NOT NULL)"
+                ),
+                params![],
+            )?;
+        }
+        Ok(Self::Sqlite(Mutex::new(conn)))
+    }
+
+    /// Connects to Postgres, creating its tables if they don't exist yet, and
+    /// to the S3 bucket. How durable writes are is up to them instead.
+    pub async fn open_postgres(config: &PostgresConfig, durability: Durability) -> Result<Self> {
+        if durability.sync_writes
+            || durability.disable_wal
+            || durability.wal_flush_interval.is_some()
+        {
+            warn!("Write-ahead log settings don't apply to Postgres; ignoring them");
+        }
+        let mut pool_config = deadpool_postgres::Config::new();
+        pool_config.url = Some(config.url.clone());
+        let pool = pool_config.create_pool(Some(Runtime::Tokio1), NoTls)?;
+        let client = pool.get().await?;
+        for name in COLUMN_FAMILIES {
+            if name != BLOB_CF_NAME {
+                client
+                    .execute(
+                        &format!(
+                            "CREATE TABLE IF NOT EXISTS {name} \
+                             (key BYTEA PRIMARY KEY, value BYTEA NOT NULL)"
+                        ),
+                        &[],
+                    )
+                    .await?;
+            }
+        }
+        let blobs = AmazonS3Builder::from_env()
+            .with_bucket_name(&config.bucket)
+            .build()?;
+        Ok(Self::Postgres {
+            pool,
+            blobs,
+            url: config.url.clone(),
+        })
+    }
+
+    /// Whether several servers may use this database at once, so they can't
+    /// each delete pastes on their own timers.
+    pub const fn is_shared(&self) -> bool {
+        matches!(self, Self::Postgres { .. })
+    }
+
+    /// Takes the sweeper lock of a shared database, if no other server has it.
+    /// Postgres releases it once the connection holding it closes, such as
+    /// when that server stops.
+    pub async fn try_lock_sweeper(&self) -> Result<Option<SweeperLock>> {
+        let url = match self {
+            Self::Postgres { url, .. } => url,
+            _ => bail!("Only shared databases need a sweeper"),
+        };
+        let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                warn!("Lost the sweeper lock connection: {e}");
+            }
+        });
+        let locked: bool = client
+            .query_one("SELECT pg_try_advisory_lock($1)", &[&SWEEPER_LOCK_ID])
+            .await?
+            .try_get(0)?;
+        Ok(locked.then_some(SweeperLock(client)))
+    }
+
+    /// The largest value that can be stored, which limits the size of pastes.
+    pub const fn max_value_size(&self) -> Option<usize> {
+        match self {
+            Self::RocksDb { .. } | Self::Postgres { .. } => None,
+            Self::Sqlite(_) => Some(SQLITE_MAX_LENGTH),
+        }
+    }
+
+    pub fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.with_value(cf, key, <[u8]>::to_vec)
+    }
+
+    /// Reads a value without copying it, where the database allows it.
+    pub fn with_value<T>(
+        &self,
+        cf: &str,
+        key: &[u8],
+        f: impl FnOnce(&[u8]) -> T,
+    ) -> Result<Option<T>> {
+        match self {
+            Self::RocksDb { db, .. } => {
+                let cf = db.cf_handle(cf).unwrap();
+                Ok(db.get_pinned_cf(cf, key)?.map(|value| f(&value)))
+            }
+            Self::Sqlite(conn) => Ok(conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    &format!("SELECT value FROM {cf} WHERE key = ?1"),
+                    params![key],
+                    |row| match row.get_ref(0)? {
+                        ValueRef::Blob(value) => Ok(f(value)),
+                        value => Err(rusqlite::Error::InvalidColumnType(
+                            0,
+                            "value".to_string(),
+                            value.data_type(),
+                        )),
+                    },
+                )
+                .optional()?),
+            Self::Postgres { blobs, .. } if cf == BLOB_CF_NAME => block_on(async {
+                match blobs.get(&blob_path(key)).await {
+                    Ok(object) => Ok(Some(f(&object.bytes().await?))),
+                    Err(object_store::Error::NotFound { .. }) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }),
+            Self::Postgres { pool, .. } => block_on(async {
+                let row = pool
+                    .get()
+                    .await?
+                    .query_opt(&format!("SELECT value FROM {cf} WHERE key = $1"), &[&key])
+                    .await?;
+                match row {
+                    Some(row) => Ok(Some(f(row.try_get::<_, &[u8]>(0)?))),
+                    None => Ok(None),
+                }
+            }),
+        }
+    }
+
+    pub fn put(&self, cf: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        match self {
+            Self::RocksDb { db, write_options } => {
+                db.put_cf_opt(db.cf_handle(cf).unwrap(), key, value, write_options)?;
+            }
+            Self::Sqlite(conn) => {
+                conn.lock().unwrap().execute(
+                    &format!("INSERT OR REPLACE INTO {cf} (key, value) VALUES (?1, ?2)"),
+                    params![key, value],
+                )?;
+            }
+            Self::Postgres { blobs, .. } if cf == BLOB_CF_NAME => {
+                block_on(blobs.put(&blob_path(key), Bytes::copy_from_slice(value)))?;
+            }
+            Self::Postgres { pool, .. } => {
+                block_on(async {
+                    pool.get()
+                        .await?
+                        .execute(
+                            &format!(
+                                "INSERT INTO {cf} (key, value) VALUES ($1, $2) \
+                                 ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value"
+                            ),
+                            &[&key, &value],
+                        )
+                        .await?;
+                    anyhow::Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, cf: &str, key: &[u8]) -> Result<()> {
+        match self {
+            Self::RocksDb { db, write_options } => {
+                db.delete_cf_opt(db.cf_handle(cf).unwrap(), key, write_options)?;
+            }
+            Self::Sqlite(conn) => {
+                conn.lock()
+                    .unwrap()
+                    .execute(&format!("DELETE FROM {cf} WHERE key = ?1"), params![key])?;
+            }
+            Self::Postgres { blobs, .. } if cf == BLOB_CF_NAME => {
+                block_on(blobs.delete(&blob_path(key)))?;
+            }
+            Self::Postgres { pool, .. } => {
+                block_on(async {
+                    pool.get()
+                        .await?
+                        .execute(&format!("DELETE FROM {cf} WHERE key = $1"), &[&key])
+                        .await?;
+                    anyhow::Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a key might exist. False positives are possible, but false
+    /// negatives aren't.
+    pub fn may_contain(&self, cf: &str, key: &[u8]) -> bool {
+        match self {
+            Self::RocksDb { db, .. } => db.key_may_exist_cf(db.cf_handle(cf).unwrap(), key),
+            // Errors are treated as the key existing, so it's never reused.
+            Self::Sqlite(_) | Self::Postgres { .. } => {
+                !matches!(self.with_value(cf, key, |_| ()), Ok(None))
+            }
+        }
+    }
+
+    pub fn entries(&self, cf: &str) -> Result<Entries<'_>> {
+        match self {
+            Self::RocksDb { db, .. } => {
+                let entries = db.iterator_cf(db.cf_handle(cf).unwrap(), IteratorMode::Start);
+                Ok(Box::new(entries.map(|entry| Ok(entry?))))
+            }
+            Self::Sqlite(conn) => {
+                let conn = conn.lock().unwrap();
+                let mut statement = conn.prepare(&format!("SELECT key, value FROM {cf}"))?;
+                let entries: Vec<_> = statement
+                    .query_map(params![], |row| {
+                        Ok((
+                            row.get::<_, Vec<u8>>(0)?.into_boxed_slice(),
+                            row.get::<_, Vec<u8>>(1)?.into_boxed_slice(),
+                        ))
+                    })?
+                    .map(|entry| Ok(entry?))
+                    .collect();
+                Ok(Box::new(entries.into_iter()))
+            }
+            Self::Postgres { .. } if cf == BLOB_CF_NAME => {
+                bail!("Paste contents in S3 can't be listed")
+            }
+            Self::Postgres { pool, .. } => {
+                let rows = block_on(async {
+                    let rows = pool
+                        .get()
+                        .await?
+                        .query(&format!("SELECT key, value FROM {cf}"), &[])
+                        .await?;
+                    anyhow::Ok(rows)
+                })?;
+                let entries = rows.into_iter().map(|row| {
+                    Ok((
+                        row.try_get::<_, Vec<u8>>(0)?.into_boxed_slice(),
+                        row.try_get::<_, Vec<u8>>(1)?.into_boxed_slice(),
+                    ))
+                });
+                Ok(Box::new(entries))
+            }
+        }
+    }
+
+    /// Writes out the buffered write-ahead log, which is only buffered by
+    /// `RocksDB`.
+    pub fn flush_wal(&self, sync: bool) -> Result<()> {
+        if let Self::RocksDb { db, .. } = self {
+            db.flush_wal(sync)?;
+        }
+        Ok(())
+    }
+
+    /// How many keys a column family has, which `RocksDB` only estimates.
+    pub fn estimate_num_keys(&self, cf: &str) -> Result<u64> {
+        match self {
+            Self::RocksDb { db, .. } => Ok(db
+                .property_int_value_cf(db.cf_handle(cf).unwrap(), "rocksdb.estimate-num-keys")?
+                .unwrap_or_default()),
+            Self::Sqlite(conn) => {
+                let count: i64 = conn.lock().unwrap().query_row(
+                    &format!("SELECT COUNT(*) FROM {cf}"),
+                    params![],
+                    |row| row.get(0),
+                )?;
+                Ok(count.try_into()?)
+            }
+            Self::Postgres { pool, .. } => {
+                let count: i64 = block_on(async {
+                    let value = pool
+                        .get()
+                        .await?
+                        .query_one(&format!("SELECT COUNT(*) FROM {cf}"), &[])
+                        .await?
+                        .try_get(0)?;
+                    anyhow::Ok(value)
+                })?;
+                Ok(count.try_into()?)
+            }
+        }
+    }
+
+    /// Roughly how many bytes the database takes up on disk and in memory. For
+    /// Postgres, this leaves out paste contents in S3.
+    pub fn estimate_size(&self) -> Result<u64> {
+        match self {
+            Self::RocksDb { db, .. } => {
+                let mut size = 0;
+                for cf_name in COLUMN_FAMILIES {
+                    let cf = db.cf_handle(cf_name).unwrap();
+                    for property in [
+                        "rocksdb.total-sst-files-size",
+                        "rocksdb.cur-size-all-mem-tables",
+                    ] {
+                        size += db.property_int_value_cf(cf, property)?.unwrap_or_default();
+                    }
+                }
+                Ok(size)
+            }
+            Self::Sqlite(conn) => {
+                let conn = conn.lock().unwrap();
+                let page_count: i64 =
+                    conn.query_row("PRAGMA page_count", params![], |row| row.get(0))?;
+                let page_size: i64 =
+                    conn.query_row("PRAGMA page_size", params![], |row| row.get(0))?;
+                Ok((page_count * page_size).try_into()?)
+            }
+            Self::Postgres { pool, .. } => {
+                let size: i64 = block_on(async {
+                    let value = pool
+                        .get()
+                        .await?
+                        .query_one("SELECT pg_database_size(current_database())", &[])
+                        .await?
+                        .try_get(0)?;
+                    anyhow::Ok(value)
+                })?;
+                Ok(size.try_into()?)
+            }
+        }
+    }
+}
+
+/// Waits for a future from the synchronous code that uses the database,
+/// blocking only the worker thread that it's on.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| Handle::current().block_on(future))
+}
+
+fn blob_path(key: &[u8]) -> ObjectPath {
+    ObjectPath::from(String::from_utf8_lossy(key).as_ref())
+}
@@ -0,0 +1,1227 @@
+#![warn(clippy::nursery, clippy::pedantic)]
+// The library is only split out so that the integration tests can build the
+// router, not for use elsewhere.
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::error_handling::HandleError;
+use axum::extract::{Extension, Path, TypedHeader};
+use axum::handler::Handler;
+use axum::http::header::{ACCEPT, ACCEPT_RANGES, CONTENT_RANGE, EXPIRES, RANGE, RETRY_AFTER};
+use axum::http::HeaderValue;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, get_service, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use futures::stream::StreamExt;
+use headers::HeaderMap;
+use lazy_static::lazy_static;
+use omegaupload_common::crypto::get_csrng;
+use omegaupload_common::{
+    base64, Expiration, NotBefore, PasteTtl, ServerInfo, Tombstone, TombstoneReason,
+    UploadResponse, Url, API_ENDPOINT, MAX_DOWNLOADS_HEADER_NAME, NOTIFY_HEADER_NAME,
+    NOT_BEFORE_HEADER_NAME, OWNER_TOKEN_HEADER_NAME, PASTE_TTL_HEADER_NAME, TOMBSTONE_HEADER_NAME,
+};
+use rand::Rng;
+use rocksdb::{Options, DB};
+use signal_hook::consts::SIGUSR1;
+use signal_hook_tokio::Signals;
+use tokio::task::{self, JoinHandle};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::services::{ServeDir, ServeFile};
+use tracing::{error, instrument, trace};
+use tracing::{info, warn};
+
+use crate::config::{Backend, Config, DefaultExpiration};
+use crate::db::Database;
+use crate::load::{Load, ReservedBody};
+use crate::paste::PasteMeta;
+use crate::short_code::ShortCode;
+use crate::storage::{blobs, Store};
+
+mod admin;
+pub mod config;
+pub mod db;
+pub mod load;
+mod notify;
+mod paste;
+mod preview;
+pub mod short_code;
+pub mod storage;
+mod sweeper;
+
+const BLOB_CF_NAME: &str = "blob";
+const DOWNLOADS_CF_NAME: &str = "downloads";
+const META_CF_NAME: &str = "meta";
+const NOTIFY_CF_NAME: &str = "notify";
+const OWNER_CF_NAME: &str = "owner";
+const TOMBSTONE_CF_NAME: &str = "tombstone";
+const TRANSFER_CF_NAME: &str = "transfer";
+
+/// [`omegaupload_common::PASTE_SIZE_LIMIT`], for comparing against lengths.
+#[allow(clippy::cast_possible_truncation)]
+const PASTE_SIZE_LIMIT: usize = omegaupload_common::PASTE_SIZE_LIMIT as usize;
+
+lazy_static! {
+    static ref MAX_PASTE_AGE: chrono::Duration = chrono::Duration::days(1);
+}
+
+pub const SHORT_CODE_SIZE: usize = 12;
+
+/// Serves pastes until the server is stopped.
+pub async fn run(config: Config) -> Result<()> {
+    const PASTE_DB_PATH: &str = "database";
+    const SQLITE_DB_PATH: &str = "database.sqlite3";
+
+    let config = Arc::new(config);
+
+    let durability = config.durability;
+    let db = Arc::new(match config.backend {
+        Backend::RocksDb => Database::open_rocksdb(PASTE_DB_PATH, durability)?,
+        Backend::Sqlite => Database::open_sqlite(SQLITE_DB_PATH, durability)?,
+        Backend::Postgres => {
+            let postgres = config.postgres.as_ref().expect("postgres to be configured");
+            Database::open_postgres(postgres, durability).await?
+        }
+    });
+    if config.backend == Backend::Postgres && config.storage.keeps_pastes_locally() {
+        warn!("Pastes in memory or a directory are only seen by the server that has them");
+    }
+
+    storage::init(config.storage.clone())?;
+    if db.is_shared() {
+        tokio::spawn(sweeper::run::<SHORT_CODE_SIZE>(
+            Arc::clone(&db),
+            config.sweep_interval,
+        ));
+    } else {
+        set_up_expirations::<SHORT_CODE_SIZE>(&db);
+    }
+
+    if let (Some(interval), Backend::RocksDb) = (durability.wal_flush_interval, config.backend) {
+        tokio::spawn(flush_wal_periodically(
+            Arc::clone(&db),
+            interval,
+            durability.sync_writes,
+        ));
+    }
+
+    let signals = Signals::new(&[SIGUSR1])?;
+    let signals_handle = signals.handle();
+    let signals_task = tokio::spawn(handle_signals(signals, Arc::clone(&db)));
+
+    let load = Arc::new(Load::new(
+        config.max_upload_bytes,
+        config.max_download_bytes,
+    ));
+    let router = router::<SHORT_CODE_SIZE>(Arc::clone(&db), Arc::clone(&load), Arc::clone(&config));
+
+    // The admin listener shouldn't take down the public one if it fails.
+    if let Some(admin) = config.admin.clone() {
+        tokio::spawn(async move {
+            if let Err(e) = admin::serve::<SHORT_CODE_SIZE>(admin, db, load).await {
+                error!("Failed to serve admin endpoints: {e}");
+            }
+        });
+    }
+
+    let mut server = axum::Server::bind(&config.bind)
+        .http1_keepalive(config.keep_alive.is_some())
+        .tcp_keepalive(config.keep_alive);
+    server = if config.http2 {
+        server
+            .http2_max_concurrent_streams(config.http2_max_streams)
+            .http2_keep_alive_interval(config.keep_alive)
+            .http2_adaptive_window(true)
+    } else {
+        server.http1_only(true)
+    };
+    info!(
+        "Now serving on {} (HTTP/2 {})",
+        config.bind,
+        if config.http2 { "enabled" } else { "disabled" }
+    );
+    server.serve(router.into_make_service()).await?;
+
+    // Must be called for correct shutdown
+    if config.backend == Backend::RocksDb {
+        DB::destroy(&Options::default(), PASTE_DB_PATH)?;
+    }
+
+    signals_handle.close();
+    signals_task.await?;
+    Ok(())
+}
+
+/// The public routes, serving pastes with `N` character short codes from
+/// `db`. Storage must be [set up](storage::init) first.
+pub fn router<const N: usize>(db: Arc<Database>, load: Arc<Load>, config: Arc<Config>) -> Router {
+    let root_service = HandleError::new(get_service(ServeDir::new("static")), |_| async {
+        Ok::<_, Infallible>(StatusCode::NOT_FOUND)
+    });
+
+    let index_service = HandleError::new(get_service(ServeFile::new("index.html")), |_| async {
+        Ok::<_, Infallible>(StatusCode::NOT_FOUND)
+    });
+
+    // Service workers can only control pages at or below their own path.
+    let service_worker_service =
+        HandleError::new(get_service(ServeFile::new("static/sw.js")), |_| async {
+            Ok::<_, Infallible>(StatusCode::NOT_FOUND)
+        });
+
+    let reserve_upload = middleware::from_fn(load::reserve_upload);
+
+    let mut router = Router::new()
+        .route(
+            "/",
+            post(upload::<N>.layer(reserve_upload.clone())).get_service(index_service.clone()),
+        )
+        .route_service("/sw.js", service_worker_service)
+        .route(&format!("{API_ENDPOINT}/info"), get(info))
+        .route_service("/:code", index_service)
+        .nest_service("/static", root_service)
+        .route(
+            &format!("{API_ENDPOINT}/:code"),
+            get(paste::<N>)
+                .head(paste_metadata::<N>)
+                .put(update::<N>.layer(reserve_upload))
+                .delete(delete::<N>),
+        )
+        .layer(axum::Extension(db))
+        .layer(axum::Extension(load));
+    if let Some(max_concurrency) = config.max_concurrency {
+        router = router.layer(GlobalConcurrencyLimitLayer::new(max_concurrency));
+    }
+    router.layer(axum::Extension(config))
+}
+
+// See https://link.eddie.sh/5JHlD
+#[allow(clippy::cognitive_complexity)]
+fn set_up_expirations<const N: usize>(db: &Arc<Database>) {
+    let mut corrupted = 0;
+    let mut lost = 0;
+    let mut expired = 0;
+    let mut pending = 0;
+
+    info!("Setting up cleanup timers, please wait...");
+
+    let db_ref = Arc::clone(db);
+
+    for item in db.entries(META_CF_NAME).unwrap() {
+        let (key, value) = item.unwrap();
+        let key: [u8; N] = (*key).try_into().unwrap();
+
+        let meta = if let Ok(meta) = PasteMeta::from_bytes(&value) {
+            meta
+        } else {
+            corrupted += 1;
+            delete_entry(Arc::clone(&db_ref), key);
+            continue;
+        };
+
+        if !blobs().exists(meta.store, &key) {
+            lost += 1;
+            delete_entry(Arc::clone(&db_ref), key);
+            continue;
+        }
+        let expiration = meta.expiration;
+
+        let expiration_time = match expiration {
+            Expiration::BurnAfterReading => {
+                warn!("Found unbounded burn after reading. Defaulting to max age");
+                Utc::now() + *MAX_PASTE_AGE
+            }
+            Expiration::ReadWindow(_) => {
+                warn!("Found unbounded read window. Defaulting to max age");
+                Utc::now() + *MAX_PASTE_AGE
+            }
+            Expiration::BurnAfterReadingWithDeadline(deadline)
+            | Expiration::ReadWindowWithDeadline(_, deadline) => deadline,
+            Expiration::UnixTime(time) => time,
+        };
+
+        let sleep_duration = (expiration_time - Utc::now()).to_std().unwrap_or_default();
+        if sleep_duration == Duration::default() {
+            expired += 1;
+            delete_entry(Arc::clone(&db_ref), key);
+        } else {
+            pending += 1;
+            schedule_expiration(Arc::clone(&db_ref), key, expiration_time);
+        }
+    }
+
+    if corrupted == 0 {
+        info!("No corrupted pastes found.");
+    } else {
+        warn!("Found {corrupted} corrupted pastes.");
+    }
+
+    if lost != 0 {
+        warn!("Found {lost} pastes whose contents didn't survive a restart.");
+    }
+    info!("Found {expired} expired pastes.");
+    info!("Found {pending} active pastes.");
+
+    let mut tombstones = 0;
+    for item in db.entries(TOMBSTONE_CF_NAME).unwrap() {
+        let (key, value) = item.unwrap();
+        let key: [u8; N] = (*key).try_into().unwrap();
+        match bincode::deserialize::<Tombstone>(&value) {
+            Ok(tombstone) => {
+                tombstones += 1;
+                schedule_tombstone_removal(Arc::clone(&db_ref), key, tombstone);
+            }
+            Err(_) => {
+                let _ = db.delete(TOMBSTONE_CF_NAME, &key);
+            }
+        }
+    }
+
+    info!("Found {tombstones} tombstones.");
+    info!("Cleanup timers have been initialized.");
+}
+
+/// Writes the buffered write-ahead log to the disk every `interval`.
+async fn flush_wal_periodically(db: Arc<Database>, interval: Duration, sync: bool) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let db = Arc::clone(&db);
+        match task::spawn_blocking(move || db.flush_wal(sync)).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => warn!("Failed to flush the write-ahead log: {e}"),
+            Err(e) => error!("Failed to join handle: {e}"),
+        }
+    }
+}
+
+async fn handle_signals(mut signals: Signals, db: Arc<Database>) {
+    while let Some(signal) = signals.next().await {
+        if signal == SIGUSR1 {
+            match db.entries(META_CF_NAME) {
+                Ok(entries) => info!("Active paste count: {}", entries.count()),
+                Err(e) => warn!("Failed to count pastes: {e}"),
+            }
+        }
+    }
+}
+
+#[instrument(skip(db, config, headers, body), err)]
+async fn upload<const N: usize>(
+    Extension(db): Extension<Arc<Database>>,
+    Extension(config): Extension<Arc<Config>>,
+    maybe_expires: Option<TypedHeader<Expiration>>,
+    maybe_not_before: Option<TypedHeader<NotBefore>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    if body.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Operators may require uploads to say when they expire.
+    let requested = match maybe_expires {
+        Some(TypedHeader(expiration)) => expiration,
+        None => config
+            .default_expiration
+            .expiration()
+            .ok_or(StatusCode::BAD_REQUEST)?,
+    };
+    validate_expiration(requested)?;
+    let burns = matches!(
+        requested,
+        Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_)
+    );
+
+    // Only burn after reading pastes are read in a way worth notifying about.
+    let notify = match headers.get(&*NOTIFY_HEADER_NAME) {
+        Some(value) => {
+            let url = notify::parse(value, &config.notify_hosts).filter(|_| burns);
+            Some(url.ok_or(StatusCode::BAD_REQUEST)?)
+        }
+        None => None,
+    };
+
+    // Burn after reading pastes can only be downloaded once anyways.
+    let max_downloads = match headers.get(&*MAX_DOWNLOADS_HEADER_NAME) {
+        Some(value) => {
+            let max = value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse::<u32>().ok())
+                .filter(|max| *max > 0 && !burns);
+            Some(max.ok_or(StatusCode::BAD_REQUEST)?)
+        }
+        None => None,
+    };
+
+    validate_size(&db, &body)?;
+
+    let expires = with_deadline(requested);
+
+    let not_before = maybe_not_before.map(|header| header.0);
+    validate_not_before(not_before, expires)?;
+
+    let mut new_key = None;
+
+    trace!("Generating short code...");
+
+    // Try finding a code; give up after 1000 attempts
+    // Statistics show that this is very unlikely to happen
+    for i in 0..1000 {
+        let code: ShortCode<N> = get_csrng().sample(short_code::Generator);
+        if code.is_reserved(&config.reserved_codes) {
+            continue;
+        }
+        let db = Arc::clone(&db);
+        let key = code.as_bytes();
+        // Codes of burned or deleted pastes aren't reused while their tombstones
+        // exist.
+        let query = task::spawn_blocking(move || {
+            db.may_contain(META_CF_NAME, &key) || db.may_contain(TOMBSTONE_CF_NAME, &key)
+        })
+        .await;
+        if matches!(query, Ok(false)) {
+            new_key = Some(key);
+            trace!("Found new key after {i} attempts.");
+            break;
+        }
+    }
+
+    let key = if let Some(key) = new_key {
+        key
+    } else {
+        error!("Failed to generate a valid short code!");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let owner_token = OwnerToken::random();
+
+    let db_ref = Arc::clone(&db);
+    match task::spawn_blocking(move || {
+        let store = blobs().store_for(expires);
+        blobs().put(&db_ref, store, &key, body)?;
+        db_ref.put(OWNER_CF_NAME, &key, &owner_token.0)?;
+        if let Some(notify) = notify {
+            db_ref.put(NOTIFY_CF_NAME, &key, notify.as_str().as_bytes())?;
+        }
+        let meta = PasteMeta {
+            not_before,
+            max_downloads,
+            ..PasteMeta::new(expires, store)
+        };
+        if db_ref.put(META_CF_NAME, &key, &meta.to_bytes()).is_err() {
+            // try and roll back on metadata write failure
+            blobs().delete(&db_ref, store, &key)?;
+            db_ref.delete(OWNER_CF_NAME, &key)?;
+            db_ref.delete(NOTIFY_CF_NAME, &key)?;
+        }
+        Result::<_, anyhow::Error>::Ok(())
+    })
+    .await
+    {
+        Ok(Ok(_)) => {
+            if let Expiration::UnixTime(expiration_time)
+            | Expiration::BurnAfterReadingWithDeadline(expiration_time)
+            | Expiration::ReadWindowWithDeadline(_, expiration_time) = expires
+            {
+                schedule_expiration(db, key, expiration_time);
+            }
+        }
+        e => {
+            error!("Failed to insert paste into db: {e:?}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // Let the client know when the paste actually expires, as it may differ
+    // from what was requested.
+    let mut map = HeaderMap::new();
+    insert_expiration(&mut map, expires);
+    map.insert(&*OWNER_TOKEN_HEADER_NAME, owner_token.to_header_value());
+
+    // Older clients expect only the short code.
+    let accepts_json = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("application/json"));
+    if !accepts_json {
+        return Ok((map, Vec::from(key)).into_response());
+    }
+
+    let code = String::from_utf8_lossy(&key).into_owned();
+    let url = config
+        .public_url
+        .as_ref()
+        .and_then(|public_url| public_url.join(&code).ok());
+    let path = url
+        .as_ref()
+        .map_or_else(|| format!("/{code}"), |url| url.path().to_string());
+    Ok((map, Json(UploadResponse { code, path, url })).into_response())
+}
+
+/// Replaces the contents of a paste, and optionally its expiration, if the
+/// owner token from when it was uploaded is provided. Pastes that can't be
+/// read can't be updated either, so that they aren't brought back.
+#[instrument(skip(db, headers, body), err)]
+async fn update<const N: usize>(
+    Extension(db): Extension<Arc<Database>>,
+    Path(url): Path<ShortCode<N>>,
+    maybe_expires: Option<TypedHeader<Expiration>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<HeaderMap, StatusCode> {
+    if body.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    validate_size(&db, &body)?;
+
+    let provided_token = headers
+        .get(&*OWNER_TOKEN_HEADER_NAME)
+        .ok_or(StatusCode::UNAUTHORIZED)?
+        .as_bytes()
+        .to_vec();
+
+    let new_expiration = match maybe_expires {
+        Some(TypedHeader(expiration)) => {
+            validate_expiration(expiration)?;
+            Some(with_deadline(expiration))
+        }
+        None => None,
+    };
+
+    let key = url.as_bytes();
+    let current = match fetch_metadata(Arc::clone(&db), key).await {
+        Err(StatusCode::NOT_FOUND) if tombstone_headers(&db, key).is_some() => {
+            return Err(StatusCode::GONE);
+        }
+        res => res?,
+    };
+
+    // Checked before the paste's state, so that only its owner learns whether
+    // it's locked. Pastes uploaded before owner tokens existed can never be
+    // updated.
+    let owner_token = db
+        .get(OWNER_CF_NAME, &key)
+        .map_err(|e| {
+            error!("Failed to read owner token: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::FORBIDDEN)?;
+    if !OwnerToken::matches(&owner_token, &provided_token) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if current.is_locked() {
+        return Err(StatusCode::LOCKED);
+    }
+
+    let db_ref = Arc::clone(&db);
+    let expires = task::spawn_blocking(move || {
+        let internal_error = |e| {
+            error!("Failed to update paste: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        let expires = new_expiration.unwrap_or(current.expiration);
+        validate_not_before(current.not_before, expires)?;
+        // Pastes only move between stores if their lifetime was changed.
+        let store = if new_expiration.is_some() {
+            blobs().store_for(expires)
+        } else {
+            current.store
+        };
+        blobs().put(&db_ref, store, &key, body).map_err(|e| {
+            error!("Failed to update paste contents: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let meta = PasteMeta {
+            expiration: expires,
+            store,
+            ..current
+        };
+        db_ref
+            .put(META_CF_NAME, &key, &meta.to_bytes())
+            .map_err(internal_error)?;
+        if store != current.store {
+            if let Err(e) = blobs().delete(&db_ref, current.store, &key) {
+                warn!("Failed to remove moved paste contents: {e}");
+            }
+        }
+        Ok(expires)
+    })
+    .await
+    .map_err(|e| {
+        error!("Failed to join handle: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })??;
+
+    if new_expiration.is_some() {
+        if let Expiration::UnixTime(expiration_time)
+        | Expiration::BurnAfterReadingWithDeadline(expiration_time)
+        | Expiration::ReadWindowWithDeadline(_, expiration_time) = expires
+        {
+            schedule_expiration(db, key, expiration_time);
+        }
+    }
+
+    let mut map = HeaderMap::new();
+    insert_expiration(&mut map, expires);
+    Ok(map)
+}
+
+/// Describes what this server accepts, so that clients can reject uploads that
+/// would fail before encrypting them.
+#[allow(clippy::unused_async)]
+async fn info(
+    Extension(db): Extension<Arc<Database>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> Json<ServerInfo> {
+    let expirations = Expiration::variants()
+        .iter()
+        .filter(|variant| {
+            Expiration::from_str(variant).map_or(false, |expiration| !exceeds_max_age(expiration))
+        })
+        .map(|variant| (*variant).to_string())
+        .collect();
+    Json(ServerInfo {
+        paste_size_limit: paste_size_limit(&db),
+        expirations,
+        expiration_required: config.default_expiration == DefaultExpiration::Required,
+    })
+}
+
+fn validate_expiration(expiration: Expiration) -> Result<(), StatusCode> {
+    if exceeds_max_age(expiration) {
+        warn!("{expiration:?} exceeds allowed paste lifetime");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
+
+/// Rejects time locks on pastes that would expire before they unlock, as
+/// they could never be read.
+fn validate_not_before(
+    not_before: Option<NotBefore>,
+    expiration: Expiration,
+) -> Result<(), StatusCode> {
+    match (not_before, expiration.deadline()) {
+        (Some(not_before), Some(deadline)) if not_before.0 >= deadline => {
+            warn!("{not_before:?} is after the paste expires");
+            Err(StatusCode::BAD_REQUEST)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn exceeds_max_age(expiration: Expiration) -> bool {
+    matches!(expiration, Expiration::UnixTime(time) if (time - Utc::now()) > *MAX_PASTE_AGE)
+        || expiration
+            .read_window()
+            .map_or(false, |window| window > *MAX_PASTE_AGE)
+}
+
+/// Gives pastes that only expire once they're read a deadline, so that they
+/// don't last forever if they're never read.
+fn with_deadline(expiration: Expiration) -> Expiration {
+    let deadline = Utc::now() + *MAX_PASTE_AGE;
+    match expiration {
+        Expiration::BurnAfterReading => Expiration::BurnAfterReadingWithDeadline(deadline),
+        Expiration::ReadWindow(window) => Expiration::ReadWindowWithDeadline(window, deadline),
+        expiration => expiration,
+    }
+}
+
+/// Pastes must be smaller than this, which is lower for databases with a
+/// smaller limit on values.
+fn paste_size_limit(db: &Database) -> u64 {
+    omegaupload_common::paste_size_limit(db.max_value_size().map(|max| max as u64))
+}
+
+fn validate_size(db: &Database, body: &Bytes) -> Result<(), StatusCode> {
+    if body.len() as u64 >= paste_size_limit(db) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    Ok(())
+}
+
+/// A secret handed to the uploader of a paste, which permits them to replace
+/// the contents of the paste later.
+struct OwnerToken([u8; 32]);
+
+impl OwnerToken {
+    fn random() -> Self {
+        let mut token = [0; 32];
+        get_csrng().fill(&mut token);
+        Self(token)
+    }
+
+    fn to_header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&base64::encode(self.0)).expect("base64 to be a valid header")
+    }
+
+    /// Compares a stored token with one provided by the client in constant
+    /// time.
+    fn matches(stored: &[u8], provided: &[u8]) -> bool {
+        let provided = match base64::decode(provided) {
+            Ok(provided) => provided,
+            Err(_) => return false,
+        };
+        if stored.len() != provided.len() {
+            return false;
+        }
+        stored
+            .iter()
+            .zip(provided.iter())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+}
+
+#[instrument(skip(db, load, config, headers), err)]
+async fn paste<const N: usize>(
+    Extension(db): Extension<Arc<Database>>,
+    Extension(load): Extension<Arc<Load>>,
+    Extension(config): Extension<Arc<Config>>,
+    Path(url): Path<ShortCode<N>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let key = url.as_bytes();
+    let metadata = match fetch_metadata(Arc::clone(&db), key).await {
+        Err(StatusCode::NOT_FOUND) => {
+            let map = tombstone_headers(&db, key).ok_or(StatusCode::NOT_FOUND)?;
+            return Ok((StatusCode::GONE, map).into_response());
+        }
+        res => res?,
+    };
+
+    // Checked before burning the paste, so that it's only burned once it
+    // unlocks.
+    if metadata.is_locked() {
+        return Ok((StatusCode::LOCKED, locked_headers(&metadata)).into_response());
+    }
+
+    // Link previews would use up the paste before anyone could read it.
+    if metadata.is_used_up_by_reads() && preview::is_preview(&headers, &config.preview_agents) {
+        info!("Refusing to serve a paste that reads use up to a preview");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (paste, reservation) = {
+        let paste = match blobs().get(&db, metadata.store, &key) {
+            Ok(Some(paste)) => paste,
+            Ok(None) => return Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                error!("Failed to fetch paste contents: {e}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+
+        // Checked before burning the paste, so that shed requests can retry.
+        let reservation = match load.downloads.try_reserve(paste.len()) {
+            Some(reservation) => reservation,
+            None => {
+                warn!(
+                    "Shedding download of {} bytes with {} bytes in flight",
+                    paste.len(),
+                    load.downloads.in_use()
+                );
+                return Ok(load::overloaded());
+            }
+        };
+        (paste, reservation)
+    };
+
+    let mut map = HeaderMap::new();
+
+    // Check if we need to burn after read
+    if matches!(
+        metadata.expiration,
+        Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_)
+    ) {
+        insert_expiration(&mut map, metadata.expiration);
+        // Read before the entry is deleted along with it.
+        let notify = db
+            .get(NOTIFY_CF_NAME, &key)
+            .ok()
+            .flatten()
+            .and_then(|url| Url::parse(std::str::from_utf8(&url).ok()?).ok());
+        delete_entry(Arc::clone(&db), key).await.map_err(|e| {
+            error!("Failed to join handle: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })??;
+        add_tombstone(&db, key, TombstoneReason::Burned);
+        if let Some(notify) = notify {
+            notify::send(notify, String::from_utf8_lossy(&key).into_owned());
+        }
+
+        // Ranges aren't supported for burned pastes, as only the first request
+        // would succeed.
+        let body = ReservedBody::new(paste, reservation);
+        return Ok((StatusCode::OK, map, axum::body::boxed(body)).into_response());
+    }
+
+    map.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    // Requests with a range we don't understand get the whole paste, which is
+    // permitted by RFC 7233.
+    let range = headers
+        .get(RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_range(value, paste.len()));
+
+    let sent = range.map_or(paste.len(), |(start, end)| end - start + 1);
+
+    // Requests for more than is left of the download limit use up the paste.
+    let downloads_left = downloads_left(&db, key, metadata.max_downloads, paste.len())?;
+    if downloads_left.map_or(false, |left| sent as u64 > left) {
+        exhaust(&db, key).await?;
+        let map = tombstone_headers(&db, key).unwrap_or_default();
+        return Ok((StatusCode::GONE, map).into_response());
+    }
+
+    if !record_transfer(&db, &config, key, paste.len(), sent)? {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    // Only counted once the request is sure to be served, so that refused
+    // requests don't use up the paste or start its read window.
+    let used_up = match downloads_left {
+        Some(left) => {
+            record_download(&db, key, sent)?;
+            left == sent as u64
+        }
+        None => false,
+    };
+    let metadata = if used_up {
+        exhaust(&db, key).await?;
+        metadata
+    } else {
+        start_read_window(&db, key, metadata)?
+    };
+    insert_expiration(&mut map, metadata.expiration);
+
+    if let Some((start, end)) = range {
+        let content_range = format!("bytes {start}-{end}/{}", paste.len());
+        map.insert(
+            CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).expect("content range to be valid"),
+        );
+        let body = ReservedBody::new(paste.slice(start..=end), reservation);
+        return Ok((StatusCode::PARTIAL_CONTENT, map, axum::body::boxed(body)).into_response());
+    }
+
+    let body = ReservedBody::new(paste, reservation);
+    Ok((StatusCode::OK, map, axum::body::boxed(body)).into_response())
+}
+
+/// Starts the read window of a paste that's being read for the first time,
+/// after which it expires like any other paste. Pastes without a read window
+/// are returned as they are.
+fn start_read_window<const N: usize>(
+    db: &Arc<Database>,
+    key: [u8; N],
+    metadata: PasteMeta,
+) -> Result<PasteMeta, StatusCode> {
+    let window = match metadata.expiration.read_window() {
+        Some(window) => window,
+        None => return Ok(metadata),
+    };
+
+    let expiration_time = Utc::now() + window;
+    let metadata = PasteMeta {
+        expiration: Expiration::UnixTime(expiration_time),
+        ..metadata
+    };
+    db.put(META_CF_NAME, &key, &metadata.to_bytes())
+        .map_err(|e| {
+            error!("Failed to start read window: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    schedule_expiration(Arc::clone(db), key, expiration_time);
+    Ok(metadata)
+}
+
+/// Returns how many more bytes of a paste of `len` bytes may be downloaded
+/// under `max_downloads`, or `None` if its downloads aren't limited. Downloads
+/// are counted in bytes, so that splitting them into ranges doesn't get around
+/// the limit.
+fn downloads_left<const N: usize>(
+    db: &Database,
+    key: [u8; N],
+    max_downloads: Option<u32>,
+    len: usize,
+) -> Result<Option<u64>, StatusCode> {
+    let max_downloads = match max_downloads {
+        Some(max_downloads) => max_downloads,
+        None => return Ok(None),
+    };
+    let limit = u64::from(max_downloads).saturating_mul(len as u64);
+    Ok(Some(limit.saturating_sub(downloaded(db, key)?)))
+}
+
+/// Counts `sent` bytes of a paste as downloaded. Like transfers, concurrent
+/// downloads may each see the old count.
+fn record_download<const N: usize>(
+    db: &Database,
+    key: [u8; N],
+    sent: usize,
+) -> Result<(), StatusCode> {
+    let downloaded = downloaded(db, key)?.saturating_add(sent as u64);
+    db.put(DOWNLOADS_CF_NAME, &key, &downloaded.to_le_bytes())
+        .map_err(|e| {
+            error!("Failed to record paste download: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Returns how many bytes of a paste have been downloaded.
+fn downloaded<const N: usize>(db: &Database, key: [u8; N]) -> Result<u64, StatusCode> {
+    Ok(db
+        .get(DOWNLOADS_CF_NAME, &key)
+        .map_err(|e| {
+            error!("Failed to read paste downloads: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .and_then(|downloaded| downloaded.try_into().ok())
+        .map_or(0, u64::from_le_bytes))
+}
+
+/// Deletes a paste that has been downloaded as many times as it may be.
+async fn exhaust<const N: usize>(db: &Arc<Database>, key: [u8; N]) -> Result<(), StatusCode> {
+    delete_entry(Arc::clone(db), key).await.map_err(|e| {
+        error!("Failed to join handle: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })??;
+    add_tombstone(db, key, TombstoneReason::Exhausted);
+    Ok(())
+}
+
+/// Adds `sent` bytes to what's been sent for a paste of `len` bytes, returning
+/// whether it's still under its [transfer cap](crate::config::TransferCap).
+/// Concurrent downloads may each see the old total, so the cap can be exceeded
+/// by the downloads in flight.
+fn record_transfer<const N: usize>(
+    db: &Database,
+    config: &Config,
+    key: [u8; N],
+    len: usize,
+    sent: usize,
+) -> Result<bool, StatusCode> {
+    let internal_error = |e| {
+        error!("Failed to record paste transfer: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let total = db
+        .get(TRANSFER_CF_NAME, &key)
+        .map_err(internal_error)?
+        .and_then(|total| total.try_into().ok())
+        .map_or(0, u64::from_le_bytes);
+    let new_total = total.saturating_add(sent as u64);
+    if let Some(limit) = config.transfer_cap.limit(len as u64) {
+        if new_total > limit {
+            warn!("Refusing to send {sent} more bytes of a paste that has sent {total} bytes");
+            return Ok(false);
+        }
+    }
+    db.put(TRANSFER_CF_NAME, &key, &new_total.to_le_bytes())
+        .map_err(internal_error)?;
+    Ok(true)
+}
+
+/// Returns a paste's expiration without returning or burning the paste, so that
+/// clients can warn users before they view burn after reading pastes.
+#[instrument(skip(db), err)]
+async fn paste_metadata<const N: usize>(
+    Extension(db): Extension<Arc<Database>>,
+    Path(url): Path<ShortCode<N>>,
+) -> Result<(StatusCode, HeaderMap), StatusCode> {
+    let key = url.as_bytes();
+    let metadata = match fetch_metadata(Arc::clone(&db), key).await {
+        Err(StatusCode::NOT_FOUND) => {
+            let map = tombstone_headers(&db, key).ok_or(StatusCode::NOT_FOUND)?;
+            return Ok((StatusCode::GONE, map));
+        }
+        res => res?,
+    };
+
+    let mut map = HeaderMap::new();
+    insert_expiration(&mut map, metadata.expiration);
+    if let Some(not_before) = metadata.not_before {
+        map.insert(&*NOT_BEFORE_HEADER_NAME, (&not_before).into());
+    }
+    Ok((StatusCode::OK, map))
+}
+
+/// Describes when a paste expires, both as a time and as how many seconds it has
+/// left, for clients whose clocks don't agree with the server's.
+fn insert_expiration(map: &mut HeaderMap, expiration: Expiration) {
+    map.insert(EXPIRES, expiration.into());
+    if let Some(ttl) = PasteTtl::remaining(expiration) {
+        map.insert(&*PASTE_TTL_HEADER_NAME, (&ttl).into());
+    }
+}
+
+/// Headers for a time-locked paste that can't be read yet, telling clients
+/// when to try again.
+fn locked_headers(metadata: &PasteMeta) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    insert_expiration(&mut map, metadata.expiration);
+    if let Some(not_before) = metadata.not_before {
+        map.insert(&*NOT_BEFORE_HEADER_NAME, (&not_before).into());
+        // Rounded up, so that retrying after this many seconds succeeds.
+        let wait = (not_before.0 - Utc::now()).num_seconds().max(0) + 1;
+        map.insert(RETRY_AFTER, HeaderValue::from(wait));
+    }
+    map
+}
+
+/// Fetches a paste's metadata, deleting the paste if it has expired.
+async fn fetch_metadata<const N: usize>(
+    db: Arc<Database>,
+    key: [u8; N],
+) -> Result<PasteMeta, StatusCode> {
+    let metadata = {
+        let query_result = db.get(META_CF_NAME, &key).map_err(|e| {
+            error!("Failed to fetch initial query: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let data = match query_result {
+            Some(data) => data,
+            None => return Err(StatusCode::NOT_FOUND),
+        };
+
+        PasteMeta::from_bytes(&data).map_err(|e| {
+            error!("Failed to deserialize paste metadata: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+    };
+
+    // Expired pastes may not have been removed yet, such as if the server
+    // restarted after their removal was scheduled.
+    if metadata.is_expired() {
+        delete_entry(db, key).await.map_err(|e| {
+            error!("Failed to join handle: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })??;
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(metadata)
+}
+
+/// Records why a paste no longer exists, and forgets it after `MAX_PASTE_AGE`.
+fn add_tombstone<const N: usize>(db: &Arc<Database>, key: [u8; N], reason: TombstoneReason) {
+    let tombstone = Tombstone::now(reason);
+    let data = bincode::serialize(&tombstone).expect("bincode to serialize");
+    if let Err(e) = db.put(TOMBSTONE_CF_NAME, &key, &data) {
+        warn!("Failed to record tombstone: {e}");
+        return;
+    }
+    schedule_tombstone_removal(Arc::clone(db), key, tombstone);
+}
+
+/// Returns the headers describing why a paste no longer exists, if it's known.
+fn tombstone_headers<const N: usize>(db: &Database, key: [u8; N]) -> Option<HeaderMap> {
+    let tombstone: Tombstone = db
+        .get(TOMBSTONE_CF_NAME, &key)
+        .ok()
+        .flatten()
+        .and_then(|data| bincode::deserialize(&data).ok())?;
+
+    let mut map = HeaderMap::new();
+    map.insert(&*TOMBSTONE_HEADER_NAME, (&tombstone).into());
+    Some(map)
+}
+
+fn schedule_tombstone_removal<const N: usize>(
+    db: Arc<Database>,
+    key: [u8; N],
+    tombstone: Tombstone,
+) {
+    // Tombstones in a shared database are left to the sweeper.
+    if db.is_shared() {
+        return;
+    }
+    let sleep_duration = (tombstone.time + *MAX_PASTE_AGE - Utc::now())
+        .to_std()
+        .unwrap_or_default();
+    task::spawn(async move {
+        tokio::time::sleep(sleep_duration).await;
+        if let Err(e) = db.delete(TOMBSTONE_CF_NAME, &key) {
+            warn!("Failed to remove tombstone: {e}");
+        }
+    });
+}
+
+/// Parses a `Range` header containing a single byte range, returning the
+/// inclusive start and end of the range. Returns `None` if the range is
+/// malformed, unsatisfiable, or contains multiple ranges.
+fn parse_range(value: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let last = len.checked_sub(1)?;
+    let (start, end) = if start.is_empty() {
+        // A suffix range, for the last `end` bytes.
+        let suffix_len: usize = end.parse().ok()?;
+        if suffix_len == 0 {
+            return None;
+        }
+        (len.saturating_sub(suffix_len), last)
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = if end.is_empty() {
+            last
+        } else {
+            end.parse::<usize>().ok()?.min(last)
+        };
+        (start, end)
+    };
+
+    (start <= end).then_some((start, end))
+}
+
+/// Deletes a paste if the owner token from when it was uploaded is provided.
+/// Pastes uploaded before owner tokens existed can be deleted by anyone.
+#[instrument(skip(db, headers))]
+async fn delete<const N: usize>(
+    Extension(db): Extension<Arc<Database>>,
+    Path(url): Path<ShortCode<N>>,
+    headers: HeaderMap,
+) -> StatusCode {
+    let key = url.as_bytes();
+    let owner_token = match db.get(OWNER_CF_NAME, &key) {
+        Ok(owner_token) => owner_token,
+        Err(e) => {
+            error!("Failed to fetch owner token: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+    if let Some(owner_token) = owner_token {
+        let provided_token = match headers.get(&*OWNER_TOKEN_HEADER_NAME) {
+            Some(provided_token) => provided_token,
+            None => return StatusCode::UNAUTHORIZED,
+        };
+        if !OwnerToken::matches(&owner_token, provided_token.as_bytes()) {
+            return StatusCode::FORBIDDEN;
+        }
+    }
+
+    let existed = db
+        .get(META_CF_NAME, &key)
+        .map_or(false, |meta| meta.is_some());
+    match delete_entry(Arc::clone(&db), key).await {
+        Ok(Ok(())) => {
+            if existed {
+                add_tombstone(&db, key, TombstoneReason::Deleted);
+            }
+            StatusCode::OK
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn delete_entry<const N: usize>(
+    db: Arc<Database>,
+    key: [u8; N],
+) -> JoinHandle<Result<(), StatusCode>> {
+    task::spawn_blocking(move || delete_entry_blocking(&db, key))
+}
+
+/// Deletes an entry from the current thread, for callers that are already on a
+/// blocking thread.
+fn delete_entry_blocking<const N: usize>(db: &Database, key: [u8; N]) -> Result<(), StatusCode> {
+    // Pastes with unreadable metadata can only be in the database.
+    let store = db
+        .get(META_CF_NAME, &key)
+        .ok()
+        .flatten()
+        .and_then(|meta| PasteMeta::from_bytes(&meta).ok())
+        .map_or(Store::Database, |meta| meta.store);
+    if let Err(e) = blobs().delete(db, store, &key) {
+        warn!("{e}");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = db.delete(META_CF_NAME, &key) {
+        warn!("{e}");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = db.delete(OWNER_CF_NAME, &key) {
+        warn!("{e}");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = db.delete(TRANSFER_CF_NAME, &key) {
+        warn!("{e}");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = db.delete(NOTIFY_CF_NAME, &key) {
+        warn!("{e}");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = db.delete(DOWNLOADS_CF_NAME, &key) {
+        warn!("{e}");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(())
+}
+
+/// Deletes an entry once it expires. As the expiration of a paste may be
+/// extended after this is called, the expiration is checked again before
+/// deleting the entry.
+fn schedule_expiration<const N: usize>(
+    db: Arc<Database>,
+    key: [u8; N],
+    expiration_time: DateTime<Utc>,
+) {
+    // Pastes in a shared database are left to the sweeper.
+    if db.is_shared() {
+        return;
+    }
+    let sleep_duration = (expiration_time - Utc::now()).to_std().unwrap_or_default();
+    task::spawn(async move {
+        tokio::time::sleep(sleep_duration).await;
+
+        let expiration = db
+            .get(META_CF_NAME, &key)
+            .ok()
+            .flatten()
+            .and_then(|meta| PasteMeta::from_bytes(&meta).ok());
+        let still_valid = expiration.map_or(false, |meta| {
+            meta.expiration.deadline().is_some() && !meta.is_expired()
+        });
+        if !still_valid {
+            delete_entry(db, key);
+        }
+    });
+}
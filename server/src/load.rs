@@ -0,0 +1,188 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Load shedding, so that the server turns requests away while it holds too
+//! much paste data in memory, instead of being killed when memory runs out.
+//!
+//! Pastes are held in memory in full while they're uploaded and downloaded, so
+//! the bytes of requests in flight make up most of the server's memory use.
+
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::body::{Bytes, HttpBody};
+use axum::extract::Extension;
+use axum::http::header::{CONTENT_LENGTH, RETRY_AFTER};
+use axum::http::{HeaderMap, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http_body::SizeHint;
+use tracing::warn;
+
+use crate::PASTE_SIZE_LIMIT;
+
+/// How long clients are asked to wait before retrying a shed request.
+const RETRY_AFTER_SECS: u64 = 5;
+
+/// The paste bytes that uploads and downloads in flight hold in memory.
+pub struct Load {
+    pub uploads: Arc<MemoryBudget>,
+    pub downloads: Arc<MemoryBudget>,
+}
+
+impl Load {
+    pub fn new(max_upload_bytes: Option<usize>, max_download_bytes: Option<usize>) -> Self {
+        Self {
+            uploads: Arc::new(MemoryBudget::new(max_upload_bytes)),
+            downloads: Arc::new(MemoryBudget::new(max_download_bytes)),
+        }
+    }
+}
+
+pub struct MemoryBudget {
+    in_use: AtomicUsize,
+    limit: Option<usize>,
+}
+
+impl MemoryBudget {
+    const fn new(limit: Option<usize>) -> Self {
+        Self {
+            in_use: AtomicUsize::new(0),
+            limit,
+        }
+    }
+
+    pub fn in_use(&self) -> usize {
+        self.in_use.load(Ordering::Relaxed)
+    }
+
+    /// Reserves `bytes` until the returned reservation is dropped, if that
+    /// stays within the limit. A request is always let through when nothing
+    /// else is in flight, so that pastes larger than the limit still work.
+    pub fn try_reserve(self: &Arc<Self>, bytes: usize) -> Option<Reservation> {
+        self.in_use
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_use| {
+                let total = in_use.saturating_add(bytes);
+                match self.limit {
+                    Some(limit) if in_use != 0 && total > limit => None,
+                    _ => Some(total),
+                }
+            })
+            .ok()?;
+        Some(Reservation {
+            budget: Arc::clone(self),
+            bytes,
+        })
+    }
+}
+
+pub struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: usize,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.in_use.fetch_sub(self.bytes, Ordering::AcqRel);
+    }
+}
+
+/// Sheds uploads before their body is read, reserving what their
+/// `Content-Length` claims, or the largest paste allowed if it's missing.
+pub async fn reserve_upload<B>(
+    Extension(load): Extension<Arc<Load>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let bytes = content_length(request.headers())
+        .unwrap_or(PASTE_SIZE_LIMIT)
+        .min(PASTE_SIZE_LIMIT);
+    if let Some(_reservation) = load.uploads.try_reserve(bytes) {
+        return next.run(request).await;
+    }
+    warn!(
+        "Shedding upload of {bytes} bytes with {} bytes in flight",
+        load.uploads.in_use()
+    );
+    overloaded()
+}
+
+fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// The response to requests that were shed.
+pub fn overloaded() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS))],
+    )
+        .into_response()
+}
+
+/// A response body that keeps its bytes reserved until they've been sent,
+/// rather than only until the handler returns.
+pub struct ReservedBody {
+    data: Bytes,
+    _reservation: Reservation,
+}
+
+impl ReservedBody {
+    /// How much is handed to hyper at once. Hyper only asks for more once it's
+    /// written out what it has, so the reservation lasts until the last chunk.
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    pub const fn new(data: Bytes, reservation: Reservation) -> Self {
+        Self {
+            data,
+            _reservation: reservation,
+        }
+    }
+}
+
+impl HttpBody for ReservedBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.data.is_empty() {
+            return Poll::Ready(None);
+        }
+        let len = self.data.len().min(Self::CHUNK_SIZE);
+        Poll::Ready(Some(Ok(self.data.split_to(len))))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.data.len() as u64)
+    }
+}
@@ -0,0 +1,75 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tells uploaders when their burn after reading pastes are read, if they
+//! asked to be told when uploading them.
+//!
+//! Notifications are posted to a URL that the uploader provides, such as an
+//! ntfy topic or a webhook. As this makes the server send requests on behalf of
+//! anyone who can upload, only hosts that the operator
+//! [allows](crate::config::Config::notify_hosts) can be notified.
+
+use std::time::Duration;
+
+use axum::http::HeaderValue;
+use lazy_static::lazy_static;
+use omegaupload_common::Url;
+use reqwest::Client;
+use tracing::warn;
+
+/// How long to wait for the notified server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref CLIENT: Client = Client::builder()
+        .timeout(TIMEOUT)
+        // Redirects could send the notification to a host that isn't allowed.
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("notification client to build");
+}
+
+/// Parses a notification URL from an upload, returning `None` if it's invalid
+/// or its host isn't allowed.
+pub fn parse(value: &HeaderValue, allowed_hosts: &[String]) -> Option<Url> {
+    let url = Url::parse(value.to_str().ok()?).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = url.host_str()?;
+    allowed_hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        .then_some(url)
+}
+
+/// Posts a notification that the paste with `code` was read, without waiting
+/// for it to be sent. Failures are only logged, as the paste is already gone.
+pub fn send(url: Url, code: String) {
+    tokio::spawn(async move {
+        // The URL isn't logged, as only the uploader should know of it.
+        match CLIENT
+            .post(url)
+            .body(format!("Paste {code} was read."))
+            .send()
+            .await
+        {
+            Ok(res) if res.status().is_success() => (),
+            Ok(res) => warn!("Burn notification was rejected with {}", res.status()),
+            Err(e) => warn!("Failed to send burn notification: {e}"),
+        }
+    });
+}
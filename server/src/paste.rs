@@ -0,0 +1,201 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The metadata stored alongside each paste.
+
+use anyhow::{bail, Result};
+use omegaupload_common::{Expiration, NotBefore};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Store;
+
+/// Marks metadata written with a version. Metadata from before it had one is a
+/// bare bincode [`Expiration`], which starts with the low byte of its variant
+/// index, so it never starts with this.
+const VERSIONED: u8 = 0xFF;
+const VERSION: u8 = 4;
+
+/// What's stored in the meta column family for each paste.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PasteMeta {
+    pub expiration: Expiration,
+    /// Where the paste's contents are. Pastes from before version 2 are always
+    /// in the database.
+    pub store: Store,
+    /// When the paste may first be read, if it's time-locked. Pastes from
+    /// before version 3 never are.
+    pub not_before: Option<NotBefore>,
+    /// How many times the paste may be downloaded, if its uploader limited it.
+    /// Pastes from before version 4 never are.
+    pub max_downloads: Option<u32>,
+}
+
+impl PasteMeta {
+    pub const fn new(expiration: Expiration, store: Store) -> Self {
+        Self {
+            expiration,
+            store,
+            not_before: None,
+            max_downloads: None,
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![VERSIONED, VERSION];
+        bytes.extend(bincode::serialize(&self).expect("bincode to serialize"));
+        bytes
+    }
+
+    /// Reads metadata written by [`Self::to_bytes`], or by servers from before
+    /// it was versioned.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes {
+            [VERSIONED, VERSION, rest @ ..] => Ok(bincode::deserialize(rest)?),
+            // Version 3 didn't have download limits.
+            [VERSIONED, 3, rest @ ..] => {
+                let (expiration, store, not_before) = bincode::deserialize(rest)?;
+                Ok(Self {
+                    not_before,
+                    ..Self::new(expiration, store)
+                })
+            }
+            // Version 2 didn't have time locks.
+            [VERSIONED, 2, rest @ ..] => {
+                let (expiration, store) = bincode::deserialize(rest)?;
+                Ok(Self::new(expiration, store))
+            }
+            // Version 1 only had the expiration.
+            [VERSIONED, 1, rest @ ..] => {
+                Ok(Self::new(bincode::deserialize(rest)?, Store::Database))
+            }
+            [VERSIONED, version, ..] => bail!("Unknown paste metadata version {version}"),
+            legacy => Ok(Self::new(bincode::deserialize(legacy)?, Store::Database)),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expiration.is_expired()
+    }
+
+    /// Whether the paste is time-locked, and can't be read yet.
+    pub fn is_locked(&self) -> bool {
+        self.not_before
+            .map_or(false, |not_before| !not_before.has_passed())
+    }
+
+    /// Whether reading the paste uses it up, by burning it, starting its read
+    /// window, or counting towards its download limit.
+    pub fn is_used_up_by_reads(&self) -> bool {
+        self.max_downloads.is_some()
+            || self.expiration.read_window().is_some()
+            || matches!(
+                self.expiration,
+                Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_)
+            )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn expirations() -> [Expiration; 3] {
+        let time = Utc.with_ymd_and_hms(2022, 1, 2, 3, 4, 5).unwrap();
+        [
+            Expiration::BurnAfterReading,
+            Expiration::BurnAfterReadingWithDeadline(time),
+            Expiration::UnixTime(time),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let not_before = NotBefore(Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap());
+        for expiration in expirations() {
+            for store in [Store::Database, Store::Memory, Store::Directory] {
+                for (not_before, max_downloads) in [(None, None), (Some(not_before), Some(3))] {
+                    let meta = PasteMeta {
+                        not_before,
+                        max_downloads,
+                        ..PasteMeta::new(expiration, store)
+                    };
+                    assert_eq!(PasteMeta::from_bytes(&meta.to_bytes()).unwrap(), meta);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_version_3_metadata() {
+        let not_before = Some(NotBefore(
+            Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+        ));
+        for expiration in expirations() {
+            let mut v3 = vec![VERSIONED, 3];
+            v3.extend(bincode::serialize(&(expiration, Store::Memory, not_before)).unwrap());
+            assert_eq!(
+                PasteMeta::from_bytes(&v3).unwrap(),
+                PasteMeta {
+                    not_before,
+                    ..PasteMeta::new(expiration, Store::Memory)
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn reads_version_2_metadata() {
+        for expiration in expirations() {
+            let mut v2 = vec![VERSIONED, 2];
+            v2.extend(bincode::serialize(&(expiration, Store::Memory)).unwrap());
+            assert_eq!(
+                PasteMeta::from_bytes(&v2).unwrap(),
+                PasteMeta::new(expiration, Store::Memory)
+            );
+        }
+    }
+
+    #[test]
+    fn reads_version_1_metadata() {
+        for expiration in expirations() {
+            let mut v1 = vec![VERSIONED, 1];
+            v1.extend(bincode::serialize(&expiration).unwrap());
+            assert_eq!(
+                PasteMeta::from_bytes(&v1).unwrap(),
+                PasteMeta::new(expiration, Store::Database)
+            );
+        }
+    }
+
+    #[test]
+    fn reads_unversioned_metadata() {
+        for expiration in expirations() {
+            let legacy = bincode::serialize(&expiration).unwrap();
+            assert_eq!(
+                PasteMeta::from_bytes(&legacy).unwrap(),
+                PasteMeta::new(expiration, Store::Database)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(PasteMeta::from_bytes(&[VERSIONED, VERSION + 1, 0, 0, 0, 0]).is_err());
+        assert!(PasteMeta::from_bytes(&[]).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tells requests that people make apart from ones that software makes on its
+//! own, such as chat apps unfurling a link or browsers prefetching it.
+//!
+//! Reading a burn after reading paste deletes it, so a link to its API URL
+//! that's pasted into a chat would be burned by the chat's preview before
+//! anyone could read it. The web frontend never fetches these pastes until the
+//! reader confirms, and the server refuses to serve them to requests that look
+//! like previews.
+
+use axum::http::header::USER_AGENT;
+use axum::http::HeaderMap;
+
+/// User agents of link previews, matched anywhere in the `User-Agent` header
+/// and ignoring case. Operators can add more with `OMEGAUPLOAD_PREVIEW_AGENTS`.
+pub const PREVIEW_AGENTS: &[&str] = &[
+    "Slackbot",
+    "Slack-ImgProxy",
+    "Discordbot",
+    "TelegramBot",
+    "WhatsApp",
+    "facebookexternalhit",
+    "Twitterbot",
+    "LinkedInBot",
+    "SkypeUriPreview",
+    "Mastodon",
+    "redditbot",
+    "Iframely",
+    "Embedly",
+];
+
+/// Headers that browsers set on speculative requests, such as prefetches,
+/// which nobody may ever look at.
+const PURPOSE_HEADERS: &[&str] = &["sec-purpose", "purpose", "x-purpose", "x-moz"];
+
+/// Whether a request looks like it was made without anyone reading the
+/// response, matching user agents in [`PREVIEW_AGENTS`] or `extra_agents`.
+///
+/// Browsers also say how a request was made with `Sec-Fetch-Mode`. The web
+/// frontend fetches pastes with `cors` or `same-origin`, while navigating to an
+/// API URL or embedding it, such as in a preview, uses other modes. Clients
+/// that aren't browsers don't send it, and aren't refused for it.
+pub fn is_preview(headers: &HeaderMap, extra_agents: &[String]) -> bool {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_ascii_lowercase)
+    };
+
+    if let Some(agent) = header(USER_AGENT.as_str()) {
+        let is_preview_agent = PREVIEW_AGENTS
+            .iter()
+            .copied()
+            .chain(extra_agents.iter().map(String::as_str))
+            .any(|preview| agent.contains(&preview.to_ascii_lowercase()));
+        if is_preview_agent {
+            return true;
+        }
+    }
+
+    let is_speculative = PURPOSE_HEADERS.iter().any(|name| {
+        header(name).map_or(false, |purpose| {
+            ["prefetch", "prerender", "preview"]
+                .iter()
+                .any(|speculative| purpose.contains(speculative))
+        })
+    });
+    if is_speculative {
+        return true;
+    }
+
+    header("sec-fetch-mode").map_or(false, |mode| {
+        !matches!(mode.as_str(), "cors" | "same-origin")
+    })
+}
@@ -21,12 +21,26 @@ use rand::Rng;
 use serde::de::{Unexpected, Visitor};
 use serde::Deserialize;
 
+/// Codes that are never handed out, as they're, or may one day be, routes of
+/// their own. Operators can reserve more with `OMEGAUPLOAD_RESERVED_CODES`.
+pub const RESERVED: &[&str] = &["api", "static", "admin", "healthz", "sw.js"];
+
 pub struct ShortCode<const N: usize>([ShortCodeChar; N]);
 
 impl<const N: usize> ShortCode<N> {
     pub fn as_bytes(&self) -> [u8; N] {
         self.0.map(|v| v.0 as u8)
     }
+
+    /// Whether the code is in [`RESERVED`] or `extra`, ignoring case.
+    pub fn is_reserved(&self, extra: &[String]) -> bool {
+        let short_code = String::from_iter(self.0.map(|v| v.0));
+        RESERVED
+            .iter()
+            .copied()
+            .chain(extra.iter().map(String::as_str))
+            .any(|reserved| reserved.eq_ignore_ascii_case(&short_code))
+    }
 }
 
 impl<const N: usize> Debug for ShortCode<N> {
@@ -64,9 +78,9 @@ impl<'de, const N: usize> Deserialize<'de> for ShortCode<N> {
                 // This is fine, it'll get overwritten anyways.
                 let mut output = [ShortCodeChar('\0'); N];
                 for (i, c) in v.char_indices() {
-                    output[i] = c.try_into().map_err(|_| {
-                        E::invalid_value(Unexpected::Char(c), &"a valid short code character")
-                    })?;
+                    output[i] = c
+                        .try_into()
+                        .map_err(|e: &str| E::invalid_value(Unexpected::Char(c), &e))?;
                 }
 
                 Ok(ShortCode(output))
@@ -99,9 +113,8 @@ impl<'de> Deserialize<'de> for ShortCodeChar {
             where
                 E: serde::de::Error,
             {
-                v.try_into().map_err(|_| {
-                    E::invalid_value(Unexpected::Char(v as char), &"a valid short code character")
-                })
+                v.try_into()
+                    .map_err(|e: &str| E::invalid_value(Unexpected::Char(v), &e))
             }
         }
 
@@ -112,11 +125,21 @@ impl<'de> Deserialize<'de> for ShortCodeChar {
 impl TryFrom<char> for ShortCodeChar {
     type Error = &'static str;
 
+    /// Accepts characters of the alphabet, as well as letters that are easily
+    /// mistaken for its digits when a code is typed by hand, such as `S` or
+    /// `s` for `5`. Other characters, such as `0` or `O`, don't look like any
+    /// one character of the alphabet, so they're rejected.
     fn try_from(v: char) -> Result<Self, Self::Error> {
+        let v = match v {
+            'B' | 'b' => '8',
+            'S' | 's' => '5',
+            'Z' | 'z' => '2',
+            v => v,
+        };
         if v.is_ascii() && ALPHABET.contains(&(v as u8)) {
             Ok(Self(v))
         } else {
-            Err("a valid short code character")
+            Err(EXPECTED_CHAR)
         }
     }
 }
@@ -125,6 +148,10 @@ pub struct Generator;
 
 const ALPHABET: &[u8; 32] = b"23456789CFGHJMPQRVWXcfghjmpqrvwx";
 
+/// Describes what a short code may contain when one is rejected, so that
+/// someone typing one by hand can spot their mistake.
+const EXPECTED_CHAR: &str = "one of 23456789CFGHJMPQRVWXcfghjmpqrvwx";
+
 impl Distribution<ShortCodeChar> for Generator {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> ShortCodeChar {
         let value = rng.gen_range(0..32);
@@ -144,3 +171,42 @@ impl<const N: usize> Distribution<ShortCode<N>> for Generator {
         ShortCode(arr)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(code: &str) -> Result<[u8; 12], serde_json::Error> {
+        serde_json::from_value::<ShortCode<12>>(code.into()).map(|code| code.as_bytes())
+    }
+
+    #[test]
+    fn expected_char_lists_the_alphabet() {
+        assert!(EXPECTED_CHAR.ends_with(std::str::from_utf8(ALPHABET).unwrap()));
+    }
+
+    #[test]
+    fn accepts_the_alphabet() {
+        assert_eq!(&parse("23456789CFGH").unwrap(), b"23456789CFGH");
+        assert_eq!(&parse("JMPQRVWXcfgh").unwrap(), b"JMPQRVWXcfgh");
+        assert_eq!(&parse("jmpqrvwx2345").unwrap(), b"jmpqrvwx2345");
+    }
+
+    #[test]
+    fn corrects_lookalike_letters() {
+        assert_eq!(&parse("BbSsZzCcFfGg").unwrap(), b"885522CcFfGg");
+    }
+
+    #[test]
+    fn rejects_other_characters() {
+        for code in [
+            "0CCCCCCCCCCC",
+            "OCCCCCCCCCCC",
+            "1CCCCCCCCCCC",
+            "lCCCCCCCCCCC",
+        ] {
+            let err = parse(code).unwrap_err().to_string();
+            assert!(err.contains(EXPECTED_CHAR), "{err}");
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Where paste contents are kept. Short-lived pastes can be kept out of the
+//! database, which otherwise writes them out several times as it compacts,
+//! only for them to be deleted soon after.
+//!
+//! Which store a paste was put in is recorded in its metadata, so pastes stay
+//! readable when the storage classes change.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use chrono::Utc;
+use omegaupload_common::Expiration;
+use serde::{Deserialize, Serialize};
+
+use crate::config::StorageClasses;
+use crate::db::Database;
+use crate::BLOB_CF_NAME;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Store {
+    /// The blob column family, which survives restarts.
+    #[default]
+    Database,
+    /// The server's memory, which is lost when the server restarts.
+    Memory,
+    /// Files in the configured directory, such as a tmpfs mount.
+    Directory,
+}
+
+static BLOBS: OnceLock<Blobs> = OnceLock::new();
+
+/// Sets up the stores, which must be done before any paste is read or written.
+pub fn init(classes: StorageClasses) -> Result<()> {
+    if let Some(directory) = &classes.directory {
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+    }
+    let blobs = Blobs {
+        classes,
+        memory: Mutex::default(),
+    };
+    if BLOBS.set(blobs).is_err() {
+        unreachable!("stores to only be set up once");
+    }
+    Ok(())
+}
+
+pub fn blobs() -> &'static Blobs {
+    BLOBS.get().expect("stores to be set up")
+}
+
+pub struct Blobs {
+    classes: StorageClasses,
+    memory: Mutex<HashMap<Vec<u8>, Bytes>>,
+}
+
+impl Blobs {
+    /// The store that pastes with this expiration should be put in.
+    pub fn store_for(&self, expiration: Expiration) -> Store {
+        match expiration {
+            // Pastes with a read window are as short-lived once they're read.
+            Expiration::BurnAfterReading
+            | Expiration::BurnAfterReadingWithDeadline(_)
+            | Expiration::ReadWindow(_)
+            | Expiration::ReadWindowWithDeadline(..) => self.classes.burn_after_reading,
+            Expiration::UnixTime(time) => {
+                let lifetime = time - Utc::now();
+                self.classes
+                    .by_lifetime
+                    .iter()
+                    .find(|(max_lifetime, _)| lifetime <= *max_lifetime)
+                    .map_or(Store::Database, |(_, store)| *store)
+            }
+        }
+    }
+
+    pub fn put(&self, db: &Database, store: Store, key: &[u8], data: Bytes) -> Result<()> {
+        match store {
+            Store::Database => {
+                let data = bincode::serialize(&data).expect("bincode to serialize");
+                db.put(BLOB_CF_NAME, key, &data)?;
+            }
+            Store::Memory => {
+                self.memory.lock().unwrap().insert(key.to_vec(), data);
+            }
+            Store::Directory => std::fs::write(self.path(key)?, data)?,
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, db: &Database, store: Store, key: &[u8]) -> Result<Option<Bytes>> {
+        match store {
+            Store::Database => db
+                .with_value(BLOB_CF_NAME, key, |data| bincode::deserialize(data))?
+                .transpose()
+                .map_err(Into::into),
+            Store::Memory => Ok(self.memory.lock().unwrap().get(key).cloned()),
+            Store::Directory => match std::fs::read(self.path(key)?) {
+                Ok(data) => Ok(Some(Bytes::from(data))),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    pub fn delete(&self, db: &Database, store: Store, key: &[u8]) -> Result<()> {
+        match store {
+            Store::Database => {
+                db.delete(BLOB_CF_NAME, key)?;
+            }
+            Store::Memory => {
+                self.memory.lock().unwrap().remove(key);
+            }
+            Store::Directory => match std::fs::remove_file(self.path(key)?) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            },
+        }
+        Ok(())
+    }
+
+    /// Whether a paste's contents are still there, which they may not be for
+    /// stores that don't survive restarts. Pastes in the database are assumed
+    /// to be.
+    pub fn exists(&self, store: Store, key: &[u8]) -> bool {
+        match store {
+            Store::Database => true,
+            Store::Memory => self.memory.lock().unwrap().contains_key(key),
+            Store::Directory => self.path(key).is_ok_and(|path| path.exists()),
+        }
+    }
+
+    /// How many bytes of paste contents are kept in memory.
+    pub fn memory_usage(&self) -> usize {
+        self.memory.lock().unwrap().values().map(Bytes::len).sum()
+    }
+
+    /// Pastes put in the directory store stay there, so this fails if it's no
+    /// longer configured.
+    fn path(&self, key: &[u8]) -> Result<PathBuf> {
+        let directory = self
+            .classes
+            .directory
+            .as_ref()
+            .context("No storage directory is configured")?;
+        // Short codes are always alphanumeric, so they're safe file names.
+        Ok(directory.join(String::from_utf8_lossy(key).as_ref()))
+    }
+}
@@ -0,0 +1,104 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Deletes expired pastes and old tombstones from a database that several
+//! servers share. Timers set by each server would miss pastes uploaded to the
+//! others and delete the same ones several times, so instead only the server
+//! holding the [sweeper lock](SweeperLock) deletes them, in periodic sweeps.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use omegaupload_common::Tombstone;
+use tokio::task;
+use tracing::{error, info, warn};
+
+use crate::db::{Database, SweeperLock};
+use crate::paste::PasteMeta;
+use crate::{delete_entry_blocking, MAX_PASTE_AGE, META_CF_NAME, TOMBSTONE_CF_NAME};
+
+/// Sweeps every `interval` while this server holds the sweeper lock, trying to
+/// take it every `interval` otherwise.
+pub async fn run<const N: usize>(db: Arc<Database>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    let mut lock: Option<SweeperLock> = None;
+    loop {
+        interval.tick().await;
+
+        if !lock.as_ref().map_or(false, SweeperLock::is_held) {
+            if lock.take().is_some() {
+                warn!("Lost the sweeper lock");
+            }
+            match db.try_lock_sweeper().await {
+                Ok(Some(new_lock)) => {
+                    info!("Took the sweeper lock, so this server deletes expired pastes");
+                    lock = Some(new_lock);
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Failed to take the sweeper lock: {e}");
+                    continue;
+                }
+            }
+        }
+
+        let db = Arc::clone(&db);
+        match task::spawn_blocking(move || sweep::<N>(&db)).await {
+            Ok(Ok((0, 0))) => (),
+            Ok(Ok((pastes, tombstones))) => {
+                info!("Swept {pastes} expired pastes and {tombstones} tombstones.");
+            }
+            Ok(Err(e)) => warn!("Failed to sweep expired pastes: {e}"),
+            Err(e) => error!("Failed to join handle: {e}"),
+        }
+    }
+}
+
+/// Deletes pastes that have expired or whose metadata can't be read, and
+/// tombstones that have outlived the longest paste lifetime. Returns how many
+/// of each were deleted.
+fn sweep<const N: usize>(db: &Database) -> Result<(usize, usize)> {
+    let mut pastes = 0;
+    for item in db.entries(META_CF_NAME)? {
+        let (key, value) = item?;
+        let key: [u8; N] = match (*key).try_into() {
+            Ok(key) => key,
+            Err(_) => continue,
+        };
+        // Pastes that failed to delete are tried again in the next sweep.
+        if PasteMeta::from_bytes(&value).map_or(true, |meta| meta.is_expired())
+            && delete_entry_blocking(db, key).is_ok()
+        {
+            pastes += 1;
+        }
+    }
+
+    let mut tombstones = 0;
+    for item in db.entries(TOMBSTONE_CF_NAME)? {
+        let (key, value) = item?;
+        let outlived = bincode::deserialize::<Tombstone>(&value).map_or(true, |tombstone| {
+            tombstone.time + *MAX_PASTE_AGE <= Utc::now()
+        });
+        if outlived {
+            db.delete(TOMBSTONE_CF_NAME, &key)?;
+            tombstones += 1;
+        }
+    }
+
+    Ok((pastes, tombstones))
+}
@@ -0,0 +1,769 @@
+// OmegaUpload Zero Knowledge File Hosting
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! End-to-end tests of the public API, run against the router with a database
+//! that's only kept in memory.
+
+use std::sync::{Arc, Once};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::header::{
+    HeaderName, ACCEPT, ACCEPT_RANGES, CONTENT_RANGE, EXPIRES, RANGE, RETRY_AFTER, USER_AGENT,
+};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::Router;
+use bytes::Bytes;
+use chrono::Utc;
+use omegaupload_common::{
+    Expiration, NotBefore, PasteTtl, ServerInfo, Tombstone, TombstoneReason, UploadResponse,
+    API_ENDPOINT, MAX_DOWNLOADS_HEADER_NAME, NOTIFY_HEADER_NAME, NOT_BEFORE_HEADER_NAME,
+    OWNER_TOKEN_HEADER_NAME, PASTE_TTL_HEADER_NAME, TOMBSTONE_HEADER_NAME,
+};
+use omegaupload_server::config::{
+    Config, DefaultExpiration, Durability, StorageClasses, TransferCap,
+};
+use omegaupload_server::db::Database;
+use omegaupload_server::load::Load;
+use omegaupload_server::{router, storage, SHORT_CODE_SIZE};
+use tower::ServiceExt;
+
+/// A short code that's never handed out, as it's only made of one character.
+const MISSING_CODE: &str = "222222222222";
+
+struct TestServer(Router);
+
+impl TestServer {
+    fn new() -> Self {
+        Self::with_config(Config::from_env().unwrap())
+    }
+
+    fn with_config(config: Config) -> Self {
+        // Storage is global, and every test keeps pastes in its own database.
+        static STORAGE: Once = Once::new();
+        STORAGE.call_once(|| storage::init(StorageClasses::default()).unwrap());
+
+        let db = Database::open_sqlite(":memory:", Durability::default()).unwrap();
+        Self(router::<SHORT_CODE_SIZE>(
+            Arc::new(db),
+            Arc::new(Load::new(None, None)),
+            Arc::new(config),
+        ))
+    }
+
+    async fn send(&self, request: Request<Body>) -> (StatusCode, HeaderMap, Bytes) {
+        let response = self.0.clone().oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        (parts.status, parts.headers, body)
+    }
+
+    async fn request(&self, method: Method, path: &str) -> (StatusCode, HeaderMap, Bytes) {
+        let request = Request::builder().method(method).uri(path);
+        self.send(request.body(Body::empty()).unwrap()).await
+    }
+
+    /// Uploads a paste, returning its short code and the response headers.
+    async fn upload(
+        &self,
+        body: &'static [u8],
+        expiration: Option<Expiration>,
+    ) -> (String, HeaderMap) {
+        let mut request = Request::builder().method(Method::POST).uri("/");
+        if let Some(expiration) = expiration {
+            request = request.header("burn-after", HeaderValue::from(expiration));
+        }
+        let (status, headers, code) = self.send(request.body(Body::from(body)).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        (String::from_utf8(code.to_vec()).unwrap(), headers)
+    }
+
+    async fn get(&self, code: &str) -> (StatusCode, HeaderMap, Bytes) {
+        self.request(Method::GET, &format!("{API_ENDPOINT}/{code}"))
+            .await
+    }
+}
+
+fn tombstone_reason(headers: &HeaderMap) -> TombstoneReason {
+    let tombstone = headers[&*TOMBSTONE_HEADER_NAME].to_str().unwrap();
+    Tombstone::try_from(tombstone).ok().unwrap().reason
+}
+
+fn in_a_second() -> Expiration {
+    Expiration::UnixTime(Utc::now() + chrono::Duration::seconds(1))
+}
+
+#[tokio::test]
+async fn uploads_and_downloads() {
+    let server = TestServer::new();
+    let (code, headers) = server.upload(b"hello world", None).await;
+    assert_eq!(code.len(), SHORT_CODE_SIZE);
+    assert!(headers.contains_key(EXPIRES));
+    assert!(headers.contains_key(&*OWNER_TOKEN_HEADER_NAME));
+
+    let (status, headers, body) = server.get(&code).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "hello world");
+    assert_eq!(headers[ACCEPT_RANGES], "bytes");
+    assert!(headers.contains_key(EXPIRES));
+
+    // Pastes that aren't burned can be read any number of times.
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn describes_remaining_lifetime() {
+    let server = TestServer::new();
+    let ttl = |headers: &HeaderMap| {
+        let ttl = headers[&*PASTE_TTL_HEADER_NAME].to_str().unwrap();
+        PasteTtl::try_from(ttl).ok().unwrap().0
+    };
+
+    let in_an_hour = Expiration::UnixTime(Utc::now() + chrono::Duration::hours(1));
+    let (code, headers) = server.upload(b"hello world", Some(in_an_hour)).await;
+    assert!((3590..=3600).contains(&ttl(&headers)));
+    let (_, headers, _) = server.get(&code).await;
+    assert!((3590..=3600).contains(&ttl(&headers)));
+    let (_, headers, _) = server
+        .request(Method::HEAD, &format!("{API_ENDPOINT}/{code}"))
+        .await;
+    assert!((3590..=3600).contains(&ttl(&headers)));
+}
+
+#[tokio::test]
+async fn uploads_describe_pastes_as_json() {
+    let server = TestServer::new();
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/")
+        .header(ACCEPT, "application/json")
+        .body(Body::from("hello world"))
+        .unwrap();
+    let (status, _, body) = server.send(request).await;
+    assert_eq!(status, StatusCode::OK);
+    let response: UploadResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.path, format!("/{}", response.code));
+    assert_eq!(response.url, None);
+    assert_eq!(server.get(&response.code).await.2, "hello world");
+}
+
+#[tokio::test]
+async fn rejects_empty_uploads() {
+    let server = TestServer::new();
+    let request = Request::builder().method(Method::POST).uri("/");
+    let (status, ..) = server.send(request.body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn unknown_pastes_are_not_found() {
+    let server = TestServer::new();
+    assert_eq!(server.get(MISSING_CODE).await.0, StatusCode::NOT_FOUND);
+    // Codes that could never be handed out aren't looked up at all.
+    assert_eq!(server.get("not-a-code").await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn burns_after_reading() {
+    let server = TestServer::new();
+    let (code, _) = server
+        .upload(b"secret", Some(Expiration::BurnAfterReading))
+        .await;
+
+    // Checking the expiration doesn't burn the paste.
+    let path = format!("{API_ENDPOINT}/{code}");
+    let (status, headers, _) = server.request(Method::HEAD, &path).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[EXPIRES], "0");
+
+    let (status, headers, body) = server.get(&code).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "secret");
+    // Only the first request could use a range, so none are offered.
+    assert!(!headers.contains_key(ACCEPT_RANGES));
+
+    let (status, headers, body) = server.get(&code).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert!(body.is_empty());
+    assert_eq!(tombstone_reason(&headers), TombstoneReason::Burned);
+
+    let (status, headers, _) = server.request(Method::HEAD, &path).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(tombstone_reason(&headers), TombstoneReason::Burned);
+}
+
+#[tokio::test]
+async fn link_previews_dont_burn_pastes() {
+    let server = TestServer::with_config(Config {
+        preview_agents: vec!["ExampleUnfurler".to_string()],
+        ..Config::from_env().unwrap()
+    });
+    let (code, _) = server
+        .upload(b"secret", Some(Expiration::BurnAfterReading))
+        .await;
+
+    let path = format!("{API_ENDPOINT}/{code}");
+    let previews = [
+        (
+            USER_AGENT,
+            "Slackbot-LinkExpanding 1.0 (+https://api.slack.com/robots)",
+        ),
+        (USER_AGENT, "Mozilla/5.0 (compatible; exampleunfurler/2.0)"),
+        (HeaderName::from_static("sec-purpose"), "prefetch"),
+        (HeaderName::from_static("sec-fetch-mode"), "navigate"),
+    ];
+    for (name, value) in previews {
+        let request = Request::builder().uri(&path).header(name, value);
+        let (status, _, body) = server.send(request.body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{value}");
+        assert!(body.is_empty());
+    }
+
+    // The web frontend's own requests are served.
+    let request = Request::builder()
+        .uri(&path)
+        .header(
+            USER_AGENT,
+            "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Firefox/120.0",
+        )
+        .header("sec-fetch-mode", "cors");
+    let (status, _, body) = server.send(request.body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "secret");
+    assert_eq!(server.get(&code).await.0, StatusCode::GONE);
+
+    // Pastes that reading doesn't use up are served to previews.
+    let (code, _) = server.upload(b"hello", None).await;
+    let request = Request::builder()
+        .uri(format!("{API_ENDPOINT}/{code}"))
+        .header(USER_AGENT, "Discordbot/2.0");
+    let (status, _, _) = server.send(request.body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn expires() {
+    let server = TestServer::new();
+    let (code, _) = server.upload(b"fleeting", Some(in_a_second())).await;
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let (status, headers, _) = server.get(&code).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // Expired pastes don't leave tombstones, unlike burned or deleted ones.
+    assert!(!headers.contains_key(&*TOMBSTONE_HEADER_NAME));
+}
+
+#[tokio::test]
+async fn read_windows() {
+    let server = TestServer::new();
+    let (code, headers) = server
+        .upload(b"one-shot", Some(Expiration::ReadWindow(1)))
+        .await;
+    assert_eq!(headers[EXPIRES], "read+1");
+
+    // Checking the expiration doesn't start the window.
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let (status, headers, _) = server
+        .request(Method::HEAD, &format!("{API_ENDPOINT}/{code}"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[EXPIRES], "read+1");
+
+    // The paste can be read again until the window ends.
+    let (status, headers, _) = server.get(&code).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(matches!(
+        Expiration::try_from(&headers[EXPIRES]),
+        Ok(Expiration::UnixTime(_))
+    ));
+    assert_eq!(server.get(&code).await.2, "one-shot");
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(server.get(&code).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn refused_reads_dont_start_read_windows() {
+    let server = TestServer::with_config(Config {
+        transfer_cap: TransferCap {
+            multiple: None,
+            bytes: Some(5),
+        },
+        ..Config::from_env().unwrap()
+    });
+    let (code, _) = server
+        .upload(b"one-shot", Some(Expiration::ReadWindow(1)))
+        .await;
+
+    assert_eq!(server.get(&code).await.0, StatusCode::TOO_MANY_REQUESTS);
+    let (status, headers, _) = server
+        .request(Method::HEAD, &format!("{API_ENDPOINT}/{code}"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[EXPIRES], "read+1");
+}
+
+#[tokio::test]
+async fn time_locks() {
+    let server = TestServer::new();
+    let upload = |not_before: NotBefore, expiration: Expiration| {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header("burn-after", HeaderValue::from(expiration))
+            .header(&*NOT_BEFORE_HEADER_NAME, HeaderValue::from(&not_before))
+            .body(Body::from("embargoed"))
+            .unwrap();
+        server.send(request)
+    };
+
+    let not_before = NotBefore(Utc::now() + chrono::Duration::seconds(1));
+    let (status, _, code) = upload(not_before, Expiration::BurnAfterReading).await;
+    assert_eq!(status, StatusCode::OK);
+    let code = String::from_utf8(code.to_vec()).unwrap();
+
+    // Locked pastes aren't burned by attempts to read them.
+    let (status, headers, _) = server.get(&code).await;
+    assert_eq!(status, StatusCode::LOCKED);
+    assert_eq!(headers[RETRY_AFTER], "1");
+    let (status, headers, _) = server
+        .request(Method::HEAD, &format!("{API_ENDPOINT}/{code}"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        NotBefore::try_from(headers[&*NOT_BEFORE_HEADER_NAME].to_str().unwrap()).ok(),
+        Some(not_before)
+    );
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let (status, _, body) = server.get(&code).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "embargoed");
+    assert_eq!(server.get(&code).await.0, StatusCode::GONE);
+
+    // Pastes that would expire before they unlock could never be read.
+    let not_before = NotBefore(Utc::now() + chrono::Duration::hours(1));
+    assert_eq!(
+        upload(not_before, in_a_second()).await.0,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn deletes() {
+    let server = TestServer::new();
+    let (code, headers) = server.upload(b"regrettable", None).await;
+    let owner_token = headers[&*OWNER_TOKEN_HEADER_NAME].clone();
+
+    let delete = |token: Option<HeaderValue>| {
+        let mut request = Request::builder()
+            .method(Method::DELETE)
+            .uri(format!("{API_ENDPOINT}/{code}"));
+        if let Some(token) = token {
+            request = request.header(&*OWNER_TOKEN_HEADER_NAME, token);
+        }
+        server.send(request.body(Body::empty()).unwrap())
+    };
+
+    assert_eq!(delete(None).await.0, StatusCode::UNAUTHORIZED);
+    let wrong_token = HeaderValue::from_static("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+    assert_eq!(delete(Some(wrong_token)).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(server.get(&code).await.2, "regrettable");
+
+    assert_eq!(delete(Some(owner_token)).await.0, StatusCode::OK);
+
+    let (status, headers, _) = server.get(&code).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(tombstone_reason(&headers), TombstoneReason::Deleted);
+}
+
+#[tokio::test]
+async fn serves_ranges() {
+    let server = TestServer::new();
+    let (code, _) = server.upload(b"0123456789", None).await;
+
+    let get_range = |range: &'static str| {
+        let request = Request::builder()
+            .uri(format!("{API_ENDPOINT}/{code}"))
+            .header(RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        server.send(request)
+    };
+
+    let (status, headers, body) = get_range("bytes=2-4").await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(headers[CONTENT_RANGE], "bytes 2-4/10");
+    assert_eq!(body, "234");
+
+    let (status, headers, body) = get_range("bytes=-3").await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(headers[CONTENT_RANGE], "bytes 7-9/10");
+    assert_eq!(body, "789");
+
+    // Ranges that can't be served get the whole paste instead.
+    for range in ["bytes=20-30", "bytes=0-1,4-5", "lines=1-2"] {
+        let (status, headers, body) = get_range(range).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key(CONTENT_RANGE));
+        assert_eq!(body, "0123456789");
+    }
+}
+
+#[tokio::test]
+async fn caps_transfers() {
+    let server = TestServer::with_config(Config {
+        transfer_cap: TransferCap {
+            multiple: Some(2),
+            bytes: None,
+        },
+        ..Config::from_env().unwrap()
+    });
+    let (code, _) = server.upload(b"0123456789", None).await;
+
+    let get_range = |range: &'static str| {
+        let request = Request::builder()
+            .uri(format!("{API_ENDPOINT}/{code}"))
+            .header(RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        server.send(request)
+    };
+
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+    assert_eq!(get_range("bytes=0-4").await.0, StatusCode::PARTIAL_CONTENT);
+    // Only 5 of the 20 bytes the paste may send are left.
+    assert_eq!(server.get(&code).await.0, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(get_range("bytes=5-9").await.0, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        get_range("bytes=0-0").await.0,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    // The cap is per paste.
+    let (other, _) = server.upload(b"0123456789", None).await;
+    assert_eq!(server.get(&other).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn limits_downloads() {
+    let server = TestServer::new();
+    let upload = |max_downloads: &'static str, expiration: Option<Expiration>| {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(&*MAX_DOWNLOADS_HEADER_NAME, max_downloads);
+        if let Some(expiration) = expiration {
+            request = request.header("burn-after", HeaderValue::from(expiration));
+        }
+        server.send(request.body(Body::from("0123456789")).unwrap())
+    };
+
+    let (status, _, code) = upload("2", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let code = String::from_utf8(code.to_vec()).unwrap();
+    let get_range = |range: &'static str| {
+        let request = Request::builder()
+            .uri(format!("{API_ENDPOINT}/{code}"))
+            .header(RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        server.send(request)
+    };
+
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+    // Ranges count for the bytes they send, so the last one uses up the paste.
+    assert_eq!(get_range("bytes=0-4").await.0, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(get_range("bytes=2-6").await.0, StatusCode::PARTIAL_CONTENT);
+
+    let (status, headers, body) = server.get(&code).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert!(body.is_empty());
+    assert_eq!(tombstone_reason(&headers), TombstoneReason::Exhausted);
+    assert_eq!(server.get(&code).await.0, StatusCode::GONE);
+
+    for (max_downloads, expiration) in [
+        ("0", None),
+        ("many", None),
+        ("2", Some(Expiration::BurnAfterReading)),
+    ] {
+        assert_eq!(
+            upload(max_downloads, expiration).await.0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
+
+#[tokio::test]
+async fn counts_downloads_through_ranges() {
+    let server = TestServer::with_config(Config {
+        transfer_cap: TransferCap {
+            multiple: None,
+            bytes: Some(15),
+        },
+        ..Config::from_env().unwrap()
+    });
+    let upload = |max_downloads: &'static str| {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header(&*MAX_DOWNLOADS_HEADER_NAME, max_downloads)
+            .body(Body::from("0123456789"))
+            .unwrap();
+        server.send(request)
+    };
+    let code_of = |(status, _, code): (StatusCode, HeaderMap, Bytes)| {
+        assert_eq!(status, StatusCode::OK);
+        String::from_utf8(code.to_vec()).unwrap()
+    };
+    let get_range = |code: &str, range: &'static str| {
+        let request = Request::builder()
+            .uri(format!("{API_ENDPOINT}/{code}"))
+            .header(RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        server.send(request)
+    };
+
+    // Ranges that never reach the end of the paste still count.
+    let code = code_of(upload("1").await);
+    let (status, _, body) = get_range(&code, "bytes=0-8").await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body, "012345678");
+    assert_eq!(
+        get_range(&code, "bytes=0-0").await.0,
+        StatusCode::PARTIAL_CONTENT
+    );
+    let (status, headers, _) = get_range(&code, "bytes=9-9").await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(tombstone_reason(&headers), TombstoneReason::Exhausted);
+
+    // Requests for more than is left use up the paste.
+    let code = code_of(upload("1").await);
+    assert_eq!(
+        get_range(&code, "bytes=0-4").await.0,
+        StatusCode::PARTIAL_CONTENT
+    );
+    assert_eq!(server.get(&code).await.0, StatusCode::GONE);
+
+    // Requests refused by the transfer cap don't count as downloads.
+    let code = code_of(upload("2").await);
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+    assert_eq!(server.get(&code).await.0, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        get_range(&code, "bytes=0-4").await.0,
+        StatusCode::PARTIAL_CONTENT
+    );
+}
+
+#[tokio::test]
+async fn notifies_when_burned() {
+    // Receives notifications in place of a real notification service.
+    let (sender, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+    let receiver = Router::new().route(
+        "/topic",
+        axum::routing::post(|body: String| async move {
+            sender.send(body).unwrap();
+        }),
+    );
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let notify_url = format!("http://{}/topic", listener.local_addr().unwrap());
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(receiver.into_make_service()),
+    );
+
+    let server = TestServer::with_config(Config {
+        notify_hosts: vec!["127.0.0.1".to_string()],
+        ..Config::from_env().unwrap()
+    });
+    let upload = |notify_url: &str, expiration: Expiration| {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/")
+            .header("burn-after", HeaderValue::from(expiration))
+            .header(&*NOTIFY_HEADER_NAME, notify_url)
+            .body(Body::from("secret"))
+            .unwrap();
+        server.send(request)
+    };
+
+    let (status, _, code) = upload(&notify_url, Expiration::BurnAfterReading).await;
+    assert_eq!(status, StatusCode::OK);
+    let code = String::from_utf8(code.to_vec()).unwrap();
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+    let notification = tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(notification, format!("Paste {code} was read."));
+
+    // Only burn after reading pastes to allowed hosts can ask to be notified.
+    let in_a_day = Expiration::UnixTime(Utc::now() + chrono::Duration::days(1));
+    assert_eq!(
+        upload(&notify_url, in_a_day).await.0,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        upload("http://example.com/topic", Expiration::BurnAfterReading)
+            .await
+            .0,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn updates_need_the_owner_token() {
+    let server = TestServer::new();
+    let (code, headers) = server.upload(b"first draft", None).await;
+    let owner_token = headers[&*OWNER_TOKEN_HEADER_NAME].clone();
+
+    let update = |token: Option<HeaderValue>| {
+        let mut request = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("{API_ENDPOINT}/{code}"));
+        if let Some(token) = token {
+            request = request.header(&*OWNER_TOKEN_HEADER_NAME, token);
+        }
+        server.send(request.body(Body::from("second draft")).unwrap())
+    };
+
+    assert_eq!(update(None).await.0, StatusCode::UNAUTHORIZED);
+    let wrong_token = HeaderValue::from_static("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+    assert_eq!(update(Some(wrong_token)).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(server.get(&code).await.2, "first draft");
+
+    let (status, headers, _) = update(Some(owner_token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.contains_key(EXPIRES));
+    assert_eq!(server.get(&code).await.2, "second draft");
+}
+
+#[tokio::test]
+async fn dead_pastes_cant_be_updated() {
+    let server = TestServer::new();
+    let update = |code: String, token: HeaderValue| {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("{API_ENDPOINT}/{code}"))
+            .header(&*OWNER_TOKEN_HEADER_NAME, token);
+        server.send(request.body(Body::from("revived")).unwrap())
+    };
+
+    let (burned, headers) = server
+        .upload(b"secret", Some(Expiration::BurnAfterReading))
+        .await;
+    let burned_token = headers[&*OWNER_TOKEN_HEADER_NAME].clone();
+    assert_eq!(server.get(&burned).await.0, StatusCode::OK);
+    assert_eq!(
+        update(burned.clone(), burned_token).await.0,
+        StatusCode::GONE
+    );
+    assert_eq!(server.get(&burned).await.0, StatusCode::GONE);
+
+    let (expired, headers) = server.upload(b"hello", Some(in_a_second())).await;
+    let expired_token = headers[&*OWNER_TOKEN_HEADER_NAME].clone();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        update(expired.clone(), expired_token).await.0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(server.get(&expired).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn updates_check_the_owner_token_first() {
+    let server = TestServer::new();
+    let not_before = NotBefore(Utc::now() + chrono::Duration::hours(1));
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/")
+        .header(
+            "burn-after",
+            HeaderValue::from(Expiration::BurnAfterReading),
+        )
+        .header(&*NOT_BEFORE_HEADER_NAME, HeaderValue::from(&not_before))
+        .body(Body::from("embargoed"))
+        .unwrap();
+    let (status, headers, code) = server.send(request).await;
+    assert_eq!(status, StatusCode::OK);
+    let code = String::from_utf8(code.to_vec()).unwrap();
+    let owner_token = headers[&*OWNER_TOKEN_HEADER_NAME].clone();
+
+    let update = |token: Option<HeaderValue>| {
+        let mut request = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("{API_ENDPOINT}/{code}"));
+        if let Some(token) = token {
+            request = request.header(&*OWNER_TOKEN_HEADER_NAME, token);
+        }
+        server.send(request.body(Body::from("released")).unwrap())
+    };
+
+    // Only the owner learns that the paste is locked.
+    assert_eq!(update(None).await.0, StatusCode::UNAUTHORIZED);
+    let wrong_token = HeaderValue::from_static("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+    assert_eq!(update(Some(wrong_token)).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(update(Some(owner_token)).await.0, StatusCode::LOCKED);
+}
+
+#[tokio::test]
+async fn describes_what_it_accepts() {
+    let server = TestServer::new();
+    let (status, _, body) = server
+        .request(Method::GET, &format!("{API_ENDPOINT}/info"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let info: ServerInfo = serde_json::from_slice(&body).unwrap();
+    assert!(info.paste_size_limit > 0);
+    assert!(info
+        .expirations
+        .iter()
+        .any(|expiration| expiration == "read"));
+    assert!(!info.expiration_required);
+}
+
+#[tokio::test]
+async fn applies_the_default_expiration() {
+    let server = TestServer::with_config(Config {
+        default_expiration: DefaultExpiration::BurnAfterReading,
+        ..Config::from_env().unwrap()
+    });
+    let (code, _) = server.upload(b"secret", None).await;
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+    assert_eq!(server.get(&code).await.0, StatusCode::GONE);
+
+    let server = TestServer::with_config(Config {
+        default_expiration: DefaultExpiration::Required,
+        ..Config::from_env().unwrap()
+    });
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/")
+        .body(Body::from("forever?"))
+        .unwrap();
+    assert_eq!(server.send(request).await.0, StatusCode::BAD_REQUEST);
+    let (code, _) = server.upload(b"fleeting", Some(in_a_second())).await;
+    assert_eq!(server.get(&code).await.0, StatusCode::OK);
+
+    let (_, _, body) = server
+        .request(Method::GET, &format!("{API_ENDPOINT}/info"))
+        .await;
+    let info: ServerInfo = serde_json::from_slice(&body).unwrap();
+    assert!(info.expiration_required);
+}
@@ -0,0 +1,245 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Renders text with ANSI escape sequences, such as terminal logs, as styled
+//! spans.
+//!
+//! Only colors and text styles are kept. Other sequences, such as those that
+//! move the cursor, can't be meaningfully rendered and are removed.
+
+use serde::Serialize;
+
+const ESCAPE: char = '\x1b';
+
+/// A run of text with the same style. Colors are CSS colors.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Span {
+    pub text: String,
+    #[serde(flatten)]
+    pub style: Style,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Style {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub dim: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub underline: bool,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Whether the text has any escape sequences that set colors or styles.
+pub fn has_escapes(text: &str) -> bool {
+    text.contains("\x1b[")
+}
+
+/// Splits text into spans by style, removing escape sequences.
+pub fn parse(text: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut current = Span::default();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESCAPE {
+            current.text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // Control sequence: parameters, then a final byte in @ to ~.
+            Some('[') => {
+                let mut params = String::new();
+                let mut terminator = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        terminator = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                if terminator == Some('m') {
+                    let mut style = current.style.clone();
+                    apply_sgr(&mut style, &params);
+                    if style != current.style {
+                        let next = Span {
+                            text: String::new(),
+                            style,
+                        };
+                        push_span(&mut spans, std::mem::replace(&mut current, next));
+                    }
+                }
+            }
+            // Operating system command, such as setting the window title,
+            // which ends with a bell or a string terminator.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == ESCAPE && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // Anything else is a single character, or the end of the text.
+            _ => {}
+        }
+    }
+    push_span(&mut spans, current);
+    spans
+}
+
+/// Adds a span, merging it with the last one if they have the same style.
+fn push_span(spans: &mut Vec<Span>, span: Span) {
+    if span.text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.style == span.style => last.text.push_str(&span.text),
+        _ => spans.push(span),
+    }
+}
+
+/// Applies Select Graphic Rendition parameters, such as `1;31` for bold red.
+fn apply_sgr(style: &mut Style, params: &str) {
+    let mut params = params
+        .split(';')
+        .map(|param| param.parse::<u8>().unwrap_or(0));
+    while let Some(param) = params.next() {
+        match param {
+            0 => *style = Style::default(),
+            1 => style.bold = true,
+            2 => style.dim = true,
+            3 => style.italic = true,
+            4 => style.underline = true,
+            22 => {
+                style.bold = false;
+                style.dim = false;
+            }
+            23 => style.italic = false,
+            24 => style.underline = false,
+            30..=37 => style.fg = Some(palette(param - 30)),
+            38 => style.fg = extended_color(&mut params),
+            39 => style.fg = None,
+            40..=47 => style.bg = Some(palette(param - 40)),
+            48 => style.bg = extended_color(&mut params),
+            49 => style.bg = None,
+            90..=97 => style.fg = Some(palette(param - 90 + 8)),
+            100..=107 => style.bg = Some(palette(param - 100 + 8)),
+            _ => {}
+        }
+    }
+}
+
+/// Reads a 256 color (`5;n`) or true color (`2;r;g;b`) parameter.
+fn extended_color(params: &mut impl Iterator<Item = u8>) -> Option<String> {
+    match params.next()? {
+        5 => Some(palette(params.next()?)),
+        2 => Some(format!(
+            "rgb({}, {}, {})",
+            params.next()?,
+            params.next()?,
+            params.next()?
+        )),
+        _ => None,
+    }
+}
+
+/// The CSS color for a color in the 256 color palette. The first 16 are left
+/// to the stylesheet, so that they match the theme.
+fn palette(index: u8) -> String {
+    match index {
+        0..=15 => format!("var(--ansi-{index})"),
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let index = index - 16;
+            format!(
+                "rgb({}, {}, {})",
+                level(index / 36),
+                level(index / 6 % 6),
+                level(index % 6)
+            )
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) * 10;
+            format!("rgb({gray}, {gray}, {gray})")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn span(text: &str, style: Style) -> Span {
+        Span {
+            text: text.to_string(),
+            style,
+        }
+    }
+
+    #[test]
+    fn plain_text() {
+        assert!(!has_escapes("no colors here"));
+        assert_eq!(
+            parse("no colors here"),
+            vec![span("no colors here", Style::default())]
+        );
+    }
+
+    #[test]
+    fn colors_and_reset() {
+        let text = "\x1b[1;31merror\x1b[0m: failed";
+        assert!(has_escapes(text));
+        assert_eq!(
+            parse(text),
+            vec![
+                span(
+                    "error",
+                    Style {
+                        fg: Some("var(--ansi-1)".to_string()),
+                        bold: true,
+                        ..Style::default()
+                    }
+                ),
+                span(": failed", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn extended_colors() {
+        let spans = parse("\x1b[38;5;196mred\x1b[48;2;1;2;3mand background");
+        assert_eq!(spans[0].style.fg.as_deref(), Some("rgb(255, 0, 0)"));
+        assert_eq!(spans[1].style.bg.as_deref(), Some("rgb(1, 2, 3)"));
+    }
+
+    #[test]
+    fn removes_other_sequences() {
+        assert_eq!(
+            parse("\x1b]0;title\x07\x1b[2Kdone\x1b[m"),
+            vec![span("done", Style::default())]
+        );
+    }
+}
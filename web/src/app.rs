@@ -0,0 +1,1983 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
+use ::http::StatusCode;
+use anyhow::{anyhow, bail, Context, Result};
+use byte_unit::{n_gib_bytes, n_mib_bytes, Byte};
+use gloo_console::{error, log};
+use js_sys::{Array, Function, JsString, Object, Promise, Reflect, Uint8Array};
+use omegaupload_common::crypto::{ChunkedSealer, ContentHasher, Envelope, CHUNK_SIZE};
+use omegaupload_common::crypto::{Error as CryptoError, Key, HASH_SIZE};
+use omegaupload_common::fragment;
+use omegaupload_common::key_format::KeyFormat;
+use omegaupload_common::link;
+use omegaupload_common::manifest::Manifest;
+use omegaupload_common::secrecy::zeroize::Zeroizing;
+use omegaupload_common::secrecy::{ExposeSecret, Secret, SecretString, SecretVec};
+use omegaupload_common::{Expiration, PartialParsedUrl, Url};
+use wasm_bindgen::prelude::{wasm_bindgen, Closure};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    Blob, BlobPropertyBag, DataTransfer, Element, Event, IdbCursorWithValue, IdbOpenDbRequest,
+    IdbRequest, IdbTransactionMode, Location, Window, WorkerGlobalScope,
+};
+
+use crate::bundle::{zip_files, BUNDLE_NAME};
+use crate::decrypt::{decrypt, ArchiveMeta, DecryptedData, MimeType, StreamingDecrypter, Verified};
+use crate::http::ApiResponse;
+use crate::i18n::{tr, tr_with, tr_with_args, Message};
+use crate::idb_object::{IdbObject, Ready};
+use crate::media_meta::MediaInfo;
+use crate::progress::{Progress, Reporter};
+use crate::upload::{check_server_info, UploadOptions, Uploaded};
+use crate::util::{as_idb_db, memory_hint, recording_extension, short_code};
+use crate::{decrypt, downscale, history, http, i18n, image_meta};
+
+/// The largest paste that can be held in wasm memory at once. wasm32 can only
+/// address 4 GiB, and a password protected paste is held three times while
+/// it's decrypted. Users are asked before downloading pastes that may not fit
+/// in the device's memory, so this is only a limit of wasm itself.
+pub const DOWNLOAD_SIZE_LIMIT: u128 = n_gib_bytes!(1);
+/// The largest chunked paste that can be streamed into a blob, which the
+/// browser manages outside of wasm memory.
+const STREAMING_DOWNLOAD_SIZE_LIMIT: u128 = n_gib_bytes!(2);
+/// How much memory decrypting a paste may take before the user is asked
+/// whether to continue.
+const LARGE_PASTE_WARNING: u128 = n_mib_bytes!(100);
+/// How much memory decrypting a paste may take before the user is warned that
+/// it may crash the page, if the browser doesn't hint at how much it may use.
+#[allow(clippy::cast_possible_truncation)]
+const DEFAULT_MEMORY_BUDGET: u64 = n_mib_bytes!(500) as u64;
+/// The largest text paste that's handed to the renderer as a single string.
+/// Larger ones are handed over as a blob and read a few lines at a time with
+/// `text_lines`, as a string that large may exceed what JS engines allow.
+#[allow(clippy::cast_possible_truncation)]
+const LARGE_TEXT_SIZE: usize = n_mib_bytes!(16) as usize;
+/// How many passwords may be tried before decryption gives up.
+const MAX_PASSWORD_ATTEMPTS: u32 = 5;
+/// The version of the idb schema, which must be raised whenever an object
+/// store is added.
+const IDB_VERSION: u32 = 2;
+/// How long decrypted pastes may stay cached in idb, regardless of whether
+/// they've expired.
+const CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[wasm_bindgen(raw_module = "../src/render")]
+extern "C" {
+    /// Renders the paste cached in idb. `from_cache` is set if it's being
+    /// shown instead of a fresh copy, such as when offline.
+    #[wasm_bindgen(js_name = loadFromDb)]
+    pub fn load_from_db(
+        mime_type: JsString,
+        name: Option<JsString>,
+        language: Option<JsString>,
+        from_cache: bool,
+    );
+    #[wasm_bindgen(js_name = renderMessage)]
+    pub fn render_message(message: JsString);
+    #[wasm_bindgen(js_name = renderProgress)]
+    pub fn render_progress(phase: JsString, loaded: Option<f64>, total: Option<f64>);
+    /// Resolves once the user agrees to view a burn after reading paste.
+    #[wasm_bindgen(js_name = confirmBurn)]
+    pub fn confirm_burn() -> Promise;
+    /// Counts down until a time-locked paste may be read, as a Unix timestamp
+    /// in milliseconds, and resolves then.
+    #[wasm_bindgen(js_name = awaitUnlock)]
+    pub fn await_unlock(unlocks_at: f64) -> Promise;
+    #[wasm_bindgen(js_name = createUploadUi)]
+    pub fn create_upload_ui(expiration_variants: Array);
+    #[wasm_bindgen(js_name = decryptInWorker)]
+    pub fn decrypt_in_worker(
+        request_uri: JsString,
+        fragment: JsString,
+        password: Option<JsString>,
+        name: Option<JsString>,
+        language: Option<JsString>,
+    );
+}
+
+fn window() -> Window {
+    web_sys::window().expect("Failed to get a reference of the window")
+}
+
+fn location() -> Location {
+    window().location()
+}
+
+/// The path the frontend is hosted under, such as `/` or `/paste/`. This is set
+/// at build time in a meta tag, as a reverse proxy may serve the frontend from
+/// a subpath.
+fn base_path() -> String {
+    let base_path = window()
+        .document()
+        .and_then(|document| {
+            document
+                .query_selector("meta[name='omegaupload-base-path']")
+                .ok()
+                .flatten()
+        })
+        .and_then(|meta| meta.get_attribute("content"))
+        .unwrap_or_default();
+    let base_path = base_path.trim_matches('/');
+    if base_path.is_empty() {
+        "/".to_string()
+    } else {
+        format!("/{base_path}/")
+    }
+}
+
+/// Opens the database from either the main thread or a worker.
+pub fn open_idb() -> Result<IdbOpenDbRequest> {
+    let global = js_sys::global();
+    let factory = if let Some(window) = global.dyn_ref::<Window>() {
+        window.indexed_db()
+    } else {
+        global.unchecked_into::<WorkerGlobalScope>().indexed_db()
+    };
+    factory
+        .map_err(|_| anyhow!("Failed to access idb"))?
+        .context("Missing browser idb impl")?
+        .open_with_u32("omegaupload", IDB_VERSION)
+        .map_err(|_| anyhow!("Failed to open idb"))
+}
+
+#[wasm_bindgen]
+#[allow(clippy::missing_panics_doc)]
+pub fn start() {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+
+    spawn_local(async {
+        if let Err(e) = evict_cached_pastes().await {
+            error!(format!("Failed to evict cached pastes: {e}"));
+        }
+    });
+
+    let base_path = base_path();
+    let path = location().pathname().unwrap();
+    let code = short_code(&path, &base_path);
+    if code.is_empty() {
+        let expiration_variants = Expiration::variants()
+            .iter()
+            .map(|variant| JsString::from(*variant))
+            .collect();
+        create_upload_ui(expiration_variants);
+        return;
+    }
+
+    render_progress("loading".into(), None, None);
+
+    let url = String::from(location().to_string());
+    let request_uri = format!("{}{base_path}api/{code}", location().origin().unwrap());
+
+    let (
+        fragment,
+        PartialParsedUrl {
+            needs_password,
+            name,
+            language,
+            ..
+        },
+    ) = {
+        let fragment = url.split_once('#').map_or("", |(_, fragment)| fragment);
+
+        let mut partial_parsed_url = match PartialParsedUrl::try_from(fragment) {
+            Ok(partial_parsed_url) => partial_parsed_url,
+            Err(e) => {
+                error!("Failed to parse text fragment; bailing.");
+                render_message(tr_with(Message::InvalidLink, &e.to_string()).into());
+                return;
+            }
+        };
+
+        // Links can be shared without their key, which is then sent over
+        // another channel.
+        let fragment = if partial_parsed_url.decryption_key.take().is_some() {
+            SecretString::new(fragment.to_string())
+        } else if let Some(key) = prompt_key() {
+            fragment::join_key(&key, fragment)
+        } else {
+            error!("Key is missing in url; bailing.");
+            render_message(tr(Message::MissingKey).into());
+            return;
+        };
+
+        (fragment, partial_parsed_url)
+    };
+
+    spawn_local(async move {
+        if !window().navigator().on_line() {
+            show_cached_paste(name, language).await;
+            return;
+        }
+
+        // Fetching the paste could burn it, so it's checked without doing so.
+        let head = match http::head(&request_uri).await {
+            Ok(resp) if resp.status.is_success() => Some(resp),
+            _ => None,
+        };
+
+        let not_before = head.as_ref().and_then(|resp| resp.not_before);
+        if let Some(not_before) = not_before.filter(|not_before| !not_before.has_passed()) {
+            #[allow(clippy::cast_precision_loss)]
+            let unlocks_at = not_before.0.timestamp_millis() as f64;
+            JsFuture::from(await_unlock(unlocks_at)).await.ok();
+            render_progress("loading".into(), None, None);
+        }
+
+        // Opening a link, such as from a chat preview, shouldn't burn it.
+        if head.as_ref().map_or(false, is_burn_after_reading) {
+            JsFuture::from(confirm_burn()).await.ok();
+            render_progress("loading".into(), None, None);
+        }
+
+        let password = if needs_password {
+            loop {
+                let pw = window().prompt_with_message(tr(Message::PasswordPrompt));
+
+                match pw {
+                    // Ok button was entered.
+                    Ok(Some(password)) if !password.is_empty() => {
+                        break Some(SecretString::new(password));
+                    }
+                    // Empty message was entered.
+                    Ok(Some(_)) => (),
+                    // Cancel button was entered.
+                    Ok(None) => {
+                        render_message(tr(Message::PasswordRequired).into());
+                        return;
+                    }
+                    e => {
+                        render_message(tr(Message::InternalError).into());
+                        error!(format!("Error occurred at pw prompt: {e:?}"));
+                        return;
+                    }
+                }
+            }
+        } else {
+            None
+        };
+
+        // Decryption can take a long time for large pastes, so it's done in a
+        // worker to keep the page responsive.
+        decrypt_in_worker(
+            JsString::from(request_uri),
+            JsString::from(fragment.expose_secret().as_str()),
+            password.map(|password| JsString::from(password.expose_secret().as_str())),
+            name.map(JsString::from),
+            language.map(JsString::from),
+        );
+    });
+}
+
+/// Asks for the key of a paste whose link was shared without one. The key can be
+/// in any [`KeyFormat`], and is returned in Base64 to go in the fragment. Keys
+/// that carry a hash, as from [`fragment::split_key`], are returned as they are.
+fn prompt_key() -> Option<SecretString> {
+    loop {
+        match window().prompt_with_message(tr(Message::KeyPrompt)) {
+            Ok(Some(key)) if !key.trim().is_empty() => {
+                let key = Zeroizing::new(key);
+                let key = key.trim();
+                if key.starts_with("key:") {
+                    match PartialParsedUrl::try_from(key) {
+                        Ok(PartialParsedUrl {
+                            decryption_key: Some(_),
+                            ..
+                        }) => break Some(SecretString::new(key.to_owned())),
+                        _ => {
+                            window().alert_with_message(tr(Message::InvalidKey)).ok();
+                        }
+                    }
+                    continue;
+                }
+                match KeyFormat::detect(key).decode(key) {
+                    Ok(key) => break Some(KeyFormat::Base64.encode(&key)),
+                    Err(_) => {
+                        window().alert_with_message(tr(Message::InvalidKey)).ok();
+                    }
+                }
+            }
+            // Empty message was entered.
+            Ok(Some(_)) => (),
+            // Cancel button was entered.
+            Ok(None) => break None,
+            e => {
+                error!(format!("Error occurred at key prompt: {e:?}"));
+                break None;
+            }
+        }
+    }
+}
+
+/// Renders the copy of the current paste that was cached when it was last
+/// viewed, as it can't be fetched while offline.
+#[allow(clippy::future_not_send)]
+async fn show_cached_paste(name: Option<String>, language: Option<String>) {
+    let cached = match location().pathname() {
+        Ok(path) => load_cached(path).await,
+        Err(_) => Err(anyhow!("Failed to read the page's path")),
+    };
+    match cached {
+        Ok(Some(cached)) => {
+            let mime_type = Reflect::get(&cached, &JsValue::from_str("mime_type"))
+                .ok()
+                .and_then(|mime_type| mime_type.as_string())
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            load_from_db(
+                mime_type.into(),
+                name.map(JsString::from),
+                language.map(JsString::from),
+                true,
+            );
+        }
+        Ok(None) => {
+            render_message(tr(Message::OfflineNotCached).into());
+        }
+        Err(e) => {
+            error!(format!("Failed to load cached paste: {e}"));
+            render_message(tr(Message::OfflineLoadFailed).into());
+        }
+    }
+}
+
+/// Fetches and decrypts a paste, storing the result in idb under `path` for
+/// the main thread to render. This is meant to be run in a worker.
+///
+/// Returns an object with the `mimeType` of the paste, or a message to show to
+/// the user if the paste couldn't be decrypted. Private pastes are never
+/// stored, and are instead returned as the object's `data`. `on_progress` is
+/// called with the arguments for `renderProgress` as the paste is downloaded
+/// and decrypted.
+///
+/// If the password is wrong, `request_password` is called with the number of
+/// attempts so far and the maximum number of attempts. It should return a
+/// promise of another password, or of nothing if the user gave up.
+///
+/// If decrypting the paste would take a lot of memory, `confirm_size` is
+/// called with a message describing how much before it's downloaded. It should
+/// return a promise of whether to continue.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn decrypt_paste(
+    request_uri: String,
+    fragment: String,
+    password: Option<String>,
+    path: String,
+    on_progress: Function,
+    request_password: Function,
+    confirm_size: Function,
+) -> Result<Object, JsString> {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+
+    let parsed = PartialParsedUrl::try_from(fragment.as_str())
+        .map_err(|e| JsString::from(tr_with(Message::InvalidLink, &e.to_string())))?;
+    let envelope = parsed.envelope();
+    let PartialParsedUrl {
+        decryption_key,
+        name,
+        chunked,
+        private,
+        hash,
+        ..
+    } = parsed;
+    let key = decryption_key.ok_or_else(|| JsString::from(tr(Message::MissingKey)))?;
+    let password = password.map(|password| SecretVec::new(password.into_bytes()));
+    let reporter = Reporter::new(on_progress);
+
+    let (decrypted, mime_type) = fetch_resources(
+        request_uri,
+        key,
+        password,
+        name,
+        chunked,
+        envelope,
+        hash,
+        &reporter,
+        &request_password,
+        &confirm_size,
+    )
+    .await?;
+
+    let result = Object::new();
+    let set = |key: &str, value: &JsValue| {
+        Reflect::set(&result, &JsValue::from_str(key), value)
+            .map_err(|_| JsString::from(tr(Message::InternalError)))
+    };
+    set("mimeType", &JsValue::from_str(&mime_type.0))?;
+    // Kept with the paste, so that it can be rendered from idb while offline.
+    Reflect::set(
+        &decrypted,
+        &JsValue::from_str("mime_type"),
+        &JsValue::from_str(&mime_type.0),
+    )
+    .map_err(|_| JsString::from(tr(Message::InternalError)))?;
+
+    if private {
+        set("data", &decrypted)?;
+    } else if let Err(e) = store_decrypted(path, decrypted).await {
+        log!(format!("[rs] Failed to store decrypted paste: {e}"));
+        return Err(tr(Message::InternalError).into());
+    } else {
+        log!("[rs] Successfully inserted encrypted item into storage.");
+    }
+
+    Ok(result)
+}
+
+/// Encrypts and uploads a paste, returning its URL. The paste is recorded in
+/// the history of pastes uploaded from this browser. This is meant to be run
+/// in a worker.
+///
+/// The blob is read and sealed a few chunks at a time, and the sealed chunks
+/// are kept in a blob, so files larger than wasm memory can be uploaded.
+/// `on_progress` is called with the arguments for `renderProgress`.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn encrypt_blob(
+    location: String,
+    data: Blob,
+    options: JsValue,
+    on_progress: Function,
+) -> Result<JsString, JsString> {
+    let reporter = Reporter::new(on_progress);
+    let res = match UploadOptions::from_js(options) {
+        Ok(options) => do_encrypt(location, &data, options, &reporter).await,
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| {
+        log!(format!("[rs] Error encrypting blob: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+/// Zips multiple files into a single paste, then encrypts and uploads it like
+/// `encrypt_blob`. The paste is named `bundle.zip` unless a name was provided
+/// in the upload options.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn encrypt_files(
+    location: String,
+    files: Array,
+    options: JsValue,
+    on_progress: Function,
+) -> Result<JsString, JsString> {
+    let reporter = Reporter::new(on_progress);
+    let res = match UploadOptions::from_js(options) {
+        Ok(options) => do_encrypt_files(location, &files, options, &reporter).await,
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| {
+        log!(format!("[rs] Error encrypting files: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+/// Downloads the file at `source`, then encrypts and uploads it like
+/// `encrypt_blob`, so that public files can be mirrored into a paste. The file
+/// must be served with CORS headers that allow it to be read. The paste is
+/// named after the last segment of the URL unless a name was provided in the
+/// upload options.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn encrypt_remote(
+    location: String,
+    source: String,
+    options: JsValue,
+    on_progress: Function,
+) -> Result<JsString, JsString> {
+    let reporter = Reporter::new(on_progress);
+    let res = match UploadOptions::from_js(options) {
+        Ok(options) => do_encrypt_remote(location, &source, options, &reporter).await,
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| {
+        log!(format!("[rs] Error mirroring {}: {}", source, e));
+        JsString::from(e.to_string())
+    })
+}
+
+/// Encrypts and uploads audio or video recorded with `MediaRecorder`, like
+/// `encrypt_blob`. Unless a name was provided in the upload options, the paste
+/// is named after when it was recorded, with an extension for its type so that
+/// viewers know how to play it.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn encrypt_recording(
+    location: String,
+    data: Blob,
+    options: JsValue,
+    on_progress: Function,
+) -> Result<JsString, JsString> {
+    let reporter = Reporter::new(on_progress);
+    let res = match UploadOptions::from_js(options) {
+        Ok(mut options) => {
+            options.name.get_or_insert_with(|| {
+                // Colons aren't allowed in file names on some systems.
+                let timestamp = String::from(js_sys::Date::new_0().to_iso_string())
+                    .chars()
+                    .take(19)
+                    .collect::<String>()
+                    .replace(':', "-");
+                format!(
+                    "recording-{timestamp}.{}",
+                    recording_extension(&data.type_())
+                )
+            });
+            // Recordings aren't text, so they have no language.
+            options.language = None;
+            do_encrypt(location, &data, options, &reporter).await
+        }
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| {
+        log!(format!("[rs] Error encrypting recording: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+/// Lists the unexpired pastes uploaded from this browser, newest first, for
+/// the upload page to show. Each has the full `url` of the paste, when it
+/// `expires` if it has a deadline, and whether it's burned after reading.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send)]
+pub async fn recent_pastes() -> Result<Array, JsString> {
+    let entries = history::load().await.map_err(|e| {
+        log!(format!("[rs] Error loading history: {}", e));
+        JsString::from(e.to_string())
+    })?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !entry.is_expired())
+        .filter_map(|mut entry| {
+            // The viewer reads it from idb when it's needed.
+            entry.owner_token = None;
+            serde_wasm_bindgen::to_value(&entry).ok()
+        })
+        .collect())
+}
+
+/// Removes a paste from the history of pastes uploaded from this browser. This
+/// doesn't delete the paste, and it can no longer be deleted from the viewer.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn forget_paste(url: String) -> Result<(), JsString> {
+    let res = match Url::from_str(&url) {
+        Ok(url) => history::remove(url.path().to_string()).await,
+        Err(e) => Err(e.into()),
+    };
+    res.map_err(|e| {
+        log!(format!("[rs] Error forgetting paste: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+/// Whether the paste on the current page was uploaded from this browser, and
+/// so can be deleted with `delete_paste`.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send)]
+pub async fn owns_paste() -> bool {
+    matches!(current_owner_token().await, Ok(Some(_)))
+}
+
+/// Deletes the paste on the current page from the server, along with its
+/// cached copy and its history entry, then tells the user it's gone. Fails if
+/// the paste wasn't uploaded from this browser.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send)]
+pub async fn delete_paste() -> Result<(), JsString> {
+    do_delete_paste().await.map_err(|e| {
+        log!(format!("[rs] Error deleting paste: {}", e));
+        JsString::from(tr(Message::DeleteFailed))
+    })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_delete_paste() -> Result<()> {
+    let path = location()
+        .pathname()
+        .map_err(|_| anyhow!("Failed to read the page's path"))?;
+    let owner_token = current_owner_token()
+        .await?
+        .context("This paste wasn't uploaded here")?;
+
+    let base_path = base_path();
+    let request_uri = format!(
+        "{}{base_path}api/{}",
+        location().origin().map_err(|e| anyhow!("{e:?}"))?,
+        short_code(&path, &base_path)
+    );
+    let resp = http::delete(&request_uri, &owner_token).await?;
+
+    match resp.status {
+        // The paste may have already expired or been burned.
+        StatusCode::OK | StatusCode::NOT_FOUND | StatusCode::GONE => (),
+        status => bail!("Got HTTP status {status}"),
+    }
+
+    remove_cached(path.clone()).await?;
+    history::remove(path).await?;
+    render_message(tr(Message::PasteDeleted).into());
+    Ok(())
+}
+
+/// The owner token of the paste on the current page, if it was uploaded from
+/// this browser.
+#[allow(clippy::future_not_send)]
+async fn current_owner_token() -> Result<Option<String>> {
+    let path = location()
+        .pathname()
+        .map_err(|_| anyhow!("Failed to read the page's path"))?;
+    Ok(history::get(path)
+        .await?
+        .and_then(|entry| entry.owner_token))
+}
+
+/// Takes the file or text being pasted onto the upload page, so that it can be
+/// uploaded right away with `encrypt_blob`. If there's something to upload,
+/// the browser's handling of the paste is prevented.
+///
+/// Returns nothing if the clipboard is empty, or if text is being pasted into a
+/// field, which is left to the browser.
+#[wasm_bindgen]
+#[must_use]
+pub fn clipboard_data(event: &Event) -> Option<Blob> {
+    // ClipboardEvent is unstable in web-sys, so its data is read through JS.
+    let data: DataTransfer = Reflect::get(event, &JsValue::from_str("clipboardData"))
+        .ok()
+        .filter(|data| !data.is_null() && !data.is_undefined())?
+        .unchecked_into();
+
+    let file = data.files().and_then(|files| files.get(0));
+    let blob = if let Some(file) = file {
+        Blob::from(file)
+    } else {
+        let in_field = event
+            .target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .map_or(false, |element| {
+                matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA")
+            });
+        if in_field {
+            return None;
+        }
+
+        let text = data
+            .get_data("text/plain")
+            .ok()
+            .filter(|text| !text.trim().is_empty())?;
+        let mut blob_props = BlobPropertyBag::new();
+        blob_props.type_("text/plain;charset=utf-8");
+        Blob::new_with_str_sequence_and_options(&Array::of1(&text.into()), &blob_props).ok()?
+    };
+
+    event.prevent_default();
+    Some(blob)
+}
+
+/// Splits the key out of the URL of a newly uploaded paste, so that the link and
+/// the key can be shared over different channels. Opening the link prompts for
+/// the key.
+///
+/// Returns an object with the `url` without its key, and the `key`.
+#[wasm_bindgen]
+#[must_use]
+pub fn split_url(url: &str) -> Object {
+    let (url, fragment) = url.split_once('#').unwrap_or((url, ""));
+    let (key, rest) = fragment::split_key(fragment);
+    let url = if rest.is_empty() {
+        url.to_owned()
+    } else {
+        format!("{url}#{rest}")
+    };
+
+    let split = Object::new();
+    // Setting properties on a new object can't fail.
+    Reflect::set(&split, &JsValue::from_str("url"), &JsValue::from_str(&url)).ok();
+    Reflect::set(
+        &split,
+        &JsValue::from_str("key"),
+        &JsValue::from_str(key.as_ref().map_or("", |key| key.expose_secret())),
+    )
+    .ok();
+    split
+}
+
+/// Rewrites the URL of the current page to open the same paste on another
+/// instance, such as a mirror, keeping its fragment. Fails if the instance
+/// isn't an http or https URL.
+#[wasm_bindgen]
+pub fn link_on_instance(instance: &str) -> Result<String, JsString> {
+    let url = String::from(location().to_string());
+    link::rewrite_instance(&url, instance)
+        .map(String::from)
+        .map_err(|e| JsString::from(e.to_string()))
+}
+
+/// Creates a blob URL for the decrypted paste cached for the current page, so
+/// that it can be saved under its original name.
+///
+/// Returns an object with the `url`, which should be revoked once the download
+/// has started, and the `name` to save it as. This is the paste's `!name:` if
+/// it has one, or its short code otherwise. Fails for private pastes, as
+/// they're never cached.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send)]
+pub async fn cached_paste_download() -> Result<Object, JsString> {
+    do_cached_paste_download().await.map_err(|e| {
+        log!(format!("[rs] Error creating download: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_cached_paste_download() -> Result<Object> {
+    let path = location()
+        .pathname()
+        .map_err(|_| anyhow!("Failed to read the page's path"))?;
+    let fragment = location()
+        .hash()
+        .map_err(|_| anyhow!("Failed to read the page's fragment"))?;
+    let name = PartialParsedUrl::try_from(fragment.trim_start_matches('#'))
+        .ok()
+        .and_then(|url| url.name)
+        .unwrap_or_else(|| short_code(&path, &base_path()).to_string());
+
+    let cached = load_cached(path)
+        .await?
+        .context("This paste isn't cached.")?;
+    let data = Reflect::get(&cached, &JsValue::from_str("data"))
+        .map_err(|e| anyhow!("Failed to read cached paste: {e:?}"))?;
+    let blob = match data.dyn_into::<Blob>() {
+        Ok(blob) => blob,
+        // Text pastes are cached as strings.
+        Err(data) => {
+            let text = data.as_string().context("Cached paste has no data.")?;
+            let mut blob_props = BlobPropertyBag::new();
+            blob_props.type_("text/plain;charset=utf-8");
+            Blob::new_with_str_sequence_and_options(&Array::of1(&text.into()), &blob_props)
+                .map_err(|e| anyhow!("Failed to create blob: {e:?}"))?
+        }
+    };
+    let url = web_sys::Url::create_object_url_with_blob(&blob)
+        .map_err(|e| anyhow!("Failed to create blob url: {e:?}"))?;
+
+    let download = Object::new();
+    Reflect::set(
+        &download,
+        &JsValue::from_str("url"),
+        &JsValue::from_str(&url),
+    )
+    .map_err(|e| anyhow!("{e:?}"))?;
+    Reflect::set(
+        &download,
+        &JsValue::from_str("name"),
+        &JsValue::from_str(&name),
+    )
+    .map_err(|e| anyhow!("{e:?}"))?;
+    Ok(download)
+}
+
+/// Copies the cached text paste for the current page to the clipboard. Fails
+/// for private pastes, as they're never cached, and for pastes that aren't
+/// text.
+///
+/// The text is handed from idb to the clipboard as a JS string, so it's never
+/// copied into wasm memory, however large it is.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send)]
+pub async fn copy_cached_text() -> Result<(), JsString> {
+    do_copy_cached_text().await.map_err(|e| {
+        log!(format!("[rs] Error copying paste: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_copy_cached_text() -> Result<()> {
+    let path = location()
+        .pathname()
+        .map_err(|_| anyhow!("Failed to read the page's path"))?;
+    let cached = load_cached(path)
+        .await?
+        .context("This paste isn't cached.")?;
+    let text = Reflect::get(&cached, &JsValue::from_str("data"))
+        .map_err(|e| anyhow!("Failed to read cached paste: {e:?}"))?;
+    if !text.is_string() {
+        bail!("This paste isn't text.");
+    }
+
+    // The Clipboard API is unstable in web-sys, so it's called through JS.
+    let js_err = |e: JsValue| anyhow!("Failed to copy to the clipboard: {e:?}");
+    let clipboard =
+        Reflect::get(&window().navigator(), &JsValue::from_str("clipboard")).map_err(js_err)?;
+    let write_text = Reflect::get(&clipboard, &JsValue::from_str("writeText"))
+        .map_err(js_err)?
+        .dyn_into::<Function>()
+        .map_err(js_err)?;
+    let promise = write_text
+        .call1(&clipboard, &text)
+        .map_err(js_err)?
+        .dyn_into::<Promise>()
+        .map_err(js_err)?;
+    JsFuture::from(promise).await.map(drop).map_err(js_err)
+}
+
+thread_local! {
+    /// The archive last listed by `archive_entries` and its entries, so that
+    /// paging through them doesn't read the archive again each time.
+    static ARCHIVE_LISTING: RefCell<Option<(Blob, Rc<Vec<ArchiveMeta>>)>> = RefCell::new(None);
+    /// The large text paste last read by `text_lines`, and the offset that
+    /// each of its lines starts at.
+    static LINE_INDEX: RefCell<Option<(Blob, Rc<Vec<u64>>)>> = RefCell::new(None);
+}
+
+/// Reads up to `count` lines of a large text paste, starting at line `start`,
+/// so that it can be rendered without ever being a single JS string.
+///
+/// Resolves to an object with the `total` number of lines, and the `lines`
+/// themselves, without their line endings.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn text_lines(text: Blob, start: u32, count: u32) -> Result<Object, JsString> {
+    do_text_lines(&text, u64::from(start), u64::from(count))
+        .await
+        .map_err(|e| {
+            log!(format!("[rs] Error reading text: {}", e));
+            JsString::from(e.to_string())
+        })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_text_lines(text: &Blob, start: u64, count: u64) -> Result<Object> {
+    let cached = LINE_INDEX.with(|index| {
+        index
+            .borrow()
+            .as_ref()
+            .filter(|(blob, _)| blob == text)
+            .map(|(_, line_starts)| Rc::clone(line_starts))
+    });
+    let line_starts = if let Some(line_starts) = cached {
+        line_starts
+    } else {
+        let line_starts = Rc::new(index_lines(text).await?);
+        LINE_INDEX.with(|index| {
+            *index.borrow_mut() = Some((text.clone(), Rc::clone(&line_starts)));
+        });
+        line_starts
+    };
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let size = text.size() as u64;
+    let total = line_starts.len() as u64;
+    let lines = Array::new();
+    let end = start.saturating_add(count).min(total);
+    if start < end {
+        #[allow(clippy::cast_possible_truncation)]
+        let (first, last) = (start as usize, end as usize);
+        let offset = line_starts[first];
+        let read_end = line_starts.get(last).copied().unwrap_or(size);
+        let bytes = read_blob(text, offset, read_end).await?;
+        let text = String::from_utf8_lossy(&bytes);
+        for line in text.lines().take(last - first) {
+            lines.push(&JsValue::from_str(line));
+        }
+    }
+
+    let result = Object::new();
+    #[allow(clippy::cast_precision_loss)]
+    Reflect::set(
+        &result,
+        &JsValue::from_str("total"),
+        &JsValue::from_f64(total as f64),
+    )
+    .map_err(|e| anyhow!("{e:?}"))?;
+    Reflect::set(&result, &JsValue::from_str("lines"), &lines).map_err(|e| anyhow!("{e:?}"))?;
+    Ok(result)
+}
+
+/// Finds the offset that each line of a text blob starts at, reading it a
+/// piece at a time so that it's never entirely in wasm memory.
+#[allow(clippy::future_not_send)]
+async fn index_lines(text: &Blob) -> Result<Vec<u64>> {
+    #[allow(clippy::cast_possible_truncation)]
+    const READ_SIZE: u64 = n_mib_bytes!(4) as u64;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let size = text.size() as u64;
+    let mut line_starts = vec![0];
+    let mut offset = 0;
+    while offset < size {
+        let end = (offset + READ_SIZE).min(size);
+        let bytes = read_blob(text, offset, end).await?;
+        line_starts.extend(
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|(_, byte)| **byte == b'\n')
+                .map(|(i, _)| offset + i as u64 + 1),
+        );
+        offset = end;
+    }
+    // A trailing newline doesn't start another line.
+    if line_starts.len() > 1 && line_starts.last() == Some(&size) {
+        line_starts.pop();
+    }
+    Ok(line_starts)
+}
+
+/// Copies text into a blob a piece at a time, so that it never needs to be a
+/// single JS string.
+fn text_blob(text: &str) -> Blob {
+    let parts = text
+        .as_bytes()
+        .chunks(65536)
+        .map(Uint8Array::from)
+        .collect::<Array>();
+    let mut blob_props = BlobPropertyBag::new();
+    blob_props.type_("text/plain;charset=utf-8");
+    Blob::new_with_u8_array_sequence_and_options(&parts, &blob_props)
+        .expect("blobs to be creatable from byte arrays")
+}
+
+/// Lists up to `limit` entries of an archive paste, starting at `offset`, so
+/// that archives with many entries can be rendered a page at a time.
+///
+/// Resolves to an object with the `total` number of entries, and the `entries`
+/// themselves, each with a `name` and `file_size`. Entries in directories are
+/// listed first, and are otherwise sorted by name.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn archive_entries(archive: Blob, offset: u32, limit: u32) -> Result<Object, JsString> {
+    do_archive_entries(&archive, offset as usize, limit as usize)
+        .await
+        .map_err(|e| {
+            log!(format!("[rs] Error listing archive: {}", e));
+            JsString::from(e.to_string())
+        })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_archive_entries(archive: &Blob, offset: usize, limit: usize) -> Result<Object> {
+    let cached = ARCHIVE_LISTING.with(|listing| {
+        listing
+            .borrow()
+            .as_ref()
+            .filter(|(blob, _)| blob == archive)
+            .map(|(_, entries)| Rc::clone(entries))
+    });
+    let entries = if let Some(entries) = cached {
+        entries
+    } else {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let size = archive.size() as u64;
+        let container = read_blob(archive, 0, size).await?;
+        let entries = Rc::new(decrypt::list_entries(container));
+        ARCHIVE_LISTING.with(|listing| {
+            *listing.borrow_mut() = Some((archive.clone(), Rc::clone(&entries)));
+        });
+        entries
+    };
+
+    let page = entries
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|entry| serde_wasm_bindgen::to_value(entry).map_err(|e| anyhow!("{e}")))
+        .collect::<Result<Array>>()?;
+    let result = Object::new();
+    #[allow(clippy::cast_precision_loss)]
+    Reflect::set(
+        &result,
+        &JsValue::from_str("total"),
+        &JsValue::from_f64(entries.len() as f64),
+    )
+    .map_err(|e| anyhow!("{e:?}"))?;
+    Reflect::set(&result, &JsValue::from_str("entries"), &page).map_err(|e| anyhow!("{e:?}"))?;
+    Ok(result)
+}
+
+/// Downscales an image so that neither of its sides are longer than
+/// `max_dimension`, so that it's smaller to upload. Only PNGs and JPEGs are
+/// downscaled, and downscaling removes their metadata.
+///
+/// Resolves to an object with the image `data` to upload, whether it was
+/// `downscaled`, its `width` and `height`, and its `original_width` and
+/// `original_height`. If it wasn't downscaled, `data` is the image as it was,
+/// and its dimensions are missing if they couldn't be read.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn downscale_image(image: Blob, max_dimension: u32) -> Result<Object, JsString> {
+    do_downscale_image(&image, max_dimension)
+        .await
+        .map_err(|e| {
+            log!(format!("[rs] Error downscaling image: {}", e));
+            JsString::from(e.to_string())
+        })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_downscale_image(image: &Blob, max_dimension: u32) -> Result<Object> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let size = image.size() as u64;
+    let data = read_blob(image, 0, size).await?;
+    let info = image_meta::read(&data);
+
+    let (blob, downscaled, original, scaled) =
+        match downscale::downscale(&data, max_dimension, info.orientation) {
+            Some(downscaled) => {
+                log!(format!(
+                    "[rs] Downscaled image from {:?} to {:?}.",
+                    downscaled.original, downscaled.scaled
+                ));
+                let parts = Array::of1(&Uint8Array::from(downscaled.data.as_slice()));
+                let mut blob_props = BlobPropertyBag::new();
+                blob_props.type_(&image.type_());
+                let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &blob_props)
+                    .map_err(|e| anyhow!("Failed to create blob: {e:?}"))?;
+                (
+                    blob,
+                    true,
+                    Some(downscaled.original),
+                    Some(downscaled.scaled),
+                )
+            }
+            None => {
+                #[allow(clippy::cast_possible_truncation)]
+                let dimensions = info
+                    .dimensions
+                    .map(|(width, height)| (width as u32, height as u32));
+                (image.clone(), false, dimensions, dimensions)
+            }
+        };
+
+    let result = Object::new();
+    let set = |key: &str, value: &JsValue| {
+        Reflect::set(&result, &JsValue::from_str(key), value).map_err(|e| anyhow!("{e:?}"))
+    };
+    let dimension = |dimension: Option<u32>| dimension.map_or(JsValue::NULL, JsValue::from);
+    set("data", &blob)?;
+    set("downscaled", &JsValue::from_bool(downscaled))?;
+    set("width", &dimension(scaled.map(|(width, _)| width)))?;
+    set("height", &dimension(scaled.map(|(_, height)| height)))?;
+    set(
+        "original_width",
+        &dimension(original.map(|(width, _)| width)),
+    )?;
+    set(
+        "original_height",
+        &dimension(original.map(|(_, height)| height)),
+    )?;
+    Ok(result)
+}
+
+/// Extracts a single file from an archive paste, so it can be downloaded or
+/// previewed without downloading the whole archive.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn extract_entry(archive: Blob, path: String) -> Result<Blob, JsString> {
+    let res = async {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let size = archive.size() as u64;
+        let container = read_blob(&archive, 0, size).await?;
+        decrypt::extract_entry(container, &path).context("File not found in archive.")
+    };
+    res.await.map_err(|e| {
+        log!(format!("[rs] Error extracting {}: {}", path, e));
+        JsString::from(e.to_string())
+    })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_encrypt_files(
+    location: String,
+    files: &Array,
+    mut options: UploadOptions,
+    reporter: &Reporter,
+) -> Result<JsString> {
+    let bundle = zip_files(files, reporter).await?;
+    options.name.get_or_insert_with(|| BUNDLE_NAME.to_string());
+    do_encrypt(location, &bundle, options, reporter).await
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_encrypt_remote(
+    location: String,
+    source: &str,
+    mut options: UploadOptions,
+    reporter: &Reporter,
+) -> Result<JsString> {
+    let source = Url::from_str(source.trim()).context("That isn't a valid URL.")?;
+    if !matches!(source.scheme(), "http" | "https") {
+        bail!("Only http and https URLs can be mirrored.");
+    }
+
+    reporter.report(Progress::Downloading {
+        loaded: 0,
+        total: None,
+    });
+    let resp = http::get(source.as_str()).await.context(
+        "Failed to download the file. The server may not allow it to be read from here.",
+    )?;
+    if !resp.status.is_success() {
+        bail!(
+            "Failed to download the file. Got HTTP status {}",
+            resp.status
+        );
+    }
+    if resp.content_length.map_or(false, |length| {
+        u128::from(length) > STREAMING_DOWNLOAD_SIZE_LIMIT
+    }) {
+        bail!("The file is too large to mirror.");
+    }
+
+    // The file is kept in a blob, so it doesn't need to fit in wasm memory.
+    let parts = Array::new();
+    let mut downloaded = 0;
+    let total = resp.content_length;
+    read_body(&resp, total, reporter, |chunk| {
+        downloaded += chunk.len() as u128;
+        if downloaded > STREAMING_DOWNLOAD_SIZE_LIMIT {
+            return Err("The file is too large to mirror.".into());
+        }
+        parts.push(&Uint8Array::from(chunk));
+        Ok(())
+    })
+    .await
+    .map_err(|e| anyhow!("{}", String::from(e)))?;
+
+    let mut blob_props = BlobPropertyBag::new();
+    if let Some(content_type) = resp.content_type.as_deref() {
+        blob_props.type_(content_type);
+    }
+    let data = Blob::new_with_u8_array_sequence_and_options(&parts, &blob_props)
+        .map_err(|e| anyhow!("Failed to create blob: {e:?}"))?;
+
+    if options.name.is_none() {
+        options.name = source
+            .path_segments()
+            .and_then(Iterator::last)
+            .filter(|name| !name.is_empty())
+            .map(ToString::to_string);
+    }
+    do_encrypt(location, &data, options, reporter).await
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_encrypt(
+    location: String,
+    data: &Blob,
+    options: UploadOptions,
+    reporter: &Reporter,
+) -> Result<JsString> {
+    // Reading several chunks at a time keeps the number of round trips to JS
+    // down.
+    const READ_SIZE: u64 = CHUNK_SIZE as u64 * 16;
+
+    let expiration = options.expiration()?;
+    let seal_options = options.seal_options()?;
+
+    let stripped = if options.strip_metadata {
+        strip_image_metadata(data).await?
+    } else {
+        None
+    };
+    let data = stripped.as_ref().unwrap_or(data);
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let size = data.size() as u64;
+    if size == 0 {
+        bail!("Nothing to upload.");
+    }
+
+    let url = Url::from_str(&location)?;
+    check_server_info(&url, size, options.expiration.as_deref()).await?;
+
+    // Chunked pastes can be decrypted while they're downloaded.
+    let enc_key = Key::random_secret();
+    let (mut sealer, header) = ChunkedSealer::new(&enc_key, options.password(), seal_options)?;
+    let sealed = Array::new();
+    sealed.push(&Uint8Array::from(header.as_slice()));
+    let mut hasher = ContentHasher::default();
+
+    // The last chunk is sealed differently, and is never empty.
+    let chunk_size = CHUNK_SIZE as u64;
+    let last_start = (size - 1) / chunk_size * chunk_size;
+    let mut offset = 0;
+    while offset < last_start {
+        let end = (offset + READ_SIZE).min(last_start);
+        for chunk in read_blob(data, offset, end).await?.chunks(CHUNK_SIZE) {
+            hasher.update(chunk);
+            sealed.push(&Uint8Array::from(sealer.seal_next(chunk)?.as_slice()));
+        }
+        offset = end;
+        reporter.report(Progress::Encrypting {
+            loaded: offset,
+            total: size,
+        });
+    }
+    let last = read_blob(data, last_start, size).await?;
+    hasher.update(&last);
+    sealed.push(&Uint8Array::from(sealer.seal_last(&last)?.as_slice()));
+    reporter.report(Progress::Encrypting {
+        loaded: size,
+        total: size,
+    });
+
+    let sealed = Blob::new_with_u8_array_sequence(&sealed)
+        .map_err(|e| anyhow!("Failed to create blob: {e:?}"))?;
+    let fragment = options.fragment(&enc_key, hasher.finish())?;
+
+    let resp = http::upload(&url, &sealed, expiration, reporter).await?;
+    let uploaded = Uploaded::from_response(resp).await?;
+    let mut url = uploaded.url(&url);
+    url.set_fragment(Some(fragment.build().expose_secret()));
+
+    let entry = history::Entry::new(&url, uploaded.expiration, uploaded.owner_token);
+    if let Err(e) = history::record(&entry).await {
+        log!(format!("[rs] Failed to record paste in history: {e}"));
+    }
+
+    Ok(JsString::from(url.as_ref()))
+}
+
+/// Reads the bytes in `start..end` of a blob.
+#[allow(clippy::future_not_send, clippy::cast_precision_loss)]
+pub async fn read_blob(blob: &Blob, start: u64, end: u64) -> Result<Vec<u8>> {
+    let slice = blob
+        .slice_with_f64_and_f64(start as f64, end as f64)
+        .map_err(|e| anyhow!("Failed to read file: {e:?}"))?;
+    let buffer = JsFuture::from(slice.array_buffer())
+        .await
+        .map_err(|e| anyhow!("Failed to read file: {e:?}"))?;
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+/// Returns a copy of an image without its metadata, or `None` if it isn't an
+/// image or has no metadata to remove.
+#[allow(clippy::future_not_send)]
+async fn strip_image_metadata(data: &Blob) -> Result<Option<Blob>> {
+    if !data.type_().starts_with("image/") {
+        return Ok(None);
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let size = data.size() as u64;
+    let stripped = match image_meta::strip(&read_blob(data, 0, size).await?) {
+        Some(stripped) => stripped,
+        None => return Ok(None),
+    };
+    log!("[rs] Removed image metadata.");
+    let parts = Array::of1(&Uint8Array::from(stripped.as_slice()));
+    Blob::new_with_u8_array_sequence(&parts)
+        .map(Some)
+        .map_err(|e| anyhow!("Failed to create blob: {e:?}"))
+}
+
+/// Returns the decrypted paste as an idb object, or a message to show to the
+/// user if the paste couldn't be fetched or decrypted.
+///
+/// Password protected pastes are kept in memory until the password is known
+/// to be right, so that another password can be tried without downloading the
+/// paste again, which may not be possible for burn after reading pastes.
+#[allow(clippy::future_not_send, clippy::too_many_arguments)]
+async fn fetch_resources(
+    request_uri: String,
+    key: Secret<Key>,
+    password: Option<SecretVec<u8>>,
+    name: Option<String>,
+    chunked: bool,
+    envelope: Envelope,
+    hash: Option<[u8; HASH_SIZE]>,
+    reporter: &Reporter,
+    request_password: &Function,
+    confirm_size: &Function,
+) -> Result<(Object, MimeType), JsString> {
+    reporter.report(Progress::Downloading {
+        loaded: 0,
+        total: None,
+    });
+
+    let resp = match http::get(&request_uri).await {
+        Ok(resp) => resp,
+        Err(err) => return Err(format!("{err}").into()),
+    };
+
+    match resp.status {
+        StatusCode::OK => (),
+        StatusCode::NOT_FOUND => {
+            return Err(tr(Message::NotFound).into());
+        }
+        StatusCode::GONE => {
+            return Err(resp.tombstone.as_ref().map_or_else(
+                || tr(Message::BurnedOrDeleted).into(),
+                |tombstone| i18n::tombstone(tombstone).into(),
+            ));
+        }
+        // Our clock may be behind the server's.
+        StatusCode::LOCKED => {
+            return Err(resp.not_before.map_or_else(
+                || resp.status.as_u16().to_string().into(),
+                |not_before| i18n::not_before(not_before).into(),
+            ));
+        }
+        StatusCode::BAD_REQUEST => return Err(tr(Message::InvalidUrl).into()),
+        status => return Err(status.as_u16().to_string().into()),
+    }
+
+    let expiration = resp.expiration;
+    let total = resp.content_length;
+    let limit = if chunked {
+        STREAMING_DOWNLOAD_SIZE_LIMIT
+    } else {
+        DOWNLOAD_SIZE_LIMIT
+    };
+    if total.map_or(false, |total| u128::from(total) > limit) {
+        return Err(tr(Message::TooLarge).into());
+    }
+
+    if let Some(total) = total {
+        let memory = estimated_memory(total, chunked, password.is_some());
+        if memory > memory_budget() {
+            confirm_large_paste(confirm_size, Message::LargerThanMemory, total, memory).await?;
+        } else if u128::from(memory) > LARGE_PASTE_WARNING {
+            confirm_large_paste(confirm_size, Message::LargePaste, total, memory).await?;
+        }
+    }
+
+    let (decrypted, mimetype, Verified(verified)) = if chunked {
+        let mut ciphertext = password.is_some().then(Vec::new);
+        let mut decrypter = StreamingDecrypter::new(&key, password, envelope, name.clone(), hash);
+        let mut wrong_password = false;
+        let mut downloaded = 0;
+        read_body(&resp, total, reporter, |chunk| {
+            downloaded += chunk.len() as u128;
+            // The rest of the paste is kept to try another password with.
+            let limit = if decrypter.is_buffering() || wrong_password {
+                DOWNLOAD_SIZE_LIMIT
+            } else {
+                STREAMING_DOWNLOAD_SIZE_LIMIT
+            };
+            if downloaded > limit {
+                return Err(tr(Message::TooLarge).into());
+            }
+
+            if let Some(ciphertext) = ciphertext.as_mut() {
+                ciphertext.extend_from_slice(chunk);
+            }
+            if wrong_password {
+                return Ok(());
+            }
+
+            if decrypter.will_derive_key() {
+                reporter.report(Progress::DerivingKey);
+            }
+            match decrypter.update(chunk) {
+                Ok(()) if decrypter.has_opened() => {
+                    ciphertext = None;
+                    Ok(())
+                }
+                Ok(()) => Ok(()),
+                Err(CryptoError::Password) => {
+                    wrong_password = true;
+                    Ok(())
+                }
+                Err(e) => Err(crypto_error_message(&e)),
+            }
+        })
+        .await?;
+
+        let result = if wrong_password {
+            Err(CryptoError::Password)
+        } else {
+            decrypter.finish(reporter)
+        };
+        retry_password(result, request_password, |password| {
+            let mut decrypter =
+                StreamingDecrypter::new(&key, Some(password), envelope, name.clone(), hash);
+            reporter.report(Progress::DerivingKey);
+            decrypter.update(ciphertext.as_deref().unwrap_or_default())?;
+            decrypter.finish(reporter)
+        })
+        .await?
+    } else {
+        let mut data = Vec::with_capacity(
+            total
+                .and_then(|total| usize::try_from(total).ok())
+                .unwrap_or_default(),
+        );
+        read_body(&resp, total, reporter, |chunk| {
+            data.extend_from_slice(chunk);
+            if data.len() as u128 > DOWNLOAD_SIZE_LIMIT {
+                return Err(tr(Message::TooLarge).into());
+            }
+            Ok(())
+        })
+        .await?;
+        let ciphertext = password.is_some().then(|| data.clone());
+        let result = decrypt(
+            data,
+            &key,
+            password,
+            envelope,
+            name.as_deref(),
+            hash.as_ref(),
+            reporter,
+        );
+        retry_password(result, request_password, |password| {
+            let ciphertext = ciphertext.clone().unwrap_or_default();
+            decrypt(
+                ciphertext,
+                &key,
+                Some(password),
+                envelope,
+                name.as_deref(),
+                hash.as_ref(),
+                reporter,
+            )
+        })
+        .await?
+    };
+
+    if verified == Some(false) {
+        log!("[rs] Decrypted paste doesn't match the hash in its URL.");
+    }
+    Ok((
+        to_idb_object(&decrypted, &key, expiration, verified),
+        mimetype,
+    ))
+}
+
+/// Estimates how much memory decrypting a paste of `size` bytes takes.
+/// Unchunked pastes hold both the ciphertext and the plaintext in wasm memory,
+/// and password protected ones keep another copy of the ciphertext to retry
+/// with. Chunked pastes are streamed into a blob, which the browser holds.
+const fn estimated_memory(size: u64, chunked: bool, has_password: bool) -> u64 {
+    if chunked {
+        size
+    } else if has_password {
+        size.saturating_mul(3)
+    } else {
+        size.saturating_mul(2)
+    }
+}
+
+/// How much memory decrypting a paste may take before it's likely to crash the
+/// page. This is a quarter of what the browser hints the page may use, as the
+/// rest of the page and browser need memory too.
+fn memory_budget() -> u64 {
+    memory_hint().map_or(DEFAULT_MEMORY_BUDGET, |hint| hint / 4)
+}
+
+/// Asks the user whether to download and decrypt a large paste with
+/// `message`, failing if they decline.
+#[allow(clippy::future_not_send)]
+async fn confirm_large_paste(
+    confirm_size: &Function,
+    message: Message,
+    size: u64,
+    memory: u64,
+) -> Result<(), JsString> {
+    let format = |bytes: u64| {
+        Byte::from_bytes(u128::from(bytes))
+            .get_appropriate_unit(true)
+            .to_string()
+    };
+    let message = tr_with_args(message, &[&format(size), &format(memory)]);
+    let promise = confirm_size
+        .call1(&JsValue::NULL, &JsString::from(message))
+        .ok()
+        .and_then(|promise| promise.dyn_into::<Promise>().ok())
+        .ok_or_else(|| JsString::from(tr(Message::InternalError)))?;
+    let confirmed = JsFuture::from(promise)
+        .await
+        .map_or(false, |confirmed| confirmed.is_truthy());
+    if confirmed {
+        Ok(())
+    } else {
+        Err(tr(Message::LargePasteCancelled).into())
+    }
+}
+
+/// Asks for another password while the password is wrong, retrying with it,
+/// until `MAX_PASSWORD_ATTEMPTS` have been made or the user gives up.
+#[allow(clippy::future_not_send)]
+async fn retry_password<T>(
+    mut result: Result<T, CryptoError>,
+    request_password: &Function,
+    mut retry: impl FnMut(SecretVec<u8>) -> Result<T, CryptoError>,
+) -> Result<T, JsString> {
+    let mut attempts = 1;
+    while matches!(result, Err(CryptoError::Password)) && attempts < MAX_PASSWORD_ATTEMPTS {
+        let promise = request_password
+            .call2(
+                &JsValue::NULL,
+                &JsValue::from(attempts),
+                &JsValue::from(MAX_PASSWORD_ATTEMPTS),
+            )
+            .ok()
+            .and_then(|promise| promise.dyn_into::<Promise>().ok())
+            .ok_or_else(|| JsString::from(tr(Message::InternalError)))?;
+        let password = JsFuture::from(promise)
+            .await
+            .ok()
+            .and_then(|password| password.as_string())
+            .filter(|password| !password.is_empty())
+            .ok_or_else(|| JsString::from(tr(Message::PasswordRequired)))?;
+        attempts += 1;
+        result = retry(SecretVec::new(password.into_bytes()));
+    }
+
+    result.map_err(|e| crypto_error_message(&e))
+}
+
+/// Checks whether a paste will be burned when it's fetched, from the response
+/// to a `HEAD` request for it.
+fn is_burn_after_reading(resp: &ApiResponse) -> bool {
+    resp.expiration.map_or(false, |expiration| {
+        matches!(
+            expiration,
+            Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_)
+        )
+    })
+}
+
+/// Reads the entire response body, passing each chunk to `on_chunk` as it's
+/// received and reporting how much has been downloaded so far. Reports are
+/// limited to one per `PROGRESS_INTERVAL` bytes, as each one re-renders the
+/// page.
+#[allow(clippy::future_not_send)]
+async fn read_body(
+    resp: &ApiResponse,
+    total: Option<u64>,
+    reporter: &Reporter,
+    mut on_chunk: impl FnMut(&[u8]) -> Result<(), JsString>,
+) -> Result<(), JsString> {
+    const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+    let chunks = resp
+        .chunks()
+        .map_err(|_| JsString::from(tr(Message::EmptyResponse)))?;
+
+    let mut loaded = 0;
+    let mut last_reported = 0;
+    loop {
+        let chunk = match chunks.next().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                log!(format!("[rs] Failed to read response body: {e}"));
+                return Err(tr(Message::DownloadFailed).into());
+            }
+        };
+
+        on_chunk(&chunk)?;
+
+        loaded += chunk.len() as u64;
+        if loaded - last_reported >= PROGRESS_INTERVAL {
+            reporter.report(Progress::Downloading { loaded, total });
+            last_reported = loaded;
+        }
+    }
+
+    reporter.report(Progress::Downloading { loaded, total });
+
+    Ok(())
+}
+
+fn crypto_error_message(e: &CryptoError) -> JsString {
+    let msg = match e {
+        CryptoError::Password => tr(Message::WrongPassword),
+        CryptoError::SecretKey => tr(Message::WrongKey),
+        e => {
+            log!(format!("Bad kdf or corrupted blob: {e}"));
+            tr(Message::InternalError)
+        }
+    };
+    JsString::from(msg)
+}
+
+/// Converts a decrypted paste into an idb object. `verified` is whether the
+/// paste matched the hash in its URL, and is left out if there wasn't one.
+fn to_idb_object(
+    decrypted: &DecryptedData,
+    key: &Secret<Key>,
+    expiration: Option<Expiration>,
+    verified: Option<bool>,
+) -> Object {
+    let mut object = to_idb_entries(decrypted, expiration);
+    // The keys of a manifest's members are derived from the manifest's key.
+    if let DecryptedData::Manifest(_, manifest) = decrypted {
+        object = object.extra("members", member_links(manifest, key));
+    }
+    match verified {
+        Some(verified) => Object::from(object.extra("verified", verified)),
+        None => Object::from(object),
+    }
+}
+
+/// The names, sizes, and links with keys of the members of a manifest.
+fn member_links(manifest: &Manifest, key: &Secret<Key>) -> Array {
+    manifest
+        .members
+        .iter()
+        .enumerate()
+        .filter_map(|(index, member)| {
+            let link = manifest.member_link(key, index)?;
+            let object = Object::new();
+            let set = |name: &str, value: &str| {
+                Reflect::set(&object, &JsValue::from_str(name), &JsValue::from_str(value))
+            };
+            set("name", &member.name).ok()?;
+            set(
+                "size",
+                &Byte::from_bytes(u128::from(member.size))
+                    .get_appropriate_unit(true)
+                    .to_string(),
+            )
+            .ok()?;
+            set("link", link.as_str()).ok()?;
+            Some(object)
+        })
+        .collect()
+}
+
+fn to_idb_entries(decrypted: &DecryptedData, expiration: Option<Expiration>) -> IdbObject<Ready> {
+    match decrypted {
+        DecryptedData::String(s, _) if s.len() > LARGE_TEXT_SIZE => IdbObject::new()
+            .large_string()
+            .expiration(expiration)
+            .data(&text_blob(s)),
+        DecryptedData::String(s, language) => IdbObject::new()
+            .string()
+            .expiration(expiration)
+            .data(&JsValue::from_str(s))
+            .extra(
+                "language",
+                language.map_or(JsValue::NULL, JsValue::from_str),
+            ),
+        DecryptedData::Markdown(source, html) => IdbObject::new()
+            .markdown()
+            .expiration(expiration)
+            .data(&JsValue::from_str(source))
+            .extra("html", html.as_str()),
+        DecryptedData::Ansi(source, spans) => IdbObject::new()
+            .ansi()
+            .expiration(expiration)
+            .data(&JsValue::from_str(source))
+            .extra(
+                "spans",
+                serde_wasm_bindgen::to_value(spans).unwrap_or(JsValue::NULL),
+            ),
+        DecryptedData::Diff(source, files) => IdbObject::new()
+            .diff()
+            .expiration(expiration)
+            .data(&JsValue::from_str(source))
+            .extra(
+                "files",
+                serde_wasm_bindgen::to_value(files).unwrap_or(JsValue::NULL),
+            ),
+        DecryptedData::Notebook(source, html) => IdbObject::new()
+            .notebook()
+            .expiration(expiration)
+            .data(&JsValue::from_str(source))
+            .extra("html", html.as_str()),
+        DecryptedData::Json(source, pretty) => IdbObject::new()
+            .json()
+            .expiration(expiration)
+            .data(&JsValue::from_str(source))
+            .extra("pretty", pretty.as_str()),
+        DecryptedData::Manifest(source, _) => IdbObject::new()
+            .manifest()
+            .expiration(expiration)
+            .data(&JsValue::from_str(source)),
+        DecryptedData::Table(source, table) => IdbObject::new()
+            .table()
+            .expiration(expiration)
+            .data(&JsValue::from_str(source))
+            .extra(
+                "rows",
+                serde_wasm_bindgen::to_value(&table.rows).unwrap_or(JsValue::NULL),
+            )
+            .extra("truncated", table.truncated),
+        DecryptedData::Blob(blob) => IdbObject::new().blob().expiration(expiration).data(blob),
+        DecryptedData::Image(blob, size, info) => {
+            let (width, height) = info
+                .dimensions
+                .map_or((JsValue::NULL, JsValue::NULL), |(w, h)| {
+                    (JsValue::from(w), JsValue::from(h))
+                });
+            IdbObject::new()
+                .image()
+                .expiration(expiration)
+                .data(blob)
+                .extra(
+                    "file_size",
+                    Byte::from_bytes(*size as u128)
+                        .get_appropriate_unit(true)
+                        .to_string(),
+                )
+                .extra("width", width)
+                .extra("height", height)
+                .extra(
+                    "orientation",
+                    info.orientation.map_or(JsValue::NULL, JsValue::from),
+                )
+                .extra("has_gps", info.has_gps)
+        }
+        DecryptedData::Audio(blob, info) => with_media_info(
+            IdbObject::new().audio().expiration(expiration).data(blob),
+            info,
+        ),
+        DecryptedData::Video(blob, info) => with_media_info(
+            IdbObject::new().video().expiration(expiration).data(blob),
+            info,
+        ),
+        // Entries are listed with `archive_entries` as they're needed, as
+        // large archives have too many to render at once.
+        #[allow(clippy::cast_precision_loss)]
+        DecryptedData::Archive(blob, entries) => IdbObject::new()
+            .archive()
+            .expiration(expiration)
+            .data(blob)
+            .extra("entry_count", entries.len() as f64),
+        DecryptedData::Transcoded(text, encoding) => {
+            to_idb_entries(text, expiration).extra("encoding", encoding.name())
+        }
+    }
+}
+
+fn with_media_info(object: IdbObject<Ready>, info: &MediaInfo) -> IdbObject<Ready> {
+    let (width, height) = info
+        .dimensions
+        .map_or((JsValue::NULL, JsValue::NULL), |(w, h)| {
+            (JsValue::from(w), JsValue::from(h))
+        });
+    object
+        .extra(
+            "duration",
+            info.duration
+                .map_or(JsValue::NULL, |duration| duration.as_secs_f64().into()),
+        )
+        .extra(
+            "codec",
+            info.codec
+                .as_deref()
+                .map_or(JsValue::NULL, JsValue::from_str),
+        )
+        .extra("width", width)
+        .extra("height", height)
+}
+
+/// Reads the decrypted paste cached under `path`, if there is one.
+#[allow(clippy::future_not_send)]
+async fn load_cached(path: String) -> Result<Option<Object>> {
+    let db_open_req = open_idb()?;
+    let mut path = Some(path);
+
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let path = path.take().expect("promise executor to run once");
+
+        create_store_on_upgrade(&db_open_req);
+        db_open_req.set_onerror(Some(reject_on_event(reject.clone()).unchecked_ref()));
+
+        let on_success = Closure::once(Box::new(move |event: Event| {
+            let get_action = as_idb_db(&event)
+                .transaction_with_str("decrypted data")
+                .unwrap()
+                .object_store("decrypted data")
+                .unwrap()
+                .get(&JsString::from(path))
+                .unwrap();
+            let on_get = Closure::once(Box::new(move |event: Event| {
+                let result = event
+                    .target()
+                    .map(JsCast::unchecked_into::<IdbRequest>)
+                    .and_then(|req| req.result().ok())
+                    .unwrap_or(JsValue::UNDEFINED);
+                let _ = resolve.call1(&JsValue::NULL, &result);
+            }));
+            get_action.set_onsuccess(Some(on_get.into_js_value().unchecked_ref()));
+            get_action.set_onerror(Some(reject_on_event(reject).unchecked_ref()));
+        }));
+        db_open_req.set_onsuccess(Some(on_success.into_js_value().unchecked_ref()));
+    });
+
+    let cached = JsFuture::from(promise)
+        .await
+        .map_err(|e| anyhow!("{e:?}"))?;
+    Ok(cached.dyn_into::<Object>().ok())
+}
+
+/// Stores the decrypted paste in idb, resolving once it has been written.
+#[allow(clippy::future_not_send)]
+async fn store_decrypted(path: String, object: Object) -> Result<()> {
+    let db_open_req = open_idb()?;
+    let mut item = Some((path, object));
+
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let (path, object) = item.take().expect("promise executor to run once");
+
+        create_store_on_upgrade(&db_open_req);
+        db_open_req.set_onerror(Some(reject_on_event(reject.clone()).unchecked_ref()));
+
+        let on_success = Closure::once(Box::new(move |event: Event| {
+            let put_action = as_idb_db(&event)
+                .transaction_with_str_and_mode("decrypted data", IdbTransactionMode::Readwrite)
+                .unwrap()
+                .object_store("decrypted data")
+                .unwrap()
+                .put_with_key(&object, &JsString::from(path))
+                .unwrap();
+            put_action.set_onsuccess(Some(resolve.unchecked_ref()));
+            put_action.set_onerror(Some(reject_on_event(reject).unchecked_ref()));
+        }));
+        db_open_req.set_onsuccess(Some(on_success.into_js_value().unchecked_ref()));
+    });
+
+    JsFuture::from(promise)
+        .await
+        .map(drop)
+        .map_err(|e| anyhow!("{e:?}"))
+}
+
+/// Removes the decrypted paste cached under `path`, if there is one.
+#[allow(clippy::future_not_send)]
+async fn remove_cached(path: String) -> Result<()> {
+    let db_open_req = open_idb()?;
+    let mut path = Some(path);
+
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let path = path.take().expect("promise executor to run once");
+
+        create_store_on_upgrade(&db_open_req);
+        db_open_req.set_onerror(Some(reject_on_event(reject.clone()).unchecked_ref()));
+
+        let on_success = Closure::once(Box::new(move |event: Event| {
+            let delete_action = as_idb_db(&event)
+                .transaction_with_str_and_mode("decrypted data", IdbTransactionMode::Readwrite)
+                .unwrap()
+                .object_store("decrypted data")
+                .unwrap()
+                .delete(&JsString::from(path))
+                .unwrap();
+            delete_action.set_onsuccess(Some(resolve.unchecked_ref()));
+            delete_action.set_onerror(Some(reject_on_event(reject).unchecked_ref()));
+        }));
+        db_open_req.set_onsuccess(Some(on_success.into_js_value().unchecked_ref()));
+    });
+
+    JsFuture::from(promise)
+        .await
+        .map(drop)
+        .map_err(|e| anyhow!("{e:?}"))
+}
+
+/// Deletes cached pastes that have expired, or that were cached more than
+/// `CACHE_MAX_AGE` ago, such as when the page was closed before it could remove
+/// them. Entries cached by older versions have no timestamps, and are always
+/// deleted.
+#[allow(clippy::future_not_send)]
+async fn evict_cached_pastes() -> Result<()> {
+    let db_open_req = open_idb()?;
+
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        create_store_on_upgrade(&db_open_req);
+        db_open_req.set_onerror(Some(reject_on_event(reject.clone()).unchecked_ref()));
+
+        let on_success = Closure::once(Box::new(move |event: Event| {
+            let cursor_req = as_idb_db(&event)
+                .transaction_with_str_and_mode("decrypted data", IdbTransactionMode::Readwrite)
+                .unwrap()
+                .object_store("decrypted data")
+                .unwrap()
+                .open_cursor()
+                .unwrap();
+
+            let now = js_sys::Date::now();
+            // Called once per entry, and once more after the last one.
+            let on_cursor = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+                let cursor = event
+                    .target()
+                    .map(JsCast::unchecked_into::<IdbRequest>)
+                    .and_then(|req| req.result().ok())
+                    .and_then(|cursor| cursor.dyn_into::<IdbCursorWithValue>().ok());
+                let cursor = match cursor {
+                    Some(cursor) => cursor,
+                    None => {
+                        let _ = resolve.call0(&JsValue::NULL);
+                        return;
+                    }
+                };
+
+                if cursor.value().map_or(true, |value| is_stale(&value, now)) {
+                    let _ = cursor.delete();
+                }
+                let _ = cursor.continue_();
+            });
+            cursor_req.set_onsuccess(Some(on_cursor.into_js_value().unchecked_ref()));
+            cursor_req.set_onerror(Some(reject_on_event(reject).unchecked_ref()));
+        }));
+        db_open_req.set_onsuccess(Some(on_success.into_js_value().unchecked_ref()));
+    });
+
+    JsFuture::from(promise)
+        .await
+        .map(drop)
+        .map_err(|e| anyhow!("{e:?}"))
+}
+
+fn is_stale(object: &JsValue, now: f64) -> bool {
+    let get = |key: &str| {
+        Reflect::get(object, &JsValue::from_str(key))
+            .ok()
+            .and_then(|value| value.as_f64())
+    };
+
+    match get("stored_at") {
+        Some(stored_at) => {
+            now - stored_at > CACHE_MAX_AGE.as_secs_f64() * 1000.0
+                || get("expires_at").map_or(false, |expires_at| expires_at < now)
+        }
+        None => true,
+    }
+}
+
+/// Creates the object stores that don't exist yet, such as when the database
+/// was created by an older version.
+pub fn create_store_on_upgrade(db_open_req: &IdbOpenDbRequest) {
+    let on_upgrade = Closure::once(Box::new(move |event: Event| {
+        let db = as_idb_db(&event);
+        for store in ["decrypted data", history::STORE_NAME] {
+            if !db.object_store_names().contains(store) {
+                let _obj_store = db.create_object_store(store).unwrap();
+            }
+        }
+    }));
+    db_open_req.set_onupgradeneeded(Some(on_upgrade.into_js_value().unchecked_ref()));
+}
+
+pub fn reject_on_event(reject: Function) -> JsValue {
+    Closure::once(Box::new(move |e: Event| {
+        let _ = reject.call1(&JsValue::NULL, &e);
+    }))
+    .into_js_value()
+}
@@ -0,0 +1,73 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+import { decrypt_paste } from '../pkg';
+
+interface BgData {
+  requestUri: string,
+  fragment: string,
+  password?: string,
+  path: string,
+}
+
+// A password to retry with, or null if the user gave up.
+interface PasswordReply {
+  type: 'password',
+  password: string | null,
+}
+
+// Whether to continue decrypting a paste that will use a lot of memory.
+interface ConfirmSizeReply {
+  type: 'confirmSize',
+  confirmed: boolean,
+}
+
+let resolvePassword: ((password: string | null) => void) | undefined;
+let resolveConfirmSize: ((confirmed: boolean) => void) | undefined;
+
+addEventListener('message', (event: MessageEvent<BgData | PasswordReply | ConfirmSizeReply>) => {
+  if ('type' in event.data) {
+    switch (event.data.type) {
+      case 'password':
+        resolvePassword?.(event.data.password);
+        break;
+      case 'confirmSize':
+        resolveConfirmSize?.(event.data.confirmed);
+        break;
+    }
+    return;
+  }
+
+  let { requestUri, fragment, password, path } = event.data;
+  console.log('[js-worker] Decrypting paste in a worker thread...');
+  const onProgress = (phase: string, loaded?: number, total?: number) => {
+    postMessage({ type: 'progress', phase, loaded, total });
+  };
+  const requestPassword = (attempts: number, maxAttempts: number) => new Promise(resolve => {
+    resolvePassword = resolve;
+    postMessage({ type: 'password', attempts, maxAttempts });
+  });
+  const confirmSize = (message: string) => new Promise(resolve => {
+    resolveConfirmSize = resolve;
+    postMessage({ type: 'confirmSize', message });
+  });
+  decrypt_paste(requestUri, fragment, password, path, onProgress, requestPassword, confirmSize).then(({ mimeType, data }) => {
+    console.log("[js-worker] Decryption done.");
+    postMessage({ type: 'done', mimeType, data });
+  }).catch(message => postMessage({ type: 'error', message }));
+})
+
+postMessage({ type: 'init' });
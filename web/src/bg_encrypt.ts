@@ -14,20 +14,47 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-import { encrypt_array_buffer } from '../pkg';
+import { encrypt_blob, encrypt_files, encrypt_recording, encrypt_remote } from '../pkg';
+
+interface UploadOptions {
+  password?: string,
+  kdf_profile?: string,
+  expiration?: string,
+  name?: string,
+  language?: string,
+  private?: boolean,
+}
 
 interface BgData {
   location: string,
-  data: any
+  // Multiple files are bundled into a single zip archive, and a URL is
+  // downloaded and mirrored.
+  data: Blob | File[] | string,
+  options: UploadOptions,
+  // Whether the data was recorded with MediaRecorder.
+  recording?: boolean,
 }
 
 addEventListener('message', (event: MessageEvent<BgData>) => {
-  let { location, data } = event.data;
+  let { location, data, options, recording } = event.data;
   console.log('[js-worker] Sending data to rust in a worker thread...');
-  encrypt_array_buffer(location, data).then(url => {
+  const onProgress = (phase: string, loaded?: number, total?: number) => {
+    postMessage({ type: 'progress', phase, loaded, total });
+  };
+  let encrypted: Promise<string>;
+  if (Array.isArray(data)) {
+    encrypted = encrypt_files(location, data, options, onProgress);
+  } else if (typeof data === 'string') {
+    encrypted = encrypt_remote(location, data, options, onProgress);
+  } else if (recording) {
+    encrypted = encrypt_recording(location, data, options, onProgress);
+  } else {
+    encrypted = encrypt_blob(location, data, options, onProgress);
+  }
+  encrypted.then(url => {
     console.log("[js-worker] Encryption done.");
-    postMessage(url);
-  }).catch(e => console.error(e));
+    postMessage({ type: 'done', url });
+  }).catch(message => postMessage({ type: 'error', message }));
 })
 
-postMessage("init");
\ No newline at end of file
+postMessage({ type: 'init' });
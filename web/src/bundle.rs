@@ -0,0 +1,70 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Bundles multiple files into a single zip archive, so they can be uploaded
+//! as one paste.
+
+use std::io::{Cursor, Write};
+
+use anyhow::{anyhow, bail, Result};
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::JsCast;
+use web_sys::{Blob, File};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::app::{read_blob, DOWNLOAD_SIZE_LIMIT};
+use crate::progress::{Progress, Reporter};
+
+/// The name given to bundles if the user didn't pick one.
+pub const BUNDLE_NAME: &str = "bundle.zip";
+
+/// Zips the provided files into a blob. The archive is built in wasm memory,
+/// so the files together must fit under the download size limit.
+#[allow(clippy::future_not_send)]
+pub async fn zip_files(files: &Array, reporter: &Reporter) -> Result<Blob> {
+    let files = files
+        .iter()
+        .map(|file| file.dyn_into::<File>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow!("Only files can be bundled."))?;
+    if files.is_empty() {
+        bail!("Nothing to upload.");
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let total = files.iter().map(|file| file.size() as u64).sum();
+    if u128::from(total) > DOWNLOAD_SIZE_LIMIT {
+        bail!("These files are too large to bundle together. Upload them one at a time instead.");
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut loaded = 0;
+    for file in files {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let size = file.size() as u64;
+        let data = read_blob(&file, 0, size).await?;
+        zip.start_file(file.name(), FileOptions::default())?;
+        zip.write_all(&data)?;
+
+        loaded += size;
+        reporter.report(Progress::Bundling { loaded, total });
+    }
+
+    let data = zip.finish()?.into_inner();
+    Blob::new_with_u8_array_sequence(&Array::of1(&Uint8Array::from(data.as_slice())))
+        .map_err(|e| anyhow!("Failed to create blob: {e:?}"))
+}
@@ -14,17 +14,39 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 use std::sync::Arc;
 
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
 use gloo_console::log;
 use js_sys::{Array, Uint8Array};
-use omegaupload_common::crypto::{open_in_place, Error, Key};
+use omegaupload_common::crypto::{
+    hash, open_in_place_with_progress, ChunkedOpener, ContentHasher, Envelope, Error, Key,
+    CHUNK_SIZE, HASH_SIZE,
+};
+use omegaupload_common::manifest::Manifest;
+use omegaupload_common::secrecy::zeroize::{Zeroize, Zeroizing};
 use omegaupload_common::secrecy::{Secret, SecretVec};
 use serde::Serialize;
 use wasm_bindgen::JsCast;
 use web_sys::{Blob, BlobPropertyBag};
 
+use crate::ansi::{self, Span};
+use crate::diff::{self, FileDiff};
+use crate::image_meta::{self, ImageInfo};
+use crate::json;
+use crate::language;
+use crate::markdown;
+use crate::media_meta::{self, MediaInfo};
+use crate::notebook;
+use crate::progress::{Progress, Reporter};
+use crate::table::{self, Table};
+
+/// The most a gzipped file is decompressed to in order to be displayed, so
+/// that a small paste can't exhaust wasm memory.
+const MAX_GUNZIPPED_SIZE: u64 = 256 * 1024 * 1024;
+
 #[derive(Clone, Serialize)]
 pub struct ArchiveMeta {
     name: String,
@@ -33,31 +55,85 @@ pub struct ArchiveMeta {
 
 #[derive(Clone)]
 pub enum DecryptedData {
-    String(Arc<String>),
+    /// The text, and the language it seems to be in.
+    String(Arc<String>, Option<&'static str>),
+    /// The Markdown source, and the sanitized HTML it renders to.
+    Markdown(Arc<String>, String),
+    /// Text with ANSI escape sequences, and the styled spans it renders to.
+    Ansi(Arc<String>, Vec<Span>),
+    /// A unified diff, and the files it changes.
+    Diff(Arc<String>, Vec<FileDiff>),
+    /// The Jupyter notebook source, and the sanitized HTML it renders to.
+    Notebook(Arc<String>, String),
+    /// The JSON source, and the same JSON pretty-printed.
+    Json(Arc<String>, String),
+    /// The JSON source of a manifest, and the pastes it lists.
+    Manifest(Arc<String>, Manifest),
+    /// The CSV or TSV source, and a preview of its rows.
+    Table(Arc<String>, Table),
     Blob(Arc<Blob>),
-    Image(Arc<Blob>, usize),
-    Audio(Arc<Blob>),
-    Video(Arc<Blob>),
+    /// The image, its size in bytes, and what was read from its metadata.
+    Image(Arc<Blob>, usize, ImageInfo),
+    Audio(Arc<Blob>, MediaInfo),
+    Video(Arc<Blob>, MediaInfo),
     Archive(Arc<Blob>, Vec<ArchiveMeta>),
+    /// Text that was transcoded to UTF-8, and the encoding it was in.
+    Transcoded(Box<DecryptedData>, &'static Encoding),
 }
 
+/// Decryption runs in a worker, which has no `Window` to get a `Performance`
+/// from.
 fn now() -> f64 {
-    web_sys::window()
-        .expect("should have a Window")
-        .performance()
-        .expect("should have a Performance")
-        .now()
+    js_sys::Date::now()
 }
 
 pub struct MimeType(pub String);
 
+/// Whether the decrypted paste matches the hash in its URL, or `None` if the
+/// URL didn't have one.
+pub struct Verified(pub Option<bool>);
+
 pub fn decrypt(
     mut container: Vec<u8>,
     key: &Secret<Key>,
     maybe_password: Option<SecretVec<u8>>,
+    envelope: Envelope,
     name_hint: Option<&str>,
-) -> Result<(DecryptedData, MimeType), Error> {
-    open_in_place(&mut container, key, maybe_password)?;
+    expected_hash: Option<&[u8; HASH_SIZE]>,
+    reporter: &Reporter,
+) -> Result<(DecryptedData, MimeType, Verified), Error> {
+    open_in_place_with_progress(&mut container, key, maybe_password, envelope, |stage| {
+        reporter.report(stage.into());
+    })?;
+
+    let verified = Verified(expected_hash.map(|expected| hash(&container) == *expected));
+    let (data, mime_type) = into_decrypted_data(container, name_hint, reporter);
+    Ok((data, mime_type, verified))
+}
+
+fn into_decrypted_data(
+    container: Vec<u8>,
+    name_hint: Option<&str>,
+    reporter: &Reporter,
+) -> (DecryptedData, MimeType) {
+    reporter.report(Progress::Processing);
+    // Whatever isn't handed on is only needed until it's in a blob.
+    let mut container = Zeroizing::new(container);
+
+    // A gzipped file is displayed as the file it contains, so that logs and
+    // the like are readable. Gzipped tarballs are still listed as archives.
+    if container.content_type() == ContentType::Gzip {
+        if let Some((inner, inner_name)) = gunzip(&container, name_hint) {
+            let is_tar = inner.content_type() == ContentType::Tar
+                || inner_name
+                    .as_deref()
+                    .map_or(false, |name| name.ends_with(".tar"));
+            if !is_tar && inner.content_type() != ContentType::Gzip {
+                log!("[rs] Decompressed a gzipped file.");
+                return into_decrypted_data(inner, inner_name.as_deref(), reporter);
+            }
+        }
+    }
 
     let mime_type = guess_mime_type(name_hint, &container);
     log!("[rs] Mime type:", mime_type);
@@ -70,35 +146,133 @@ pub fn decrypt(
         array.copy_from(chunk);
         blob_chunks.set(i.try_into().unwrap(), array.dyn_into().unwrap());
     }
-    let mut blob_props = BlobPropertyBag::new();
-    blob_props.type_(mime_type);
-    let blob = Arc::new(
-        Blob::new_with_u8_array_sequence_and_options(blob_chunks.dyn_ref().unwrap(), &blob_props)
-            .unwrap(),
-    );
+    let blob = to_blob(&blob_chunks, mime_type);
 
     log!(format!(
         "[rs] Blob conversion completed in {}ms",
         now() - start
     ));
 
+    let is_markdown = markdown::is_markdown(name_hint, mime_type);
+    let table_delimiter = table::delimiter(name_hint, mime_type);
+    let handle_text = |text: String| {
+        if is_markdown {
+            let html = markdown::render(&text);
+            DecryptedData::Markdown(Arc::new(text), html)
+        } else if let Some(files) = diff::is_diff(name_hint, mime_type, &text)
+            .then(|| diff::parse(&text))
+            .flatten()
+        {
+            DecryptedData::Diff(Arc::new(text), files)
+        } else if let Some(table) =
+            table_delimiter.and_then(|delimiter| table::parse(&text, delimiter))
+        {
+            DecryptedData::Table(Arc::new(text), table)
+        } else if let Some(html) = notebook::is_notebook(name_hint, &text)
+            .then(|| notebook::render(&text))
+            .flatten()
+        {
+            DecryptedData::Notebook(Arc::new(text), html)
+        } else if let Some(manifest) = serde_json::from_str::<Manifest>(&text)
+            .ok()
+            .filter(Manifest::is_supported)
+        {
+            DecryptedData::Manifest(Arc::new(text), manifest)
+        } else if let Some(pretty) = json::pretty(&text) {
+            DecryptedData::Json(Arc::new(text), pretty)
+        } else if ansi::has_escapes(&text) {
+            let spans = ansi::parse(&text);
+            DecryptedData::Ansi(Arc::new(text), spans)
+        } else {
+            let language = language::detect(name_hint, &text);
+            DecryptedData::String(Arc::new(text), language)
+        }
+    };
     let data = match container.content_type() {
-        ContentType::Text => DecryptedData::String(Arc::new(
+        ContentType::Text => {
             // SAFETY: ContentType::Text is guaranteed to be valid UTF-8.
-            unsafe { String::from_utf8_unchecked(container) },
-        )),
-        ContentType::Image => DecryptedData::Image(blob, container.len()),
-        ContentType::Audio => DecryptedData::Audio(blob),
-        ContentType::Video => DecryptedData::Video(blob),
-        ContentType::ZipArchive => handle_zip_archive(blob, container),
-        ContentType::Gzip => handle_gzip(blob, container),
+            handle_text(unsafe { String::from_utf8_unchecked(std::mem::take(&mut *container)) })
+        }
+        ContentType::EncodedText => match transcode(&container) {
+            Some((text, encoding)) => {
+                log!("[rs] Transcoded text from", encoding.name());
+                DecryptedData::Transcoded(Box::new(handle_text(text)), encoding)
+            }
+            None => DecryptedData::Blob(blob),
+        },
+        ContentType::Image => {
+            DecryptedData::Image(blob, container.len(), image_meta::read(&container))
+        }
+        ContentType::Audio => DecryptedData::Audio(blob, media_meta::read(&container)),
+        ContentType::Video => DecryptedData::Video(blob, media_meta::read(&container)),
+        ContentType::ZipArchive => handle_zip_archive(blob, std::mem::take(&mut *container)),
+        content_type @ (ContentType::Tar
+        | ContentType::Gzip
+        | ContentType::Zstd
+        | ContentType::Xz
+        | ContentType::Bzip2) => match tar_reader(content_type, std::mem::take(&mut *container)) {
+            Some(reader) => handle_tar(blob, reader),
+            None => DecryptedData::Blob(blob),
+        },
         ContentType::Unknown => DecryptedData::Blob(blob),
     };
 
-    Ok((data, MimeType(mime_type.to_owned())))
+    (data, MimeType(mime_type.to_owned()))
+}
+
+/// Decodes text that isn't UTF-8, returning it along with the encoding it was
+/// in. Without a BOM, the encoding has to be guessed, so this should only be
+/// used on data that already looks like text.
+fn transcode(data: &[u8]) -> Option<(String, &'static Encoding)> {
+    let encoding = Encoding::for_bom(data).map_or_else(
+        || {
+            let mut detector = EncodingDetector::new();
+            detector.feed(data, true);
+            detector.guess(None, true)
+        },
+        |(encoding, _)| encoding,
+    );
+    let (text, encoding, had_errors) = encoding.decode(data);
+    (!had_errors).then(|| (text.into_owned(), encoding))
+}
+
+/// Decompresses a gzipped file, returning its contents and the name it had
+/// before it was compressed, if known. Returns `None` if it isn't a single
+/// gzip member, or is too large once decompressed.
+fn gunzip(data: &[u8], name_hint: Option<&str>) -> Option<(Vec<u8>, Option<String>)> {
+    let mut decoder = flate2::bufread::GzDecoder::new(data);
+    let mut inner = vec![];
+    (&mut decoder)
+        .take(MAX_GUNZIPPED_SIZE + 1)
+        .read_to_end(&mut inner)
+        .ok()?;
+    if inner.len() as u64 > MAX_GUNZIPPED_SIZE {
+        return None;
+    }
+
+    let name = name_hint
+        .and_then(|name| {
+            name.strip_suffix(".gz")
+                .map(ToOwned::to_owned)
+                .or_else(|| name.strip_suffix(".tgz").map(|name| format!("{name}.tar")))
+        })
+        .or_else(|| {
+            decoder
+                .header()
+                .and_then(|header| header.filename())
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+        });
+
+    // Anything left over is another member, which would be a surprise to
+    // leave out.
+    decoder.into_inner().is_empty().then_some((inner, name))
 }
 
 fn handle_zip_archive(blob: Arc<Blob>, container: Vec<u8>) -> DecryptedData {
+    DecryptedData::Archive(blob, zip_entries(container))
+}
+
+fn zip_entries(container: Vec<u8>) -> Vec<ArchiveMeta> {
     let mut entries = vec![];
     let cursor = Cursor::new(container);
     if let Ok(mut zip) = zip::ZipArchive::new(cursor) {
@@ -120,15 +294,55 @@ fn handle_zip_archive(blob: Arc<Blob>, container: Vec<u8>) -> DecryptedData {
         }
     }
 
-    entries.sort_by(|a, b| a.name.cmp(&b.name));
-    DecryptedData::Archive(blob, entries)
+    sort_entries(&mut entries);
+    entries
 }
 
-fn handle_gzip(blob: Arc<Blob>, container: Vec<u8>) -> DecryptedData {
-    let mut entries = vec![];
+/// Sorts entries by name, ignoring case, with entries in directories first.
+/// This doesn't group subdirectories with their parents, but it's close enough.
+fn sort_entries(entries: &mut [ArchiveMeta]) {
+    entries.sort_by_cached_key(|entry| (!entry.name.contains('/'), entry.name.to_lowercase()));
+}
+
+/// Returns a reader over the contents of a tarball, decompressing it if
+/// needed. Returns `None` if support for its compression format is disabled.
+fn tar_reader(content_type: ContentType, container: Vec<u8>) -> Option<Box<dyn Read>> {
     let cursor = Cursor::new(container);
-    let gzip_dec = flate2::read::GzDecoder::new(cursor);
-    let mut archive = tar::Archive::new(gzip_dec);
+    match content_type {
+        ContentType::Tar => Some(Box::new(cursor)),
+        ContentType::Gzip => Some(Box::new(flate2::read::GzDecoder::new(cursor))),
+        #[cfg(feature = "zstd")]
+        ContentType::Zstd => ruzstd::StreamingDecoder::new(cursor)
+            .ok()
+            .map(|decoder| Box::new(decoder) as Box<dyn Read>),
+        #[cfg(feature = "xz")]
+        ContentType::Xz => {
+            // lzma-rs can't decompress incrementally.
+            let mut input = cursor;
+            let mut decompressed = vec![];
+            lzma_rs::xz_decompress(&mut input, &mut decompressed).ok()?;
+            Some(Box::new(Cursor::new(decompressed)))
+        }
+        #[cfg(feature = "bzip2")]
+        ContentType::Bzip2 => Some(Box::new(bzip2_rs::DecoderReader::new(cursor))),
+        _ => None,
+    }
+}
+
+/// Lists the entries of a tarball. Compressed files that aren't tarballs are
+/// treated as blobs.
+fn handle_tar(blob: Arc<Blob>, reader: impl Read) -> DecryptedData {
+    let entries = tar_entries(reader);
+    if entries.is_empty() {
+        DecryptedData::Blob(blob)
+    } else {
+        DecryptedData::Archive(blob, entries)
+    }
+}
+
+fn tar_entries(reader: impl Read) -> Vec<ArchiveMeta> {
+    let mut entries = vec![];
+    let mut archive = tar::Archive::new(reader);
     if let Ok(files) = archive.entries() {
         for file in files.flatten() {
             let file_path = if let Ok(file_path) = file.path() {
@@ -142,10 +356,242 @@ fn handle_gzip(blob: Arc<Blob>, container: Vec<u8>) -> DecryptedData {
             });
         }
     }
-    if entries.is_empty() {
-        DecryptedData::Blob(blob)
-    } else {
-        DecryptedData::Archive(blob, entries)
+    sort_entries(&mut entries);
+    entries
+}
+
+/// Lists the entries of an archive, in the same order as when it was
+/// decrypted. Returns an empty list if the archive couldn't be read.
+pub fn list_entries(container: Vec<u8>) -> Vec<ArchiveMeta> {
+    match container.content_type() {
+        ContentType::ZipArchive => zip_entries(container),
+        content_type @ (ContentType::Tar
+        | ContentType::Gzip
+        | ContentType::Zstd
+        | ContentType::Xz
+        | ContentType::Bzip2) => {
+            tar_reader(content_type, container).map_or_else(Vec::new, tar_entries)
+        }
+        _ => vec![],
+    }
+}
+
+/// Extracts a single file from an archive, typed by its name or contents so
+/// that it can be previewed. Returns `None` if the archive couldn't be read or
+/// doesn't contain a file at `path`.
+pub fn extract_entry(container: Vec<u8>, path: &str) -> Option<Blob> {
+    let data = read_archive_entry(container, path)?;
+    let parts = Array::of1(&Uint8Array::from(data.as_slice()));
+    Some(Blob::clone(&to_blob(
+        &parts,
+        guess_mime_type(Some(path), &data),
+    )))
+}
+
+fn read_archive_entry(container: Vec<u8>, path: &str) -> Option<Vec<u8>> {
+    let mut data = vec![];
+    match container.content_type() {
+        ContentType::ZipArchive => {
+            let mut zip = zip::ZipArchive::new(Cursor::new(container)).ok()?;
+            let mut file = zip.by_name(path).ok()?;
+            file.read_to_end(&mut data).ok()?;
+        }
+        content_type @ (ContentType::Tar
+        | ContentType::Gzip
+        | ContentType::Zstd
+        | ContentType::Xz
+        | ContentType::Bzip2) => {
+            let mut archive = tar::Archive::new(tar_reader(content_type, container)?);
+            let mut file = archive.entries().ok()?.flatten().find(|file| {
+                file.path()
+                    .map_or(false, |file_path| file_path.display().to_string() == path)
+            })?;
+            file.read_to_end(&mut data).ok()?;
+        }
+        _ => return None,
+    }
+    Some(data)
+}
+
+fn to_blob(parts: &Array, mime_type: &str) -> Arc<Blob> {
+    let mut blob_props = BlobPropertyBag::new();
+    blob_props.type_(mime_type);
+    Arc::new(Blob::new_with_u8_array_sequence_and_options(parts, &blob_props).unwrap())
+}
+
+/// Decrypts a chunked paste as it's downloaded.
+///
+/// Pastes that must be fully in memory to be displayed, such as text and
+/// archives, are buffered. Everything else is handed to the browser as blob
+/// parts as soon as it is decrypted, so it never needs to fit in wasm memory.
+/// Which one is used is decided from the first chunk of the paste.
+pub struct StreamingDecrypter {
+    opener: ChunkedOpener,
+    sink: Sink,
+    /// Hashes the paste as it's decrypted, along with the hash to compare to.
+    verifier: Option<(ContentHasher, [u8; HASH_SIZE])>,
+}
+
+impl StreamingDecrypter {
+    pub fn new(
+        key: &Secret<Key>,
+        maybe_password: Option<SecretVec<u8>>,
+        envelope: Envelope,
+        name_hint: Option<String>,
+        expected_hash: Option<[u8; HASH_SIZE]>,
+    ) -> Self {
+        Self {
+            opener: ChunkedOpener::new(key, maybe_password, envelope),
+            verifier: expected_hash.map(|expected| (ContentHasher::default(), expected)),
+            sink: Sink {
+                name_hint,
+                buffer: vec![],
+                blob: None,
+                sniffed: false,
+                len: 0,
+            },
+        }
+    }
+
+    pub const fn will_derive_key(&self) -> bool {
+        self.opener.will_derive_key()
+    }
+
+    /// Whether any of the paste has been decrypted, which means the key and
+    /// password are right.
+    pub const fn has_opened(&self) -> bool {
+        self.sink.len > 0
+    }
+
+    /// Whether the decrypted paste is being kept in wasm memory.
+    pub const fn is_buffering(&self) -> bool {
+        self.sink.blob.is_none()
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<(), Error> {
+        let opened = self.opener.update(data)?;
+        self.push(opened);
+        Ok(())
+    }
+
+    pub fn finish(
+        mut self,
+        reporter: &Reporter,
+    ) -> Result<(DecryptedData, MimeType, Verified), Error> {
+        let opened = self.opener.finish()?;
+        // The opener was consumed, so this can't go through `push`.
+        if let Some((hasher, _)) = self.verifier.as_mut() {
+            hasher.update(&opened);
+        }
+        self.sink.push(opened);
+        let verified = Verified(
+            self.verifier
+                .map(|(hasher, expected)| hasher.finish() == expected),
+        );
+        let (data, mime_type) = self.sink.finish(reporter);
+        Ok((data, mime_type, verified))
+    }
+
+    fn push(&mut self, opened: Vec<u8>) {
+        if let Some((hasher, _)) = self.verifier.as_mut() {
+            hasher.update(&opened);
+        }
+        self.sink.push(opened);
+    }
+}
+
+struct Sink {
+    name_hint: Option<String>,
+    buffer: Vec<u8>,
+    blob: Option<StreamingBlob>,
+    sniffed: bool,
+    len: usize,
+}
+
+struct StreamingBlob {
+    parts: Array,
+    mime_type: &'static str,
+    content_type: ContentType,
+    image_info: Option<ImageInfo>,
+    media_info: Option<MediaInfo>,
+}
+
+impl Sink {
+    fn push(&mut self, opened: Vec<u8>) {
+        let opened = Zeroizing::new(opened);
+        self.len += opened.len();
+        if let Some(blob) = self.blob.as_ref() {
+            blob.parts.push(&Uint8Array::from(opened.as_slice()));
+            return;
+        }
+
+        self.buffer.extend_from_slice(&opened);
+        if !self.sniffed && self.buffer.len() >= CHUNK_SIZE {
+            self.sniffed = true;
+            self.sniff();
+        }
+    }
+
+    fn sniff(&mut self) {
+        // Only the start of the paste is available, so this errs on the side of
+        // buffering. A multibyte character may have been cut off, for example.
+        let content_type = self.buffer.content_type();
+        if tree_magic_mini::match_u8("text/plain", &self.buffer)
+            || matches!(
+                content_type,
+                ContentType::Text
+                    | ContentType::EncodedText
+                    | ContentType::ZipArchive
+                    | ContentType::Tar
+                    | ContentType::Gzip
+                    | ContentType::Zstd
+                    | ContentType::Xz
+                    | ContentType::Bzip2
+            )
+        {
+            log!("[rs] Buffering paste.");
+            return;
+        }
+
+        log!("[rs] Streaming paste into a blob.");
+        let parts = Array::new();
+        parts.push(&Uint8Array::from(self.buffer.as_slice()));
+        self.blob = Some(StreamingBlob {
+            parts,
+            mime_type: guess_mime_type(self.name_hint.as_deref(), &self.buffer),
+            content_type,
+            image_info: (content_type == ContentType::Image)
+                .then(|| image_meta::read(&self.buffer)),
+            media_info: matches!(content_type, ContentType::Audio | ContentType::Video)
+                .then(|| media_meta::read(&self.buffer)),
+        });
+        self.buffer.zeroize();
+        self.buffer.shrink_to_fit();
+    }
+
+    fn finish(self, reporter: &Reporter) -> (DecryptedData, MimeType) {
+        let StreamingBlob {
+            parts,
+            mime_type,
+            content_type,
+            image_info,
+            media_info,
+        } = match self.blob {
+            Some(blob) => blob,
+            None => return into_decrypted_data(self.buffer, self.name_hint.as_deref(), reporter),
+        };
+
+        reporter.report(Progress::Processing);
+        let blob = to_blob(&parts, mime_type);
+        let data = match content_type {
+            ContentType::Image => {
+                DecryptedData::Image(blob, self.len, image_info.unwrap_or_default())
+            }
+            ContentType::Audio => DecryptedData::Audio(blob, media_info.unwrap_or_default()),
+            ContentType::Video => DecryptedData::Video(blob, media_info.unwrap_or_default()),
+            _ => DecryptedData::Blob(blob),
+        };
+        (data, MimeType(mime_type.to_owned()))
     }
 }
 
@@ -168,11 +614,17 @@ fn guess_mime_type(name_hint: Option<&str>, data: &[u8]) -> &'static str {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 enum ContentType {
     Text,
+    /// Text in an encoding other than UTF-8.
+    EncodedText,
     Image,
     Audio,
     Video,
     ZipArchive,
+    Tar,
     Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
     Unknown,
 }
 
@@ -187,6 +639,7 @@ impl<T: AsRef<[u8]>> ContentTypeExt for T {
     }
 
     fn content_type(&self) -> ContentType {
+        let bytes = self.as_ref();
         let mime_type = self.mime_type();
         // check image first; tree magic match_u8 matches SVGs as plain text
         if mime_type.starts_with("image/")
@@ -198,7 +651,7 @@ impl<T: AsRef<[u8]>> ContentTypeExt for T {
             if std::str::from_utf8(self.as_ref()).is_ok() {
                 ContentType::Text
             } else {
-                ContentType::Unknown
+                ContentType::EncodedText
             }
         } else if mime_type.starts_with("audio/") {
             ContentType::Audio
@@ -211,12 +664,29 @@ impl<T: AsRef<[u8]>> ContentTypeExt for T {
             ContentType::ZipArchive
         } else if mime_type == "application/gzip" {
             ContentType::Gzip
+        } else if mime_type == "application/x-tar" || is_tar(bytes) {
+            ContentType::Tar
+        } else if mime_type == "application/zstd" || bytes.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            ContentType::Zstd
+        } else if mime_type == "application/x-xz" || bytes.starts_with(b"\xFD7zXZ\0") {
+            ContentType::Xz
+        } else if mime_type == "application/x-bzip2" || bytes.starts_with(b"BZh") {
+            ContentType::Bzip2
+        } else if Encoding::for_bom(bytes).is_some() {
+            // UTF-16 isn't detected as text, as many of its bytes are null.
+            ContentType::EncodedText
         } else {
             ContentType::Unknown
         }
     }
 }
 
+/// Checks for the `ustar` magic in the first tar header. Older v7 tarballs
+/// lack it, but nothing produces those anymore.
+fn is_tar(bytes: &[u8]) -> bool {
+    bytes.get(257..262) == Some(b"ustar")
+}
+
 #[cfg(test)]
 mod content_type {
     use super::*;
@@ -244,6 +714,708 @@ mod content_type {
     test_content_type!(mkv_is_video, "movie.mkv", ContentType::Video);
     test_content_type!(zip_is_zip, "archive.zip", ContentType::ZipArchive);
     test_content_type!(gzip_is_gzip, "image.png.gz", ContentType::Gzip);
+    test_content_type!(tar_is_tar, "archive.tar", ContentType::Tar);
+    test_content_type!(zstd_is_zstd, "archive.tar.zst", ContentType::Zstd);
+    test_content_type!(xz_is_xz, "archive.tar.xz", ContentType::Xz);
+    test_content_type!(bzip2_is_bzip2, "archive.tar.bz2", ContentType::Bzip2);
     test_content_type!(binary_is_unknown, "omegaupload", ContentType::Unknown);
     test_content_type!(pgp_is_text, "text.pgp", ContentType::Text);
 }
+
+#[cfg(test)]
+mod extract {
+    use super::*;
+
+    macro_rules! test_extract {
+        ($($name:ident, $archive:literal, $path:literal, $expected:literal),*) => {
+            $(
+                #[test]
+                fn $name() {
+                    let archive = include_bytes!(concat!("../../test/", $archive));
+                    let expected = include_bytes!(concat!("../../test/", $expected));
+                    let data = read_archive_entry(archive.to_vec(), $path);
+                    assert_eq!(data.as_deref(), Some(expected.as_slice()));
+                }
+            )*
+        };
+    }
+
+    test_extract!(tar, "archive.tar", "code.rs", "code.rs");
+    test_extract!(tar_zst, "archive.tar.zst", "LICENSE.md", "LICENSE.md");
+    test_extract!(tar_xz, "archive.tar.xz", "code.rs", "code.rs");
+    test_extract!(tar_bz2, "archive.tar.bz2", "LICENSE.md", "LICENSE.md");
+
+    #[test]
+    fn zip() {
+        let archive = include_bytes!("../../test/archive.zip");
+        let data = read_archive_entry(archive.to_vec(), "zsh/.zshrc");
+        assert!(data.is_some());
+    }
+
+    #[test]
+    fn missing_entry() {
+        let archive = include_bytes!("../../test/archive.tar");
+        assert!(read_archive_entry(archive.to_vec(), "missing.rs").is_none());
+    }
+
+    #[test]
+    fn entries_in_directories_first() {
+        let archive = include_bytes!("../../test/archive.zip");
+        let entries = list_entries(archive.to_vec());
+        assert!(!entries.is_empty());
+        let first_top_level = entries
+            .iter()
+            .position(|entry| !entry.name.contains('/'))
+            .unwrap_or(entries.len());
+        assert!(entries[first_top_level..]
+            .iter()
+            .all(|entry| !entry.name.contains('/')));
+    }
+}
+
+#[cfg(test)]
+mod gunzip {
+    use super::*;
+
+    #[test]
+    fn single_member() {
+        let data = include_bytes!("../../test/image.png.gz");
+        let (inner, name) = gunzip(data, Some("image.png.gz")).unwrap();
+        assert_eq!(inner, include_bytes!("../../test/image.png"));
+        assert_eq!(name.as_deref(), Some("image.png"));
+    }
+
+    #[test]
+    fn tgz_is_tar() {
+        let data = include_bytes!("../../test/image.png.gz");
+        let (_, name) = gunzip(data, Some("archive.tgz")).unwrap();
+        assert_eq!(name.as_deref(), Some("archive.tar"));
+    }
+
+    #[test]
+    fn multiple_members() {
+        let data = include_bytes!("../../test/image.png.gz").repeat(2);
+        assert!(gunzip(&data, None).is_none());
+    }
+}
+
+#[cfg(test)]
+mod transcode {
+    use encoding_rs::{SHIFT_JIS, UTF_16LE};
+
+    use super::*;
+
+    #[test]
+    fn utf16_with_bom() {
+        let mut data = vec![0xFF, 0xFE];
+        data.extend("Hello, world!".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(data.content_type(), ContentType::EncodedText);
+        assert_eq!(
+            transcode(&data),
+            Some(("Hello, world!".to_string(), UTF_16LE))
+        );
+    }
+
+    #[test]
+    fn shift_jis() {
+        let text = "これは日本語のテキストです。文字コードはシフトJISで保存されています。";
+        let (data, _, _) = SHIFT_JIS.encode(text);
+        assert_eq!(transcode(&data), Some((text.to_string(), SHIFT_JIS)));
+    }
+}
+
+/// Runs in a browser with `wasm-pack test --headless --firefox web`, as blobs
+/// and the progress callback need one.
+#[cfg(all(test, target_arch = "wasm32"))]
+mod browser {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use encoding_rs::UTF_16LE;
+    use js_sys::Function;
+    use omegaupload_common::crypto::{
+        seal_chunked_in_place, seal_chunked_in_place_with_key, seal_in_place,
+        seal_in_place_with_key, Cipher, KdfParams, SealOptions,
+    };
+    use omegaupload_common::PartialParsedUrl;
+    use wasm_bindgen::closure::Closure;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    /// Splits sealed pastes into pieces that don't line up with chunks, like
+    /// downloads do.
+    const PIECE_SIZE: usize = 10_000;
+
+    macro_rules! vector {
+        ($name:literal) => {
+            ($name, include_bytes!(concat!("../../testdata/", $name)))
+        };
+    }
+
+    /// The sealed pastes in `testdata`, which the common library and the CLI
+    /// also test against. Blobs can't be read at runtime, so they're listed here
+    /// as well as in `vectors.txt`.
+    const VECTORS: [(&str, &[u8]); 10] = [
+        vector!("plain.bin"),
+        vector!("password.bin"),
+        vector!("password-kdf.bin"),
+        vector!("chunked.bin"),
+        vector!("chunked-password.bin"),
+        vector!("chunked-password-kdf.bin"),
+        vector!("aes.bin"),
+        vector!("aes-password-kdf.bin"),
+        vector!("chunked-aes.bin"),
+        vector!("chunked-aes-password-kdf.bin"),
+    ];
+
+    /// A reporter, along with the phases that it has reported so far.
+    fn reporter() -> (Reporter, Rc<RefCell<Vec<String>>>) {
+        let phases = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&phases);
+        let callback = Closure::<dyn FnMut(String)>::new(move |phase: String| {
+            recorded.borrow_mut().push(phase);
+        });
+        let reporter = Reporter::new(callback.as_ref().unchecked_ref::<Function>().clone());
+        callback.forget();
+        (reporter, phases)
+    }
+
+    fn password(password: &str) -> SecretVec<u8> {
+        SecretVec::new(password.as_bytes().to_vec())
+    }
+
+    /// Seals and decrypts a paste, as if it were uploaded with `name`.
+    fn open(data: &[u8], name: Option<&str>) -> DecryptedData {
+        let mut sealed = data.to_vec();
+        let key = seal_in_place(&mut sealed, None).unwrap();
+        let (reporter, _) = reporter();
+        match decrypt(
+            sealed,
+            &key,
+            None,
+            Envelope::default(),
+            name,
+            None,
+            &reporter,
+        ) {
+            Ok((data, _, _)) => data,
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+    }
+
+    /// Feeds a sealed chunked paste to a decrypter a piece at a time.
+    fn stream(
+        mut decrypter: StreamingDecrypter,
+        sealed: &[u8],
+        reporter: &Reporter,
+    ) -> Result<(DecryptedData, MimeType, Verified), Error> {
+        for piece in sealed.chunks(PIECE_SIZE) {
+            decrypter.update(piece)?;
+        }
+        decrypter.finish(reporter)
+    }
+
+    fn assert_blob_len(blob: &Blob, len: usize) {
+        #[allow(clippy::cast_precision_loss)]
+        let len = len as f64;
+        assert_eq!(blob.size(), len);
+    }
+
+    #[wasm_bindgen_test]
+    fn text() {
+        let code = include_str!("../../test/code.rs");
+        match open(code.as_bytes(), Some("code.rs")) {
+            DecryptedData::String(text, language) => {
+                assert_eq!(*text, code);
+                assert!(language.is_some());
+            }
+            _ => panic!("expected text"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn markdown() {
+        match open(b"# Title\n\nSome *text*.\n", Some("notes.md")) {
+            DecryptedData::Markdown(_, html) => assert!(html.contains("<h1>Title</h1>"), "{html}"),
+            _ => panic!("expected Markdown"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn ansi() {
+        match open(b"\x1b[31merror\x1b[0m: oops\n", None) {
+            DecryptedData::Ansi(_, spans) => {
+                let text: String = spans.iter().map(|span| span.text.as_str()).collect();
+                assert_eq!(text, "error: oops\n");
+            }
+            _ => panic!("expected ANSI text"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn diff() {
+        let patch = include_bytes!("../../test/0000-test-patch.patch");
+        match open(patch, Some("0000-test-patch.patch")) {
+            DecryptedData::Diff(_, files) => assert!(!files.is_empty()),
+            _ => panic!("expected a diff"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn notebook() {
+        let notebook = br##"{
+            "nbformat": 4,
+            "nbformat_minor": 5,
+            "metadata": {},
+            "cells": [{"cell_type": "markdown", "metadata": {}, "source": ["# Findings"]}]
+        }"##;
+        match open(notebook, Some("analysis.ipynb")) {
+            DecryptedData::Notebook(_, html) => assert!(html.contains("Findings")),
+            _ => panic!("expected a notebook"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn json() {
+        match open(br#"{"a":[1,2]}"#, None) {
+            DecryptedData::Json(_, pretty) => {
+                assert_eq!(pretty, "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+            }
+            _ => panic!("expected JSON"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn table() {
+        match open(b"fruit,count\napples,3\npears,5\n", Some("fruit.csv")) {
+            DecryptedData::Table(_, table) => {
+                assert_eq!(table.rows.len(), 3);
+                assert_eq!(table.rows[1], ["apples", "3"]);
+            }
+            _ => panic!("expected a table"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn manifest() {
+        let source = br#"{"omegaupload_manifest":1,"members":[{"name":"a.txt","size":5,"url":"https://example.com/2345","hints":"!name:a.txt"}]}"#;
+        match open(source, None) {
+            DecryptedData::Manifest(_, manifest) => {
+                assert_eq!(manifest.members.len(), 1);
+                assert_eq!(manifest.members[0].name, "a.txt");
+            }
+            _ => panic!("expected a manifest"),
+        }
+
+        // Manifests from newer clients are shown as they are.
+        let source = br#"{"omegaupload_manifest":2,"members":[]}"#;
+        assert!(matches!(open(source, None), DecryptedData::Json(..)));
+    }
+
+    #[wasm_bindgen_test]
+    fn transcoded() {
+        let mut data = vec![0xFF, 0xFE];
+        data.extend("Hello, world!".encode_utf16().flat_map(u16::to_le_bytes));
+        match open(&data, None) {
+            DecryptedData::Transcoded(inner, encoding) => {
+                assert_eq!(encoding, UTF_16LE);
+                match *inner {
+                    DecryptedData::String(text, _) => assert_eq!(*text, "Hello, world!"),
+                    _ => panic!("expected text"),
+                }
+            }
+            _ => panic!("expected transcoded text"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn blob() {
+        let binary = include_bytes!("../../test/omegaupload");
+        match open(binary, None) {
+            DecryptedData::Blob(blob) => assert_blob_len(&blob, binary.len()),
+            _ => panic!("expected a blob"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn image() {
+        let png = include_bytes!("../../test/image.png");
+        match open(png, None) {
+            DecryptedData::Image(blob, len, info) => {
+                assert_eq!(len, png.len());
+                assert_blob_len(&blob, png.len());
+                assert!(info.dimensions.is_some());
+            }
+            _ => panic!("expected an image"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn gzipped_image() {
+        let gzipped = include_bytes!("../../test/image.png.gz");
+        match open(gzipped, Some("image.png.gz")) {
+            DecryptedData::Image(_, len, _) => {
+                assert_eq!(len, include_bytes!("../../test/image.png").len());
+            }
+            _ => panic!("expected the gzipped image"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn audio() {
+        let mp3 = include_bytes!("../../test/music.mp3");
+        match open(mp3, None) {
+            DecryptedData::Audio(blob, _) => assert_blob_len(&blob, mp3.len()),
+            _ => panic!("expected audio"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn video() {
+        let mp4 = include_bytes!("../../test/movie.mp4");
+        match open(mp4, None) {
+            DecryptedData::Video(blob, _) => assert_blob_len(&blob, mp4.len()),
+            _ => panic!("expected video"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn archives() {
+        for (archive, name) in [
+            (
+                include_bytes!("../../test/archive.zip").as_slice(),
+                "archive.zip",
+            ),
+            (
+                include_bytes!("../../test/archive.tar").as_slice(),
+                "archive.tar",
+            ),
+            (
+                include_bytes!("../../test/archive.tar.zst").as_slice(),
+                "archive.tar.zst",
+            ),
+        ] {
+            match open(archive, Some(name)) {
+                DecryptedData::Archive(_, entries) => assert!(!entries.is_empty(), "{name}"),
+                _ => panic!("expected {name} to be an archive"),
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn verifies_hashes() {
+        let data = b"hello world";
+        for (expected, verified) in [
+            (Some(hash(data)), Some(true)),
+            (Some([0; HASH_SIZE]), Some(false)),
+            (None, None),
+        ] {
+            let mut sealed = data.to_vec();
+            let key = seal_in_place(&mut sealed, None).unwrap();
+            let (reporter, _) = reporter();
+            let result = decrypt(
+                sealed,
+                &key,
+                None,
+                Envelope::default(),
+                None,
+                expected.as_ref(),
+                &reporter,
+            );
+            assert_eq!(result.ok().map(|(_, _, Verified(v))| v), Some(verified));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn opens_with_the_password() {
+        let mut sealed = b"hello world".to_vec();
+        let key = seal_in_place(&mut sealed, Some(password("hunter2"))).unwrap();
+        let (reporter, phases) = reporter();
+        match decrypt(
+            sealed,
+            &key,
+            Some(password("hunter2")),
+            Envelope::default(),
+            None,
+            None,
+            &reporter,
+        ) {
+            Ok((DecryptedData::String(text, _), _, _)) => assert_eq!(*text, "hello world"),
+            Ok(_) => panic!("expected text"),
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+        assert_eq!(
+            *phases.borrow(),
+            ["deriving-key", "decrypting", "processing"]
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn rejects_wrong_passwords_and_keys() {
+        let mut sealed = b"hello world".to_vec();
+        let key = seal_in_place(&mut sealed, Some(password("hunter2"))).unwrap();
+        let (reporter, _) = reporter();
+
+        let wrong_password = Some(password("hunter3"));
+        let result = decrypt(
+            sealed.clone(),
+            &key,
+            wrong_password,
+            Envelope::default(),
+            None,
+            None,
+            &reporter,
+        );
+        assert!(matches!(result, Err(Error::Password)));
+
+        let result = decrypt(
+            sealed.clone(),
+            &key,
+            None,
+            Envelope::default(),
+            None,
+            None,
+            &reporter,
+        );
+        assert!(result.is_err());
+
+        let wrong_key = Key::random_secret();
+        let result = decrypt(
+            sealed,
+            &wrong_key,
+            Some(password("hunter2")),
+            Envelope::default(),
+            None,
+            None,
+            &reporter,
+        );
+        assert!(matches!(result, Err(Error::SecretKey)));
+    }
+
+    #[wasm_bindgen_test]
+    fn streams_media_into_a_blob() {
+        let mp4 = include_bytes!("../../test/movie.mp4");
+        let mut sealed = mp4.to_vec();
+        let key = seal_chunked_in_place(&mut sealed, None).unwrap();
+        let mut decrypter =
+            StreamingDecrypter::new(&key, None, Envelope::default(), None, Some(hash(mp4)));
+        assert!(!decrypter.will_derive_key());
+        assert!(!decrypter.has_opened());
+
+        let (first, rest) = sealed.split_at(CHUNK_SIZE * 3);
+        decrypter.update(first).unwrap();
+        assert!(decrypter.has_opened());
+        assert!(!decrypter.is_buffering());
+
+        let (reporter, _) = reporter();
+        match stream(decrypter, rest, &reporter) {
+            Ok((DecryptedData::Video(blob, _), _, Verified(verified))) => {
+                assert_blob_len(&blob, mp4.len());
+                assert_eq!(verified, Some(true));
+            }
+            Ok(_) => panic!("expected video"),
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn streams_images_with_their_metadata() {
+        let png = include_bytes!("../../test/image.png");
+        let mut sealed = png.to_vec();
+        let key = seal_chunked_in_place(&mut sealed, None).unwrap();
+        let decrypter = StreamingDecrypter::new(&key, None, Envelope::default(), None, None);
+        let (reporter, _) = reporter();
+        match stream(decrypter, &sealed, &reporter) {
+            Ok((DecryptedData::Image(blob, len, info), _, Verified(None))) => {
+                assert_eq!(len, png.len());
+                assert_blob_len(&blob, png.len());
+                assert!(info.dimensions.is_some());
+            }
+            Ok(_) => panic!("expected an unverified image"),
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn buffers_text() {
+        let text = "a line of text\n".repeat(CHUNK_SIZE / 4);
+        let mut sealed = text.clone().into_bytes();
+        let key = seal_chunked_in_place(&mut sealed, None).unwrap();
+        let mut decrypter = StreamingDecrypter::new(&key, None, Envelope::default(), None, None);
+
+        let (first, rest) = sealed.split_at(CHUNK_SIZE * 3);
+        decrypter.update(first).unwrap();
+        assert!(decrypter.is_buffering());
+
+        let (reporter, _) = reporter();
+        match stream(decrypter, rest, &reporter) {
+            Ok((DecryptedData::String(decrypted, _), _, _)) => assert_eq!(*decrypted, text),
+            Ok(_) => panic!("expected text"),
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn buffers_small_archives() {
+        let zip = include_bytes!("../../test/archive.zip");
+        let mut sealed = zip.to_vec();
+        let key = seal_chunked_in_place(&mut sealed, None).unwrap();
+        let decrypter = StreamingDecrypter::new(
+            &key,
+            None,
+            Envelope::default(),
+            Some("archive.zip".to_string()),
+            None,
+        );
+        let (reporter, _) = reporter();
+        match stream(decrypter, &sealed, &reporter) {
+            Ok((DecryptedData::Archive(_, entries), MimeType(mime_type), _)) => {
+                assert!(!entries.is_empty());
+                assert_eq!(mime_type, "application/zip");
+            }
+            Ok(_) => panic!("expected an archive"),
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn streams_with_the_password() {
+        let png = include_bytes!("../../test/image.png");
+        let mut sealed = png.to_vec();
+        let key = seal_chunked_in_place(&mut sealed, Some(password("hunter2"))).unwrap();
+        let (reporter, _) = reporter();
+
+        let decrypter = StreamingDecrypter::new(
+            &key,
+            Some(password("hunter3")),
+            Envelope::default(),
+            None,
+            None,
+        );
+        assert!(decrypter.will_derive_key());
+        let result = stream(decrypter, &sealed, &reporter);
+        assert!(matches!(result, Err(Error::Password)));
+
+        let decrypter = StreamingDecrypter::new(
+            &key,
+            Some(password("hunter2")),
+            Envelope::default(),
+            None,
+            None,
+        );
+        match stream(decrypter, &sealed, &reporter) {
+            Ok((DecryptedData::Image(blob, _, _), _, _)) => assert_blob_len(&blob, png.len()),
+            Ok(_) => panic!("expected an image"),
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn streams_with_recorded_kdf() {
+        let png = include_bytes!("../../test/image.png");
+        let mut sealed = png.to_vec();
+        let key = Key::random_secret();
+        let kdf = KdfParams {
+            m_cost: 8 * 1024,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let options = SealOptions {
+            kdf: Some(kdf),
+            ..SealOptions::default()
+        };
+        seal_chunked_in_place_with_key(&mut sealed, &key, Some(password("hunter2")), options)
+            .unwrap();
+        let (reporter, _) = reporter();
+
+        let envelope = options.envelope(true);
+        let decrypter =
+            StreamingDecrypter::new(&key, Some(password("hunter2")), envelope, None, None);
+        match stream(decrypter, &sealed, &reporter) {
+            Ok((DecryptedData::Image(blob, _, _), _, _)) => assert_blob_len(&blob, png.len()),
+            Ok(_) => panic!("expected an image"),
+            Err(e) => panic!("failed to decrypt: {e}"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn opens_either_cipher() {
+        let data = b"hello world";
+        for cipher in [Cipher::XChaCha20Poly1305, Cipher::Aes256Gcm] {
+            let options = SealOptions {
+                cipher: Some(cipher),
+                ..SealOptions::default()
+            };
+            let key = Key::random_secret();
+            let (reporter, _) = reporter();
+
+            let mut sealed = data.to_vec();
+            seal_in_place_with_key(&mut sealed, &key, None, options).unwrap();
+            match decrypt(
+                sealed,
+                &key,
+                None,
+                options.envelope(false),
+                None,
+                None,
+                &reporter,
+            ) {
+                Ok((DecryptedData::String(text, _), _, _)) => assert_eq!(*text, "hello world"),
+                Ok(_) => panic!("expected text"),
+                Err(e) => panic!("failed to decrypt: {e}"),
+            }
+
+            let png = include_bytes!("../../test/image.png");
+            let mut sealed = png.to_vec();
+            seal_chunked_in_place_with_key(&mut sealed, &key, None, options).unwrap();
+            let decrypter =
+                StreamingDecrypter::new(&key, None, options.envelope(false), None, None);
+            match stream(decrypter, &sealed, &reporter) {
+                Ok((DecryptedData::Image(blob, _, _), _, _)) => assert_blob_len(&blob, png.len()),
+                Ok(_) => panic!("expected an image"),
+                Err(e) => panic!("failed to decrypt: {e}"),
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn opens_all_vectors() {
+        let manifest = include_str!("../../testdata/vectors.txt");
+        for vector in manifest.lines().filter(|line| !line.starts_with('#')) {
+            let [name, password, fragment]: [&str; 3] =
+                vector.split('\t').collect::<Vec<_>>().try_into().unwrap();
+            let (_, sealed) = VECTORS
+                .iter()
+                .find(|(file, _)| *file == name)
+                .unwrap_or_else(|| panic!("{name} isn't in VECTORS"));
+            let url = PartialParsedUrl::try_from(fragment).unwrap();
+            let key = url.decryption_key.as_ref().unwrap();
+            let password = (password != "-").then(|| SecretVec::new(password.as_bytes().to_vec()));
+            let (reporter, _) = reporter();
+
+            let result = if url.chunked {
+                let decrypter =
+                    StreamingDecrypter::new(key, password, url.envelope(), None, url.hash);
+                stream(decrypter, sealed, &reporter)
+            } else {
+                let hash = url.hash.as_ref();
+                decrypt(
+                    sealed.to_vec(),
+                    key,
+                    password,
+                    url.envelope(),
+                    None,
+                    hash,
+                    &reporter,
+                )
+            };
+            match result {
+                Ok((_, _, Verified(verified))) => assert_eq!(verified, Some(true), "{name}"),
+                Err(e) => panic!("failed to open {name}: {e}"),
+            }
+        }
+    }
+}
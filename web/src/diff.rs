@@ -0,0 +1,228 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parses unified diffs, such as patches, so that they can be shown with
+//! added and removed lines highlighted.
+
+use serde::Serialize;
+
+const EXTENSIONS: &[&str] = &["diff", "patch"];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FileDiff {
+    pub old_path: String,
+    pub new_path: String,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Hunk {
+    /// The `@@ -1,2 +1,3 @@` line, including any section heading after it.
+    pub header: String,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Line {
+    pub kind: LineKind,
+    pub text: String,
+    /// The line number in the old file, unless the line was added.
+    pub old: Option<u32>,
+    /// The line number in the new file, unless the line was removed.
+    pub new: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+/// Whether a text paste may be a diff, based on its name, guessed mime type,
+/// or first lines. It should still be parsed, as it may not be one.
+pub fn is_diff(name_hint: Option<&str>, mime_type: &str, text: &str) -> bool {
+    matches!(mime_type, "text/x-diff" | "text/x-patch")
+        || name_hint
+            .and_then(|name| name.rsplit_once('.'))
+            .map_or(false, |(_, extension)| {
+                EXTENSIONS
+                    .iter()
+                    .any(|diff| extension.eq_ignore_ascii_case(diff))
+            })
+        || text.starts_with("--- ")
+        || text.lines().any(|line| line.starts_with("diff --git "))
+}
+
+/// Parses the files changed by a unified diff. Anything outside of a file's
+/// hunks, such as the commit message of a patch, is skipped. Returns `None` if
+/// there are no hunks, in which case the paste should be shown as plain text.
+pub fn parse(text: &str) -> Option<Vec<FileDiff>> {
+    let mut files: Vec<FileDiff> = vec![];
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(old_path) = line.strip_prefix("--- ") {
+            if let Some(new_path) = lines.next_if(|line| line.starts_with("+++ ")) {
+                files.push(FileDiff {
+                    old_path: path(old_path, "a/"),
+                    new_path: path(&new_path[4..], "b/"),
+                    hunks: vec![],
+                });
+            }
+            continue;
+        }
+
+        let (file, (old_start, old_len, new_start, new_len)) =
+            match (files.last_mut(), hunk_range(line)) {
+                (Some(file), Some(range)) => (file, range),
+                _ => continue,
+            };
+
+        let mut hunk = Hunk {
+            header: line.to_string(),
+            lines: vec![],
+        };
+        let (mut old, mut new) = (old_start, new_start);
+        let (old_end, new_end) = (
+            old_start.saturating_add(old_len),
+            new_start.saturating_add(new_len),
+        );
+        while old < old_end || new < new_end {
+            let line = match lines.next() {
+                Some(line) => line,
+                None => break,
+            };
+            // Some editors strip the trailing space of empty context lines.
+            let (kind, text) = match line.chars().next() {
+                Some('+') => (LineKind::Added, &line[1..]),
+                Some('-') => (LineKind::Removed, &line[1..]),
+                Some(' ') => (LineKind::Context, &line[1..]),
+                None => (LineKind::Context, ""),
+                // A note, such as for a missing newline at the end of a file.
+                Some('\\') => continue,
+                _ => break,
+            };
+            hunk.lines.push(Line {
+                kind,
+                text: text.to_string(),
+                old: (kind != LineKind::Added).then_some(old),
+                new: (kind != LineKind::Removed).then_some(new),
+            });
+            if kind != LineKind::Added {
+                old += 1;
+            }
+            if kind != LineKind::Removed {
+                new += 1;
+            }
+        }
+        file.hunks.push(hunk);
+    }
+
+    files.retain(|file| !file.hunks.is_empty());
+    (!files.is_empty()).then_some(files)
+}
+
+/// The path from a `---` or `+++` line, without git's `a/` or `b/` prefix or
+/// the timestamp that `diff -u` adds.
+fn path(path: &str, prefix: &str) -> String {
+    let path = path.split('\t').next().unwrap_or_default();
+    path.strip_prefix(prefix).unwrap_or(path).to_string()
+}
+
+/// Parses the start and length of both sides from a hunk header, such as
+/// `@@ -1,2 +1,3 @@`. A missing length means the hunk is one line long.
+fn hunk_range(line: &str) -> Option<(u32, u32, u32, u32)> {
+    let (ranges, _) = line.strip_prefix("@@ -")?.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |range: &str| -> Option<(u32, u32)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_len) = range(old)?;
+    let (new_start, new_len) = range(new)?;
+    Some((old_start, old_len, new_start, new_len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_diffs() {
+        assert!(is_diff(Some("fix.patch"), "text/plain", ""));
+        assert!(is_diff(None, "text/x-diff", ""));
+        assert!(is_diff(None, "text/plain", "--- a\n+++ b\n"));
+        assert!(!is_diff(Some("notes.txt"), "text/plain", "hello"));
+    }
+
+    #[test]
+    fn parses_hunks() {
+        let text =
+            "--- a/old.rs\t2022-01-01\n+++ b/new.rs\n@@ -1,3 +1,3 @@ fn main\n a\n-b\n+c\n\n";
+        let files = parse(text).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].old_path, "old.rs");
+        assert_eq!(files[0].new_path, "new.rs");
+        let hunk = &files[0].hunks[0];
+        assert_eq!(hunk.header, "@@ -1,3 +1,3 @@ fn main");
+        assert_eq!(
+            hunk.lines,
+            vec![
+                Line {
+                    kind: LineKind::Context,
+                    text: "a".to_string(),
+                    old: Some(1),
+                    new: Some(1),
+                },
+                Line {
+                    kind: LineKind::Removed,
+                    text: "b".to_string(),
+                    old: Some(2),
+                    new: None,
+                },
+                Line {
+                    kind: LineKind::Added,
+                    text: "c".to_string(),
+                    old: None,
+                    new: Some(2),
+                },
+                Line {
+                    kind: LineKind::Context,
+                    text: String::new(),
+                    old: Some(3),
+                    new: Some(3),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_patch() {
+        let text = include_str!("../../test/0000-test-patch.patch");
+        let files = parse(text).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].new_path, "Cargo.lock");
+        assert_eq!(files[1].new_path, "cli/Cargo.toml");
+    }
+
+    #[test]
+    fn text_is_not_a_diff() {
+        assert!(parse("just some text\n--- not a diff").is_none());
+    }
+}
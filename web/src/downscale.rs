@@ -0,0 +1,101 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Downscales images before they're uploaded.
+//!
+//! Screenshots from high resolution displays are often far larger than they
+//! need to be to be read.
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::ImageFormat;
+
+const JPEG_QUALITY: u8 = 90;
+
+pub struct Downscaled {
+    pub data: Vec<u8>,
+    /// The width and height of the image before it was downscaled.
+    pub original: (u32, u32),
+    pub scaled: (u32, u32),
+}
+
+/// Downscales a PNG or JPEG so that neither of its sides are longer than
+/// `max_dimension`, keeping its aspect ratio and format. Returns `None` if the
+/// image is already small enough, or isn't a PNG or JPEG.
+///
+/// Only the pixels are kept, so this also removes the image's metadata. The
+/// EXIF `orientation` is applied first, so that the image isn't rotated.
+pub fn downscale(data: &[u8], max_dimension: u32, orientation: Option<u32>) -> Option<Downscaled> {
+    let format = image::guess_format(data)
+        .ok()
+        .filter(|format| matches!(format, ImageFormat::Png | ImageFormat::Jpeg))?;
+    let mut image = image::load_from_memory_with_format(data, format).ok()?;
+    if let Some(orientation) = orientation
+        .and_then(|orientation| u8::try_from(orientation).ok())
+        .and_then(Orientation::from_exif)
+    {
+        image.apply_orientation(orientation);
+    }
+
+    let original = (image.width(), image.height());
+    if original.0.max(original.1) <= max_dimension {
+        return None;
+    }
+
+    let image = image.resize(max_dimension, max_dimension, FilterType::Lanczos3);
+    let mut scaled = Cursor::new(vec![]);
+    let written = if format == ImageFormat::Jpeg {
+        image.write_with_encoder(JpegEncoder::new_with_quality(&mut scaled, JPEG_QUALITY))
+    } else {
+        image.write_to(&mut scaled, format)
+    };
+    written.ok()?;
+
+    Some(Downscaled {
+        data: scaled.into_inner(),
+        original,
+        scaled: (image.width(), image.height()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn downscales_large_png() {
+        let downscaled = downscale(include_bytes!("../../test/image.png"), 16, None).unwrap();
+        assert!(downscaled.original.0.max(downscaled.original.1) > 16);
+        assert_eq!(downscaled.scaled.0.max(downscaled.scaled.1), 16);
+        assert_eq!(
+            image::guess_format(&downscaled.data).ok(),
+            Some(ImageFormat::Png)
+        );
+    }
+
+    #[test]
+    fn keeps_small_images() {
+        assert!(downscale(include_bytes!("../../test/image.png"), u32::MAX, None).is_none());
+    }
+
+    #[test]
+    fn ignores_other_formats() {
+        assert!(downscale(include_bytes!("../../test/image.webp"), 16, None).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A minimal API for browser extensions and Node, built with the `extension`
+//! feature.
+//!
+//! This uses the same crypto and upload code as the frontend, but never touches
+//! the DOM, idb, or XHR, so it can run in an extension's background service
+//! worker, or in Node 18 or later, which has a global `fetch`. Pastes are held
+//! in memory, and nothing is cached or recorded in the history.
+
+use std::str::FromStr;
+
+use ::http::StatusCode;
+use anyhow::{anyhow, bail, Context, Result};
+use gloo_console::log;
+use js_sys::{JsString, Uint8Array};
+use omegaupload_common::crypto::{
+    self, open_chunked_into_secret, open_into_secret, seal_chunked_in_place_with_key, Key,
+};
+use omegaupload_common::secrecy::{ExposeSecret, SecretVec};
+use omegaupload_common::{PartialParsedUrl, Url, API_ENDPOINT};
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::JsValue;
+
+use crate::http;
+use crate::image_meta;
+use crate::upload::{check_server_info, UploadOptions, Uploaded};
+
+/// Encrypts and uploads a paste to the instance at `location`, returning its
+/// URL. `options` are the same as for the upload UI's `encrypt_blob`.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn encrypt_and_upload(
+    location: String,
+    data: Uint8Array,
+    options: JsValue,
+) -> Result<JsString, JsString> {
+    let res = match UploadOptions::from_js(options) {
+        Ok(options) => do_encrypt_and_upload(&location, data.to_vec(), options).await,
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| {
+        log!(format!("[rs] Error uploading paste: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_encrypt_and_upload(
+    location: &str,
+    mut data: Vec<u8>,
+    options: UploadOptions,
+) -> Result<JsString> {
+    let expiration = options.expiration()?;
+    let seal_options = options.seal_options()?;
+
+    if options.strip_metadata {
+        if let Some(stripped) = image_meta::strip(&data) {
+            data = stripped;
+        }
+    }
+    if data.is_empty() {
+        bail!("Nothing to upload.");
+    }
+
+    let url = Url::from_str(location)?;
+    check_server_info(&url, data.len() as u64, options.expiration.as_deref()).await?;
+
+    let key = Key::random_secret();
+    let fragment = options.fragment(&key, crypto::hash(&data))?;
+    seal_chunked_in_place_with_key(&mut data, &key, options.password(), seal_options)?;
+
+    let resp = http::post(&url, &Uint8Array::from(data.as_slice()), expiration).await?;
+    let uploaded = Uploaded::from_response(resp).await?;
+    let mut url = uploaded.url(&url);
+    url.set_fragment(Some(fragment.build().expose_secret()));
+    Ok(JsString::from(url.as_ref()))
+}
+
+/// Downloads and decrypts the paste at `url`, which must include its key,
+/// returning its contents. Fails if the paste doesn't match the hash in its
+/// URL. Burn after reading pastes are burned.
+#[wasm_bindgen]
+#[allow(clippy::future_not_send, clippy::needless_pass_by_value)]
+pub async fn fetch_and_decrypt(
+    url: String,
+    password: Option<String>,
+) -> Result<Uint8Array, JsString> {
+    do_fetch_and_decrypt(&url, password).await.map_err(|e| {
+        log!(format!("[rs] Error downloading paste: {}", e));
+        JsString::from(e.to_string())
+    })
+}
+
+#[allow(clippy::future_not_send)]
+async fn do_fetch_and_decrypt(url: &str, password: Option<String>) -> Result<Uint8Array> {
+    let url = Url::from_str(url)?;
+    let parsed = PartialParsedUrl::try_from(url.fragment().unwrap_or_default())?;
+    let envelope = parsed.envelope();
+    let key = parsed
+        .decryption_key
+        .context("The URL is missing its decryption key")?;
+    let password = password.map(|password| SecretVec::new(password.into_bytes()));
+    if parsed.needs_password && password.is_none() {
+        bail!("This paste requires a password.");
+    }
+
+    let resp = http::get(api_url(&url)?.as_str()).await?;
+    match resp.status {
+        StatusCode::OK => (),
+        StatusCode::NOT_FOUND => bail!("The paste doesn't exist, or has expired."),
+        StatusCode::GONE => match &resp.tombstone {
+            Some(tombstone) => bail!("{tombstone}"),
+            None => bail!("The paste was burned or deleted."),
+        },
+        status => bail!("Download failed. Got HTTP status {status}"),
+    }
+
+    let chunks = resp.chunks()?;
+    let mut data = Vec::new();
+    while let Some(chunk) = chunks.next().await? {
+        data.extend_from_slice(&chunk);
+    }
+
+    let open = if parsed.chunked {
+        open_chunked_into_secret
+    } else {
+        open_into_secret
+    };
+    let opened = open(data, &key, password, envelope).map_err(|e| anyhow!(e))?;
+    if let Some(hash) = parsed.hash {
+        if crypto::hash(opened.expose_secret()) != hash {
+            bail!("The paste doesn't match the hash in its URL.");
+        }
+    }
+    Ok(Uint8Array::from(opened.expose_secret().as_slice()))
+}
+
+/// Where a paste is downloaded from. Pastes are at `{base}/{code}`, and the API
+/// is under the same base as the frontend.
+fn api_url(url: &Url) -> Result<Url> {
+    let (base, code) = url
+        .path()
+        .rsplit_once('/')
+        .filter(|(_, code)| !code.is_empty())
+        .context("The URL doesn't point to a paste")?;
+    let mut api_url = url.clone();
+    api_url.set_path(&format!("{base}{API_ENDPOINT}/{code}"));
+    api_url.set_query(None);
+    api_url.set_fragment(None);
+    Ok(api_url)
+}
@@ -0,0 +1,176 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A record of pastes uploaded from this browser, like the CLI's history.
+//!
+//! Entries contain full paste URLs, including their decryption keys, and are
+//! kept in idb under the path of the paste, so they can be looked up from the
+//! viewer.
+
+use anyhow::{anyhow, Result};
+use js_sys::{Array, Date, Function, JsString, Promise};
+use omegaupload_common::{Expiration, Url};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Event, IdbObjectStore, IdbRequest, IdbTransactionMode};
+
+use crate::app::{create_store_on_upgrade, open_idb, reject_on_event};
+use crate::util::as_idb_db;
+
+/// The idb object store that entries are kept in.
+pub const STORE_NAME: &str = "history";
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    /// When the paste expires, as an RFC3339 timestamp. This is missing for
+    /// pastes that are burned after reading without a deadline, and for
+    /// servers that don't report it.
+    pub expires: Option<String>,
+    pub burn_after_reading: bool,
+    /// Permits deleting the paste. Servers that don't support this don't
+    /// provide one.
+    pub owner_token: Option<String>,
+    /// When the paste was uploaded, in milliseconds since the Unix epoch.
+    pub uploaded_at: f64,
+}
+
+impl Entry {
+    pub fn new(url: &Url, expiration: Option<Expiration>, owner_token: Option<String>) -> Self {
+        let expires = match expiration {
+            Some(
+                Expiration::UnixTime(time)
+                | Expiration::BurnAfterReadingWithDeadline(time)
+                | Expiration::ReadWindowWithDeadline(_, time),
+            ) => Some(time.to_rfc3339()),
+            Some(Expiration::BurnAfterReading | Expiration::ReadWindow(_)) | None => None,
+        };
+        Self {
+            url: url.to_string(),
+            expires,
+            burn_after_reading: matches!(
+                expiration,
+                Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_))
+            ),
+            owner_token,
+            uploaded_at: Date::now(),
+        }
+    }
+
+    /// Whether the paste is known to no longer exist. Burn after reading pastes
+    /// are only considered expired once their deadline passes, as we can't
+    /// know if they were read.
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .as_deref()
+            .map_or(false, |expires| Date::parse(expires) < Date::now())
+    }
+}
+
+/// Records a paste under its path, replacing any entry already there.
+#[allow(clippy::future_not_send)]
+pub async fn record(entry: &Entry) -> Result<()> {
+    let path = Url::parse(&entry.url)?.path().to_string();
+    let value = serde_wasm_bindgen::to_value(entry).map_err(|e| anyhow!("{e}"))?;
+    request(IdbTransactionMode::Readwrite, move |store| {
+        store.put_with_key(&value, &JsString::from(path))
+    })
+    .await
+    .map(drop)
+}
+
+/// Loads all entries, newest first. Entries that can't be read are skipped.
+#[allow(clippy::future_not_send)]
+pub async fn load() -> Result<Vec<Entry>> {
+    let entries: Array = request(IdbTransactionMode::Readonly, |store| store.get_all())
+        .await?
+        .unchecked_into();
+    let mut entries: Vec<Entry> = entries
+        .iter()
+        .filter_map(|entry| serde_wasm_bindgen::from_value(entry).ok())
+        .collect();
+    entries.sort_by(|a, b| b.uploaded_at.total_cmp(&a.uploaded_at));
+    Ok(entries)
+}
+
+/// Loads the entry for the paste at `path`, if there is one.
+#[allow(clippy::future_not_send)]
+pub async fn get(path: String) -> Result<Option<Entry>> {
+    let entry = request(IdbTransactionMode::Readonly, move |store| {
+        store.get(&JsString::from(path))
+    })
+    .await?;
+    if entry.is_undefined() {
+        return Ok(None);
+    }
+    serde_wasm_bindgen::from_value(entry).map_err(|e| anyhow!("{e}"))
+}
+
+/// Removes the entry for the paste at `path`, if there is one.
+#[allow(clippy::future_not_send)]
+pub async fn remove(path: String) -> Result<()> {
+    request(IdbTransactionMode::Readwrite, move |store| {
+        store.delete(&JsString::from(path))
+    })
+    .await
+    .map(drop)
+}
+
+/// Makes a request against the history store, resolving with its result.
+#[allow(clippy::future_not_send)]
+async fn request(
+    mode: IdbTransactionMode,
+    make_request: impl FnOnce(IdbObjectStore) -> Result<IdbRequest, JsValue> + 'static,
+) -> Result<JsValue> {
+    let db_open_req = open_idb()?;
+    let mut make_request = Some(make_request);
+
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let make_request = make_request.take().expect("promise executor to run once");
+
+        create_store_on_upgrade(&db_open_req);
+        db_open_req.set_onerror(Some(reject_on_event(reject.clone()).unchecked_ref()));
+
+        let on_success = Closure::once(Box::new(move |event: Event| {
+            let request = as_idb_db(&event)
+                .transaction_with_str_and_mode(STORE_NAME, mode)
+                .and_then(|transaction| transaction.object_store(STORE_NAME))
+                .and_then(make_request);
+            let request = match request {
+                Ok(request) => request,
+                Err(e) => {
+                    let _ = reject.call1(&JsValue::NULL, &e);
+                    return;
+                }
+            };
+            let on_request = Closure::once(Box::new(move |event: Event| {
+                let result = event
+                    .target()
+                    .map(JsCast::unchecked_into::<IdbRequest>)
+                    .and_then(|req| req.result().ok())
+                    .unwrap_or(JsValue::UNDEFINED);
+                let _ = resolve.call1(&JsValue::NULL, &result);
+            }));
+            request.set_onsuccess(Some(on_request.into_js_value().unchecked_ref()));
+            request.set_onerror(Some(reject_on_event(reject).unchecked_ref()));
+        }));
+        db_open_req.set_onsuccess(Some(on_success.into_js_value().unchecked_ref()));
+    });
+
+    JsFuture::from(promise).await.map_err(|e| anyhow!("{e:?}"))
+}
@@ -0,0 +1,291 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Requests to the server, which can be made from the main thread, a worker, or
+//! Node.
+//!
+//! Every request returns an [`ApiResponse`], so headers are parsed the same
+//! way no matter which request was made, or whether it was made with fetch or
+//! XHR.
+
+use ::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, EXPIRES};
+use ::http::{HeaderValue, StatusCode};
+use anyhow::{anyhow, Context, Result};
+use js_sys::{Promise, Reflect, Uint8Array};
+use omegaupload_common::{
+    Expiration, NotBefore, PasteTtl, Tombstone, Url, EXPIRATION_HEADER_NAME,
+    NOT_BEFORE_HEADER_NAME, OWNER_TOKEN_HEADER_NAME, PASTE_TTL_HEADER_NAME, TOMBSTONE_HEADER_NAME,
+};
+use serde::de::DeserializeOwned;
+use wasm_bindgen::prelude::{wasm_bindgen, Closure};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, Headers, ProgressEvent, ReadableStreamDefaultReader, RequestInit, Response,
+    XmlHttpRequest,
+};
+
+use crate::progress::{Progress, Reporter};
+
+/// A response from the server, with the headers the frontend uses already
+/// parsed. Headers that are missing or invalid are `None`.
+pub struct ApiResponse {
+    pub status: StatusCode,
+    /// When the paste expires, on the browser's clock if the server said how
+    /// long the paste has left.
+    pub expiration: Option<Expiration>,
+    /// What happened to a paste that no longer exists.
+    pub tombstone: Option<Tombstone>,
+    /// When a time-locked paste may first be read.
+    pub not_before: Option<NotBefore>,
+    /// Permits deleting a paste that was just uploaded.
+    pub owner_token: Option<String>,
+    pub content_length: Option<u64>,
+    pub content_type: Option<String>,
+    body: Body,
+}
+
+enum Body {
+    /// A fetch response, whose body can be read as it's received.
+    Fetch(Response),
+    /// An XHR response, whose body has already been received.
+    Xhr(Option<String>),
+}
+
+impl ApiResponse {
+    fn new(status: u16, header: impl Fn(&str) -> Option<String>, body: Body) -> Result<Self> {
+        let ttl = header(PASTE_TTL_HEADER_NAME.as_str())
+            .and_then(|header| PasteTtl::try_from(header.as_str()).ok());
+        Ok(Self {
+            status: StatusCode::from_u16(status)?,
+            expiration: header(EXPIRES.as_str())
+                .and_then(|header| Expiration::try_from(header.as_str()).ok())
+                .map(|expiration| ttl.map_or(expiration, |ttl| ttl.rebase(expiration))),
+            tombstone: header(TOMBSTONE_HEADER_NAME.as_str())
+                .and_then(|header| Tombstone::try_from(header.as_str()).ok()),
+            not_before: header(NOT_BEFORE_HEADER_NAME.as_str())
+                .and_then(|header| NotBefore::try_from(header.as_str()).ok()),
+            owner_token: header(OWNER_TOKEN_HEADER_NAME.as_str()),
+            content_length: header(CONTENT_LENGTH.as_str())
+                .and_then(|length| length.parse::<u64>().ok()),
+            content_type: header(CONTENT_TYPE.as_str()),
+            body,
+        })
+    }
+
+    fn from_fetch(resp: Response) -> Result<Self> {
+        let headers = resp.headers();
+        let header = |name: &str| headers.get(name).ok().flatten();
+        Self::new(resp.status(), header, Body::Fetch(resp))
+    }
+
+    fn from_xhr(xhr: &XmlHttpRequest) -> Result<Self> {
+        let js_err = |e: JsValue| anyhow!("Failed to read response: {e:?}");
+        let header = |name: &str| xhr.get_response_header(name).ok().flatten();
+        Self::new(
+            xhr.status().map_err(js_err)?,
+            header,
+            Body::Xhr(xhr.response_text().map_err(js_err)?),
+        )
+    }
+
+    /// Returns a reader for the body, so that it can be read as it's received.
+    /// Fails if the body is missing, or was already received.
+    pub fn chunks(&self) -> Result<Chunks> {
+        match &self.body {
+            Body::Fetch(resp) => {
+                let reader = resp
+                    .body()
+                    .context("The server sent an empty response")?
+                    .get_reader()
+                    .unchecked_into();
+                Ok(Chunks(reader))
+            }
+            Body::Xhr(_) => Err(anyhow!("The response was already received")),
+        }
+    }
+
+    /// Reads the entire body as text.
+    #[allow(clippy::future_not_send)]
+    pub async fn text(self) -> Result<String> {
+        let text = match self.body {
+            Body::Fetch(resp) => {
+                let promise = resp
+                    .text()
+                    .map_err(|e| anyhow!("Failed to read response: {e:?}"))?;
+                JsFuture::from(promise)
+                    .await
+                    .map_err(|e| anyhow!("Failed to read response: {e:?}"))?
+                    .as_string()
+            }
+            Body::Xhr(text) => text,
+        };
+        text.context("The server sent an empty response")
+    }
+
+    /// Reads the entire body as JSON.
+    #[allow(clippy::future_not_send)]
+    pub async fn json<T: DeserializeOwned>(self) -> Result<T> {
+        let text = self.text().await?;
+        let value = js_sys::JSON::parse(&text).map_err(|e| anyhow!("Invalid JSON: {e:?}"))?;
+        serde_wasm_bindgen::from_value(value).map_err(|e| anyhow!("Invalid JSON: {e}"))
+    }
+}
+
+/// Reads a response body as it's received.
+pub struct Chunks(ReadableStreamDefaultReader);
+
+impl Chunks {
+    /// Reads the next chunk, returning `None` once the body has been fully
+    /// read.
+    #[allow(clippy::future_not_send)]
+    pub async fn next(&self) -> Result<Option<Vec<u8>>> {
+        let result = JsFuture::from(self.0.read())
+            .await
+            .map_err(|e| anyhow!("Failed to read response: {e:?}"))?;
+        let done = Reflect::get(&result, &JsValue::from_str("done"))
+            .map_err(|e| anyhow!("{e:?}"))?
+            .is_truthy();
+        if done {
+            return Ok(None);
+        }
+
+        let chunk: Uint8Array = Reflect::get(&result, &JsValue::from_str("value"))
+            .map_err(|e| anyhow!("{e:?}"))?
+            .unchecked_into();
+        Ok(Some(chunk.to_vec()))
+    }
+}
+
+#[allow(clippy::future_not_send)]
+pub async fn get(request_uri: &str) -> Result<ApiResponse> {
+    fetch(request_uri, &RequestInit::new()).await
+}
+
+/// Fetches only the headers for a resource, which doesn't burn burn after
+/// reading pastes.
+#[allow(clippy::future_not_send)]
+pub async fn head(request_uri: &str) -> Result<ApiResponse> {
+    let mut init = RequestInit::new();
+    init.method("HEAD");
+    fetch(request_uri, &init).await
+}
+
+#[allow(clippy::future_not_send)]
+pub async fn delete(request_uri: &str, owner_token: &str) -> Result<ApiResponse> {
+    let headers = Headers::new().map_err(|e| anyhow!("{e:?}"))?;
+    headers
+        .set(OWNER_TOKEN_HEADER_NAME.as_str(), owner_token)
+        .map_err(|e| anyhow!("{e:?}"))?;
+    let mut init = RequestInit::new();
+    init.method("DELETE").headers(&headers);
+    fetch(request_uri, &init).await
+}
+
+/// Uploads a sealed paste, reporting how much has been sent so far.
+///
+/// This uses XHR, as fetch can't report upload progress. The browser reads the
+/// body from the blob as it's sent, so it's never in wasm memory.
+#[allow(clippy::future_not_send)]
+pub async fn upload(
+    url: &Url,
+    body: &Blob,
+    expiration: Option<Expiration>,
+    reporter: &Reporter,
+) -> Result<ApiResponse> {
+    let js_err = |e: JsValue| anyhow!("Upload failed: {e:?}");
+
+    let xhr = XmlHttpRequest::new().map_err(js_err)?;
+    xhr.open_with_async("POST", url.as_str(), true)
+        .map_err(js_err)?;
+    // Servers that support it return the canonical URL of the paste.
+    xhr.set_request_header(ACCEPT.as_str(), "application/json")
+        .map_err(js_err)?;
+    if let Some(expiration) = expiration {
+        let value = HeaderValue::from(expiration);
+        xhr.set_request_header(EXPIRATION_HEADER_NAME.as_str(), value.to_str()?)
+            .map_err(js_err)?;
+    }
+
+    let on_progress = {
+        let reporter = reporter.clone();
+        Closure::<dyn FnMut(ProgressEvent)>::new(move |event: ProgressEvent| {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            reporter.report(Progress::Uploading {
+                loaded: event.loaded() as u64,
+                total: event.length_computable().then(|| event.total() as u64),
+            });
+        })
+    };
+    xhr.upload()
+        .map_err(js_err)?
+        .set_onprogress(Some(on_progress.as_ref().unchecked_ref()));
+
+    let done = Promise::new(&mut |resolve, reject| {
+        xhr.set_onload(Some(&resolve));
+        xhr.set_onerror(Some(&reject));
+        xhr.set_onabort(Some(&reject));
+    });
+    xhr.send_with_opt_blob(Some(body)).map_err(js_err)?;
+    JsFuture::from(done).await.map_err(js_err)?;
+    // The closure must outlive the request.
+    drop(on_progress);
+
+    ApiResponse::from_xhr(&xhr)
+}
+
+/// Uploads a sealed paste with fetch, for where XHR isn't available, such as in
+/// an extension's service worker or Node. Unlike [`upload`], this can't report
+/// progress.
+#[cfg(feature = "extension")]
+#[allow(clippy::future_not_send)]
+pub async fn post(
+    url: &Url,
+    body: &Uint8Array,
+    expiration: Option<Expiration>,
+) -> Result<ApiResponse> {
+    let js_err = |e: JsValue| anyhow!("Upload failed: {e:?}");
+
+    let headers = Headers::new().map_err(js_err)?;
+    // Servers that support it return the canonical URL of the paste.
+    headers
+        .set(ACCEPT.as_str(), "application/json")
+        .map_err(js_err)?;
+    if let Some(expiration) = expiration {
+        let value = HeaderValue::from(expiration);
+        headers
+            .set(EXPIRATION_HEADER_NAME.as_str(), value.to_str()?)
+            .map_err(js_err)?;
+    }
+    let mut init = RequestInit::new();
+    init.method("POST").headers(&headers).body(Some(body));
+    fetch(url.as_str(), &init).await
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// The global `fetch`, which windows, workers, and Node all have.
+    #[wasm_bindgen(js_name = fetch)]
+    fn global_fetch(input: &str, init: &RequestInit) -> Promise;
+}
+
+#[allow(clippy::future_not_send)]
+async fn fetch(request_uri: &str, init: &RequestInit) -> Result<ApiResponse> {
+    let resp = JsFuture::from(global_fetch(request_uri, init))
+        .await
+        .map_err(|e| anyhow!("Request failed: {e:?}"))?;
+    ApiResponse::from_fetch(resp.unchecked_into())
+}
@@ -0,0 +1,395 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Translations of the messages shown to users by the wasm module.
+//!
+//! Translations are compiled into the module, and picked by the browser's
+//! preferred language. Languages without translations fall back to English.
+
+use js_sys::{Date, Object, Reflect};
+use omegaupload_common::{Expiration, NotBefore, Tombstone, TombstoneReason};
+use wasm_bindgen::JsValue;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Language {
+    English,
+    German,
+    French,
+    Spanish,
+}
+
+impl Language {
+    /// The browser's preferred language. This works in workers too.
+    pub fn current() -> Self {
+        let tag = Reflect::get(&js_sys::global(), &JsValue::from_str("navigator"))
+            .and_then(|navigator| Reflect::get(&navigator, &JsValue::from_str("language")))
+            .ok()
+            .and_then(|language| language.as_string())
+            .unwrap_or_default();
+        Self::from_tag(&tag)
+    }
+
+    fn from_tag(tag: &str) -> Self {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "de" => Self::German,
+            "fr" => Self::French,
+            "es" => Self::Spanish,
+            _ => Self::English,
+        }
+    }
+
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::German => "de",
+            Self::French => "fr",
+            Self::Spanish => "es",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    /// Takes the reason the link is invalid.
+    InvalidLink,
+    MissingKey,
+    KeyPrompt,
+    InvalidKey,
+    PasswordPrompt,
+    PasswordRequired,
+    InternalError,
+    OfflineNotCached,
+    OfflineLoadFailed,
+    NotFound,
+    BurnedOrDeleted,
+    InvalidUrl,
+    TooLarge,
+    /// Takes the size of the paste and how much memory decrypting it takes.
+    LargePaste,
+    /// Like `LargePaste`, for pastes that need more memory than the device
+    /// seems to have to spare.
+    LargerThanMemory,
+    LargePasteCancelled,
+    EmptyResponse,
+    DownloadFailed,
+    WrongPassword,
+    WrongKey,
+    DeleteFailed,
+    PasteDeleted,
+    DoesNotExpire,
+    BurnedCopy,
+    /// Takes the time the paste expires.
+    ExpiresOn,
+    /// Takes how many minutes the paste lasts once it's first read.
+    ExpiresAfterRead,
+    /// Takes the time the paste was burned.
+    BurnedOn,
+    /// Takes the time the paste was deleted.
+    DeletedOn,
+    /// Takes the time the paste reached its download limit.
+    ExhaustedOn,
+    /// Takes the time the paste may first be read.
+    LockedUntil,
+}
+
+/// Translations in the order of the variants of [`Language`]. Messages that
+/// take an argument have a `{}` where it goes.
+#[allow(clippy::too_many_lines)]
+const fn translations(message: Message) -> [&'static str; 4] {
+    match message {
+        Message::InvalidLink => [
+            "Invalid paste link: {}",
+            "Ungültiger Paste-Link: {}",
+            "Lien de paste invalide : {}",
+            "Enlace de paste no válido: {}",
+        ],
+        Message::MissingKey => [
+            "Invalid paste link: Missing decryption key.",
+            "Ungültiger Paste-Link: Entschlüsselungsschlüssel fehlt.",
+            "Lien de paste invalide : clé de déchiffrement manquante.",
+            "Enlace de paste no válido: falta la clave de descifrado.",
+        ],
+        Message::KeyPrompt => [
+            "This link was shared without its key. Enter the key to decrypt this paste:",
+            "Dieser Link wurde ohne Schlüssel geteilt. Gib den Schlüssel ein, um diesen Paste zu entschlüsseln:",
+            "Ce lien a été partagé sans sa clé. Saisissez la clé pour déchiffrer ce paste :",
+            "Este enlace se compartió sin su clave. Introduce la clave para descifrar este paste:",
+        ],
+        Message::InvalidKey => [
+            "That key isn't valid. Check it for typos.",
+            "Dieser Schlüssel ist ungültig. Prüfe ihn auf Tippfehler.",
+            "Cette clé n'est pas valide. Vérifiez qu'elle ne contient pas de faute de frappe.",
+            "Esa clave no es válida. Comprueba que no tenga errores.",
+        ],
+        Message::PasswordPrompt => [
+            "A password is required to decrypt this paste:",
+            "Zum Entschlüsseln dieses Pastes wird ein Passwort benötigt:",
+            "Un mot de passe est requis pour déchiffrer ce paste :",
+            "Se requiere una contraseña para descifrar este paste:",
+        ],
+        Message::PasswordRequired => [
+            "This paste requires a password.",
+            "Dieser Paste erfordert ein Passwort.",
+            "Ce paste nécessite un mot de passe.",
+            "Este paste requiere una contraseña.",
+        ],
+        Message::InternalError => [
+            "An internal error occurred.",
+            "Ein interner Fehler ist aufgetreten.",
+            "Une erreur interne est survenue.",
+            "Se produjo un error interno.",
+        ],
+        Message::OfflineNotCached => [
+            "You're offline, and this paste hasn't been viewed in this browser.",
+            "Du bist offline, und dieser Paste wurde in diesem Browser noch nicht angesehen.",
+            "Vous êtes hors ligne, et ce paste n'a pas été consulté dans ce navigateur.",
+            "Estás sin conexión y este paste no se ha visto en este navegador.",
+        ],
+        Message::OfflineLoadFailed => [
+            "You're offline, and this paste couldn't be loaded.",
+            "Du bist offline, und dieser Paste konnte nicht geladen werden.",
+            "Vous êtes hors ligne, et ce paste n'a pas pu être chargé.",
+            "Estás sin conexión y no se pudo cargar este paste.",
+        ],
+        Message::NotFound => [
+            "This paste never existed or has expired.",
+            "Dieser Paste hat nie existiert oder ist abgelaufen.",
+            "Ce paste n'a jamais existé ou a expiré.",
+            "Este paste nunca existió o ha caducado.",
+        ],
+        Message::BurnedOrDeleted => [
+            "This paste was burned or deleted.",
+            "Dieser Paste wurde verbrannt oder gelöscht.",
+            "Ce paste a été brûlé ou supprimé.",
+            "Este paste fue quemado o eliminado.",
+        ],
+        Message::InvalidUrl => [
+            "Invalid paste URL.",
+            "Ungültige Paste-URL.",
+            "URL de paste invalide.",
+            "URL de paste no válida.",
+        ],
+        Message::TooLarge => [
+            "The paste is too large to decrypt from the web browser. You must use the CLI tool to download this paste.",
+            "Der Paste ist zu groß, um im Browser entschlüsselt zu werden. Lade ihn mit dem CLI-Tool herunter.",
+            "Ce paste est trop volumineux pour être déchiffré dans le navigateur. Utilisez l'outil en ligne de commande pour le télécharger.",
+            "El paste es demasiado grande para descifrarlo en el navegador. Usa la herramienta de línea de comandos para descargarlo.",
+        ],
+        Message::LargePaste => [
+            "This paste is {}, and decrypting it will use about {} of memory. This may be slow or crash the page on some devices. Continue?",
+            "Dieser Paste ist {} groß, und das Entschlüsseln braucht etwa {} Arbeitsspeicher. Auf manchen Geräten kann das langsam sein oder die Seite abstürzen lassen. Fortfahren?",
+            "Ce paste fait {}, et le déchiffrer utilisera environ {} de mémoire. Cela peut être lent ou faire planter la page sur certains appareils. Continuer ?",
+            "Este paste ocupa {} y descifrarlo usará unos {} de memoria. Puede ser lento o bloquear la página en algunos dispositivos. ¿Continuar?",
+        ],
+        Message::LargerThanMemory => [
+            "This paste is {}, and decrypting it will use about {} of memory, which is more than this device seems to have to spare. This will likely crash the page. Continue anyway?",
+            "Dieser Paste ist {} groß, und das Entschlüsseln braucht etwa {} Arbeitsspeicher, mehr als dieses Gerät anscheinend übrig hat. Die Seite wird wahrscheinlich abstürzen. Trotzdem fortfahren?",
+            "Ce paste fait {}, et le déchiffrer utilisera environ {} de mémoire, plus que ce dont cet appareil semble disposer. La page va probablement planter. Continuer quand même ?",
+            "Este paste ocupa {} y descifrarlo usará unos {} de memoria, más de la que este dispositivo parece tener disponible. Es probable que la página se bloquee. ¿Continuar de todos modos?",
+        ],
+        Message::LargePasteCancelled => [
+            "The paste wasn't downloaded, as it's too large for this device.",
+            "Der Paste wurde nicht heruntergeladen, da er für dieses Gerät zu groß ist.",
+            "Le paste n'a pas été téléchargé, car il est trop volumineux pour cet appareil.",
+            "El paste no se descargó, ya que es demasiado grande para este dispositivo.",
+        ],
+        Message::EmptyResponse => [
+            "The server sent an empty response.",
+            "Der Server hat eine leere Antwort gesendet.",
+            "Le serveur a envoyé une réponse vide.",
+            "El servidor envió una respuesta vacía.",
+        ],
+        Message::DownloadFailed => [
+            "Failed to download the paste.",
+            "Der Paste konnte nicht heruntergeladen werden.",
+            "Le téléchargement du paste a échoué.",
+            "No se pudo descargar el paste.",
+        ],
+        Message::WrongPassword => [
+            "The provided password was incorrect.",
+            "Das angegebene Passwort ist falsch.",
+            "Le mot de passe fourni est incorrect.",
+            "La contraseña proporcionada es incorrecta.",
+        ],
+        Message::WrongKey => [
+            "The secret key in the URL was incorrect.",
+            "Der geheime Schlüssel in der URL ist falsch.",
+            "La clé secrète dans l'URL est incorrecte.",
+            "La clave secreta de la URL es incorrecta.",
+        ],
+        Message::DeleteFailed => [
+            "Failed to delete the paste.",
+            "Der Paste konnte nicht gelöscht werden.",
+            "La suppression du paste a échoué.",
+            "No se pudo eliminar el paste.",
+        ],
+        Message::PasteDeleted => [
+            "The paste was deleted.",
+            "Der Paste wurde gelöscht.",
+            "Le paste a été supprimé.",
+            "El paste fue eliminado.",
+        ],
+        Message::DoesNotExpire => [
+            "This item does not expire.",
+            "Dieses Element läuft nicht ab.",
+            "Cet élément n'expire pas.",
+            "Este elemento no caduca.",
+        ],
+        Message::BurnedCopy => [
+            "This item has been burned. You now have the only copy.",
+            "Dieses Element wurde verbrannt. Du hast jetzt die einzige Kopie.",
+            "Cet élément a été brûlé. Vous en avez maintenant la seule copie.",
+            "Este elemento ha sido quemado. Ahora tienes la única copia.",
+        ],
+        Message::ExpiresOn => [
+            "This item will expire on {}.",
+            "Dieses Element läuft am {} ab.",
+            "Cet élément expirera le {}.",
+            "Este elemento caducará el {}.",
+        ],
+        Message::ExpiresAfterRead => [
+            "This item will expire {} minutes after it's first read.",
+            "Dieses Element läuft {} Minuten nach dem ersten Lesen ab.",
+            "Cet élément expirera {} minutes après sa première lecture.",
+            "Este elemento caducará {} minutos después de su primera lectura.",
+        ],
+        Message::BurnedOn => [
+            "This paste was burned on {}.",
+            "Dieser Paste wurde am {} verbrannt.",
+            "Ce paste a été brûlé le {}.",
+            "Este paste fue quemado el {}.",
+        ],
+        Message::DeletedOn => [
+            "This paste was deleted by its owner on {}.",
+            "Dieser Paste wurde am {} von seinem Besitzer gelöscht.",
+            "Ce paste a été supprimé par son propriétaire le {}.",
+            "Este paste fue eliminado por su propietario el {}.",
+        ],
+        Message::ExhaustedOn => [
+            "This paste reached its download limit on {}.",
+            "Dieser Paste hat am {} sein Download-Limit erreicht.",
+            "Ce paste a atteint sa limite de téléchargements le {}.",
+            "Este paste alcanzó su límite de descargas el {}.",
+        ],
+        Message::LockedUntil => [
+            "This paste can't be viewed until {}.",
+            "Dieser Paste kann erst am {} angesehen werden.",
+            "Ce paste ne peut pas être consulté avant le {}.",
+            "Este paste no se puede ver hasta el {}.",
+        ],
+    }
+}
+
+/// Translates a message into the browser's preferred language.
+pub fn tr(message: Message) -> &'static str {
+    translations(message)[Language::current() as usize]
+}
+
+/// Translates a message that takes an argument.
+pub fn tr_with(message: Message, arg: &str) -> String {
+    tr_with_args(message, &[arg])
+}
+
+/// Translates a message that takes several arguments, which are filled in
+/// order.
+pub fn tr_with_args(message: Message, args: &[&str]) -> String {
+    args.iter().fold(tr(message).to_string(), |text, arg| {
+        text.replacen("{}", arg, 1)
+    })
+}
+
+/// Describes when a paste expires.
+pub fn expiration(expiration: Option<Expiration>) -> String {
+    match expiration {
+        None => tr(Message::DoesNotExpire).to_string(),
+        Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_)) => {
+            tr(Message::BurnedCopy).to_string()
+        }
+        Some(Expiration::UnixTime(time)) => {
+            tr_with(Message::ExpiresOn, &format_time(time.timestamp_millis()))
+        }
+        Some(Expiration::ReadWindow(window) | Expiration::ReadWindowWithDeadline(window, _)) => {
+            tr_with(Message::ExpiresAfterRead, &(window / 60).to_string())
+        }
+    }
+}
+
+/// Describes what happened to a paste that no longer exists.
+pub fn tombstone(tombstone: &Tombstone) -> String {
+    let message = match tombstone.reason {
+        TombstoneReason::Burned => Message::BurnedOn,
+        TombstoneReason::Deleted => Message::DeletedOn,
+        TombstoneReason::Exhausted => Message::ExhaustedOn,
+    };
+    tr_with(message, &format_time(tombstone.time.timestamp_millis()))
+}
+
+/// Describes when a time-locked paste may first be read.
+pub fn not_before(not_before: NotBefore) -> String {
+    tr_with(
+        Message::LockedUntil,
+        &format_time(not_before.0.timestamp_millis()),
+    )
+}
+
+/// Formats a Unix timestamp in milliseconds the way the browser would for the
+/// current language.
+#[allow(clippy::cast_precision_loss)]
+fn format_time(millis: i64) -> String {
+    let options = Object::new();
+    let _ = Reflect::set(&options, &"dateStyle".into(), &"full".into());
+    let _ = Reflect::set(&options, &"timeStyle".into(), &"long".into());
+    Date::new(&JsValue::from_f64(millis as f64))
+        .to_locale_string(Language::current().tag(), &options)
+        .into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_language_tags() {
+        assert_eq!(Language::from_tag("de-DE"), Language::German);
+        assert_eq!(Language::from_tag("fr"), Language::French);
+        assert_eq!(Language::from_tag("es_MX"), Language::Spanish);
+        assert_eq!(Language::from_tag("EN-us"), Language::English);
+        assert_eq!(Language::from_tag("ja"), Language::English);
+        assert_eq!(Language::from_tag(""), Language::English);
+    }
+
+    #[test]
+    fn translations_keep_placeholders() {
+        for message in [
+            Message::InvalidLink,
+            Message::ExpiresOn,
+            Message::BurnedOn,
+            Message::DeletedOn,
+            Message::ExhaustedOn,
+        ] {
+            for translation in translations(message) {
+                assert_eq!(translation.matches("{}").count(), 1, "{translation}");
+            }
+        }
+
+        for message in [Message::LargePaste, Message::LargerThanMemory] {
+            for translation in translations(message) {
+                assert_eq!(translation.matches("{}").count(), 2, "{translation}");
+            }
+        }
+    }
+}
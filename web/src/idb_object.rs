@@ -17,9 +17,12 @@
 use std::{hint::unreachable_unchecked, marker::PhantomData};
 
 use gloo_console::log;
-use js_sys::{Array, JsString, Object};
+use js_sys::{Array, Date, JsString, Object};
+use omegaupload_common::Expiration;
 use wasm_bindgen::JsValue;
 
+use crate::i18n;
+
 pub struct IdbObject<State>(Array, PhantomData<State>);
 
 impl<State: IdbObjectState> IdbObject<State> {
@@ -47,8 +50,10 @@ impl From<IdbObject<Ready>> for Object {
 }
 
 impl IdbObject<NeedsType> {
+    /// Creates an object that records when it was created, so that stale
+    /// objects can be evicted.
     pub fn new() -> Self {
-        Self(Array::new(), PhantomData)
+        Self(Array::new(), PhantomData).add_tuple("stored_at", &JsValue::from_f64(Date::now()))
     }
 
     pub fn archive(self) -> IdbObject<NeedsExpiration> {
@@ -74,6 +79,40 @@ impl IdbObject<NeedsType> {
     pub fn string(self) -> IdbObject<NeedsExpiration> {
         self.add_tuple("type", &JsString::from("string"))
     }
+
+    /// Text that's too large to be a single string, stored as a blob instead.
+    pub fn large_string(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("large_string"))
+    }
+
+    pub fn markdown(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("markdown"))
+    }
+
+    /// Text with ANSI escape sequences, such as a terminal log.
+    pub fn ansi(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("ansi"))
+    }
+
+    pub fn diff(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("diff"))
+    }
+
+    pub fn notebook(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("notebook"))
+    }
+
+    pub fn json(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("json"))
+    }
+
+    pub fn table(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("table"))
+    }
+
+    pub fn manifest(self) -> IdbObject<NeedsExpiration> {
+        self.add_tuple("type", &JsString::from("manifest"))
+    }
 }
 
 impl Default for IdbObject<NeedsType> {
@@ -83,8 +122,41 @@ impl Default for IdbObject<NeedsType> {
 }
 
 impl IdbObject<NeedsExpiration> {
-    pub fn expiration_text(self, expires: &str) -> IdbObject<NeedsData> {
-        self.add_tuple("expiration", &JsString::from(expires))
+    /// Records when the paste expires, both as text to show to the user and
+    /// as a timestamp for eviction, if it has a deadline. The deadline is also
+    /// kept as RFC3339, so that the UI can count down to it and format it for
+    /// the user's locale. Burn after reading pastes are flagged, so that
+    /// they're removed once they're closed.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn expiration(self, expiration: Option<Expiration>) -> IdbObject<NeedsData> {
+        let text = i18n::expiration(expiration);
+        let expires_at = match expiration {
+            Some(
+                Expiration::UnixTime(time)
+                | Expiration::BurnAfterReadingWithDeadline(time)
+                | Expiration::ReadWindowWithDeadline(_, time),
+            ) => JsValue::from_f64(time.timestamp_millis() as f64),
+            Some(Expiration::BurnAfterReading | Expiration::ReadWindow(_)) | None => JsValue::NULL,
+        };
+        let expires = match expiration {
+            Some(
+                Expiration::UnixTime(time)
+                | Expiration::BurnAfterReadingWithDeadline(time)
+                | Expiration::ReadWindowWithDeadline(_, time),
+            ) => JsValue::from_str(&time.to_rfc3339()),
+            Some(Expiration::BurnAfterReading | Expiration::ReadWindow(_)) | None => JsValue::NULL,
+        };
+        let burn_after_reading = matches!(
+            expiration,
+            Some(Expiration::BurnAfterReading | Expiration::BurnAfterReadingWithDeadline(_))
+        );
+        self.add_tuple::<NeedsExpiration>("expiration", &JsString::from(text))
+            .add_tuple::<NeedsExpiration>("expires_at", &expires_at)
+            .add_tuple::<NeedsExpiration>("expires", &expires)
+            .add_tuple(
+                "burn_after_reading",
+                &JsValue::from_bool(burn_after_reading),
+            )
     }
 }
 
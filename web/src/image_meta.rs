@@ -0,0 +1,167 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reads and strips image metadata.
+//!
+//! Photos often carry EXIF metadata, such as where they were taken, which
+//! uploaders may not realize they are sharing.
+
+use std::io::Cursor;
+
+use exif::{In, Tag};
+
+/// What could be read from an image. Fields are missing if the image is in an
+/// unsupported format or has no such metadata.
+#[derive(Clone, Copy, Default)]
+pub struct ImageInfo {
+    pub dimensions: Option<(usize, usize)>,
+    /// The EXIF orientation, from 1 to 8.
+    pub orientation: Option<u32>,
+    pub has_gps: bool,
+}
+
+/// Reads an image's metadata. Only its start is needed, as metadata comes
+/// before the image data in all supported formats.
+pub fn read(data: &[u8]) -> ImageInfo {
+    let dimensions = imagesize::blob_size(data)
+        .ok()
+        .map(|size| (size.width, size.height));
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok();
+    let orientation = exif
+        .as_ref()
+        .and_then(|exif| exif.get_field(Tag::Orientation, In::PRIMARY))
+        .and_then(|field| field.value.get_uint(0));
+    let has_gps = exif.as_ref().map_or(false, |exif| {
+        exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some()
+            || exif.get_field(Tag::GPSLongitude, In::PRIMARY).is_some()
+    });
+
+    ImageInfo {
+        dimensions,
+        orientation,
+        has_gps,
+    }
+}
+
+/// Removes EXIF and XMP metadata from JPEGs and PNGs. Returns `None` if the
+/// image is in another format, is malformed, or has nothing to remove.
+pub fn strip(data: &[u8]) -> Option<Vec<u8>> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg(data)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        strip_png(data)
+    } else {
+        None
+    }
+}
+
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+    let mut stripped = Vec::with_capacity(data.len());
+    stripped.extend_from_slice(&data[..2]);
+    let mut pos = 2;
+    loop {
+        let marker = match data.get(pos..pos + 2)? {
+            [0xFF, marker] => *marker,
+            _ => return None,
+        };
+        // Markers without a length.
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            stripped.extend_from_slice(&data[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+        // The compressed image data follows, which has no more metadata.
+        if marker == 0xDA || marker == 0xD9 {
+            stripped.extend_from_slice(&data[pos..]);
+            break;
+        }
+
+        let len = u16::from_be_bytes(data.get(pos + 2..pos + 4)?.try_into().ok()?);
+        let end = pos + 2 + usize::from(len);
+        let segment = data.get(pos..end)?;
+        let payload = &segment[4..];
+        let is_metadata =
+            marker == 0xE1 && (payload.starts_with(b"Exif\0\0") || payload.starts_with(XMP_HEADER));
+        if !is_metadata {
+            stripped.extend_from_slice(segment);
+        }
+        pos = end;
+    }
+
+    (stripped.len() < data.len()).then_some(stripped)
+}
+
+fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+    let mut stripped = Vec::with_capacity(data.len());
+    stripped.extend_from_slice(&data[..8]);
+    let mut pos = 8;
+    while pos < data.len() {
+        let len = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?);
+        // Length, type, data, and CRC.
+        let end = pos + 12 + usize::try_from(len).ok()?;
+        let chunk = data.get(pos..end)?;
+        let chunk_type = &chunk[4..8];
+        let is_metadata = chunk_type == b"eXIf"
+            || (chunk_type == b"iTXt" && chunk[8..].starts_with(b"XML:com.adobe.xmp\0"));
+        if !is_metadata {
+            stripped.extend_from_slice(chunk);
+        }
+        pos = end;
+    }
+
+    (stripped.len() < data.len()).then_some(stripped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_dimensions() {
+        let info = read(include_bytes!("../../test/image.png"));
+        assert!(info.dimensions.is_some());
+        assert!(!info.has_gps);
+    }
+
+    #[test]
+    fn strips_jpeg_exif() {
+        let jpeg = [
+            &[0xFF, 0xD8][..],
+            &[0xFF, 0xE1, 0x00, 0x08],
+            b"Exif\0\0",
+            &[0xFF, 0xDB, 0x00, 0x03, 0x00],
+            &[0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9],
+        ]
+        .concat();
+        let expected = [
+            &[0xFF, 0xD8][..],
+            &[0xFF, 0xDB, 0x00, 0x03, 0x00],
+            &[0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9],
+        ]
+        .concat();
+        assert_eq!(strip(&jpeg), Some(expected));
+    }
+
+    #[test]
+    fn leaves_clean_images_alone() {
+        assert_eq!(strip(include_bytes!("../../test/image.png")), None);
+        assert_eq!(strip(include_bytes!("../../test/image.webp")), None);
+    }
+}
@@ -3,4 +3,9 @@ import './main.scss';
 
 start();
 
+if ('serviceWorker' in navigator) {
+  const basePath = document.querySelector("meta[name='omegaupload-base-path']").getAttribute("content");
+  navigator.serviceWorker.register(basePath + 'sw.js').catch(e => console.warn("[js] Failed to register service worker:", e));
+}
+
 window.addEventListener("hashchange", () => location.reload());
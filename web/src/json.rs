@@ -0,0 +1,58 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Pretty-prints JSON pastes.
+
+use serde::Serialize;
+use serde_json::ser::PrettyFormatter;
+use serde_json::{Serializer, Value};
+
+/// Returns the paste pretty-printed if it's a JSON object or array, with its
+/// keys in their original order. Other JSON values, such as a lone number, are
+/// more likely to be plain text, so they're left alone.
+pub fn pretty(text: &str) -> Option<String> {
+    if !matches!(text.trim_start().chars().next(), Some('{' | '[')) {
+        return None;
+    }
+
+    let value: Value = serde_json::from_str(text).ok()?;
+    let mut pretty = Vec::with_capacity(text.len());
+    let mut serializer =
+        Serializer::with_formatter(&mut pretty, PrettyFormatter::with_indent(b"  "));
+    value.serialize(&mut serializer).ok()?;
+    String::from_utf8(pretty).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pretty_prints_objects() {
+        assert_eq!(
+            pretty(r#"{"b":1,"a":[true,null]}"#).as_deref(),
+            Some("{\n  \"b\": 1,\n  \"a\": [\n    true,\n    null\n  ]\n}")
+        );
+    }
+
+    #[test]
+    fn ignores_other_values() {
+        assert!(pretty("42").is_none());
+        assert!(pretty("\"text\"").is_none());
+        assert!(pretty("{ not json }").is_none());
+        assert!(pretty("[1, 2").is_none());
+    }
+}
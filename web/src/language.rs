@@ -0,0 +1,278 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Guesses the language of text pastes, so that they can be highlighted
+//! without a `!lang:` hint.
+//!
+//! Languages are named as highlight.js knows them.
+
+/// How much of a paste is looked at when guessing from its contents.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// How many points a language needs for its guess to be trusted.
+const MIN_SCORE: u32 = 4;
+
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("bash", "bash"),
+    ("sh", "bash"),
+    ("zsh", "bash"),
+    ("fish", "fish"),
+    ("python", "python"),
+    ("node", "javascript"),
+    ("deno", "typescript"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+    ("pwsh", "powershell"),
+];
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("go", "go"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("hpp", "cpp"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("rb", "ruby"),
+    ("sh", "bash"),
+    ("html", "xml"),
+    ("xml", "xml"),
+    ("json", "json"),
+    ("yml", "yaml"),
+    ("yaml", "yaml"),
+    ("toml", "ini"),
+    ("ini", "ini"),
+    ("sql", "sql"),
+    ("diff", "diff"),
+    ("patch", "diff"),
+];
+
+/// Substrings that hint at a language, and how strongly.
+const HINTS: &[(&str, &[(&str, u32)])] = &[
+    (
+        "rust",
+        &[
+            ("fn main()", 4),
+            ("let mut ", 3),
+            ("impl ", 2),
+            ("use std::", 4),
+            ("pub fn ", 3),
+            ("#[derive(", 4),
+            ("-> Result<", 3),
+        ],
+    ),
+    (
+        "python",
+        &[
+            ("def ", 2),
+            ("import ", 1),
+            ("from ", 1),
+            ("self.", 2),
+            ("elif ", 4),
+            ("__init__", 4),
+            ("if __name__ == ", 4),
+        ],
+    ),
+    (
+        "go",
+        &[
+            ("package main", 4),
+            ("func ", 2),
+            (":= ", 2),
+            ("fmt.", 3),
+            ("if err != nil", 4),
+        ],
+    ),
+    (
+        "c",
+        &[
+            ("#include <", 3),
+            ("int main(", 2),
+            ("printf(", 2),
+            ("malloc(", 2),
+            ("->", 1),
+        ],
+    ),
+    (
+        "cpp",
+        &[
+            ("#include <", 2),
+            ("std::", 3),
+            ("template <", 3),
+            ("template<", 3),
+            ("namespace ", 2),
+            ("cout <<", 3),
+        ],
+    ),
+    (
+        "java",
+        &[
+            ("public class ", 4),
+            ("public static void main", 4),
+            ("System.out.", 3),
+            ("private final ", 2),
+            ("import java.", 4),
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            ("function ", 2),
+            ("const ", 1),
+            ("=> ", 1),
+            ("console.log(", 3),
+            ("require(", 3),
+            ("document.", 2),
+        ],
+    ),
+    (
+        "typescript",
+        &[
+            ("interface ", 2),
+            (": string", 3),
+            (": number", 3),
+            ("export type ", 3),
+        ],
+    ),
+    ("php", &[("<?php", 8)]),
+    (
+        "xml",
+        &[
+            ("<!DOCTYPE html", 8),
+            ("<?xml ", 8),
+            ("<html", 4),
+            ("</div>", 2),
+        ],
+    ),
+    (
+        "sql",
+        &[
+            ("SELECT ", 2),
+            (" FROM ", 2),
+            ("WHERE ", 2),
+            ("CREATE TABLE ", 4),
+            ("INSERT INTO ", 4),
+        ],
+    ),
+    (
+        "diff",
+        &[
+            ("diff --git ", 8),
+            ("\n@@ -", 4),
+            ("\n+++ ", 2),
+            ("\n--- ", 2),
+        ],
+    ),
+];
+
+/// Guesses the language of a text paste from its shebang, its name, or its
+/// contents, in that order.
+pub fn detect(name_hint: Option<&str>, text: &str) -> Option<&'static str> {
+    from_shebang(text)
+        .or_else(|| name_hint.and_then(from_extension))
+        .or_else(|| from_contents(text))
+}
+
+fn from_shebang(text: &str) -> Option<&'static str> {
+    let line = text.lines().next()?.strip_prefix("#!")?;
+    let mut args = line.split_whitespace();
+    let mut program = args.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = args.find(|arg| !arg.starts_with('-'))?;
+    }
+    // Handles versioned interpreters, like python3.
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(interpreter, _)| *interpreter == program)
+        .map(|(_, language)| *language)
+}
+
+fn from_extension(name: &str) -> Option<&'static str> {
+    let (_, extension) = name.rsplit_once('.')?;
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| extension.eq_ignore_ascii_case(known))
+        .map(|(_, language)| *language)
+}
+
+fn from_contents(text: &str) -> Option<&'static str> {
+    let mut end = text.len().min(SAMPLE_SIZE);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let sample = &text[..end];
+
+    HINTS
+        .iter()
+        .map(|(language, hints)| {
+            let score: u32 = hints
+                .iter()
+                .filter(|(hint, _)| sample.contains(hint))
+                .map(|(_, weight)| weight)
+                .sum();
+            (score, *language)
+        })
+        .filter(|(score, _)| *score >= MIN_SCORE)
+        // Ties go to the language listed first.
+        .rev()
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, language)| language)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_shebangs() {
+        assert_eq!(detect(None, "#!/bin/sh\necho hi"), Some("bash"));
+        assert_eq!(
+            detect(None, "#!/usr/bin/env python3\nprint(1)"),
+            Some("python")
+        );
+        assert_eq!(
+            detect(None, "#!/usr/bin/env -S node --harmony\n"),
+            Some("javascript")
+        );
+    }
+
+    #[test]
+    fn detects_extensions() {
+        assert_eq!(detect(Some("main.RS"), ""), Some("rust"));
+        assert_eq!(detect(Some("fix.patch"), ""), Some("diff"));
+        assert_eq!(detect(Some("notes"), ""), None);
+    }
+
+    #[test]
+    fn detects_contents() {
+        let code = include_str!("../../test/code.rs");
+        assert_eq!(detect(None, code), Some("rust"));
+        let patch = include_str!("../../test/0000-test-patch.patch");
+        assert_eq!(detect(None, patch), Some("diff"));
+        let license = include_str!("../../test/LICENSE.md");
+        assert_eq!(detect(None, license), None);
+    }
+}
@@ -0,0 +1,83 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Renders Markdown pastes to HTML.
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+
+const EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd"];
+
+/// Whether a text paste should be rendered as Markdown, based on its name or
+/// guessed mime type.
+pub fn is_markdown(name_hint: Option<&str>, mime_type: &str) -> bool {
+    mime_type == "text/markdown"
+        || mime_type == "text/x-markdown"
+        || name_hint
+            .and_then(|name| name.rsplit_once('.'))
+            .map_or(false, |(_, extension)| {
+                EXTENSIONS
+                    .iter()
+                    .any(|md| extension.eq_ignore_ascii_case(md))
+            })
+}
+
+/// Renders Markdown to HTML that is safe to insert into the page.
+///
+/// Pastes are untrusted, so scripts and the like are removed. Images are
+/// removed as well, as loading them would tell their host who is viewing the
+/// paste.
+pub fn render(source: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut html = String::with_capacity(source.len() * 3 / 2);
+    html::push_html(&mut html, Parser::new_ext(source, options));
+
+    Builder::default()
+        .rm_tags(&["img"])
+        .clean(&html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_markdown() {
+        assert!(is_markdown(Some("README.md"), "text/plain"));
+        assert!(is_markdown(Some("notes.MARKDOWN"), "text/plain"));
+        assert!(is_markdown(None, "text/markdown"));
+        assert!(!is_markdown(Some("main.rs"), "text/plain"));
+        assert!(!is_markdown(None, "text/plain"));
+    }
+
+    #[test]
+    fn renders_markdown() {
+        assert_eq!(render("# Title"), "<h1>Title</h1>\n");
+    }
+
+    #[test]
+    fn removes_scripts() {
+        let html = render("<script>alert(1)</script>\n\n[link](javascript:alert(1))");
+        assert!(!html.contains("script"));
+        assert!(!html.contains("alert"));
+    }
+
+    #[test]
+    fn removes_images() {
+        assert!(!render("![tracker](https://example.com/pixel.png)").contains("example.com"));
+    }
+}
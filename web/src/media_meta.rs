@@ -0,0 +1,127 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reads audio and video metadata, so that it can be shown before playback.
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use mp4::{Mp4Reader, TrackType};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// How much of a non-MP4 file is probed. Other containers keep their headers
+/// at the start.
+const PROBE_SIZE: usize = 1024 * 1024;
+
+/// What could be read from an audio or video file. Fields are missing if the
+/// format is unsupported, or if the metadata wasn't in the part that was read.
+#[derive(Clone, Default)]
+pub struct MediaInfo {
+    pub duration: Option<Duration>,
+    /// Codec names, separated by commas if there are several tracks.
+    pub codec: Option<String>,
+    pub dimensions: Option<(u16, u16)>,
+}
+
+/// Reads metadata from the start of an audio or video file, or all of it.
+pub fn read(data: &[u8]) -> MediaInfo {
+    read_mp4(data).or_else(|| probe(data)).unwrap_or_default()
+}
+
+fn read_mp4(data: &[u8]) -> Option<MediaInfo> {
+    if data.get(4..8) != Some(b"ftyp") {
+        return None;
+    }
+
+    // Fails if the moov box is after the part that was read.
+    let mp4 = Mp4Reader::read_header(Cursor::new(data), data.len() as u64).ok()?;
+    let mut codecs = vec![];
+    let mut dimensions = None;
+    for track in mp4.tracks().values() {
+        if let Ok(media_type) = track.media_type() {
+            codecs.push(media_type.to_string());
+        }
+        if matches!(track.track_type(), Ok(TrackType::Video)) {
+            dimensions = Some((track.width(), track.height()));
+        }
+    }
+
+    Some(MediaInfo {
+        duration: Some(mp4.duration()),
+        codec: (!codecs.is_empty()).then(|| codecs.join(", ")),
+        dimensions,
+    })
+}
+
+fn probe(data: &[u8]) -> Option<MediaInfo> {
+    let source = Cursor::new(data[..data.len().min(PROBE_SIZE)].to_vec());
+    let stream = MediaSourceStream::new(Box::new(source), MediaSourceStreamOptions::default());
+    let probed = symphonia::default::get_probe()
+        .format(
+            &Hint::new(),
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+
+    let params = &probed.format.default_track()?.codec_params;
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|codec| codec.short_name.to_string());
+    let duration = params
+        .time_base
+        .zip(params.n_frames)
+        .map(|(time_base, frames)| {
+            let time = time_base.calc_time(frames);
+            Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+        });
+
+    Some(MediaInfo {
+        duration,
+        codec,
+        dimensions: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_mp4() {
+        let info = read(include_bytes!("../../test/movie.mp4"));
+        assert!(info.duration.is_some());
+        assert!(info.codec.is_some());
+        assert!(info.dimensions.is_some());
+    }
+
+    #[test]
+    fn reads_mp3() {
+        let info = read(include_bytes!("../../test/music.mp3"));
+        assert_eq!(info.codec.as_deref(), Some("mp3"));
+        assert!(info.dimensions.is_none());
+    }
+
+    #[test]
+    fn ignores_truncated_mp4() {
+        let data = include_bytes!("../../test/movie.mp4");
+        assert!(read(&data[..64 * 1024]).dimensions.is_none());
+    }
+}
@@ -0,0 +1,198 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Renders Jupyter notebooks to HTML.
+//!
+//! Notebooks are untrusted like any other paste, so Markdown and HTML from
+//! them are sanitized, and only images embedded in the notebook are shown.
+
+use ammonia::clean_text;
+use serde_json::Value;
+
+use crate::{ansi, markdown};
+
+/// Image outputs that are shown, in order of preference.
+const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif"];
+
+/// Whether a text paste may be a notebook, based on its name or contents. It
+/// should still be rendered, as it may not be one.
+pub fn is_notebook(name_hint: Option<&str>, text: &str) -> bool {
+    name_hint
+        .and_then(|name| name.rsplit_once('.'))
+        .map_or(false, |(_, extension)| {
+            extension.eq_ignore_ascii_case("ipynb")
+        })
+        || (text.trim_start().starts_with('{') && text.contains("\"nbformat\""))
+}
+
+/// Renders a notebook's cells and their outputs to HTML that is safe to insert
+/// into the page. Returns `None` if the paste isn't a notebook.
+pub fn render(text: &str) -> Option<String> {
+    let notebook: Value = serde_json::from_str(text).ok()?;
+    notebook.get("nbformat")?;
+    let cells = notebook.get("cells")?.as_array()?;
+    let language = notebook
+        .pointer("/metadata/language_info/name")
+        .or_else(|| notebook.pointer("/metadata/kernelspec/language"))
+        .and_then(Value::as_str)
+        .unwrap_or("python");
+
+    let mut html = String::new();
+    for cell in cells {
+        let source = joined(cell.get("source"));
+        match cell.get("cell_type").and_then(Value::as_str) {
+            Some("markdown") => {
+                html.push_str("<div class=\"notebook-markdown\">");
+                html.push_str(&markdown::render(&source));
+                html.push_str("</div>");
+            }
+            Some("code") => {
+                html.push_str("<div class=\"notebook-code\">");
+                push_code(&mut html, &source, Some(language));
+                for output in cell
+                    .get("outputs")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                {
+                    push_output(&mut html, output);
+                }
+                html.push_str("</div>");
+            }
+            _ => push_code(&mut html, &source, None),
+        }
+    }
+    Some(html)
+}
+
+fn push_output(html: &mut String, output: &Value) {
+    html.push_str("<div class=\"notebook-output\">");
+    match output.get("output_type").and_then(Value::as_str) {
+        Some("stream") => push_code(html, &joined(output.get("text")), None),
+        Some("error") => {
+            // Tracebacks are colored with ANSI escape sequences.
+            let traceback = joined(output.get("traceback"));
+            let text: String = ansi::parse(&traceback)
+                .into_iter()
+                .map(|span| span.text)
+                .collect();
+            push_code(html, &text, None);
+        }
+        _ => {
+            let data = output.get("data");
+            let image = IMAGE_TYPES.iter().find_map(|mime_type| {
+                let image = joined(data?.get(*mime_type));
+                is_base64(&image).then(|| (mime_type, image))
+            });
+            if let Some((mime_type, image)) = image {
+                // Notebooks embed images as base64, which is safe to use in a
+                // data URL as is. Newlines are allowed in it, but not in URLs.
+                html.push_str(&format!(
+                    "<img src=\"data:{mime_type};base64,{}\">",
+                    image.replace('\n', "")
+                ));
+            } else if let Some(markdown) = data.and_then(|data| data.get("text/markdown")) {
+                html.push_str(&markdown::render(&joined(Some(markdown))));
+            } else if let Some(text) = data.and_then(|data| data.get("text/plain")) {
+                push_code(html, &joined(Some(text)), None);
+            }
+        }
+    }
+    html.push_str("</div>");
+}
+
+fn push_code(html: &mut String, code: &str, language: Option<&str>) {
+    match language {
+        Some(language) => html.push_str(&format!(
+            "<pre><code class=\"language-{}\">",
+            clean_text(language)
+        )),
+        None => html.push_str("<pre><code class=\"nohighlight\">"),
+    }
+    html.push_str(&clean_text(code));
+    html.push_str("</code></pre>");
+}
+
+/// Notebooks store multiline text as either a string or a list of lines.
+fn joined(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn is_base64(data: &str) -> bool {
+    !data.is_empty()
+        && data
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'=' | b'\n'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_notebooks() {
+        assert!(is_notebook(Some("analysis.ipynb"), ""));
+        assert!(is_notebook(None, r#"{"cells": [], "nbformat": 4}"#));
+        assert!(!is_notebook(None, r#"{"cells": []}"#));
+    }
+
+    #[test]
+    fn renders_cells() {
+        let notebook = r##"{
+            "nbformat": 4,
+            "metadata": {"kernelspec": {"language": "python"}},
+            "cells": [
+                {"cell_type": "markdown", "source": ["# Title"]},
+                {
+                    "cell_type": "code",
+                    "source": ["print(1 < 2)"],
+                    "outputs": [
+                        {"output_type": "stream", "text": ["True\n"]},
+                        {"output_type": "display_data", "data": {"image/png": "aGk=\n"}}
+                    ]
+                }
+            ]
+        }"##;
+        let html = render(notebook).unwrap();
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<code class=\"language-python\">print"));
+        assert!(html.contains("&lt;"));
+        assert!(html.contains("True"));
+        assert!(html.contains("<img src=\"data:image/png;base64,aGk=\">"));
+    }
+
+    #[test]
+    fn escapes_outputs() {
+        let notebook = r#"{
+            "nbformat": 4,
+            "cells": [{
+                "cell_type": "code",
+                "source": "",
+                "outputs": [
+                    {"output_type": "execute_result", "data": {"text/plain": "<script>"}},
+                    {"output_type": "display_data", "data": {"image/png": "\"><script>"}}
+                ]
+            }]
+        }"#;
+        let html = render(notebook).unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<img"));
+    }
+}
@@ -0,0 +1,113 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use gloo_console::error;
+use js_sys::{Function, JsString};
+use omegaupload_common::crypto::OpenStage;
+use wasm_bindgen::JsValue;
+
+/// What is currently being done with a paste, as shown by `renderProgress`.
+#[derive(Clone, Copy)]
+pub enum Progress {
+    /// Zipping multiple files into a single paste.
+    Bundling {
+        loaded: u64,
+        total: u64,
+    },
+    Encrypting {
+        loaded: u64,
+        total: u64,
+    },
+    Uploading {
+        loaded: u64,
+        total: Option<u64>,
+    },
+    Downloading {
+        loaded: u64,
+        total: Option<u64>,
+    },
+    DerivingKey,
+    Decrypting,
+    /// Preparing the decrypted paste to be displayed.
+    Processing,
+}
+
+impl Progress {
+    const fn phase(self) -> &'static str {
+        match self {
+            Self::Bundling { .. } => "bundling",
+            Self::Encrypting { .. } => "encrypting",
+            Self::Uploading { .. } => "uploading",
+            Self::Downloading { .. } => "downloading",
+            Self::DerivingKey => "deriving-key",
+            Self::Decrypting => "decrypting",
+            Self::Processing => "processing",
+        }
+    }
+
+    /// The number of bytes processed and expected, for phases that have them.
+    const fn bytes(self) -> Option<(u64, Option<u64>)> {
+        match self {
+            Self::Bundling { loaded, total } | Self::Encrypting { loaded, total } => {
+                Some((loaded, Some(total)))
+            }
+            Self::Uploading { loaded, total } | Self::Downloading { loaded, total } => {
+                Some((loaded, total))
+            }
+            Self::DerivingKey | Self::Decrypting | Self::Processing => None,
+        }
+    }
+}
+
+impl From<OpenStage> for Progress {
+    fn from(stage: OpenStage) -> Self {
+        match stage {
+            OpenStage::DerivingKey => Self::DerivingKey,
+            OpenStage::Decrypting => Self::Decrypting,
+        }
+    }
+}
+
+/// Reports progress to a JS callback, which is called with the phase name, and
+/// the number of bytes processed and expected for phases that have them.
+#[derive(Clone)]
+pub struct Reporter(Function);
+
+impl Reporter {
+    pub const fn new(callback: Function) -> Self {
+        Self(callback)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn report(&self, progress: Progress) {
+        let (loaded, total) = match progress.bytes() {
+            Some((loaded, total)) => (
+                JsValue::from_f64(loaded as f64),
+                total.map_or(JsValue::UNDEFINED, |total| JsValue::from_f64(total as f64)),
+            ),
+            None => (JsValue::UNDEFINED, JsValue::UNDEFINED),
+        };
+
+        if let Err(e) = self.0.call3(
+            &JsValue::NULL,
+            &JsString::from(progress.phase()),
+            &loaded,
+            &total,
+        ) {
+            error!(e);
+        }
+    }
+}
@@ -15,7 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 import ReactDom from 'react-dom';
-import React, { useState } from 'react';
+import React, { useEffect, useMemo, useRef, useState } from 'react';
 
 let hljs;
 if (typeof WorkerGlobalScope === 'undefined' || !(self instanceof WorkerGlobalScope)) {
@@ -25,31 +25,99 @@ if (typeof WorkerGlobalScope === 'undefined' || !(self instanceof WorkerGlobalSc
 }
 
 
-const FileForm = () => {
+interface UploadOptions {
+  password?: string,
+  // How expensive it is to derive a key from the password.
+  kdf_profile?: string,
+  expiration?: string,
+  name?: string,
+  language?: string,
+  private?: boolean,
+  strip_metadata?: boolean,
+  // Shows the link and its key separately once uploaded, rather than opening
+  // the paste. This is handled here rather than by the encryption worker.
+  split_url?: boolean,
+  // Images with a side longer than this are downscaled before they're
+  // encrypted. This is handled here rather than by the encryption worker.
+  max_dimension?: number,
+}
+
+interface UploadFormProps {
+  options: UploadOptions,
+}
+
+const OptionsForm = ({ options, setOptions, expirationVariants }) => {
+  const set = (key: keyof UploadOptions) => (event: React.ChangeEvent<HTMLInputElement | HTMLSelectElement>) => {
+    setOptions({ ...options, [key]: event.target.value });
+  };
+
+  return <div className='upload-options'>
+    <input type="password" placeholder="Password (optional)" value={options.password ?? ""} onChange={set("password")} />
+    {options.password && <select value={options.kdf_profile ?? ""} onChange={set("kdf_profile")}>
+      <option value="">Standard password hardening</option>
+      <option value="moderate">Moderate password hardening (slower to open)</option>
+      <option value="paranoid">Paranoid password hardening (much slower to open)</option>
+    </select>}
+    <select value={options.expiration ?? ""} onChange={set("expiration")}>
+      <option value="">Default expiration</option>
+      {expirationVariants.map((variant: string) => <option key={variant} value={variant}>{variant}</option>)}
+    </select>
+    <input type="text" placeholder="File name (optional)" value={options.name ?? ""} onChange={set("name")} />
+    <input type="text" placeholder="Language (optional)" value={options.language ?? ""} onChange={set("language")} />
+    <label>
+      <input
+        type="checkbox"
+        checked={options.private ?? false}
+        onChange={(e) => setOptions({ ...options, private: e.target.checked })}
+      />
+      Don't cache when viewed
+    </label>
+    <label>
+      <input
+        type="checkbox"
+        checked={options.strip_metadata ?? false}
+        onChange={(e) => setOptions({ ...options, strip_metadata: e.target.checked })}
+      />
+      Remove image metadata
+    </label>
+    <label>
+      <input
+        type="checkbox"
+        checked={options.split_url ?? false}
+        onChange={(e) => setOptions({ ...options, split_url: e.target.checked })}
+      />
+      Show the key separately
+    </label>
+    <select
+      value={options.max_dimension ?? ""}
+      onChange={(e) => setOptions({ ...options, max_dimension: e.target.value ? Number(e.target.value) : undefined })}
+    >
+      <option value="">Keep image size</option>
+      {[1080, 1440, 2160, 3840].map(size => <option key={size} value={size}>Downscale images to {size}px</option>)}
+    </select>
+  </div>;
+}
+
+const FileForm = ({ options }: UploadFormProps) => {
   const handleChange = (event: React.ChangeEvent<HTMLInputElement>) => {
-    let file = event.target.files![0];
-    const fr = new FileReader();
-    fr.onload = (_e) => {
-      encryptMessage(new Uint8Array(fr.result as ArrayBuffer));
-    }
-    fr.readAsArrayBuffer(file);
+    uploadFiles(Array.from(event.target.files!), options);
   }
 
   return <>
     <label className="file-upload hljs-meta" >
       Select a file
-      <input type="file" onChange={handleChange} />
+      <input type="file" multiple onChange={handleChange} />
     </label>
   </>
 }
 
-const PasteForm = () => {
+const PasteForm = ({ options }: UploadFormProps) => {
   const [data, setValue] = useState("");
 
   const handleSubmit = (event: React.FormEvent<HTMLFormElement>) => {
     event.preventDefault();
     if (data.trim() !== "") {
-      encryptMessage(new TextEncoder().encode(data));
+      encryptMessage(new Blob([data]), options);
     } else {
       console.log("[js] Not sending string because it was empty.");
     }
@@ -67,45 +135,389 @@ const PasteForm = () => {
   )
 }
 
-function encryptMessage(data: Uint8Array) {
-  const worker = new Worker(new URL('./bg_encrypt.ts', import.meta.url));
-  worker.onmessage = (event: MessageEvent<string>) => {
-    console.log(event);
-    if (event.data === 'init') {
-      console.log("[js] Sending data to worker");
-      const message = { data, location: window.location.toString() };
-      worker.postMessage(message, [message.data.buffer]);
+type EncryptMessage =
+  | { type: 'init' }
+  | { type: 'progress', phase: string, loaded?: number, total?: number }
+  | { type: 'done', url: string }
+  | { type: 'error', message: string };
+
+function uploadFiles(files: File[], options: UploadOptions) {
+  if (files.length === 1) {
+    // Default to the name of the selected file, so the paste keeps it.
+    const { max_dimension, ...fileOptions } = { ...options, name: options.name || files[0].name };
+    if (max_dimension && ["image/png", "image/jpeg"].includes(files[0].type)) {
+      downscaleAndUpload(files[0], max_dimension, fileOptions);
     } else {
-      window.location.assign(event.data);
+      encryptMessage(files[0], fileOptions);
     }
+  } else if (files.length > 1) {
+    encryptMessage(files, options);
   }
 }
 
-function createUploadUi() {
-  const html = <main className='hljs centered fullscreen'>
-    <FileForm />
-    <p>or paste your data below</p>
-    <PasteForm />
+// Blobs are read by the worker as they're encrypted, so files don't need to
+// fit in memory. Multiple files are zipped into a single paste.
+// Records audio, video, or the screen, and uploads the recording once it's
+// stopped.
+const RecordForm = ({ options }: UploadFormProps) => {
+  const [recorder, setRecorder] = useState<MediaRecorder | null>(null);
+
+  const start = (getStream: () => Promise<MediaStream>) => {
+    getStream().then(stream => {
+      const chunks: Blob[] = [];
+      const mediaRecorder = new MediaRecorder(stream);
+      mediaRecorder.ondataavailable = event => chunks.push(event.data);
+      mediaRecorder.onstop = () => {
+        stream.getTracks().forEach(track => track.stop());
+        setRecorder(null);
+        encryptMessage(new Blob(chunks, { type: mediaRecorder.mimeType }), options, true);
+      };
+      mediaRecorder.start();
+      setRecorder(mediaRecorder);
+    }).catch(error => alert(`Couldn't start recording: ${error}`));
+  };
+
+  if (recorder) {
+    return <p className='hljs centered'>
+      <button className='button hljs-meta' onClick={() => recorder.stop()}>Stop and upload recording</button>
+    </p>;
+  }
+
+  const devices = navigator.mediaDevices;
+  if (!devices || typeof MediaRecorder === 'undefined') {
+    return null;
+  }
+  return <p className='hljs centered'>
+    <button className='button' onClick={() => start(() => devices.getUserMedia({ audio: true }))}>Record audio</button>
+    <button className='button' onClick={() => start(() => devices.getUserMedia({ audio: true, video: true }))}>Record video</button>
+    {devices.getDisplayMedia &&
+      <button className='button' onClick={() => start(() => devices.getDisplayMedia({ video: true, audio: true }))}>Record screen</button>}
+  </p>;
+}
+
+// Mirrors a file from another site, if it allows being read from here.
+const RemoteForm = ({ options }: UploadFormProps) => {
+  const [source, setSource] = useState("");
+
+  const handleSubmit = (event: React.FormEvent<HTMLFormElement>) => {
+    event.preventDefault();
+    if (source.trim() !== "") {
+      encryptMessage(source.trim(), options);
+    }
+  }
+
+  return (
+    <form className='hljs centered remote-upload' onSubmit={handleSubmit}>
+      <input
+        type="url"
+        placeholder="https://example.com/file.txt"
+        value={source}
+        onChange={(e) => setSource(e.target.value)}
+      />
+      <input className="text-upload hljs-meta" type="submit" value="Mirror" />
+    </form>
+  )
+}
+
+function downscaleAndUpload(image: File, maxDimension: number, options: UploadOptions) {
+  renderProgress("downscaling");
+  // Imported lazily, as the wasm module itself imports this file.
+  import('../pkg').then(({ downscale_image }) => downscale_image(image, maxDimension)).then(result => {
+    if (result.downscaled) {
+      console.info(`[js] Downscaled image from ${result.original_width}x${result.original_height} to ${result.width}x${result.height}.`);
+    } else {
+      console.info("[js] Image is already small enough.");
+    }
+    encryptMessage(result.data, options);
+  }).catch(renderMessage);
+}
+
+// Uploads a blob, a list of files to bundle, or the file at a URL.
+function encryptMessage(data: Blob | File[] | string, options: UploadOptions, recording?: boolean) {
+  const worker = new Worker(new URL('./bg_encrypt.ts', import.meta.url));
+  worker.onmessage = (event: MessageEvent<EncryptMessage>) => {
+    const message = event.data;
+    switch (message.type) {
+      case 'init':
+        console.log("[js] Sending data to worker");
+        // Pastes are uploaded to, and linked from, the base path.
+        worker.postMessage({ data, options, recording, location: new URL(basePath(), window.location.href).toString() });
+        break;
+      case 'progress':
+        renderProgress(message.phase, message.loaded, message.total);
+        break;
+      case 'done':
+        worker.terminate();
+        if (options.split_url) {
+          renderSplitUrl(message.url);
+        } else {
+          window.location.assign(message.url);
+        }
+        break;
+      case 'error':
+        worker.terminate();
+        renderMessage(message.message);
+        break;
+    }
+  }
+}
+
+const UploadUi = ({ expirationVariants }) => {
+  const [options, setOptions] = useState<UploadOptions>({});
+  // The paste handler is only registered once, so it reads the latest options
+  // from here.
+  const optionsRef = useRef(options);
+  optionsRef.current = options;
+
+  // Pasting a file, or text outside of the form, uploads it right away.
+  useEffect(() => {
+    let onPaste: ((event: ClipboardEvent) => void) | undefined;
+    let unmounted = false;
+    // Imported lazily, as the wasm module itself imports this file.
+    import('../pkg').then(({ clipboard_data }) => {
+      if (unmounted) {
+        return;
+      }
+      onPaste = (event: ClipboardEvent) => {
+        const data = clipboard_data(event);
+        if (data) {
+          uploadFiles([data as File], optionsRef.current);
+        }
+      };
+      document.addEventListener('paste', onPaste);
+    });
+    return () => {
+      unmounted = true;
+      if (onPaste) {
+        document.removeEventListener('paste', onPaste);
+      }
+    };
+  }, []);
+
+  const handleDrop = (event: React.DragEvent<HTMLElement>) => {
+    if (event.dataTransfer.files.length > 0) {
+      event.preventDefault();
+      uploadFiles(Array.from(event.dataTransfer.files), options);
+    }
+  }
+
+  // Empty fields are ignored by the wasm side.
+  return <main
+    className='hljs centered fullscreen'
+    onDragOver={(event) => event.preventDefault()}
+    onDrop={handleDrop}
+  >
+    <OptionsForm options={options} setOptions={setOptions} expirationVariants={expirationVariants} />
+    <FileForm options={options} />
+    <p>or record something</p>
+    <RecordForm options={options} />
+    <p>or mirror a file from a URL</p>
+    <RemoteForm options={options} />
+    <p>or paste a file anywhere on the page, or your data below</p>
+    <PasteForm options={options} />
+    <RecentPastes />
   </main>;
+}
 
-  ReactDom.render(html, document.body);
+interface RecentPaste {
+  url: string,
+  expires?: string,
+  burn_after_reading: boolean,
+}
+
+// Pastes uploaded from this browser, which are kept in idb by wasm.
+const RecentPastes = () => {
+  const [pastes, setPastes] = useState<RecentPaste[]>([]);
+  useEffect(() => {
+    // Imported lazily, as the wasm module itself imports this file.
+    import('../pkg')
+      .then(({ recent_pastes }) => recent_pastes())
+      .then(setPastes)
+      .catch(() => console.warn("[js] Failed to load recent pastes."));
+  }, []);
+
+  const forget = (url: string) => {
+    import('../pkg')
+      .then(({ forget_paste }) => forget_paste(url))
+      .then(() => setPastes(pastes.filter(paste => paste.url !== url)))
+      .catch(() => console.warn("[js] Failed to forget paste."));
+  };
+
+  if (pastes.length === 0) {
+    return null;
+  }
+
+  return <section className='recent-pastes'>
+    <p>Your recent pastes</p>
+    <ul>
+      {pastes.map(({ url, expires, burn_after_reading }) => {
+        const expiration = burn_after_reading
+          ? "Burns after reading"
+          : expires ? "Expires " + new Date(expires).toLocaleString() : "Doesn't expire";
+        return <li key={url}>
+          <a href={url} className='hljs-meta'>{new URL(url).pathname.slice(basePath().length)}</a>
+          {" \u2014 "}{expiration}{" "}
+          <a onClick={() => forget(url)} className='hljs-comment clickable'>Forget</a>
+        </li>;
+      })}
+    </ul>
+  </section>;
+}
+
+type DecryptMessage =
+  | { type: 'init' }
+  | { type: 'progress', phase: string, loaded?: number, total?: number }
+  | { type: 'password', attempts: number, maxAttempts: number }
+  // Sent before downloading a paste that will use a lot of memory.
+  | { type: 'confirmSize', message: string }
+  // Private pastes are sent back directly instead of being stored in idb.
+  | { type: 'done', mimeType: string, data?: any }
+  | { type: 'error', message: string };
+
+function decryptInWorker(requestUri: string, fragment: string, password?: string, name?: string, language?: string) {
+  const worker = new Worker(new URL('./bg_decrypt.ts', import.meta.url));
+  worker.onmessage = (event: MessageEvent<DecryptMessage>) => {
+    const message = event.data;
+    switch (message.type) {
+      case 'init':
+        console.log("[js] Sending paste to worker");
+        worker.postMessage({ requestUri, fragment, password, path: window.location.pathname });
+        break;
+      case 'progress':
+        renderProgress(message.phase, message.loaded, message.total);
+        break;
+      case 'password': {
+        // The worker keeps the paste, so it doesn't need to be downloaded
+        // again.
+        const remaining = message.maxAttempts - message.attempts;
+        const password = window.prompt(`The password was incorrect. Please try again (${remaining} ${remaining === 1 ? "attempt" : "attempts"} left):`);
+        worker.postMessage({ type: 'password', password });
+        break;
+      }
+      case 'confirmSize':
+        worker.postMessage({ type: 'confirmSize', confirmed: window.confirm(message.message) });
+        break;
+      case 'done':
+        worker.terminate();
+        if (message.data) {
+          renderDecrypted(message.data, message.mimeType, name, language);
+        } else {
+          loadFromDb(message.mimeType, name, language);
+        }
+        break;
+      case 'error':
+        worker.terminate();
+        renderMessage(message.message);
+        break;
+    }
+  }
+}
+
+function createUploadUi(expirationVariants: string[]) {
+  ReactDom.render(<UploadUi expirationVariants={expirationVariants} />, document.body);
 }
 
-function loadFromDb(mimeType: string, name?: string, language?: string) {
+function renderDecrypted(data, mimeType: string, name?: string, language?: string) {
   let resolvedName: string;
   if (name) {
     resolvedName = name;
   } else {
-    const pathName = window.location.pathname;
-    const leafIndex = pathName.indexOf("/");
-    resolvedName = pathName.slice(leafIndex + 1);
+    resolvedName = window.location.pathname.slice(basePath().length);
   }
 
   console.log("[js] Resolved name:", resolvedName);
   console.log("[js] Got language:", language);
   console.log("[js] Got mime type:", mimeType);
 
-  const dbReq = window.indexedDB.open("omegaupload", 1);
+  switch (data.type) {
+    case "string":
+      console.info("[js] Rendering string UI.");
+      // A language from the URL beats one guessed from the paste.
+      createStringPasteUi(data, mimeType, resolvedName, language || data.language);
+      break;
+    case "large_string":
+      console.info("[js] Rendering large string UI.");
+      createLargeStringPasteUi(data, resolvedName);
+      break;
+    case "markdown":
+      console.info("[js] Rendering markdown UI.");
+      createMarkdownPasteUi(data, mimeType, resolvedName);
+      break;
+    case "ansi":
+      console.info("[js] Rendering ANSI UI.");
+      createAnsiPasteUi(data, mimeType, resolvedName);
+      break;
+    case "diff":
+      console.info("[js] Rendering diff UI.");
+      createDiffPasteUi(data, mimeType, resolvedName);
+      break;
+    case "notebook":
+      console.info("[js] Rendering notebook UI.");
+      createNotebookPasteUi(data, mimeType, resolvedName);
+      break;
+    case "json":
+      console.info("[js] Rendering JSON UI.");
+      createJsonPasteUi(data, mimeType, resolvedName);
+      break;
+    case "table":
+      console.info("[js] Rendering table UI.");
+      createTablePasteUi(data, mimeType, resolvedName);
+      break;
+    case "manifest":
+      console.info("[js] Rendering manifest UI.");
+      createManifestPasteUi(data);
+      break;
+    case "blob":
+      console.info("[js] Rendering blob UI.");
+      createBlobPasteUi(data, resolvedName);
+      break;
+    case "image":
+      console.info("[js] Rendering image UI.");
+      createImagePasteUi(data, resolvedName, mimeType);
+      break;
+    case "audio":
+      console.info("[js] Rendering audio UI.");
+      createAudioPasteUi(data, resolvedName, mimeType);
+      break;
+    case "video":
+      console.info("[js] Rendering video UI.");
+      createVideoPasteUi(data, resolvedName, mimeType);
+      break;
+    case "archive":
+      console.info("[js] Rendering archive UI.");
+      createArchivePasteUi(data, resolvedName);
+      break;
+    default:
+      console.info("[js] Rendering unknown UI.");
+      renderMessage("Something went wrong. Try clearing local data.");
+      return;
+  }
+
+  addDeleteButton();
+}
+
+// Pastes uploaded from this browser can be deleted from the viewer.
+function addDeleteButton() {
+  // Imported lazily, as the wasm module itself imports this file.
+  import('../pkg').then(async ({ owns_paste, delete_paste }) => {
+    if (!await owns_paste()) {
+      return;
+    }
+
+    const deleteEle = document.createElement("a");
+    deleteEle.classList.add("delete-paste", "hljs-meta", "clickable");
+    deleteEle.textContent = "Delete now.";
+    deleteEle.onclick = () => {
+      if (window.confirm("Delete this paste? This can't be undone.")) {
+        delete_paste().catch(renderMessage);
+      }
+    };
+    document.body.prepend(deleteEle);
+  });
+}
+
+function loadFromDb(mimeType: string, name?: string, language?: string, fromCache?: boolean) {
+  const dbReq = window.indexedDB.open("omegaupload", 2);
   dbReq.onsuccess = (evt) => {
     const db = (evt.target as IDBRequest).result;
     const obj_store = db
@@ -114,38 +526,21 @@ function loadFromDb(mimeType: string, name?: string, language?: string) {
     const fetchReq = obj_store.get(window.location.pathname);
     fetchReq.onsuccess = (evt) => {
       const data = (evt.target as IDBRequest).result;
-      switch (data.type) {
-        case "string":
-          console.info("[js] Rendering string UI.");
-          createStringPasteUi(data, mimeType, resolvedName, language);
-          break;
-        case "blob":
-          console.info("[js] Rendering blob UI.");
-          createBlobPasteUi(data, resolvedName);
-          break;
-        case "image":
-          console.info("[js] Rendering image UI.");
-          createImagePasteUi(data, resolvedName, mimeType);
-          break;
-        case "audio":
-          console.info("[js] Rendering audio UI.");
-          createAudioPasteUi(data, resolvedName, mimeType);
-          break;
-        case "video":
-          console.info("[js] Rendering video UI.");
-          createVideoPasteUi(data, resolvedName, mimeType);
-          break;
-        case "archive":
-          console.info("[js] Rendering archive UI.");
-          createArchivePasteUi(data, resolvedName);
-          break;
-        default:
-          console.info("[js] Rendering unknown UI.");
-          renderMessage("Something went wrong. Try clearing local data.");
-          break;
+      renderDecrypted(data, mimeType, name, language);
+
+      if (fromCache) {
+        const noticeEle = document.createElement("p");
+        noticeEle.classList.add("cache-notice", "hljs-comment");
+        noticeEle.textContent = "You're offline. This is a copy saved in this browser, and the paste may have since expired or been deleted.";
+        document.body.prepend(noticeEle);
+      }
+
+      // Other pastes are kept so they can be viewed offline, until they're
+      // evicted.
+      if (!data.burn_after_reading) {
+        return;
       }
 
-      // IDB was only used as a temporary medium;
       window.onbeforeunload = (_e) => {
         // See https://link.eddie.sh/NrIIq on why .commit is necessary.
         const transaction = db.transaction("decrypted data", "readwrite");
@@ -166,13 +561,314 @@ function loadFromDb(mimeType: string, name?: string, language?: string) {
   };
 }
 
+function createMarkdownPasteUi(data, mimeType: string, name: string) {
+  // The HTML was sanitized when it was rendered in wasm.
+  const html = <main>
+    <div className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      {data.encoding && <p className='unselectable centered hljs-comment'>Converted from {data.encoding}.</p>}
+      <a href={getObjectUrl([data.data], mimeType)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
+        Download file.
+      </a>
+      <CopyLink text={data.data} />
+      <hr />
+      <article className='markdown' dangerouslySetInnerHTML={{ __html: data.html }} />
+    </div>
+  </main>;
+
+  ReactDom.render(html, document.body);
+}
+
+interface DiffLine {
+  kind: "context" | "added" | "removed",
+  text: string,
+  old?: number,
+  new?: number,
+}
+
+interface FileDiff {
+  old_path: string,
+  new_path: string,
+  hunks: { header: string, lines: DiffLine[] }[],
+}
+
+function createDiffPasteUi(data, mimeType: string, name: string) {
+  const html = <main>
+    <div className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      {data.encoding && <p className='unselectable centered hljs-comment'>Converted from {data.encoding}.</p>}
+      <a href={getObjectUrl([data.data], mimeType)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
+        Download file.
+      </a>
+      <CopyLink text={data.data} />
+      <hr />
+      <DiffView files={data.files} />
+    </div>
+  </main>;
+
+  ReactDom.render(html, document.body);
+}
+
+const DiffView = ({ files }: { files: FileDiff[] }) => {
+  const [sideBySide, setSideBySide] = useState(false);
+  return <>
+    <p className='unselectable centered'>
+      <button className={sideBySide ? 'button' : 'button hljs-meta'} onClick={() => setSideBySide(false)}>Inline</button>
+      <button className={sideBySide ? 'button hljs-meta' : 'button'} onClick={() => setSideBySide(true)}>Side by side</button>
+    </p>
+    {files.map((file, i) => <table key={i} className='diff'>
+      <thead>
+        <tr>
+          <th colSpan={sideBySide ? 4 : 3}>
+            {file.old_path === file.new_path ? file.new_path : `${file.old_path} → ${file.new_path}`}
+          </th>
+        </tr>
+      </thead>
+      {file.hunks.map((hunk, j) => <tbody key={j}>
+        <tr className='diff-hunk hljs-comment'><td colSpan={sideBySide ? 4 : 3}>{hunk.header}</td></tr>
+        {sideBySide ? <SideBySideLines lines={hunk.lines} /> : hunk.lines.map((line, k) =>
+          <tr key={k} className={`diff-${line.kind}`}>
+            <td className='diff-number unselectable'>{line.old}</td>
+            <td className='diff-number unselectable'>{line.new}</td>
+            <td className='diff-text'>{line.kind === "added" ? "+" : line.kind === "removed" ? "-" : " "}{line.text}</td>
+          </tr>)}
+      </tbody>)}
+    </table>)}
+  </>;
+}
+
+// Pairs up runs of removed and added lines, so that changed lines are shown
+// next to what they were changed to.
+const SideBySideLines = ({ lines }: { lines: DiffLine[] }) => {
+  const rows: [DiffLine | undefined, DiffLine | undefined][] = [];
+  let i = 0;
+  while (i < lines.length) {
+    if (lines[i].kind === "context") {
+      rows.push([lines[i], lines[i]]);
+      i++;
+      continue;
+    }
+    const removed: DiffLine[] = [];
+    const added: DiffLine[] = [];
+    for (; i < lines.length && lines[i].kind === "removed"; i++) {
+      removed.push(lines[i]);
+    }
+    for (; i < lines.length && lines[i].kind === "added"; i++) {
+      added.push(lines[i]);
+    }
+    for (let j = 0; j < Math.max(removed.length, added.length); j++) {
+      rows.push([removed[j], added[j]]);
+    }
+  }
+
+  return <>
+    {rows.map(([old, current], k) => <tr key={k}>
+      <td className='diff-number unselectable'>{old?.old}</td>
+      <td className={`diff-text diff-${old?.kind ?? "empty"}`}>{old?.text}</td>
+      <td className='diff-number unselectable'>{current?.new}</td>
+      <td className={`diff-text diff-${current?.kind ?? "empty"}`}>{current?.text}</td>
+    </tr>)}
+  </>;
+}
+
+interface AnsiSpan {
+  text: string,
+  fg?: string,
+  bg?: string,
+  bold?: boolean,
+  dim?: boolean,
+  italic?: boolean,
+  underline?: boolean,
+}
+
+function createAnsiPasteUi(data, mimeType: string, name: string) {
+  const spans = (data.spans as AnsiSpan[]).map((span, i) =>
+    <span key={i} style={{
+      color: span.fg,
+      backgroundColor: span.bg,
+      fontWeight: span.bold ? 'bold' : undefined,
+      opacity: span.dim ? 0.6 : undefined,
+      fontStyle: span.italic ? 'italic' : undefined,
+      textDecoration: span.underline ? 'underline' : undefined,
+    }}>{span.text}</span>);
+
+  const html = <main>
+    <pre className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      {data.encoding && <p className='unselectable centered hljs-comment'>Converted from {data.encoding}.</p>}
+      <a href={getObjectUrl([data.data], mimeType)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
+        Download file.
+      </a>
+      <CopyLink text={data.data} />
+      <hr />
+      <code className='ansi'>{spans}</code>
+    </pre>
+  </main>;
+
+  ReactDom.render(html, document.body);
+}
+
+function createJsonPasteUi(data, mimeType: string, name: string) {
+  const html = <main>
+    <div className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      {data.encoding && <p className='unselectable centered hljs-comment'>Converted from {data.encoding}.</p>}
+      <a href={getObjectUrl([data.data], mimeType)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
+        Download file.
+      </a>
+      <CopyLink text={data.data} />
+      <hr />
+      <JsonView source={data.data} pretty={data.pretty} />
+    </div>
+  </main>;
+
+  ReactDom.render(html, document.body);
+}
+
+type JsonMode = "formatted" | "tree" | "raw";
+
+const JsonView = ({ source, pretty }: { source: string, pretty: string }) => {
+  const [mode, setMode] = useState<JsonMode>("formatted");
+  // The JSON was checked in wasm, so highlighting and parsing can't fail.
+  const highlighted = useMemo(() => hljs.highlight(pretty, { language: 'json' }).value, [pretty]);
+  // Only parsed once the tree is shown, as it's slow for large pastes.
+  const [tree, setTree] = useState<any>(undefined);
+
+  const modeButton = (target: JsonMode, label: string) =>
+    <button
+      className={mode === target ? 'button hljs-meta' : 'button'}
+      onClick={() => {
+        if (target === "tree" && tree === undefined) {
+          setTree(JSON.parse(source));
+        }
+        setMode(target);
+      }}>
+      {label}
+    </button>;
+
+  let view;
+  if (mode === "tree") {
+    view = <div className='json-tree'><JsonNode value={tree} open /></div>;
+  } else if (mode === "formatted") {
+    // hljs escapes the JSON, so this is safe.
+    view = <pre><code className='hljs language-json' dangerouslySetInnerHTML={{ __html: highlighted }} /></pre>;
+  } else {
+    view = <pre><code>{source}</code></pre>;
+  }
+
+  return <>
+    <p className='unselectable centered'>
+      {modeButton("formatted", "Formatted")}
+      {modeButton("tree", "Tree")}
+      {modeButton("raw", "Raw")}
+    </p>
+    {view}
+  </>;
+}
+
+// A JSON value whose objects and arrays can be collapsed. Only the top level
+// is expanded at first.
+const JsonNode = ({ name, value, open }: { name?: string, value: any, open?: boolean }) => {
+  const label = name !== undefined && <span className='hljs-attr'>{JSON.stringify(name)}: </span>;
+  if (value === null || typeof value !== "object") {
+    const className = typeof value === "string" ? 'hljs-string' : 'hljs-literal';
+    return <div>{label}<span className={className}>{JSON.stringify(value)}</span></div>;
+  }
+
+  const entries = Array.isArray(value)
+    ? value.map((item, i) => [String(i), item])
+    : Object.entries(value);
+  const summary = Array.isArray(value) ? `[${entries.length} items]` : `{${entries.length} keys}`;
+  return <details open={open}>
+    <summary>{label}<span className='hljs-comment'>{summary}</span></summary>
+    {entries.map(([key, item]) => <JsonNode key={key} name={Array.isArray(value) ? undefined : key} value={item} />)}
+  </details>;
+}
+
+function createNotebookPasteUi(data, mimeType: string, name: string) {
+  // The HTML was sanitized when it was rendered in wasm.
+  const html = <main>
+    <div className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      {data.encoding && <p className='unselectable centered hljs-comment'>Converted from {data.encoding}.</p>}
+      <a href={getObjectUrl([data.data], mimeType)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
+        Download file.
+      </a>
+      <hr />
+      <article className='notebook markdown' dangerouslySetInnerHTML={{ __html: data.html }} />
+    </div>
+  </main>;
+
+  ReactDom.render(html, document.body);
+
+  document.querySelectorAll('.notebook code[class^="language-"]').forEach(code => {
+    hljs.highlightElement(code);
+  });
+}
+
+function createTablePasteUi(data, mimeType: string, name: string) {
+  const [header, ...rows] = data.rows as string[][];
+  const html = <main>
+    <div className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      {data.encoding && <p className='unselectable centered hljs-comment'>Converted from {data.encoding}.</p>}
+      <a href={getObjectUrl([data.data], mimeType)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
+        Download file.
+      </a>
+      <CopyLink text={data.data} />
+      <hr />
+      <table className='data-table'>
+        <thead>
+          <tr>{header.map((cell, i) => <th key={i}>{cell}</th>)}</tr>
+        </thead>
+        <tbody>
+          {rows.map((row, i) => <tr key={i}>{row.map((cell, j) => <td key={j}>{cell}</td>)}</tr>)}
+        </tbody>
+      </table>
+      {data.truncated && <p className='unselectable centered'>Only the first {data.rows.length} rows are shown.</p>}
+    </div>
+  </main>;
+
+  ReactDom.render(html, document.body);
+}
+
+// Lists the pastes of a bundle. Each is opened from its own link, so that it's
+// only downloaded and decrypted when it's wanted.
+function createManifestPasteUi(data) {
+  const members = data.members as { name: string, size: string, link: string }[];
+  const html = <main>
+    <div className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      <p className='unselectable centered'>
+        {members.length === 1 ? "1 file was" : members.length + " files were"} uploaded together.
+      </p>
+      <hr />
+      <table className='data-table'>
+        <tbody>
+          {members.map(({ name, size, link }, i) => <tr key={i}>
+            <td><a href={link}>{name}</a></td>
+            <td className='align-right'>
+              <a href={link} target='_blank' rel='noopener noreferrer' className='hljs-comment'>Open in new tab</a>
+            </td>
+            <td className='align-right hljs-number'>{size}</td>
+          </tr>)}
+        </tbody>
+      </table>
+    </div>
+  </main>;
+
+  ReactDom.render(html, document.body);
+}
+
 function createStringPasteUi(data, mimeType: string, name: string, lang?: string, skipSyntaxHighlight?: boolean) {
   const html = <main>
     <pre className='paste'>
-      <p className='unselectable centered'>{data.expiration}</p>
-      <a href={getObjectUrl([data.data], mimeType)} download={name} className='hljs-meta centered'>
+      <p className='unselectable centered'><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      {data.encoding && <p className='unselectable centered hljs-comment'>Converted from {data.encoding}.</p>}
+      <a href={getObjectUrl([data.data], mimeType)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
         Download file.
       </a>
+      <CopyLink text={data.data} />
       <hr />
       <code>
         {data.data}
@@ -237,11 +933,90 @@ function createStringPasteUi(data, mimeType: string, name: string, lang?: string
   (hljs as any).initLineNumbersOnLoad();
 }
 
+// Text too large to be a single string is kept as a blob, and read from wasm
+// a few lines at a time as it's scrolled through. It isn't highlighted.
+function createLargeStringPasteUi(paste, name: string) {
+  const html = <main>
+    <section className='paste'>
+      <p className='unselectable centered'><ExpirationNotice paste={paste} /> <VerificationBadge paste={paste} /> <OpenElsewhere paste={paste} /></p>
+      {paste.encoding && <p className='unselectable centered hljs-comment'>Converted from {paste.encoding}.</p>}
+      <a href={getObjectUrl(paste.data, paste.data.type)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>
+        Download file.
+      </a>
+      <hr />
+      <LargeText text={paste.data} />
+    </section>
+  </main>;
+
+  ReactDom.render(html, document.body);
+}
+
+const TEXT_LINE_HEIGHT = 20;
+const TEXT_PAGE_SIZE = 500;
+
+// Like ArchiveListing, only the lines scrolled into view are rendered, and
+// they're fetched from wasm a page at a time.
+const LargeText = ({ text }: { text: Blob }) => {
+  const [total, setTotal] = useState<number | null>(null);
+  const [pages, setPages] = useState<Map<number, string[]>>(new Map());
+  const [scrollTop, setScrollTop] = useState(0);
+  const [viewHeight, setViewHeight] = useState(window.innerHeight);
+  const requested = useRef(new Set<number>());
+  const textRef = useRef<HTMLPreElement>(null);
+
+  useEffect(() => {
+    const updateHeight = () => setViewHeight(textRef.current?.clientHeight ?? window.innerHeight);
+    updateHeight();
+    window.addEventListener("resize", updateHeight);
+    return () => window.removeEventListener("resize", updateHeight);
+  }, []);
+
+  const first = Math.floor(scrollTop / TEXT_LINE_HEIGHT);
+  const last = Math.min(total ?? TEXT_PAGE_SIZE, Math.ceil((scrollTop + viewHeight) / TEXT_LINE_HEIGHT) + 1);
+
+  useEffect(() => {
+    const firstPage = Math.floor(first / TEXT_PAGE_SIZE);
+    const lastPage = Math.floor(Math.max(first, last - 1) / TEXT_PAGE_SIZE);
+    for (let page = firstPage; page <= lastPage; page++) {
+      if (requested.current.has(page)) {
+        continue;
+      }
+      requested.current.add(page);
+      // Imported lazily, as the wasm module itself imports this file.
+      import('../pkg')
+        .then(({ text_lines }) => text_lines(text, page * TEXT_PAGE_SIZE, TEXT_PAGE_SIZE))
+        .then(({ total, lines }) => {
+          setTotal(total);
+          setPages(pages => new Map(pages).set(page, lines));
+        })
+        .catch(message => alert(message));
+    }
+  }, [text, first, last]);
+
+  const lines = [];
+  for (let i = first; i < last; i++) {
+    const line = pages.get(Math.floor(i / TEXT_PAGE_SIZE))?.[i % TEXT_PAGE_SIZE];
+    lines.push(<div key={i} className='large-text-line'>
+      <span className='unselectable hljs-comment large-text-number'>{i + 1}</span>{line ?? ""}
+    </div>);
+  }
+
+  return <pre
+    className='large-text hljs'
+    ref={textRef}
+    onScroll={event => setScrollTop(event.currentTarget.scrollTop)}
+  >
+    <div style={{ height: first * TEXT_LINE_HEIGHT }} />
+    {lines}
+    <div style={{ height: Math.max(0, (total ?? 0) - last) * TEXT_LINE_HEIGHT }} />
+  </pre>;
+}
+
 function createBlobPasteUi(data, name: string) {
   const html = <main className='hljs centered fullscreen'>
     <div className='centered'>
-      <p>{data.expiration}</p>
-      <a href={getObjectUrl(data.data, name)} download={name} className='hljs-meta'>
+      <p><ExpirationNotice paste={data} /> <VerificationBadge paste={data} /> <OpenElsewhere paste={data} /></p>
+      <a href={getObjectUrl(data.data, name)} download={name} onClick={downloadCachedPaste} className='hljs-meta'>
         Download binary file.
       </a>
     </div>
@@ -256,54 +1031,64 @@ function createBlobPasteUi(data, name: string) {
   ReactDom.render(html, document.body);
 }
 
-function createImagePasteUi({ expiration, data, file_size }, name: string, mimeType: string) {
-  createMultiMediaPasteUi("img", expiration, data, name, mimeType, (downloadEle, imgEle) => {
-    imgEle.onload = () => {
-      const width = imgEle.naturalWidth || imgEle.width;
-      const height = imgEle.naturalHeight || imgEle.height;
+function createImagePasteUi(paste, name: string, mimeType: string) {
+  const { data, file_size, width, height, has_gps } = paste;
+  createMultiMediaPasteUi("img", paste, data, name, mimeType, (downloadEle, imgEle) => {
+    const setText = (width: number, height: number) => {
       downloadEle.textContent = "Download " + file_size + " \u2014 " + width + " by " + height;
+    };
+    if (width && height) {
+      setText(width, height);
+    } else {
+      imgEle.onload = () => setText(imgEle.naturalWidth || imgEle.width, imgEle.naturalHeight || imgEle.height);
+    }
+
+    if (has_gps) {
+      const warningEle = document.createElement("p");
+      warningEle.classList.add("hljs-comment");
+      warningEle.textContent = "This image contains the location it was taken at.";
+      downloadEle.after(warningEle);
     }
   });
 }
 
-function createAudioPasteUi({ expiration, data }, name: string, mimeType: string) {
-  createMultiMediaPasteUi("audio", expiration, data, name, mimeType, "Download");
+function createAudioPasteUi(data, name: string, mimeType: string) {
+  createMultiMediaPasteUi("audio", data, data.data, name, mimeType, describeMedia(data));
 }
 
-function createVideoPasteUi({ expiration, data }, name: string, mimeType: string) {
-  createMultiMediaPasteUi("video", expiration, data, name, mimeType, "Download");
+function createVideoPasteUi(data, name: string, mimeType: string) {
+  createMultiMediaPasteUi("video", data, data.data, name, mimeType, describeMedia(data));
 }
 
-function createArchivePasteUi({ expiration, data, entries }, name: string) {
-  // Because it's a stable sort, we can first sort by name (to get all folder
-  // items grouped together) and then sort by if there's a / or not.
-  entries.sort((a, b) => {
-    return a.name.toLowerCase().localeCompare(b.name.toLowerCase());
-  });
+// Describes whatever metadata wasm could read from an audio or video paste.
+function describeMedia({ duration, codec, width, height }): string {
+  const details: string[] = [];
+  if (duration) {
+    const seconds = Math.round(duration);
+    const hours = Math.floor(seconds / 3600);
+    const minutes = Math.floor(seconds / 60) % 60;
+    const time = String(minutes).padStart(hours ? 2 : 1, "0") + ":" + String(seconds % 60).padStart(2, "0");
+    details.push(hours ? hours + ":" + time : time);
+  }
+  if (codec) {
+    details.push(codec);
+  }
+  if (width && height) {
+    details.push(width + " by " + height);
+  }
+  return ["Download", ...details].join(" \u2014 ");
+}
 
-  // This doesn't get sub directories and their folders, but hey it's close
-  // enough
-  entries.sort((a, b) => {
-    return b.name.includes("/") - a.name.includes("/");
-  });
+function createArchivePasteUi(paste, name: string) {
+  const { data } = paste;
 
   const html = <main>
     <section className='paste'>
-      <p className='centered'>{expiration}</p>
-      <a href={getObjectUrl(data)} download={name} className='hljs-meta centered'>Download</a>
+      <p className='centered'><ExpirationNotice paste={paste} /> <VerificationBadge paste={paste} /> <OpenElsewhere paste={paste} /></p>
+      <a href={getObjectUrl(data)} download={name} onClick={downloadCachedPaste} className='hljs-meta centered'>Download</a>
       <hr />
-      <table className='archive-table'>
-        <thead>
-          <tr className='hljs-title'><th>Name</th><th className='align-right'>File Size</th></tr>
-        </thead>
-        <tbody>
-          {
-            entries.map(({ name, file_size }) => {
-              return <tr><td>{name}</td><td className='align-right hljs-number'>{file_size}</td></tr>;
-            })
-          }
-        </tbody>
-      </table>
+      <ArchiveListing archive={data} />
+      <section id='archive-preview' />
     </section>
   </main>;
 
@@ -311,7 +1096,138 @@ function createArchivePasteUi({ expiration, data, entries }, name: string) {
 
 }
 
-function createMultiMediaPasteUi(tag, expiration, data, name: string, mimeType: string, on_create?: Function | string) {
+const ARCHIVE_ROW_HEIGHT = 24;
+const ARCHIVE_PAGE_SIZE = 200;
+
+// Lists the entries of an archive, fetching them from wasm a page at a time
+// and only rendering the rows that are scrolled into view, so that archives
+// with tens of thousands of entries don't lock up the page.
+const ArchiveListing = ({ archive }: { archive: Blob }) => {
+  const [total, setTotal] = useState<number | null>(null);
+  const [pages, setPages] = useState<Map<number, { name: string, file_size: number }[]>>(new Map());
+  const [scrollTop, setScrollTop] = useState(0);
+  const [viewHeight, setViewHeight] = useState(window.innerHeight);
+  const requested = useRef(new Set<number>());
+  const listingRef = useRef<HTMLDivElement>(null);
+
+  useEffect(() => {
+    const updateHeight = () => setViewHeight(listingRef.current?.clientHeight ?? window.innerHeight);
+    updateHeight();
+    window.addEventListener("resize", updateHeight);
+    return () => window.removeEventListener("resize", updateHeight);
+  }, []);
+
+  const first = Math.floor(scrollTop / ARCHIVE_ROW_HEIGHT);
+  const last = Math.min(total ?? ARCHIVE_PAGE_SIZE, Math.ceil((scrollTop + viewHeight) / ARCHIVE_ROW_HEIGHT) + 1);
+
+  useEffect(() => {
+    const firstPage = Math.floor(first / ARCHIVE_PAGE_SIZE);
+    const lastPage = Math.floor(Math.max(first, last - 1) / ARCHIVE_PAGE_SIZE);
+    for (let page = firstPage; page <= lastPage; page++) {
+      if (requested.current.has(page)) {
+        continue;
+      }
+      requested.current.add(page);
+      // Imported lazily, as the wasm module itself imports this file.
+      import('../pkg')
+        .then(({ archive_entries }) => archive_entries(archive, page * ARCHIVE_PAGE_SIZE, ARCHIVE_PAGE_SIZE))
+        .then(({ total, entries }) => {
+          setTotal(total);
+          setPages(pages => new Map(pages).set(page, entries));
+        })
+        .catch(message => alert(message));
+    }
+  }, [archive, first, last]);
+
+  const rows = [];
+  for (let i = first; i < last; i++) {
+    const entry = pages.get(Math.floor(i / ARCHIVE_PAGE_SIZE))?.[i % ARCHIVE_PAGE_SIZE];
+    if (!entry) {
+      rows.push(<tr key={i}><td colSpan={3} /></tr>);
+      continue;
+    }
+
+    const { name: path, file_size } = entry;
+    // Directories have no contents to extract.
+    const entryName = path.endsWith("/")
+      ? path
+      : <a className='clickable' onClick={() => extractEntry(archive, path, true)}>{path}</a>;
+    const previewLink = path.endsWith("/")
+      ? null
+      : <a className='clickable hljs-comment' onClick={() => extractEntry(archive, path, false)}>View</a>;
+    rows.push(<tr key={i}>
+      <td>{entryName}</td>
+      <td className='align-right'>{previewLink}</td>
+      <td className='align-right hljs-number'>{file_size}</td>
+    </tr>);
+  }
+
+  return <div
+    className='archive-listing'
+    ref={listingRef}
+    onScroll={event => setScrollTop(event.currentTarget.scrollTop)}
+  >
+    <table className='archive-table'>
+      <thead>
+        <tr className='hljs-title'>
+          <th>Name{total === null ? null : ` (${total} entries)`}</th><th /><th className='align-right'>File Size</th>
+        </tr>
+      </thead>
+      <tbody>
+        <tr style={{ height: first * ARCHIVE_ROW_HEIGHT }} />
+        {rows}
+        <tr style={{ height: Math.max(0, (total ?? 0) - last) * ARCHIVE_ROW_HEIGHT }} />
+      </tbody>
+    </table>
+  </div>;
+}
+
+// Extracts a single file from an archive in wasm, either downloading it or
+// previewing it below the entry listing.
+function extractEntry(archive: Blob, path: string, download: boolean) {
+  // Imported lazily, as the wasm module itself imports this file.
+  import('../pkg').then(({ extract_entry }) => extract_entry(archive, path)).then((file: Blob) => {
+    const url = URL.createObjectURL(file);
+    const fileName = path.slice(path.lastIndexOf("/") + 1);
+    if (download) {
+      saveUrl(url, fileName);
+      return;
+    }
+
+    ReactDom.render(<EntryPreview file={file} url={url} fileName={fileName} />, document.getElementById('archive-preview'));
+  }).catch(message => alert(message));
+}
+
+function EntryPreview({ file, url, fileName }: { file: Blob, url: string, fileName: string }) {
+  const [text, setText] = useState<string | null>(null);
+  useEffect(() => {
+    setText(null);
+    if (file.type.startsWith("text/")) {
+      file.text().then(setText);
+    }
+  }, [file]);
+
+  let preview;
+  if (file.type.startsWith("image/")) {
+    preview = <img src={url} />;
+  } else if (file.type.startsWith("audio/")) {
+    preview = <audio src={url} controls />;
+  } else if (file.type.startsWith("video/")) {
+    preview = <video src={url} controls />;
+  } else if (text !== null) {
+    preview = <pre className='hljs'>{text}</pre>;
+  } else {
+    preview = <p className='display-anyways hljs-comment' onClick={() => file.text().then(setText)}>Display as text?</p>;
+  }
+
+  return <>
+    <hr />
+    <a href={url} download={fileName} className='hljs-meta centered'>Download {fileName}</a>
+    {preview}
+  </>;
+}
+
+function createMultiMediaPasteUi(tag, paste, data, name: string, mimeType: string, on_create?: Function | string) {
   const bodyEle = document.body;
   bodyEle.textContent = '';
 
@@ -323,7 +1239,7 @@ function createMultiMediaPasteUi(tag, expiration, data, name: string, mimeType:
   const downloadLink = getObjectUrl(data, mimeType);
 
   const expirationEle = document.createElement("p");
-  expirationEle.textContent = expiration;
+  ReactDom.render(<><ExpirationNotice paste={paste} /> <VerificationBadge paste={paste} /> <OpenElsewhere paste={paste} /></>, expirationEle);
   mainEle.appendChild(expirationEle);
 
   const mediaEle = document.createElement(tag);
@@ -334,6 +1250,7 @@ function createMultiMediaPasteUi(tag, expiration, data, name: string, mimeType:
   const downloadEle = document.createElement("a");
   downloadEle.href = downloadLink;
   downloadEle.download = name;
+  downloadEle.addEventListener("click", downloadCachedPaste);
   downloadEle.classList.add("hljs-meta");
   mainEle.appendChild(downloadEle);
 
@@ -355,9 +1272,233 @@ function renderMessage(message) {
   );
 }
 
+// Shows the link to a new paste and its key separately, so that they can be
+// sent over different channels.
+function renderSplitUrl(url: string) {
+  // Imported lazily, as the wasm module itself imports this file.
+  import('../pkg').then(({ split_url }) => {
+    const split = split_url(url);
+    ReactDom.render(
+      <main className='hljs centered fullscreen split-url'>
+        <p>Link</p>
+        <CopyField text={split.url} />
+        <p>Key</p>
+        <CopyField text={split.key} />
+      </main>,
+      document.body,
+    );
+  });
+}
+
+const CopyField = ({ text }: { text: string }) => {
+  const [copied, setCopied] = useState(false);
+  const copy = () => {
+    navigator.clipboard.writeText(text)
+      .then(() => setCopied(true))
+      .catch(() => renderMessage("Failed to copy to the clipboard."));
+  };
+
+  return <div className='copy-field'>
+    <input type="text" readOnly value={text} onFocus={(e) => e.target.select()} />
+    <a onClick={copy} className='hljs-meta clickable'>{copied ? "Copied." : "Copy"}</a>
+  </div>;
+}
+
+function confirmBurn(): Promise<void> {
+  return new Promise(resolve => {
+    ReactDom.render(
+      <main className='hljs centered fullscreen'>
+        <p>This paste will be deleted once it's viewed.</p>
+        <button className='hljs-meta' onClick={() => resolve()}>View paste</button>
+      </main>,
+      document.body,
+    );
+  });
+}
+
+// Counts down until a time-locked paste can be viewed, then resolves.
+function awaitUnlock(unlocksAt: number): Promise<void> {
+  return new Promise(resolve => {
+    ReactDom.render(
+      <main className='hljs centered fullscreen'>
+        <p>This paste can't be viewed until {new Date(unlocksAt).toLocaleString()}.</p>
+        <Countdown until={unlocksAt} onDone={resolve} />
+      </main>,
+      document.body,
+    );
+  });
+}
+
+const Countdown = ({ until, onDone }: { until: number, onDone: () => void }) => {
+  const [now, setNow] = useState(Date.now());
+  useEffect(() => {
+    if (now >= until) {
+      onDone();
+      return;
+    }
+    const timeout = setTimeout(() => setNow(Date.now()), Math.min(1000, until - now));
+    return () => clearTimeout(timeout);
+  }, [now]);
+
+  const seconds = Math.max(0, Math.ceil((until - now) / 1000));
+  const days = Math.floor(seconds / 86400);
+  const clock = [Math.floor(seconds / 3600) % 24, Math.floor(seconds / 60) % 60, seconds % 60]
+    .map(part => part.toString().padStart(2, "0"))
+    .join(":");
+  return <p className='hljs-meta'>{days > 0 ? `${days}d ${clock}` : clock}</p>;
+}
+
+const PROGRESS_MESSAGES = {
+  "bundling": "Bundling files...",
+  "downscaling": "Downscaling image...",
+  "encrypting": "Encrypting paste...",
+  "uploading": "Uploading paste...",
+  "loading": "Loading paste...",
+  "downloading": "Downloading paste...",
+  "deriving-key": "Checking password...",
+  "decrypting": "Decrypting paste...",
+  "processing": "Preparing paste...",
+};
+
+function formatBytes(bytes: number) {
+  const units = ["B", "KiB", "MiB", "GiB"];
+  let unit = 0;
+  while (bytes >= 1024 && unit < units.length - 1) {
+    bytes /= 1024;
+    unit++;
+  }
+  return `${bytes.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+}
+
+function renderProgress(phase: string, loaded?: number, total?: number) {
+  const message = PROGRESS_MESSAGES[phase] ?? "Loading paste...";
+
+  let details = null;
+  if (loaded !== undefined) {
+    details = total
+      ? <>
+        <progress value={loaded} max={total} />
+        <p>{formatBytes(loaded)} of {formatBytes(total)}</p>
+      </>
+      : <p>{formatBytes(loaded)}</p>;
+  }
+
+  ReactDom.render(
+    <main className='hljs centered fullscreen'>
+      <p>{message}</p>
+      {details}
+    </main>,
+    document.body,
+  );
+}
+
+// Counts down to when a paste expires. Pastes that don't expire, burn after
+// reading pastes, and pastes cached by older versions only show the text from
+// wasm.
+const ExpirationNotice = ({ paste }) => {
+  const [now, setNow] = useState(Date.now());
+  useEffect(() => {
+    if (!paste.expires || paste.burn_after_reading) {
+      return;
+    }
+    const timer = setInterval(() => setNow(Date.now()), 1000);
+    return () => clearInterval(timer);
+  }, [paste.expires, paste.burn_after_reading]);
+
+  if (!paste.expires || paste.burn_after_reading) {
+    return <>{paste.expiration}</>;
+  }
+
+  const expires = new Date(paste.expires);
+  const remaining = Math.max(0, Math.floor((expires.getTime() - now) / 1000));
+  const hours = Math.floor(remaining / 3600);
+  const minutes = String(Math.floor(remaining / 60) % 60).padStart(2, "0");
+  const seconds = String(remaining % 60).padStart(2, "0");
+  return <time dateTime={paste.expires} title={expires.toLocaleString()}>
+    {paste.expiration} ({hours}:{minutes}:{seconds})
+  </time>;
+}
+
+// Shows whether the paste matched the hash in its URL. Links without a hash,
+// and pastes cached by older versions, show nothing.
+const VerificationBadge = ({ paste }) => {
+  if (paste.verified === undefined || paste.verified === null) {
+    return null;
+  }
+  return paste.verified
+    ? <span className='verification verified' title="The paste matches the hash in its link.">Contents verified.</span>
+    : <span className='verification tampered' title="The paste doesn't match the hash in its link.">Warning: contents don't match the link!</span>;
+}
+
+// Opens the paste on another instance, such as a mirror, with the same link
+// otherwise. Burn after reading pastes are gone once they've been read, so
+// there's nothing to open elsewhere.
+const OpenElsewhere = ({ paste }) => {
+  if (paste.burn_after_reading) {
+    return null;
+  }
+
+  const open = () => {
+    const instance = window.prompt("Which instance should this paste be opened on?");
+    if (!instance) {
+      return;
+    }
+    // Imported lazily, as the wasm module itself imports this file.
+    import('../pkg')
+      .then(({ link_on_instance }) => link_on_instance(instance.includes("://") ? instance : "https://" + instance))
+      .then((url) => window.location.assign(url))
+      .catch((e) => renderMessage(`Can't open this paste there: ${e}`));
+  };
+
+  return <a onClick={open} className='hljs-comment clickable'>Open on another instance.</a>;
+}
+
+const CopyLink = ({ text }: { text: string }) => {
+  const [copied, setCopied] = useState(false);
+  const copy = () => {
+    // Imported lazily, as the wasm module itself imports this file.
+    import('../pkg').then(({ copy_cached_text }) => copy_cached_text())
+      // Private pastes aren't cached, so they're copied from the page instead.
+      .catch(() => navigator.clipboard.writeText(text))
+      .then(() => setCopied(true))
+      .catch(() => renderMessage("Failed to copy the paste to the clipboard."));
+  };
+
+  return <a onClick={copy} className='hljs-meta centered clickable'>
+    {copied ? "Copied." : "Copy to clipboard."}
+  </a>;
+}
+
+// Saves the cached paste under its original name, as the link's name may just
+// be the short code. Private pastes aren't cached, so the link is followed as
+// is for them.
+function downloadCachedPaste(event: React.MouseEvent<HTMLAnchorElement> | MouseEvent) {
+  const link = event.currentTarget as HTMLAnchorElement;
+  event.preventDefault();
+  // Imported lazily, as the wasm module itself imports this file.
+  import('../pkg').then(({ cached_paste_download }) => cached_paste_download()).then(({ url, name }) => {
+    saveUrl(url, name);
+    // Revoking the URL immediately may cancel the download.
+    setTimeout(() => URL.revokeObjectURL(url), 60 * 1000);
+  }).catch(() => saveUrl(link.href, link.download));
+}
+
+function saveUrl(url: string, name: string) {
+  const link = document.createElement("a");
+  link.href = url;
+  link.download = name;
+  link.click();
+}
+
+// The path the frontend is hosted under, ending with a slash.
+function basePath(): string {
+  const basePath = document.querySelector("meta[name='omegaupload-base-path']")?.getAttribute("content") || "/";
+  return basePath.endsWith("/") ? basePath : basePath + "/";
+}
+
 function getObjectUrl(data, mimeType?: string) {
   return URL.createObjectURL(new Blob([data], { type: mimeType }));
 }
 
 
-export { renderMessage, createUploadUi, loadFromDb };
+export { renderMessage, renderProgress, confirmBurn, awaitUnlock, createUploadUi, loadFromDb, decryptInWorker };
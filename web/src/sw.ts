@@ -0,0 +1,58 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+// Caches the frontend itself, so that pastes saved in this browser can be
+// viewed while offline. Pastes are only ever saved in IndexedDB by the
+// frontend, never here.
+
+const CACHE_NAME = "omegaupload-frontend";
+
+addEventListener('install', () => {
+  (self as any).skipWaiting();
+});
+
+addEventListener('activate', (event: any) => {
+  event.waitUntil((self as any).clients.claim());
+});
+
+addEventListener('fetch', (event: any) => {
+  const request: Request = event.request;
+  const url = new URL(request.url);
+  if (request.method !== 'GET' || url.origin !== location.origin) {
+    return;
+  }
+
+  // The worker is served from the base path, so that's its scope.
+  const scope: string = (self as any).registration.scope;
+  let key: Request | string;
+  if (request.mode === 'navigate') {
+    // Every page is the same, so this doesn't record which pastes were viewed.
+    key = scope;
+  } else if (url.href.startsWith(scope + 'static/')) {
+    key = request;
+  } else {
+    return;
+  }
+
+  event.respondWith(fetch(request).then(response => {
+    if (response.ok) {
+      const copy = response.clone();
+      caches.open(CACHE_NAME).then(cache => cache.put(key, copy));
+    }
+    return response;
+  }).catch(() => caches.match(key).then(cached => cached || Response.error())));
+});
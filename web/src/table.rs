@@ -0,0 +1,108 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Previews CSV and TSV pastes as tables.
+
+use csv::ReaderBuilder;
+use serde::Serialize;
+
+/// Only this many rows are previewed, as rendering more makes the page slow.
+/// The full paste can still be downloaded.
+const MAX_ROWS: usize = 1000;
+
+#[derive(Clone, Serialize)]
+pub struct Table {
+    /// The first row is the header.
+    pub rows: Vec<Vec<String>>,
+    /// Whether there were more than `MAX_ROWS` rows.
+    pub truncated: bool,
+}
+
+/// Returns the delimiter of a paste that should be previewed as a table, based
+/// on its name or guessed mime type.
+pub fn delimiter(name_hint: Option<&str>, mime_type: &str) -> Option<u8> {
+    let extension = name_hint
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match (extension.as_deref(), mime_type) {
+        (Some("csv"), _) | (_, "text/csv") => Some(b','),
+        (Some("tsv" | "tab"), _) | (_, "text/tab-separated-values") => Some(b'\t'),
+        _ => None,
+    }
+}
+
+/// Parses the first `MAX_ROWS` rows of a table. Returns `None` if the table is
+/// malformed, such as when rows have different numbers of fields, in which
+/// case the paste should be shown as plain text instead.
+pub fn parse(text: &str, delimiter: u8) -> Option<Table> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .from_reader(text.as_bytes());
+
+    let mut rows = Vec::new();
+    let mut records = reader.records();
+    for record in records.by_ref().take(MAX_ROWS) {
+        rows.push(record.ok()?.iter().map(ToString::to_string).collect());
+    }
+    let truncated = records.next().is_some();
+
+    if rows.is_empty() {
+        return None;
+    }
+
+    Some(Table { rows, truncated })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_tables() {
+        assert_eq!(delimiter(Some("data.csv"), "text/plain"), Some(b','));
+        assert_eq!(delimiter(Some("DATA.TSV"), "text/plain"), Some(b'\t'));
+        assert_eq!(delimiter(None, "text/csv"), Some(b','));
+        assert_eq!(delimiter(Some("notes.txt"), "text/plain"), None);
+    }
+
+    #[test]
+    fn parses_csv() {
+        let table = parse("a,b\n1,\"2,3\"\n", b',').unwrap();
+        assert_eq!(table.rows, vec![vec!["a", "b"], vec!["1", "2,3"]]);
+        assert!(!table.truncated);
+    }
+
+    #[test]
+    fn parses_tsv() {
+        let table = parse("a\tb\n1\t2\n", b'\t').unwrap();
+        assert_eq!(table.rows, vec![vec!["a", "b"], vec!["1", "2"]]);
+    }
+
+    #[test]
+    fn truncates_long_tables() {
+        let text = "a\n".repeat(MAX_ROWS + 1);
+        let table = parse(&text, b',').unwrap();
+        assert_eq!(table.rows.len(), MAX_ROWS);
+        assert!(table.truncated);
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        assert!(parse("a,b\n1,2,3\n", b',').is_none());
+        assert!(parse("", b',').is_none());
+    }
+}
@@ -0,0 +1,258 @@
+// OmegaUpload Web Frontend
+// Copyright (C) 2021  Edward Shen
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The parts of an upload that don't depend on where the paste came from, so
+//! that the upload UI and the extension API upload pastes the same way.
+
+use std::str::FromStr;
+
+use ::http::StatusCode;
+use anyhow::{anyhow, bail, Context, Result};
+use byte_unit::Byte;
+use gloo_console::log;
+use omegaupload_common::crypto::{KdfProfile, Key, SealOptions, HASH_SIZE};
+use omegaupload_common::fragment::Builder;
+use omegaupload_common::secrecy::{ExposeSecret, Secret, SecretString, SecretVec};
+use omegaupload_common::{
+    base64, Expiration, ServerInfo, UploadResponse, Url, API_ENDPOINT, PASTE_SIZE_LIMIT,
+};
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+
+use crate::http::{self, ApiResponse};
+
+/// Options for an upload, provided as a plain JS object. All fields are
+/// optional, and empty strings are treated as missing.
+#[derive(Default, Deserialize)]
+pub struct UploadOptions {
+    pub password: Option<String>,
+    /// One of `KdfProfile::variants`. This is ignored without a password.
+    pub kdf_profile: Option<String>,
+    /// One of `Expiration::variants`.
+    pub expiration: Option<String>,
+    pub name: Option<String>,
+    pub language: Option<String>,
+    /// Whether viewers should keep the decrypted paste out of idb.
+    #[serde(default)]
+    pub private: bool,
+    /// Whether to remove EXIF and XMP metadata from images before encrypting
+    /// them.
+    #[serde(default)]
+    pub strip_metadata: bool,
+}
+
+impl UploadOptions {
+    pub fn from_js(options: JsValue) -> Result<Self> {
+        if options.is_undefined() || options.is_null() {
+            return Ok(Self::default());
+        }
+
+        let mut options: Self = serde_wasm_bindgen::from_value(options)
+            .map_err(|e| anyhow!("Invalid upload options: {e}"))?;
+        for field in [
+            &mut options.password,
+            &mut options.kdf_profile,
+            &mut options.expiration,
+            &mut options.name,
+            &mut options.language,
+        ] {
+            if field.as_deref() == Some("") {
+                *field = None;
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn expiration(&self) -> Result<Option<Expiration>> {
+        self.expiration
+            .as_deref()
+            .map(Expiration::from_str)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid expiration: {e}"))
+    }
+
+    pub fn password(&self) -> Option<SecretVec<u8>> {
+        self.password
+            .clone()
+            .map(|password| SecretVec::new(password.into_bytes()))
+    }
+
+    pub fn seal_options(&self) -> Result<SealOptions> {
+        let kdf = self
+            .kdf_profile
+            .as_deref()
+            .map(KdfProfile::from_str)
+            .transpose()
+            .map_err(|e| anyhow!("Invalid KDF profile: {e}"))?
+            .unwrap_or_default()
+            .recorded_params();
+        // Browsers can't rely on AES instructions, so we keep the default
+        // cipher.
+        Ok(SealOptions {
+            kdf,
+            ..SealOptions::default()
+        })
+    }
+
+    /// Builds the fragment for a paste sealed with `seal_chunked_in_place`
+    /// using these options.
+    pub fn fragment(&self, key: &Secret<Key>, hash: [u8; HASH_SIZE]) -> Result<Builder> {
+        let needs_password = self.password.is_some();
+        let key = SecretString::new(base64::encode(&key.expose_secret().as_ref()));
+        let mut fragment = Builder::new(key)
+            .chunked()
+            .hash(hash)
+            .envelope(self.seal_options()?.envelope(needs_password));
+        if needs_password {
+            fragment = fragment.needs_password();
+        }
+        if let Some(name) = self.name.clone() {
+            fragment = fragment.file_name(name);
+        }
+        if let Some(language) = self.language.clone() {
+            fragment = fragment.language(language);
+        }
+        if self.private {
+            fragment = fragment.private();
+        }
+        Ok(fragment)
+    }
+}
+
+/// What the server returned for an upload.
+pub struct Uploaded {
+    pub short_code: String,
+    /// The URL of the paste, if the server knows its public URL.
+    pub canonical_url: Option<Url>,
+    /// When the paste actually expires, which may differ from what was
+    /// requested.
+    pub expiration: Option<Expiration>,
+    /// Permits deleting the paste, if the server supports it.
+    pub owner_token: Option<String>,
+}
+
+impl Uploaded {
+    /// Reads the server's response to an upload.
+    #[allow(clippy::future_not_send)]
+    pub async fn from_response(resp: ApiResponse) -> Result<Self> {
+        match resp.status {
+            StatusCode::OK => (),
+            StatusCode::PAYLOAD_TOO_LARGE => bail!("The paste is too large for this server."),
+            status => bail!("Upload failed. Got HTTP status {status}"),
+        }
+
+        let expiration = resp.expiration;
+        let owner_token = resp.owner_token.clone();
+        let is_json = resp.content_type.as_deref().map_or(false, |content_type| {
+            content_type.starts_with("application/json")
+        });
+        // Older servers only return the short code.
+        let (short_code, canonical_url) = if is_json {
+            let response: UploadResponse = resp
+                .json()
+                .await
+                .context("The server returned an invalid upload response")?;
+            (response.code, response.url)
+        } else {
+            let short_code = resp
+                .text()
+                .await
+                .context("The server didn't return a short code")?;
+            (short_code, None)
+        };
+        Ok(Self {
+            short_code,
+            canonical_url,
+            expiration,
+            owner_token,
+        })
+    }
+
+    /// The URL of the paste, without its fragment. `location` is where it was
+    /// uploaded to, which is the base the frontend is hosted under.
+    pub fn url(&self, location: &Url) -> Url {
+        self.canonical_url.clone().unwrap_or_else(|| {
+            let mut url = location.clone();
+            let path = format!(
+                "{}/{}",
+                location.path().trim_end_matches('/'),
+                self.short_code
+            );
+            url.set_path(&path);
+            url
+        })
+    }
+}
+
+/// Checks an upload against what the server accepts, so that it's rejected
+/// before it's encrypted instead of after it's uploaded. Uploads to servers
+/// that can't describe themselves, such as older ones, are only checked
+/// against the limit every server has.
+#[allow(clippy::future_not_send)]
+pub async fn check_server_info(url: &Url, size: u64, expiration: Option<&str>) -> Result<()> {
+    let mut info_url = url.clone();
+    info_url.set_path(&format!(
+        "{}{API_ENDPOINT}/info",
+        url.path().trim_end_matches('/')
+    ));
+    info_url.set_query(None);
+    info_url.set_fragment(None);
+
+    let info = match fetch_server_info(info_url.as_str()).await {
+        Ok(info) => info,
+        Err(e) => {
+            log!(format!("[rs] Not checking upload against the server: {e}"));
+            return check_size(size, PASTE_SIZE_LIMIT);
+        }
+    };
+
+    check_size(size, info.paste_size_limit)?;
+    match expiration {
+        Some(expiration) if !info.expirations.iter().any(|variant| variant == expiration) => {
+            bail!(
+                "This server doesn't accept pastes that expire after {expiration}. Try one of: {}",
+                info.expirations.join(", ")
+            );
+        }
+        None if info.expiration_required => {
+            bail!(
+                "This server requires pastes to say when they expire. Try one of: {}",
+                info.expirations.join(", ")
+            );
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn check_size(size: u64, limit: u64) -> Result<()> {
+    if size >= limit {
+        bail!(
+            "The paste is too large for this server, which only accepts pastes smaller than {}.",
+            Byte::from_bytes(u128::from(limit)).get_appropriate_unit(true)
+        );
+    }
+    Ok(())
+}
+
+#[allow(clippy::future_not_send)]
+async fn fetch_server_info(request_uri: &str) -> Result<ServerInfo> {
+    let resp = http::get(request_uri).await?;
+    if !resp.status.is_success() {
+        bail!("Got HTTP status {}", resp.status);
+    }
+    resp.json().await.context("Invalid server info")
+}
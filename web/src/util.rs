@@ -14,7 +14,8 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use wasm_bindgen::JsCast;
+use js_sys::Reflect;
+use wasm_bindgen::{JsCast, JsValue};
 use web_sys::{Event, IdbDatabase, IdbOpenDbRequest};
 
 /// # Panics
@@ -24,3 +25,81 @@ pub fn as_idb_db(event: &Event) -> IdbDatabase {
     let target: IdbOpenDbRequest = event.target().map(JsCast::unchecked_into).unwrap();
     target.result().map(JsCast::unchecked_into).unwrap()
 }
+
+/// How much memory the browser hints the page may use, in bytes, if it gives
+/// any hints. This works in workers too.
+///
+/// `navigator.deviceMemory` is the device's memory in GiB, which browsers round
+/// down and cap at 8 GiB. `performance.memory.jsHeapSizeLimit` is how large the
+/// JS heap may grow. Both are only available in some browsers.
+pub fn memory_hint() -> Option<u64> {
+    let global = js_sys::global();
+    let get = |target: &JsValue, key: &str| Reflect::get(target, &JsValue::from_str(key)).ok();
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let device_memory = get(&global, "navigator")
+        .and_then(|navigator| get(&navigator, "deviceMemory"))
+        .and_then(|memory| memory.as_f64())
+        .filter(|memory| *memory > 0.0)
+        .map(|gib| (gib * 1024.0 * 1024.0 * 1024.0) as u64);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let heap_limit = get(&global, "performance")
+        .and_then(|performance| get(&performance, "memory"))
+        .and_then(|memory| get(&memory, "jsHeapSizeLimit"))
+        .and_then(|limit| limit.as_f64())
+        .filter(|limit| *limit > 0.0)
+        .map(|limit| limit as u64);
+
+    device_memory.into_iter().chain(heap_limit).min()
+}
+
+/// Returns the short code in a page's path, which is empty for the upload
+/// page. `base_path` must start and end with a slash.
+pub fn short_code<'a>(path: &'a str, base_path: &str) -> &'a str {
+    path.strip_prefix(base_path)
+        .or_else(|| path.strip_prefix(base_path.trim_end_matches('/')))
+        .unwrap_or(path)
+        .trim_matches('/')
+}
+
+/// The file extension for a recording made with `MediaRecorder`, whose MIME
+/// type may have parameters such as `audio/webm;codecs=opus`. The extension is
+/// what viewers use to tell audio from video.
+pub fn recording_extension(mime_type: &str) -> &'static str {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    match essence.to_ascii_lowercase().as_str() {
+        "audio/webm" => "weba",
+        "audio/ogg" => "ogg",
+        "video/ogg" => "ogv",
+        "audio/mp4" => "m4a",
+        "video/mp4" => "mp4",
+        "audio/wav" | "audio/wave" => "wav",
+        _ => "webm",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn short_code_at_root() {
+        assert_eq!(short_code("/", "/"), "");
+        assert_eq!(short_code("/abc123", "/"), "abc123");
+    }
+
+    #[test]
+    fn short_code_under_subpath() {
+        assert_eq!(short_code("/paste", "/paste/"), "");
+        assert_eq!(short_code("/paste/", "/paste/"), "");
+        assert_eq!(short_code("/paste/abc123", "/paste/"), "abc123");
+    }
+
+    #[test]
+    fn recording_extension_ignores_codecs() {
+        assert_eq!(recording_extension("audio/webm;codecs=opus"), "weba");
+        assert_eq!(recording_extension("video/webm; codecs=vp9"), "webm");
+        assert_eq!(recording_extension("video/mp4"), "mp4");
+        assert_eq!(recording_extension(""), "webm");
+    }
+}
@@ -4,8 +4,15 @@ const webpack = require('webpack');
 const WasmPackPlugin = require("@wasm-tool/wasm-pack-plugin");
 const { SourceMapDevToolPlugin } = require('webpack');
 
+// The path the frontend is hosted under, if a reverse proxy serves it from a
+// subpath. Must start and end with a slash.
+const basePath = process.env.OMEGAUPLOAD_BASE_PATH || "/";
+
 module.exports = {
-  entry: './web/src/index.js',
+  entry: {
+    index: './web/src/index.js',
+    sw: './web/src/sw.ts',
+  },
   module: {
     rules: [
       {
@@ -33,12 +40,14 @@ module.exports = {
   },
   output: {
     path: path.resolve(__dirname, 'dist/static'),
-    filename: 'index.js',
+    filename: '[name].js',
   },
   plugins: [
     new HtmlWebpackPlugin({
       template: path.resolve(__dirname, 'web/src/index.html'),
-      publicPath: "/static",
+      publicPath: basePath + "static",
+      basePath,
+      chunks: ['index'],
     }),
     new WasmPackPlugin({
       crateDirectory: path.resolve(__dirname, "web"),